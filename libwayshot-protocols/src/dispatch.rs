@@ -0,0 +1,153 @@
+use std::{
+    io,
+    io::ErrorKind,
+    time::{Duration, Instant},
+};
+
+use nix::{
+    errno::Errno,
+    poll::{poll, PollFd, PollFlags},
+};
+use wayland_client::{
+    backend::WaylandError, globals::GlobalListContents, protocol::wl_registry, Connection,
+    DispatchError, EventQueue, QueueHandle,
+};
+
+use crate::{Error, Result};
+
+/// Block on `event_queue` until `done` holds for `state`.
+pub fn dispatch_until<S>(
+    event_queue: &mut EventQueue<S>,
+    state: &mut S,
+    done: impl Fn(&S) -> bool,
+) -> Result<()> {
+    while !done(state) {
+        event_queue.blocking_dispatch(state)?;
+    }
+    Ok(())
+}
+
+/// Like [`dispatch_until`], but gives up with [`Error::Timeout`] once `deadline` passed.
+///
+/// Only a readable socket wakes the wait up, and `done` is checked after every dispatch, so
+/// events for other objects on the queue do not end it early.
+pub fn dispatch_until_deadline<S>(
+    event_queue: &mut EventQueue<S>,
+    state: &mut S,
+    done: impl Fn(&S) -> bool,
+    deadline: Instant,
+) -> Result<()> {
+    loop {
+        event_queue.dispatch_pending(state)?;
+        if done(state) {
+            return Ok(());
+        }
+        event_queue.flush().map_err(DispatchError::from)?;
+
+        // `None` means another reader already queued events for us, dispatch those first.
+        let Some(guard) = event_queue.prepare_read() else {
+            continue;
+        };
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(Error::Timeout);
+        }
+        let connection_fd = guard.connection_fd();
+        let mut fds = [PollFd::new(&connection_fd, PollFlags::POLLIN)];
+        // Round up, so the last poll does not spin on a sub-millisecond remainder.
+        let timeout_ms = remaining.as_micros().div_ceil(1000).min(i32::MAX as u128) as i32;
+        match poll(&mut fds, timeout_ms) {
+            Ok(0) | Err(Errno::EINTR) => {}
+            Ok(_) => match guard.read() {
+                Ok(_) => {}
+                Err(WaylandError::Io(e)) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(DispatchError::from(e).into()),
+            },
+            Err(e) => return Err(io::Error::from(e).into()),
+        }
+    }
+}
+
+/// Dispatch the events of `event_queue` like [`EventQueue::blocking_dispatch`], but wait at most
+/// `timeout` for some to arrive. Returns how many events were dispatched, 0 when none arrived.
+pub fn dispatch_timeout<S>(
+    event_queue: &mut EventQueue<S>,
+    state: &mut S,
+    timeout: Duration,
+) -> Result<usize> {
+    let dispatched = event_queue.dispatch_pending(state)?;
+    if dispatched > 0 {
+        return Ok(dispatched);
+    }
+    event_queue.flush().map_err(DispatchError::from)?;
+    // `None` means another reader already queued events for us.
+    if let Some(guard) = event_queue.prepare_read() {
+        let connection_fd = guard.connection_fd();
+        let mut fds = [PollFd::new(&connection_fd, PollFlags::POLLIN)];
+        let timeout_ms = timeout.as_millis().min(i32::MAX as u128) as i32;
+        match poll(&mut fds, timeout_ms) {
+            Ok(0) | Err(Errno::EINTR) => return Ok(0),
+            Ok(_) => match guard.read() {
+                Ok(_) => {}
+                Err(WaylandError::Io(e)) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(DispatchError::from(e).into()),
+            },
+            Err(e) => return Err(io::Error::from(e).into()),
+        }
+    }
+    Ok(event_queue.dispatch_pending(state)?)
+}
+
+/// Like [`dispatch_until`], but waits for the Wayland socket through the tokio reactor instead of
+/// blocking the thread.
+#[cfg(feature = "async")]
+pub async fn dispatch_until_async<S>(
+    conn: &Connection,
+    event_queue: &mut EventQueue<S>,
+    state: &mut S,
+    done: impl Fn(&S) -> bool,
+) -> Result<()> {
+    use tokio::io::{unix::AsyncFd, Interest};
+
+    // Register a duplicate of the socket so concurrent waits on one connection do not collide in
+    // the reactor.
+    let socket_fd = conn.backend().poll_fd().try_clone_to_owned()?;
+    // SAFETY: the AsyncFd owns the duplicate descriptor, which stays open and refers to the
+    // Wayland socket until the AsyncFd is dropped at the end of this function.
+    let socket = unsafe { AsyncFd::register_with_interest(socket_fd, Interest::READABLE) }
+        .map_err(|e| e.into_parts().1)?;
+    loop {
+        event_queue.dispatch_pending(state)?;
+        if done(state) {
+            return Ok(());
+        }
+        event_queue.flush().map_err(DispatchError::from)?;
+
+        let mut ready = socket.readable().await?;
+        // `None` means another reader already queued events for us, dispatch those first.
+        if let Some(guard) = event_queue.prepare_read() {
+            match guard.read() {
+                Ok(_) => {}
+                Err(WaylandError::Io(e)) if e.kind() == ErrorKind::WouldBlock => {
+                    ready.clear_ready()
+                }
+                Err(e) => return Err(DispatchError::from(e).into()),
+            }
+        }
+    }
+}
+
+/// State for `registry_queue_init`, the globals are read from the returned list instead.
+pub struct WayshotState {}
+
+impl wayland_client::Dispatch<wl_registry::WlRegistry, GlobalListContents> for WayshotState {
+    fn event(
+        _: &mut WayshotState,
+        _: &wl_registry::WlRegistry,
+        _: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<WayshotState>,
+    ) {
+    }
+}
@@ -0,0 +1,18 @@
+use std::{io, result};
+
+use thiserror::Error;
+use wayland_client::{protocol::wl_shm, DispatchError};
+
+pub type Result<T, E = Error> = result::Result<T, E>;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("dispatch error: {0}")]
+    Dispatch(#[from] DispatchError),
+    #[error("unsupported buffer format {0:?}")]
+    UnsupportedFormat(wl_shm::Format),
+    #[error("timed out waiting for the compositor")]
+    Timeout,
+}
@@ -0,0 +1,338 @@
+//! Event handling of ext-image-copy-capture sessions and their cursor sessions, and of the
+//! ext_foreign_toplevel_list_v1 toplevels they can capture.
+
+use std::time::Duration;
+
+use wayland_client::{
+    delegate_noop, event_created_child,
+    protocol::{
+        wl_buffer::WlBuffer,
+        wl_output::Transform,
+        wl_pointer::WlPointer,
+        wl_seat::{self, WlSeat},
+        wl_shm::{Format, WlShm},
+        wl_shm_pool::WlShmPool,
+    },
+    Connection, Dispatch, Proxy, QueueHandle,
+    WEnum::Value,
+};
+use wayland_protocols::ext::{
+    foreign_toplevel_list::v1::client::{
+        ext_foreign_toplevel_handle_v1::{self, ExtForeignToplevelHandleV1},
+        ext_foreign_toplevel_list_v1::{self, ExtForeignToplevelListV1},
+    },
+    image_capture_source::v1::client::{
+        ext_foreign_toplevel_image_capture_source_manager_v1::ExtForeignToplevelImageCaptureSourceManagerV1,
+        ext_image_capture_source_v1::ExtImageCaptureSourceV1,
+        ext_output_image_capture_source_manager_v1::ExtOutputImageCaptureSourceManagerV1,
+    },
+    image_copy_capture::v1::client::{
+        ext_image_copy_capture_cursor_session_v1::{self, ExtImageCopyCaptureCursorSessionV1},
+        ext_image_copy_capture_frame_v1::{self, ExtImageCopyCaptureFrameV1, FailureReason},
+        ext_image_copy_capture_manager_v1::ExtImageCopyCaptureManagerV1,
+        ext_image_copy_capture_session_v1::{self, ExtImageCopyCaptureSessionV1},
+    },
+};
+
+use crate::screencopy::Damage;
+
+/// What the compositor announced about one ext_foreign_toplevel_handle_v1.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ToplevelProperties {
+    pub title: String,
+    pub app_id: String,
+    /// Stable across the lifetime of the toplevel, unlike the handle object.
+    pub identifier: String,
+    /// Every property was sent at least once.
+    pub done: bool,
+    pub closed: bool,
+}
+
+/// Collects the toplevels of an ext_foreign_toplevel_list_v1.
+#[derive(Debug, Default)]
+pub struct ToplevelListState {
+    pub toplevels: Vec<(ExtForeignToplevelHandleV1, ToplevelProperties)>,
+}
+
+impl ToplevelListState {
+    /// Whether every listed toplevel sent its properties.
+    pub fn is_done(&self) -> bool {
+        self.toplevels.iter().all(|(_, properties)| properties.done)
+    }
+}
+
+impl Dispatch<ExtForeignToplevelListV1, ()> for ToplevelListState {
+    fn event(
+        state: &mut Self,
+        _: &ExtForeignToplevelListV1,
+        event: ext_foreign_toplevel_list_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let ext_foreign_toplevel_list_v1::Event::Toplevel { toplevel } = event {
+            tracing::debug!("Received Toplevel event");
+            state
+                .toplevels
+                .push((toplevel, ToplevelProperties::default()));
+        }
+    }
+
+    event_created_child!(ToplevelListState, ExtForeignToplevelListV1, [
+        ext_foreign_toplevel_list_v1::EVT_TOPLEVEL_OPCODE => (ExtForeignToplevelHandleV1, ()),
+    ]);
+}
+
+impl Dispatch<ExtForeignToplevelHandleV1, ()> for ToplevelListState {
+    fn event(
+        state: &mut Self,
+        handle: &ExtForeignToplevelHandleV1,
+        event: ext_foreign_toplevel_handle_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let Some((_, properties)) = state
+            .toplevels
+            .iter_mut()
+            .find(|(toplevel, _)| toplevel.id() == handle.id())
+        else {
+            return;
+        };
+        match event {
+            ext_foreign_toplevel_handle_v1::Event::Title { title } => properties.title = title,
+            ext_foreign_toplevel_handle_v1::Event::AppId { app_id } => properties.app_id = app_id,
+            ext_foreign_toplevel_handle_v1::Event::Identifier { identifier } => {
+                properties.identifier = identifier
+            }
+            ext_foreign_toplevel_handle_v1::Event::Done => properties.done = true,
+            ext_foreign_toplevel_handle_v1::Event::Closed => {
+                // Closed toplevels never send done, they must not hold up the listing.
+                properties.closed = true;
+                properties.done = true;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Buffer constraints of an ext_image_copy_capture_session_v1.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SessionConstraints {
+    /// Size frames must be copied at, from the `buffer_size` event.
+    pub buffer_size: Option<(u32, u32)>,
+    /// wl_shm formats frames can be copied in, in the compositor's order of preference.
+    pub shm_formats: Vec<Format>,
+    /// The constraints are complete. Constraints sent afterwards, eg: when the captured window was
+    /// resized, replace them and clear it until the next `done`.
+    pub done: bool,
+}
+
+/// How an ext_image_copy_capture_frame_v1 ended.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CopyOutcome {
+    Ready,
+    Failed(FailureReason),
+}
+
+/// Collects the events of one ext_image_copy_capture_frame_v1.
+#[derive(Debug, Default)]
+pub struct CopyFrameState {
+    /// Transform the compositor applied to the buffer contents.
+    pub transform: Option<Transform>,
+    pub damage: Vec<Damage>,
+    /// On the compositor's presentation clock, usually CLOCK_MONOTONIC.
+    pub presentation_time: Option<Duration>,
+    /// `None` while the copy is in flight.
+    pub outcome: Option<CopyOutcome>,
+    /// Every event received for the frame, in order, to explain failures.
+    pub events: Vec<String>,
+}
+
+impl CopyFrameState {
+    /// The received events as one line, eg: for an error message.
+    pub fn event_log(&self) -> String {
+        self.events.join(", ")
+    }
+}
+
+/// Where the pointer of an ext_image_copy_capture_cursor_session_v1 is.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CursorState {
+    /// The cursor image intersects the captured source.
+    pub entered: bool,
+    /// Position of the hotspot, in buffer pixels of the source. It may lie outside of it.
+    pub position: Option<(i32, i32)>,
+    /// Offset of the hotspot from the top left corner of the cursor image of the latest frame.
+    pub hotspot: Option<(i32, i32)>,
+    /// The hotspot sent for the next frame, it applies once that frame is ready.
+    pub pending_hotspot: Option<(i32, i32)>,
+}
+
+/// Collects the events of an ext_image_copy_capture_session_v1 and of its current frame, which
+/// share one event queue, and of the cursor session the session belongs to, if any.
+#[derive(Debug, Default)]
+pub struct ImageCopyState {
+    pub constraints: SessionConstraints,
+    /// The compositor ended the session, eg: the captured window was closed.
+    pub stopped: bool,
+    pub frame: CopyFrameState,
+    pub cursor: CursorState,
+    /// The wl_seat a cursor session is created for has a pointer.
+    pub seat_has_pointer: bool,
+}
+
+impl Dispatch<ExtImageCopyCaptureSessionV1, ()> for ImageCopyState {
+    fn event(
+        state: &mut Self,
+        _: &ExtImageCopyCaptureSessionV1,
+        event: ext_image_copy_capture_session_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let constraints = &mut state.constraints;
+        let renegotiating = matches!(
+            event,
+            ext_image_copy_capture_session_v1::Event::BufferSize { .. }
+                | ext_image_copy_capture_session_v1::Event::ShmFormat { .. }
+                | ext_image_copy_capture_session_v1::Event::DmabufDevice { .. }
+                | ext_image_copy_capture_session_v1::Event::DmabufFormat { .. }
+        );
+        if renegotiating && constraints.done {
+            tracing::debug!("Capture session constraints changed");
+            *constraints = SessionConstraints::default();
+        }
+        match event {
+            ext_image_copy_capture_session_v1::Event::BufferSize { width, height } => {
+                constraints.buffer_size = Some((width, height));
+            }
+            ext_image_copy_capture_session_v1::Event::ShmFormat {
+                format: Value(format),
+            } => constraints.shm_formats.push(format),
+            ext_image_copy_capture_session_v1::Event::Done => constraints.done = true,
+            ext_image_copy_capture_session_v1::Event::Stopped => {
+                tracing::debug!("Received Stopped event");
+                state.stopped = true;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ExtImageCopyCaptureFrameV1, ()> for ImageCopyState {
+    fn event(
+        state: &mut Self,
+        _: &ExtImageCopyCaptureFrameV1,
+        event: ext_image_copy_capture_frame_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let frame = &mut state.frame;
+        frame.events.push(format!("{event:?}"));
+        match event {
+            ext_image_copy_capture_frame_v1::Event::Transform {
+                transform: Value(transform),
+            } => frame.transform = Some(transform),
+            ext_image_copy_capture_frame_v1::Event::Damage {
+                x,
+                y,
+                width,
+                height,
+            } => {
+                // Negative values would violate the protocol, they are dropped rather than wrapped.
+                if let (Ok(x), Ok(y), Ok(width), Ok(height)) = (
+                    u32::try_from(x),
+                    u32::try_from(y),
+                    u32::try_from(width),
+                    u32::try_from(height),
+                ) {
+                    frame.damage.push(Damage {
+                        x,
+                        y,
+                        width,
+                        height,
+                    });
+                }
+            }
+            ext_image_copy_capture_frame_v1::Event::PresentationTime {
+                tv_sec_hi,
+                tv_sec_lo,
+                tv_nsec,
+            } => {
+                let seconds = (u64::from(tv_sec_hi) << 32) | u64::from(tv_sec_lo);
+                frame.presentation_time =
+                    Duration::from_secs(seconds).checked_add(Duration::from_nanos(tv_nsec.into()));
+            }
+            ext_image_copy_capture_frame_v1::Event::Ready => {
+                frame.outcome = Some(CopyOutcome::Ready);
+                if let Some(hotspot) = state.cursor.pending_hotspot.take() {
+                    state.cursor.hotspot = Some(hotspot);
+                }
+            }
+            ext_image_copy_capture_frame_v1::Event::Failed { reason } => {
+                let reason = match reason {
+                    Value(reason) => reason,
+                    _ => FailureReason::Unknown,
+                };
+                frame.outcome = Some(CopyOutcome::Failed(reason));
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ExtImageCopyCaptureCursorSessionV1, ()> for ImageCopyState {
+    fn event(
+        state: &mut Self,
+        _: &ExtImageCopyCaptureCursorSessionV1,
+        event: ext_image_copy_capture_cursor_session_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let cursor = &mut state.cursor;
+        match event {
+            ext_image_copy_capture_cursor_session_v1::Event::Enter => cursor.entered = true,
+            ext_image_copy_capture_cursor_session_v1::Event::Leave => {
+                cursor.entered = false;
+                cursor.position = None;
+            }
+            ext_image_copy_capture_cursor_session_v1::Event::Position { x, y } => {
+                cursor.position = Some((x, y));
+            }
+            ext_image_copy_capture_cursor_session_v1::Event::Hotspot { x, y } => {
+                cursor.pending_hotspot = Some((x, y));
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<WlSeat, ()> for ImageCopyState {
+    fn event(
+        state: &mut Self,
+        _: &WlSeat,
+        event: wl_seat::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wl_seat::Event::Capabilities {
+            capabilities: Value(capabilities),
+        } = event
+        {
+            state.seat_has_pointer = capabilities.contains(wl_seat::Capability::Pointer);
+        }
+    }
+}
+
+delegate_noop!(ImageCopyState: ignore WlShm);
+delegate_noop!(ImageCopyState: ignore WlShmPool);
+delegate_noop!(ImageCopyState: ignore WlBuffer);
+delegate_noop!(ImageCopyState: ExtImageCopyCaptureManagerV1);
+delegate_noop!(ImageCopyState: ExtForeignToplevelImageCaptureSourceManagerV1);
+delegate_noop!(ImageCopyState: ExtOutputImageCaptureSourceManagerV1);
+delegate_noop!(ImageCopyState: ExtImageCaptureSourceV1);
+delegate_noop!(ImageCopyState: ignore WlPointer);
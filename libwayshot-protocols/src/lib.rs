@@ -0,0 +1,21 @@
+//! `libwayshot-protocols` holds the Wayland event handling [libwayshot] captures screens with:
+//! the states collecting what the compositor announces about outputs, screencopy frames and
+//! ext-image-copy-capture sessions, and helpers to dispatch until they are complete.
+//!
+//! It does not touch pixel data, the image pipeline lives in libwayshot.
+//!
+//! [libwayshot]: https://docs.rs/libwayshot
+#![deny(clippy::unwrap_used, clippy::undocumented_unsafe_blocks)]
+
+mod dispatch;
+mod error;
+pub mod image_copy;
+pub mod output;
+pub mod screencopy;
+
+#[cfg(feature = "async")]
+pub use crate::dispatch::dispatch_until_async;
+pub use crate::{
+    dispatch::{dispatch_timeout, dispatch_until, dispatch_until_deadline, WayshotState},
+    error::{Error, Result},
+};
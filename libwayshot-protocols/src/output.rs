@@ -0,0 +1,363 @@
+use std::fs::File;
+
+use memmap2::MmapOptions;
+use wayland_client::{
+    delegate_noop,
+    protocol::{wl_output, wl_output::WlOutput, wl_registry, wl_registry::WlRegistry},
+    Connection, Dispatch, QueueHandle, WEnum,
+    WEnum::Value,
+};
+use wayland_protocols::{
+    wp::color_management::v1::client::{
+        wp_color_management_output_v1::WpColorManagementOutputV1,
+        wp_color_manager_v1::{Primaries, TransferFunction, WpColorManagerV1},
+        wp_image_description_info_v1::{self, WpImageDescriptionInfoV1},
+        wp_image_description_v1::{self, WpImageDescriptionV1},
+    },
+    xdg::xdg_output::zv1::client::{
+        zxdg_output_manager_v1::ZxdgOutputManagerV1, zxdg_output_v1, zxdg_output_v1::ZxdgOutputV1,
+    },
+};
+
+/// Everything the compositor announced about one wl_output.
+#[derive(Debug, Clone)]
+pub struct OutputProperties {
+    pub wl_output: WlOutput,
+    pub name: String,
+    pub description: String,
+    pub make: String,
+    pub model: String,
+    pub transform: wl_output::Transform,
+    pub dimensions: OutputPositioning,
+    pub mode: WlOutputMode,
+    /// `None` when the compositor does not implement wp_color_management_v1.
+    pub color: Option<ColorDescription>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct WlOutputMode {
+    pub width: i32,
+    pub height: i32,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct OutputPositioning {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+/// Color characteristics of an output as described by the compositor.
+///
+/// Depending on the compositor an output is described either by an ICC profile or parametrically,
+/// so any of the fields may be missing.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ColorDescription {
+    /// ICC profile of the output.
+    pub icc_profile: Option<Vec<u8>>,
+    /// Well-known primaries, when the compositor names them.
+    pub primaries_named: Option<Primaries>,
+    /// CIE 1931 xy chromaticities of the primaries and the white point.
+    pub primaries: Option<Chromaticities>,
+    /// Well-known transfer function, when the compositor names it.
+    pub transfer_function: Option<TransferFunction>,
+    /// Exponent of a pure power curve transfer function.
+    pub transfer_function_power: Option<f64>,
+}
+
+/// CIE 1931 xy chromaticity coordinates of the red, green and blue primaries and the white point.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Chromaticities {
+    pub red: (f64, f64),
+    pub green: (f64, f64),
+    pub blue: (f64, f64),
+    pub white: (f64, f64),
+}
+
+/// Collects the outputs announced through the registry, wl_output, xdg-output and
+/// wp-color-management events.
+#[derive(Debug, Default)]
+pub struct OutputCaptureState {
+    pub outputs: Vec<OutputProperties>,
+}
+
+impl Dispatch<WlRegistry, ()> for OutputCaptureState {
+    fn event(
+        state: &mut Self,
+        wl_registry: &WlRegistry,
+        event: wl_registry::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        /* > The name event is sent after binding the output object. This event
+         * is only sent once per output object, and the name does not change
+         * over the lifetime of the wl_output global. */
+
+        if let wl_registry::Event::Global {
+            name,
+            interface,
+            version,
+        } = event
+        {
+            if interface == "wl_output" {
+                if version >= 4 {
+                    let output = wl_registry.bind::<wl_output::WlOutput, _, _>(name, 4, qh, ());
+                    state.outputs.push(OutputProperties {
+                        wl_output: output,
+                        name: "".to_string(),
+                        description: String::new(),
+                        make: String::new(),
+                        model: String::new(),
+                        transform: wl_output::Transform::Normal,
+                        dimensions: OutputPositioning {
+                            x: 0,
+                            y: 0,
+                            width: 0,
+                            height: 0,
+                        },
+                        mode: WlOutputMode {
+                            width: 0,
+                            height: 0,
+                        },
+                        color: None,
+                    });
+                } else {
+                    tracing::error!("Ignoring a wl_output with version < 4.");
+                }
+            }
+        }
+    }
+}
+
+impl Dispatch<WlOutput, ()> for OutputCaptureState {
+    fn event(
+        state: &mut Self,
+        wl_output: &WlOutput,
+        event: wl_output::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let Some(output) = state.outputs.iter_mut().find(|x| x.wl_output == *wl_output) else {
+            tracing::debug!("Received wl_output event for an unknown output");
+            return;
+        };
+
+        match event {
+            wl_output::Event::Name { name } => {
+                output.name = name;
+            }
+            wl_output::Event::Description { description } => {
+                output.description = description;
+            }
+            wl_output::Event::Mode { width, height, .. } => {
+                output.mode = WlOutputMode { width, height };
+            }
+            wl_output::Event::Geometry {
+                make,
+                model,
+                transform,
+                ..
+            } => {
+                output.make = make;
+                output.model = model;
+                if let WEnum::Value(transform) = transform {
+                    output.transform = transform;
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+delegate_noop!(OutputCaptureState: ignore ZxdgOutputManagerV1);
+
+impl Dispatch<ZxdgOutputV1, usize> for OutputCaptureState {
+    fn event(
+        state: &mut Self,
+        _: &ZxdgOutputV1,
+        event: zxdg_output_v1::Event,
+        index: &usize,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let Some(output_info) = state.outputs.get_mut(*index) else {
+            tracing::debug!("Received xdg_output event for an unknown output");
+            return;
+        };
+
+        match event {
+            zxdg_output_v1::Event::LogicalPosition { x, y } => {
+                output_info.dimensions.x = x;
+                output_info.dimensions.y = y;
+                tracing::debug!("Logical position event fired!");
+            }
+            zxdg_output_v1::Event::LogicalSize { width, height } => {
+                output_info.dimensions.width = width;
+                output_info.dimensions.height = height;
+                tracing::debug!("Logical size event fired!");
+            }
+            _ => {}
+        };
+    }
+}
+
+delegate_noop!(OutputCaptureState: ignore WpColorManagerV1);
+delegate_noop!(OutputCaptureState: ignore WpColorManagementOutputV1);
+
+impl Dispatch<WpImageDescriptionV1, usize> for OutputCaptureState {
+    fn event(
+        state: &mut Self,
+        image_description: &WpImageDescriptionV1,
+        event: wp_image_description_v1::Event,
+        index: &usize,
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        let Some(output_info) = state.outputs.get_mut(*index) else {
+            tracing::debug!("Received image description event for an unknown output");
+            return;
+        };
+
+        match event {
+            wp_image_description_v1::Event::Ready { .. }
+            | wp_image_description_v1::Event::Ready2 { .. } => {
+                output_info.color = Some(ColorDescription::default());
+                image_description.get_information(qh, *index);
+            }
+            wp_image_description_v1::Event::Failed { msg, .. } => {
+                tracing::debug!("No image description for {}: {msg}", output_info.name);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Chromaticity coordinates are sent multiplied by a million.
+fn chromaticity(x: i32, y: i32) -> (f64, f64) {
+    (f64::from(x) / 1_000_000.0, f64::from(y) / 1_000_000.0)
+}
+
+impl Dispatch<WpImageDescriptionInfoV1, usize> for OutputCaptureState {
+    fn event(
+        state: &mut Self,
+        _: &WpImageDescriptionInfoV1,
+        event: wp_image_description_info_v1::Event,
+        index: &usize,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let Some(color) = state
+            .outputs
+            .get_mut(*index)
+            .and_then(|output_info| output_info.color.as_mut())
+        else {
+            tracing::debug!("Received image description info for an unknown output");
+            return;
+        };
+
+        match event {
+            wp_image_description_info_v1::Event::IccFile { icc, icc_size } => {
+                // The profile is only readable through a private read-only mapping.
+                // SAFETY: the compositor creates the file for this event and does not modify it
+                // afterwards, and the mapping is copied out before the file is closed.
+                let icc_profile = unsafe {
+                    MmapOptions::new()
+                        .len(icc_size as usize)
+                        .map_copy_read_only(&File::from(icc))
+                };
+                match icc_profile {
+                    Ok(icc_profile) => color.icc_profile = Some(icc_profile.to_vec()),
+                    Err(e) => tracing::debug!("Failed to read the output ICC profile: {e}"),
+                }
+            }
+            wp_image_description_info_v1::Event::Primaries {
+                r_x,
+                r_y,
+                g_x,
+                g_y,
+                b_x,
+                b_y,
+                w_x,
+                w_y,
+            } => {
+                color.primaries = Some(Chromaticities {
+                    red: chromaticity(r_x, r_y),
+                    green: chromaticity(g_x, g_y),
+                    blue: chromaticity(b_x, b_y),
+                    white: chromaticity(w_x, w_y),
+                });
+            }
+            wp_image_description_info_v1::Event::PrimariesNamed {
+                primaries: Value(primaries),
+            } => color.primaries_named = Some(primaries),
+            wp_image_description_info_v1::Event::TfNamed { tf: Value(tf) } => {
+                color.transfer_function = Some(tf);
+            }
+            wp_image_description_info_v1::Event::TfPower { eexp } => {
+                color.transfer_function_power = Some(f64::from(eexp) / 10_000.0);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Tracks the wl_output globals for as long as the registry is listened to, to notice outputs
+/// being plugged, unplugged or reconfigured.
+#[derive(Debug, Default)]
+pub struct OutputWatchState {
+    /// Bound outputs along with the name of their registry global.
+    pub outputs: Vec<(u32, WlOutput)>,
+    /// Set whenever an output appeared, disappeared or finished announcing a change, the owner
+    /// resets it once handled.
+    pub changed: bool,
+}
+
+impl Dispatch<WlRegistry, ()> for OutputWatchState {
+    fn event(
+        state: &mut Self,
+        wl_registry: &WlRegistry,
+        event: wl_registry::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_registry::Event::Global {
+                name,
+                interface,
+                version,
+            } if interface == "wl_output" && version >= 4 => {
+                let output = wl_registry.bind::<WlOutput, _, _>(name, 4, qh, ());
+                state.outputs.push((name, output));
+                state.changed = true;
+            }
+            wl_registry::Event::GlobalRemove { name } => {
+                if let Some(index) = state.outputs.iter().position(|(global, _)| *global == name) {
+                    let (_, output) = state.outputs.remove(index);
+                    output.release();
+                    state.changed = true;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<WlOutput, ()> for OutputWatchState {
+    fn event(
+        state: &mut Self,
+        _: &WlOutput,
+        event: wl_output::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        // Mode, geometry and xdg-output changes are all followed by a done event.
+        if let wl_output::Event::Done = event {
+            state.changed = true;
+        }
+    }
+}
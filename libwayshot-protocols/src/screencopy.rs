@@ -0,0 +1,280 @@
+use std::time::Duration;
+
+use wayland_client::{
+    delegate_noop,
+    protocol::{wl_buffer::WlBuffer, wl_shm::Format, wl_shm::WlShm, wl_shm_pool::WlShmPool},
+    Connection, Dispatch, QueueHandle,
+    WEnum::Value,
+};
+use wayland_protocols_wlr::screencopy::v1::client::{
+    zwlr_screencopy_frame_v1, zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1,
+    zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1,
+};
+
+use crate::{Error, Result};
+
+/// Type of frame supported by the compositor. For now we only support Argb8888, Xrgb8888, and
+/// Xbgr8888.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FrameFormat {
+    pub format: Format,
+    pub width: u32,
+    pub height: u32,
+    pub stride: u32,
+}
+
+impl FrameFormat {
+    /// Size of a single pixel in bytes, excluding any row padding.
+    pub fn bytes_per_pixel(&self) -> Result<usize> {
+        match self.format {
+            Format::Xbgr2101010
+            | Format::Abgr2101010
+            | Format::Argb8888
+            | Format::Xrgb8888
+            | Format::Abgr8888
+            | Format::Xbgr8888 => Ok(4),
+            Format::Bgr888 => Ok(3),
+            format => Err(Error::UnsupportedFormat(format)),
+        }
+    }
+
+    /// Name of the GStreamer raw video format with the memory layout of the frame, eg: `BGRx`.
+    ///
+    /// wl_shm formats are named from the most significant bits of a little endian word, GStreamer
+    /// ones in memory order, so `Xrgb8888` is `BGRx`. `Xbgr2101010` has no GStreamer equivalent,
+    /// its padding bits are not guaranteed to be opaque like the alpha of `RGB10A2_LE`.
+    pub fn gst_video_format(&self) -> Result<&'static str> {
+        match self.format {
+            Format::Xrgb8888 => Ok("BGRx"),
+            Format::Argb8888 => Ok("BGRA"),
+            Format::Xbgr8888 => Ok("RGBx"),
+            Format::Abgr8888 => Ok("RGBA"),
+            Format::Bgr888 => Ok("RGB"),
+            Format::Abgr2101010 => Ok("RGB10A2_LE"),
+            format => Err(Error::UnsupportedFormat(format)),
+        }
+    }
+
+    /// `video/x-raw` caps describing frames of this format at `framerate` frames per second, to set
+    /// on an `appsrc` or a `capsfilter`.
+    ///
+    /// Caps cannot carry the stride, GStreamer assumes rows padded to 4 bytes. Frames with a
+    /// larger stride need their rows packed, or a `GstVideoMeta` with the stride attached to each
+    /// buffer.
+    pub fn gst_caps(&self, framerate: u32) -> Result<String> {
+        Ok(format!(
+            "video/x-raw,format={},width={},height={},framerate={framerate}/1",
+            self.gst_video_format()?,
+            self.width,
+            self.height,
+        ))
+    }
+}
+
+/// A DMA-BUF buffer the compositor offers to copy a frame into.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DmabufFormat {
+    /// DRM fourcc code.
+    pub format: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// A rectangle of a frame that changed since the previous frame, in buffer coordinates.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Damage {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// State of the frame after attemting to copy it's data to a wl_buffer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FrameState {
+    /// Compositor returned a failed event on calling `frame.copy`.
+    Failed,
+    /// Compositor sent a Ready event on calling `frame.copy`.
+    Finished,
+}
+
+/// How far a zwlr_screencopy_frame_v1 got, events only ever move it forward.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum FramePhase {
+    /// The compositor is still announcing buffer formats.
+    #[default]
+    Negotiating,
+    /// Every buffer format was announced, the frame can be copied.
+    BufferDone,
+    /// The copy ended with a Ready or a Failed event.
+    Copied(FrameState),
+}
+
+impl FramePhase {
+    /// Whether the buffer formats are complete, which stays true once the frame was copied.
+    pub fn is_buffer_done(self) -> bool {
+        self != Self::Negotiating
+    }
+
+    /// Outcome of the copy, `None` while it is in flight.
+    pub fn copied(self) -> Option<FrameState> {
+        match self {
+            Self::Copied(frame_state) => Some(frame_state),
+            _ => None,
+        }
+    }
+}
+
+/// Collects the events of one zwlr_screencopy_frame_v1.
+#[derive(Default)]
+pub struct CaptureFrameState {
+    pub formats: Vec<FrameFormat>,
+    /// Offered through `linux_dmabuf` events, from zwlr_screencopy_manager_v1 version 3 on.
+    pub dmabuf_formats: Vec<DmabufFormat>,
+    pub phase: FramePhase,
+    /// Regions reported by `damage` events, only sent for `copy_with_damage`.
+    pub damage: Vec<Damage>,
+    /// Every event received for the frame, in order, to explain protocol violations.
+    pub events: Vec<String>,
+    /// Sent by the `flags` event just before `ready`, eg: whether the frame is y-inverted.
+    pub flags: Option<zwlr_screencopy_frame_v1::Flags>,
+    /// Presentation time of the copied content from the `ready` event, on the compositor's
+    /// presentation clock (usually CLOCK_MONOTONIC).
+    pub presentation_time: Option<Duration>,
+}
+
+impl CaptureFrameState {
+    /// The received events as one line, eg: for an error message.
+    pub fn event_log(&self) -> String {
+        self.events.join(", ")
+    }
+}
+
+impl Dispatch<ZwlrScreencopyFrameV1, ()> for CaptureFrameState {
+    fn event(
+        frame: &mut Self,
+        _: &ZwlrScreencopyFrameV1,
+        event: zwlr_screencopy_frame_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        frame.handle_event(event);
+    }
+}
+
+/// Collects the events of several zwlr_screencopy_frame_v1 sharing one event queue, so they can
+/// be copied at the same time. Each frame is created with its index in `frames` as user data.
+#[derive(Default)]
+pub struct CaptureFramesState {
+    pub frames: Vec<CaptureFrameState>,
+}
+
+impl Dispatch<ZwlrScreencopyFrameV1, usize> for CaptureFramesState {
+    fn event(
+        state: &mut Self,
+        _: &ZwlrScreencopyFrameV1,
+        event: zwlr_screencopy_frame_v1::Event,
+        index: &usize,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let Some(frame) = state.frames.get_mut(*index) {
+            frame.handle_event(event);
+        }
+    }
+}
+
+impl CaptureFrameState {
+    /// Record one event of the frame.
+    fn handle_event(&mut self, event: zwlr_screencopy_frame_v1::Event) {
+        self.events.push(format!("{event:?}"));
+        match event {
+            zwlr_screencopy_frame_v1::Event::Buffer {
+                format,
+                width,
+                height,
+                stride,
+            } => {
+                tracing::debug!("Received Buffer event");
+                if let Value(f) = format {
+                    self.formats.push(FrameFormat {
+                        format: f,
+                        width,
+                        height,
+                        stride,
+                    })
+                } else {
+                    tracing::debug!("Received Buffer event with unidentified format");
+                }
+            }
+            zwlr_screencopy_frame_v1::Event::Flags { flags } => {
+                tracing::debug!("Received Flags event");
+                if let Value(flags) = flags {
+                    self.flags = Some(flags);
+                }
+            }
+            zwlr_screencopy_frame_v1::Event::Ready {
+                tv_sec_hi,
+                tv_sec_lo,
+                tv_nsec,
+            } => {
+                // If the frame is successfully copied, a “flags” and a “ready” events are sent. Otherwise, a “failed” event is sent.
+                // This is useful when we call .copy on the frame object.
+                tracing::debug!("Received Ready event");
+                let seconds = (u64::from(tv_sec_hi) << 32) | u64::from(tv_sec_lo);
+                self.presentation_time =
+                    Duration::from_secs(seconds).checked_add(Duration::from_nanos(tv_nsec.into()));
+                self.phase = FramePhase::Copied(FrameState::Finished);
+            }
+            zwlr_screencopy_frame_v1::Event::Failed => {
+                tracing::debug!("Received Failed event");
+                self.phase = FramePhase::Copied(FrameState::Failed);
+            }
+            zwlr_screencopy_frame_v1::Event::Damage {
+                x,
+                y,
+                width,
+                height,
+            } => {
+                tracing::debug!("Received Damage event");
+                self.damage.push(Damage {
+                    x,
+                    y,
+                    width,
+                    height,
+                });
+            }
+            zwlr_screencopy_frame_v1::Event::LinuxDmabuf {
+                format,
+                width,
+                height,
+            } => {
+                tracing::debug!("Received LinuxDmaBuf event");
+                self.dmabuf_formats.push(DmabufFormat {
+                    format,
+                    width,
+                    height,
+                });
+            }
+            zwlr_screencopy_frame_v1::Event::BufferDone => {
+                tracing::debug!("Received bufferdone event");
+                if self.phase == FramePhase::Negotiating {
+                    self.phase = FramePhase::BufferDone;
+                }
+            }
+            _ => {
+                tracing::debug!("Received unknown screencopy frame event");
+            }
+        };
+    }
+}
+
+delegate_noop!(CaptureFrameState: ignore WlShm);
+delegate_noop!(CaptureFrameState: ignore WlShmPool);
+delegate_noop!(CaptureFrameState: ignore WlBuffer);
+delegate_noop!(CaptureFrameState: ignore ZwlrScreencopyManagerV1);
+delegate_noop!(CaptureFramesState: ignore WlShm);
+delegate_noop!(CaptureFramesState: ignore WlShmPool);
+delegate_noop!(CaptureFramesState: ignore WlBuffer);
+delegate_noop!(CaptureFramesState: ignore ZwlrScreencopyManagerV1);
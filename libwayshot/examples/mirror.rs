@@ -0,0 +1,153 @@
+//! Live viewer: mirror the first output into a window at half its size, showing the frames of a
+//! `StreamingCaptureContext` without copying them.
+//!
+//! ```sh
+//! cargo run --release --example mirror
+//! ```
+
+use std::error::Error;
+
+use libwayshot::{ViewportOptions, WayshotConnection};
+use wayland_client::{
+    delegate_noop,
+    globals::{registry_queue_init, GlobalListContents},
+    protocol::{
+        wl_compositor::WlCompositor,
+        wl_registry::{self, WlRegistry},
+        wl_surface::WlSurface,
+    },
+    Connection, Dispatch, QueueHandle,
+};
+use wayland_protocols::{
+    wp::viewporter::client::{wp_viewport::WpViewport, wp_viewporter::WpViewporter},
+    xdg::shell::client::{
+        xdg_surface::{self, XdgSurface},
+        xdg_toplevel::{self, XdgToplevel},
+        xdg_wm_base::{self, XdgWmBase},
+    },
+};
+
+#[derive(Default)]
+struct Window {
+    configured: bool,
+    closed: bool,
+    /// Size the compositor asked for, `None` to pick one.
+    size: Option<(i32, i32)>,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let conn = Connection::connect_to_env()?;
+    // The window and the capture share the connection, so the captured buffers can be attached.
+    let wayshot_connection = WayshotConnection::from_connection(conn.clone())?;
+    let output = &wayshot_connection.get_all_outputs()[0];
+
+    let (globals, mut event_queue) = registry_queue_init::<Window>(&conn)?;
+    let qh = event_queue.handle();
+    let compositor = globals.bind::<WlCompositor, _, _>(&qh, 4..=6, ())?;
+    let wm_base = globals.bind::<XdgWmBase, _, _>(&qh, 1..=6, ())?;
+    let viewporter = globals.bind::<WpViewporter, _, _>(&qh, 1..=1, ())?;
+
+    let surface = compositor.create_surface(&qh, ());
+    let viewport = viewporter.get_viewport(&surface, &qh, ());
+    let xdg_surface = wm_base.get_xdg_surface(&surface, &qh, ());
+    let toplevel = xdg_surface.get_toplevel(&qh, ());
+    toplevel.set_title(format!("Mirror of {}", output.name));
+    surface.commit();
+
+    let mut window = Window::default();
+    while !window.configured {
+        event_queue.blocking_dispatch(&mut window)?;
+    }
+
+    let mut context = wayshot_connection.start_streaming_capture(output, true)?;
+    while !window.closed {
+        // Blocks until the output changed, an idle output leaves the last frame up.
+        context.capture_frame_with_damage()?;
+        let (width, height) = window
+            .size
+            .unwrap_or((output.dimensions.width / 2, output.dimensions.height / 2));
+        context.present_frame(
+            &surface,
+            Some(ViewportOptions {
+                viewport: &viewport,
+                width,
+                height,
+            }),
+        )?;
+        // Waiting on the capture reads the window events from the socket as well.
+        event_queue.dispatch_pending(&mut window)?;
+    }
+
+    viewport.destroy();
+    toplevel.destroy();
+    xdg_surface.destroy();
+    surface.destroy();
+    Ok(())
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for Window {
+    fn event(
+        _: &mut Self,
+        _: &WlRegistry,
+        _: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<XdgWmBase, ()> for Window {
+    fn event(
+        _: &mut Self,
+        wm_base: &XdgWmBase,
+        event: xdg_wm_base::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let xdg_wm_base::Event::Ping { serial } = event {
+            wm_base.pong(serial);
+        }
+    }
+}
+
+impl Dispatch<XdgSurface, ()> for Window {
+    fn event(
+        window: &mut Self,
+        xdg_surface: &XdgSurface,
+        event: xdg_surface::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let xdg_surface::Event::Configure { serial } = event {
+            xdg_surface.ack_configure(serial);
+            window.configured = true;
+        }
+    }
+}
+
+impl Dispatch<XdgToplevel, ()> for Window {
+    fn event(
+        window: &mut Self,
+        _: &XdgToplevel,
+        event: xdg_toplevel::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            xdg_toplevel::Event::Configure { width, height, .. } => {
+                window.size = (width > 0 && height > 0).then_some((width, height));
+            }
+            xdg_toplevel::Event::Close => window.closed = true,
+            _ => {}
+        }
+    }
+}
+
+delegate_noop!(Window: ignore WlCompositor);
+delegate_noop!(Window: ignore WlSurface);
+delegate_noop!(Window: ignore WpViewporter);
+delegate_noop!(Window: ignore WpViewport);
@@ -0,0 +1,102 @@
+//! Time PNG encoding of synthetic desktops at each `SpeedVsSize` level, to tune the size above
+//! which `SpeedVsSize::Auto` switches to the fast level:
+//!
+//! ```sh
+//! cargo run --release --features write --example png_speed [capture.png]
+//! ```
+//!
+//! Without a capture, the desktops mix flat backgrounds, text-like runs and noisy photo-like
+//! areas. Their encoding times follow real captures closely, their file sizes less so: pass a
+//! capture, which is repeated to fill each desktop, for representative sizes. Encoding the
+//! largest desktop at every level takes around half a minute.
+
+use std::{env, error::Error, time::Instant};
+
+use image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
+use libwayshot::write::{self, Capture, EncodeOptions, SpeedVsSize};
+
+/// Desktops encoded, from one 1080p output to three 4K outputs side by side.
+const DESKTOPS: [(&str, u32, u32); 5] = [
+    ("1080p", 1920, 1080),
+    ("1440p", 2560, 1440),
+    ("4K", 3840, 2160),
+    ("2x4K", 7680, 2160),
+    ("3x4K", 11520, 2160),
+];
+
+const LEVELS: [SpeedVsSize; 3] = [SpeedVsSize::Speed, SpeedVsSize::Balanced, SpeedVsSize::Size];
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let capture = match env::args_os().nth(1) {
+        Some(path) => Some(image::open(path)?.into_rgba8()),
+        None => None,
+    };
+    println!(
+        "{:<8} {:<10} {:>10} {:>12}",
+        "desktop", "level", "time", "size"
+    );
+    for (name, width, height) in DESKTOPS {
+        let image = match &capture {
+            Some(capture) => tiled(capture, width, height),
+            None => desktop(width, height),
+        };
+        let capture = Capture::new(&image);
+        for level in LEVELS.into_iter().chain([SpeedVsSize::Auto]) {
+            let options = EncodeOptions {
+                format: Some(ImageFormat::Png),
+                speed_vs_size: level,
+            };
+            let started = Instant::now();
+            let encoded = write::encode(&capture, options)?;
+            println!(
+                "{name:<8} {:<10} {:>8} ms {:>9} KiB",
+                format!("{level:?}"),
+                started.elapsed().as_millis(),
+                encoded.len() / 1024
+            );
+        }
+    }
+    Ok(())
+}
+
+/// A desktop of 480x270 tiles: every fourth tile holds a noisy picture, the others a window with
+/// a title bar and lines of text-like runs on a flat background.
+fn desktop(width: u32, height: u32) -> DynamicImage {
+    let mut noise = 0x2545_f491_u32;
+    let image = RgbaImage::from_fn(width, height, |x, y| {
+        let (tile_x, tile_y) = (x / 480, y / 270);
+        let (local_x, local_y) = (x % 480, y % 270);
+        if (tile_x + tile_y) % 4 == 0 {
+            // xorshift, so the example needs no random number crate.
+            noise ^= noise << 13;
+            noise ^= noise >> 17;
+            noise ^= noise << 5;
+            let grain = (noise & 0x7) as u8;
+            return Rgba([
+                (local_x / 2) as u8 ^ grain,
+                local_y as u8 ^ grain,
+                (tile_x * 40) as u8 ^ grain,
+                255,
+            ]);
+        }
+        if local_y < 24 {
+            return Rgba([48, 48, 56, 255]);
+        }
+        let text_line = local_y % 18 < 12 && local_x > 16 && local_x < 460;
+        let glyph = (local_x * 7 + local_y * 3 + tile_x * 11) % 9 < 4;
+        if text_line && glyph {
+            Rgba([30, 30, 30, 255])
+        } else {
+            Rgba([236, 236, 240, 255])
+        }
+    });
+    DynamicImage::ImageRgba8(image)
+}
+
+/// `capture` repeated to fill a `width`x`height` desktop.
+fn tiled(capture: &RgbaImage, width: u32, height: u32) -> DynamicImage {
+    let image = RgbaImage::from_fn(width, height, |x, y| {
+        *capture.get_pixel(x % capture.width(), y % capture.height())
+    });
+    DynamicImage::ImageRgba8(image)
+}
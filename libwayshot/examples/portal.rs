@@ -0,0 +1,93 @@
+//! A minimal xdg-desktop-portal Screenshot backend: every request captures all outputs and
+//! answers with the URI of a PNG in the temporary directory.
+//!
+//! See `wayshot-portal` for a complete backend with interactive selection and color picking.
+//!
+//! ```sh
+//! cargo run --example portal
+//! busctl --user call org.freedesktop.impl.portal.desktop.wayshot_example \
+//!     /org/freedesktop/portal/desktop org.freedesktop.impl.portal.Screenshot Screenshot \
+//!     ossa{sv} /request/1 example "" 0
+//! ```
+
+use std::{
+    collections::HashMap,
+    env,
+    error::Error,
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use libwayshot::WayshotConnection;
+use zbus::{
+    blocking::connection,
+    interface,
+    zvariant::{ObjectPath, OwnedValue, Value},
+};
+
+const BUS_NAME: &str = "org.freedesktop.impl.portal.desktop.wayshot_example";
+const OBJECT_PATH: &str = "/org/freedesktop/portal/desktop";
+
+type Results = HashMap<String, OwnedValue>;
+
+struct Screenshot {
+    wayshot_conn: WayshotConnection,
+}
+
+impl Screenshot {
+    fn take_screenshot(&self) -> Result<Results, Box<dyn Error>> {
+        let image = self.wayshot_conn.screenshot_all(false)?;
+        let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        let path = env::temp_dir().join(format!("wayshot-example-{time}.png"));
+        image.save(&path)?;
+
+        let uri = format!("file://{}", path.display());
+        Ok(Results::from([(
+            "uri".to_string(),
+            OwnedValue::try_from(Value::from(uri))?,
+        )]))
+    }
+}
+
+#[interface(name = "org.freedesktop.impl.portal.Screenshot")]
+impl Screenshot {
+    /// Answers with response 0 and the URI on success, 2 without results otherwise.
+    #[zbus(out_args("response", "results"))]
+    fn screenshot(
+        &self,
+        _handle: ObjectPath<'_>,
+        app_id: &str,
+        _parent_window: &str,
+        _options: HashMap<String, OwnedValue>,
+    ) -> (u32, Results) {
+        println!("Screenshot requested by {app_id:?}");
+        match self.take_screenshot() {
+            Ok(results) => (0, results),
+            Err(e) => {
+                eprintln!("Screenshot failed: {e}");
+                (2, Results::new())
+            }
+        }
+    }
+
+    #[zbus(property)]
+    fn version(&self) -> u32 {
+        2
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let screenshot = Screenshot {
+        wayshot_conn: WayshotConnection::new()?,
+    };
+    let _connection = connection::Builder::session()?
+        .name(BUS_NAME)?
+        .serve_at(OBJECT_PATH, screenshot)?
+        .build()?;
+    println!("Serving {BUS_NAME}");
+
+    // Requests are handled on the connection's executor thread.
+    loop {
+        thread::park();
+    }
+}
@@ -0,0 +1,54 @@
+//! Stream the first output as raw video on stdout, eg: to publish it as a PipeWire node through
+//! GStreamer:
+//!
+//! ```sh
+//! cargo run --release --example raw_stream | gst-launch-1.0 fdsrc \
+//!     ! video/x-raw,format=BGRx,width=1920,height=1080,framerate=30/1 \
+//!     ! rawvideoparse use-sink-caps=true ! videoconvert ! pipewiresink
+//! ```
+//!
+//! The caps of the stream are printed on stderr, the same string can be set on an `appsrc`.
+
+use std::{
+    error::Error,
+    io::{self, Write},
+    thread,
+    time::{Duration, Instant},
+};
+
+use libwayshot::WayshotConnection;
+
+const FRAMERATE: u32 = 30;
+const FRAME_INTERVAL: Duration = Duration::from_millis(1000 / FRAMERATE as u64);
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let wayshot_connection = WayshotConnection::new()?;
+    let output = &wayshot_connection.get_all_outputs()[0];
+    let mut context = wayshot_connection.start_streaming_capture(output, true)?;
+    let mut stdout = io::stdout().lock();
+
+    let mut announced = None;
+    loop {
+        let started = Instant::now();
+        let (frame_format, pixels) = context.screenshot_fast()?;
+        if announced != Some(frame_format) {
+            eprintln!("{}", frame_format.gst_caps(FRAMERATE)?);
+            announced = Some(frame_format);
+        }
+
+        let row_len = frame_format.width as usize * frame_format.bytes_per_pixel()?;
+        // The rows GStreamer expects from the caps are padded to 4 bytes.
+        let padding = [0; 3];
+        let padding = &padding[..row_len.next_multiple_of(4) - row_len];
+        for row in pixels
+            .chunks(frame_format.stride as usize)
+            .take(frame_format.height as usize)
+        {
+            stdout.write_all(&row[..row_len])?;
+            stdout.write_all(padding)?;
+        }
+        stdout.flush()?;
+
+        thread::sleep(FRAME_INTERVAL.saturating_sub(started.elapsed()));
+    }
+}
@@ -0,0 +1,345 @@
+//! Async counterparts of the capture functions, enabled with the `async` feature.
+//!
+//! They share the capture pipeline with the blocking functions, but wait for the compositor through
+//! the tokio reactor instead of `blocking_dispatch`, so they need to run inside a tokio runtime.
+
+#[cfg(feature = "image")]
+use std::io;
+use std::os::fd::AsFd;
+
+#[cfg(feature = "image")]
+use image::DynamicImage;
+use libwayshot_protocols::{dispatch_until_async, screencopy::CaptureFrameState};
+#[cfg(feature = "image")]
+use tokio::task;
+use tokio::time;
+#[cfg(feature = "image")]
+use wayland_client::Proxy;
+use wayland_client::{protocol::wl_output::WlOutput, EventQueue};
+use wayland_protocols_wlr::screencopy::v1::client::zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1;
+#[cfg(feature = "image")]
+use wayland_protocols_wlr::screencopy::v1::client::zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1;
+
+#[cfg(feature = "image")]
+use crate::{
+    all_buffers_done, all_copied, check_frame_copies, compose_frame_copies, composed_size,
+    conversion_panicked, convert_frame, outputs_region, screencopy::FrameCopy, CaptureSource,
+    FramePacing, FrameStream,
+};
+use crate::{
+    check_frame_copy, finish_frame_copy,
+    output::OutputInfo,
+    pool::ShmBuffer,
+    reexport::Transform,
+    screencopy::{FrameFormat, FrameMetadata, RawFrame},
+    select_frame_format, CaptureRegion, Error, Result, WayshotConnection,
+};
+
+impl WayshotConnection {
+    /// Async version of [`WayshotConnection::dispatch_frame`], giving up with [`Error::Timeout`]
+    /// after the capture timeout as well.
+    pub(crate) async fn dispatch_frame_async<S>(
+        &self,
+        event_queue: &mut EventQueue<S>,
+        state: &mut S,
+        done: impl Fn(&S) -> bool,
+    ) -> Result<()> {
+        let dispatched = dispatch_until_async(&self.conn, event_queue, state, done);
+        match self.capture_timeout {
+            Some(capture_timeout) => time::timeout(capture_timeout, dispatched)
+                .await
+                .map_err(|_| Error::Timeout)??,
+            None => dispatched.await?,
+        }
+        Ok(())
+    }
+
+    /// Async version of [`WayshotConnection::capture_output_frame_shm_fd`].
+    pub async fn capture_output_frame_shm_fd_async<T: AsFd>(
+        &self,
+        cursor_overlay: i32,
+        output: &WlOutput,
+        fd: T,
+        capture_region: Option<CaptureRegion>,
+    ) -> Result<FrameFormat> {
+        if let Some(capture_region) = capture_region {
+            capture_region.validate()?;
+        }
+        let fd = fd.as_fd();
+        let (frame_format, _) = self
+            .retry_policy
+            .run_async(|| async move {
+                let (mut state, mut event_queue, frame) =
+                    self.request_output_frame(cursor_overlay, output, capture_region)?;
+                self.dispatch_frame_async(&mut event_queue, &mut state, |state| {
+                    state.phase.is_buffer_done()
+                })
+                .await?;
+                let frame_format = select_frame_format(&state)?;
+                self.copy_output_frame_async(state, event_queue, frame, frame_format, fd)
+                    .await
+            })
+            .await?;
+        Ok(frame_format)
+    }
+
+    async fn copy_output_frame_async<T: AsFd>(
+        &self,
+        mut state: CaptureFrameState,
+        mut event_queue: EventQueue<CaptureFrameState>,
+        frame: ZwlrScreencopyFrameV1,
+        frame_format: FrameFormat,
+        fd: T,
+    ) -> Result<FrameFormat> {
+        let (buffer, shm_pool) =
+            self.attach_frame_buffer(&event_queue, &frame, &frame_format, fd)?;
+        self.dispatch_frame_async(&mut event_queue, &mut state, |state| {
+            state.phase.copied().is_some()
+        })
+        .await?;
+        finish_frame_copy(&state, frame, buffer, shm_pool, frame_format)
+    }
+
+    /// Async version of [`WayshotConnection::capture_raw_frame`].
+    pub async fn capture_raw_frame_async(
+        &self,
+        output_info: &OutputInfo,
+        cursor_overlay: bool,
+        capture_region: Option<CaptureRegion>,
+    ) -> Result<RawFrame> {
+        output_info.check_ready()?;
+        if let Some(capture_region) = capture_region {
+            capture_region.validate()?;
+        }
+        self.capture_output_raw_frame_async(
+            cursor_overlay,
+            &output_info.wl_output,
+            output_info.transform,
+            capture_region,
+        )
+        .await
+    }
+
+    #[cfg(feature = "image")]
+    async fn capture_output_frame_async(
+        &self,
+        cursor_overlay: bool,
+        output: &WlOutput,
+        transform: Transform,
+        capture_region: Option<CaptureRegion>,
+    ) -> Result<FrameCopy> {
+        convert_frame(
+            self.capture_output_raw_frame_async(cursor_overlay, output, transform, capture_region)
+                .await?,
+        )
+    }
+
+    async fn capture_output_raw_frame_async(
+        &self,
+        cursor_overlay: bool,
+        output: &WlOutput,
+        transform: Transform,
+        capture_region: Option<CaptureRegion>,
+    ) -> Result<RawFrame> {
+        let ((frame_format, mut metadata, shm_buffer), attempts) = self
+            .retry_policy
+            .run_async(|| {
+                self.capture_output_frame_pooled_async(cursor_overlay, output, capture_region)
+            })
+            .await?;
+        metadata.attempts = attempts;
+        self.raw_frame_from_buffer(frame_format, metadata, shm_buffer, transform)
+    }
+
+    async fn capture_output_frame_pooled_async(
+        &self,
+        cursor_overlay: bool,
+        output: &WlOutput,
+        capture_region: Option<CaptureRegion>,
+    ) -> Result<(FrameFormat, FrameMetadata, ShmBuffer)> {
+        let (mut state, mut event_queue, frame) =
+            self.request_output_frame(cursor_overlay as i32, output, capture_region)?;
+        self.dispatch_frame_async(&mut event_queue, &mut state, |state| {
+            state.phase.is_buffer_done()
+        })
+        .await?;
+        let frame_format = select_frame_format(&state)?;
+
+        let shm_buffer = self.pooled_buffer(&event_queue.handle(), output, frame_format)?;
+        self.copy_frame(&frame, &shm_buffer.buffer);
+        self.dispatch_frame_async(&mut event_queue, &mut state, |state| {
+            state.phase.copied().is_some()
+        })
+        .await?;
+        frame.destroy();
+        let metadata = check_frame_copy(&state)?;
+        Ok((frame_format, metadata, shm_buffer))
+    }
+}
+
+#[cfg(feature = "image")]
+impl WayshotConnection {
+    /// Async version of [`WayshotConnection::screenshot`].
+    ///
+    /// The frames are converted and composed on tokio's blocking threads, off the runtime's.
+    pub async fn screenshot_async(
+        &self,
+        capture_region: CaptureRegion,
+        cursor_overlay: bool,
+    ) -> Result<DynamicImage> {
+        self.check_capture_region(capture_region)?;
+        if !self.advertises(ZwlrScreencopyManagerV1::interface().name) {
+            tracing::debug!(
+                "No zwlr_screencopy_manager_v1, capturing through ext-image-copy-capture"
+            );
+            return self
+                .screenshot_ext_async(capture_region, cursor_overlay)
+                .await;
+        }
+
+        let intersecting_outputs = self.intersecting_outputs(capture_region);
+        let (width, height) = composed_size(capture_region, &intersecting_outputs);
+        let requests: Vec<_> = intersecting_outputs
+            .iter()
+            .map(|intersecting_output| {
+                (
+                    intersecting_output.output.clone(),
+                    Some(intersecting_output.region),
+                )
+            })
+            .collect();
+        let (shm_buffers, attempts) = self
+            .retry_policy
+            .run_async(|| self.capture_output_frames_pooled_async(cursor_overlay, &requests))
+            .await?;
+        let raw_frames = shm_buffers
+            .into_iter()
+            .zip(&intersecting_outputs)
+            .map(
+                |((frame_format, mut metadata, shm_buffer), intersecting_output)| {
+                    metadata.attempts = attempts;
+                    self.raw_frame_from_buffer(
+                        frame_format,
+                        metadata,
+                        shm_buffer,
+                        intersecting_output.transform,
+                    )
+                },
+            )
+            .collect::<Result<Vec<_>>>()?;
+        task::spawn_blocking(move || {
+            let frame_copies = raw_frames
+                .into_iter()
+                .map(convert_frame)
+                .collect::<Result<_>>()?;
+            compose_frame_copies(frame_copies, width, height)
+        })
+        .await
+        .map_err(|e| match e.try_into_panic() {
+            Ok(payload) => conversion_panicked(payload),
+            // Cancelled, the runtime is shutting down.
+            Err(e) => io::Error::from(e).into(),
+        })?
+    }
+
+    /// Async version of [`WayshotConnection::screenshot_ext`].
+    async fn screenshot_ext_async(
+        &self,
+        capture_region: CaptureRegion,
+        cursor_overlay: bool,
+    ) -> Result<DynamicImage> {
+        let (image, _) = self
+            .retry_policy
+            .run_async(|| async move {
+                let mut context = self.start_ext_region_capture(capture_region, cursor_overlay)?;
+                context.try_capture_frame_with_damage_async().await?;
+                context.image()
+            })
+            .await?;
+        Ok(image)
+    }
+
+    /// Async version of [`WayshotConnection::capture_output_frames_pooled`].
+    async fn capture_output_frames_pooled_async(
+        &self,
+        cursor_overlay: bool,
+        outputs: &[(WlOutput, Option<CaptureRegion>)],
+    ) -> Result<Vec<(FrameFormat, FrameMetadata, ShmBuffer)>> {
+        let (mut state, mut event_queue, frames) =
+            self.request_output_frames(cursor_overlay, outputs)?;
+        let copied = async {
+            self.dispatch_frame_async(&mut event_queue, &mut state, all_buffers_done)
+                .await?;
+            let shm_buffers = self.copy_output_frames(&event_queue, &state, &frames, outputs)?;
+            self.dispatch_frame_async(&mut event_queue, &mut state, all_copied)
+                .await?;
+            check_frame_copies(&state, shm_buffers)
+        }
+        .await;
+        for frame in frames {
+            frame.destroy();
+        }
+        copied
+    }
+
+    /// Async version of [`WayshotConnection::screenshot_single_output`].
+    pub async fn screenshot_single_output_async(
+        &self,
+        output_info: &OutputInfo,
+        cursor_overlay: bool,
+    ) -> Result<DynamicImage> {
+        output_info.check_ready()?;
+        let frame_copy = self
+            .capture_output_frame_async(
+                cursor_overlay,
+                &output_info.wl_output,
+                output_info.transform,
+                None,
+            )
+            .await?;
+        frame_copy.try_into()
+    }
+
+    /// Async version of [`WayshotConnection::screenshot_outputs`].
+    pub async fn screenshot_outputs_async(
+        &self,
+        outputs: &[OutputInfo],
+        cursor_overlay: bool,
+    ) -> Result<DynamicImage> {
+        self.screenshot_async(outputs_region(outputs)?, cursor_overlay)
+            .await
+    }
+
+    /// Async version of [`WayshotConnection::screenshot_all`].
+    pub async fn screenshot_all_async(&self, cursor_overlay: bool) -> Result<DynamicImage> {
+        self.screenshot_outputs_async(self.get_all_outputs(), cursor_overlay)
+            .await
+    }
+
+    /// Async version of [`WayshotConnection::capture`].
+    pub async fn capture_async(
+        &self,
+        source: &CaptureSource,
+        cursor_overlay: bool,
+    ) -> Result<DynamicImage> {
+        match source {
+            CaptureSource::All => self.screenshot_all_async(cursor_overlay).await,
+            CaptureSource::Output(name_or_id) => {
+                let output = self.find_output(name_or_id).ok_or(Error::NoOutputs)?;
+                self.screenshot_single_output_async(output, cursor_overlay)
+                    .await
+            }
+            CaptureSource::Region(region) => self.screenshot_async(*region, cursor_overlay).await,
+        }
+    }
+
+    /// Async version of [`WayshotConnection::frames`], yielding frames as a `Stream`.
+    pub fn frames_async(
+        &self,
+        source: CaptureSource,
+        cursor_overlay: bool,
+        pacing: FramePacing,
+    ) -> FrameStream<'_> {
+        FrameStream::new(self, source, cursor_overlay, pacing)
+    }
+}
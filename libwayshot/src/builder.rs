@@ -0,0 +1,163 @@
+use std::{
+    env,
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    sync::{atomic::AtomicBool, Arc},
+    time::Duration,
+};
+
+use wayland_client::{globals::registry_queue_init, ConnectError, Connection, Proxy};
+use wayland_protocols_wlr::screencopy::v1::client::zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1;
+
+use libwayshot_protocols::WayshotState;
+
+use crate::{
+    pool::{BufferPool, DEFAULT_BUFFER_POOL_CAPACITY},
+    Error, FrameTiming, Result, RetryPolicy, WayshotConnection, MIN_SCREENCOPY_VERSION,
+};
+
+/// Where the builder gets its Wayland connection from.
+#[derive(Debug, Default)]
+enum ConnectionSource {
+    /// `WAYLAND_DISPLAY` / `WAYLAND_SOCKET`.
+    #[default]
+    Env,
+    Display(String),
+    Existing(Connection),
+}
+
+/// Options for creating a [`WayshotConnection`].
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use libwayshot::{RetryPolicy, WayshotConnection};
+///
+/// let wayshot_connection = WayshotConnection::builder()
+///     .display("wayland-1")
+///     .retry_policy(RetryPolicy {
+///         attempts: 5,
+///         backoff: Duration::from_millis(50),
+///     })
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Default)]
+pub struct WayshotConnectionBuilder {
+    source: ConnectionSource,
+    retry_policy: RetryPolicy,
+    capture_timeout: Option<Duration>,
+    frame_timing: FrameTiming,
+    require_screencopy: bool,
+    buffer_pool_capacity: Option<usize>,
+    interrupt: Option<Arc<AtomicBool>>,
+}
+
+impl WayshotConnectionBuilder {
+    /// Connect to the compositor listening on the given Wayland socket instead of the one named by
+    /// `WAYLAND_DISPLAY`. Relative names such as `wayland-1` are looked up in
+    /// `XDG_RUNTIME_DIR`, absolute paths are used as is.
+    pub fn display(mut self, display: impl Into<String>) -> Self {
+        self.source = ConnectionSource::Display(display.into());
+        self
+    }
+
+    /// Use a [`Connection`] the application already has open.
+    pub fn connection(mut self, conn: Connection) -> Self {
+        self.source = ConnectionSource::Existing(conn);
+        self
+    }
+
+    /// How failed frame copies are retried, see [`RetryPolicy`].
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Give up on captures the compositor does not answer within `capture_timeout`, see
+    /// [`WayshotConnection::set_capture_timeout`].
+    pub fn capture_timeout(mut self, capture_timeout: Duration) -> Self {
+        self.capture_timeout = Some(capture_timeout);
+        self
+    }
+
+    /// Which frame captures copy, see [`WayshotConnection::set_frame_timing`].
+    pub fn frame_timing(mut self, frame_timing: FrameTiming) -> Self {
+        self.frame_timing = frame_timing;
+        self
+    }
+
+    /// Fail in [`Self::build`] already when the compositor does not implement
+    /// zwlr_screencopy_v1, instead of on the first capture.
+    pub fn require_screencopy(mut self, require_screencopy: bool) -> Self {
+        self.require_screencopy = require_screencopy;
+        self
+    }
+
+    /// Keep up to `capacity` shm buffers around for the next captures, see [`BufferPool`]. `0`
+    /// disables pooling.
+    pub fn buffer_pool_capacity(mut self, capacity: usize) -> Self {
+        self.buffer_pool_capacity = Some(capacity);
+        self
+    }
+
+    /// Stop waiting on the user once `interrupt` is set, see
+    /// [`WayshotConnection::set_interrupt`].
+    pub fn interrupt(mut self, interrupt: Arc<AtomicBool>) -> Self {
+        self.interrupt = Some(interrupt);
+        self
+    }
+
+    pub fn build(self) -> Result<WayshotConnection> {
+        let conn = match self.source {
+            ConnectionSource::Env => Connection::connect_to_env()?,
+            ConnectionSource::Display(display) => connect_to_display(&display)?,
+            ConnectionSource::Existing(conn) => conn,
+        };
+        let (globals, _) = registry_queue_init::<WayshotState>(&conn)?;
+
+        if self.require_screencopy
+            && !globals.contents().with_list(|globals| {
+                globals
+                    .iter()
+                    .any(|global| global.interface == ZwlrScreencopyManagerV1::interface().name)
+            })
+        {
+            return Err(Error::Protocol {
+                protocol: ZwlrScreencopyManagerV1::interface().name,
+                version: MIN_SCREENCOPY_VERSION,
+            });
+        }
+
+        let mut wayshot_conn = WayshotConnection {
+            conn,
+            globals,
+            output_infos: Vec::new(),
+            retry_policy: self.retry_policy,
+            capture_timeout: self.capture_timeout,
+            frame_timing: self.frame_timing,
+            buffer_pool: Arc::new(BufferPool::new(
+                self.buffer_pool_capacity
+                    .unwrap_or(DEFAULT_BUFFER_POOL_CAPACITY),
+            )),
+            interrupt: self.interrupt,
+        };
+        wayshot_conn.refresh_outputs()?;
+        Ok(wayshot_conn)
+    }
+}
+
+fn connect_to_display(display: &str) -> Result<Connection> {
+    let socket_path = if Path::new(display).is_absolute() {
+        PathBuf::from(display)
+    } else {
+        let Some(runtime_dir) = env::var_os("XDG_RUNTIME_DIR") else {
+            return Err(ConnectError::NoCompositor.into());
+        };
+        Path::new(&runtime_dir).join(display)
+    };
+    tracing::debug!("Connecting to Wayland socket {}", socket_path.display());
+
+    let stream = UnixStream::connect(socket_path)?;
+    Ok(Connection::from_socket(stream)?)
+}
@@ -0,0 +1,346 @@
+use std::{fs::File, io, mem};
+
+use libwayshot_protocols::screencopy::{CaptureFrameState, FrameState};
+use memmap2::MmapOptions;
+use wayland_client::{
+    protocol::wl_shm::{self, WlShm},
+    Connection, Dispatch, Proxy, QueueHandle, WEnum,
+};
+use wayland_protocols::wp::linux_dmabuf::zv1::client::{
+    zwp_linux_dmabuf_feedback_v1::{self, ZwpLinuxDmabufFeedbackV1},
+    zwp_linux_dmabuf_v1::{self, ZwpLinuxDmabufV1},
+};
+
+use crate::{
+    copy_failure, image_copy::MAX_IMAGE_COPY_CAPTURE_VERSION, output::OutputInfo,
+    screencopy_versions, FrameFormat, Result, WayshotConnection,
+};
+
+/// `DRM_FORMAT_MOD_INVALID`, reported for formats advertised without a modifier.
+pub const DRM_FORMAT_MOD_INVALID: u64 = 0x00ff_ffff_ffff_ffff;
+
+/// A DRM fourcc format along with one modifier the compositor accepts it with.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct DrmFormat {
+    pub fourcc: u32,
+    pub modifier: u64,
+}
+
+/// Devices and formats the compositor prefers DMA-BUF buffers in, sent through
+/// zwp_linux_dmabuf_feedback_v1 from zwp_linux_dmabuf_v1 version 4 on.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DmabufFeedback {
+    /// `dev_t` of the device buffers have to be importable by, usually the GPU the compositor
+    /// renders with.
+    pub main_device: u64,
+    /// Most preferred first. On hybrid graphics or with direct scan-out, the first tranches may
+    /// target another device than the main one.
+    pub tranches: Vec<DmabufTranche>,
+}
+
+/// A set of formats the compositor accepts equally well for one device.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DmabufTranche {
+    /// `dev_t` of the device buffers of this tranche are allocated on.
+    pub target_device: u64,
+    pub formats: Vec<DrmFormat>,
+    /// Buffers of this tranche are candidates for direct scan-out.
+    pub scanout: bool,
+}
+
+/// What the compositor offers, so callers can adapt without attempting a capture first.
+///
+/// Protocol fields hold the version the compositor advertises, `None` when it does not implement
+/// the protocol at all.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    /// zwlr_screencopy_manager_v1, the protocol libwayshot captures with. Version 3 is required.
+    pub wlr_screencopy: Option<u32>,
+    /// Version of zwlr_screencopy_manager_v1 captures use, the advertised one capped to the
+    /// newest libwayshot implements. `None` when the compositor cannot be captured from.
+    pub wlr_screencopy_bound: Option<u32>,
+    /// ext_image_copy_capture_manager_v1.
+    pub ext_image_copy_capture: Option<u32>,
+    /// Version of ext_image_copy_capture_manager_v1 captures through it use, the advertised one
+    /// capped to the newest libwayshot implements. See
+    /// [`ImageCopyCaptureContext::copy_capture_version`](crate::ImageCopyCaptureContext::copy_capture_version).
+    pub ext_image_copy_capture_bound: Option<u32>,
+    /// zwp_linux_dmabuf_v1.
+    pub linux_dmabuf: Option<u32>,
+    /// ext_foreign_toplevel_list_v1.
+    pub foreign_toplevel_list: Option<u32>,
+    /// wp_viewporter.
+    pub viewporter: Option<u32>,
+    /// zwlr_layer_shell_v1.
+    pub layer_shell: Option<u32>,
+    /// Formats of wl_shm buffers the compositor accepts.
+    pub shm_formats: Vec<wl_shm::Format>,
+    /// Formats and modifiers of DMA-BUF buffers the compositor accepts, empty without
+    /// zwp_linux_dmabuf_v1. Ordered by preference when the compositor sends feedback.
+    pub drm_formats: Vec<DrmFormat>,
+    /// Default DMA-BUF feedback, `None` before zwp_linux_dmabuf_v1 version 4.
+    pub dmabuf_feedback: Option<DmabufFeedback>,
+}
+
+#[derive(Debug, Default)]
+struct CapabilitiesState {
+    shm_formats: Vec<wl_shm::Format>,
+    drm_formats: Vec<DrmFormat>,
+    /// Formats tranches refer to by index.
+    format_table: Vec<DrmFormat>,
+    /// Feedback being received, moved to `feedback` once done.
+    pending_feedback: DmabufFeedback,
+    pending_tranche: DmabufTranche,
+    feedback: Option<DmabufFeedback>,
+}
+
+impl Dispatch<WlShm, ()> for CapabilitiesState {
+    fn event(
+        state: &mut Self,
+        _: &WlShm,
+        event: wl_shm::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wl_shm::Event::Format {
+            format: WEnum::Value(format),
+        } = event
+        {
+            state.shm_formats.push(format);
+        }
+    }
+}
+
+impl Dispatch<ZwpLinuxDmabufV1, ()> for CapabilitiesState {
+    fn event(
+        state: &mut Self,
+        _: &ZwpLinuxDmabufV1,
+        event: zwp_linux_dmabuf_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwp_linux_dmabuf_v1::Event::Format { format } => state.drm_formats.push(DrmFormat {
+                fourcc: format,
+                modifier: DRM_FORMAT_MOD_INVALID,
+            }),
+            zwp_linux_dmabuf_v1::Event::Modifier {
+                format,
+                modifier_hi,
+                modifier_lo,
+            } => state.drm_formats.push(DrmFormat {
+                fourcc: format,
+                modifier: (u64::from(modifier_hi) << 32) | u64::from(modifier_lo),
+            }),
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ZwpLinuxDmabufFeedbackV1, ()> for CapabilitiesState {
+    fn event(
+        state: &mut Self,
+        _: &ZwpLinuxDmabufFeedbackV1,
+        event: zwp_linux_dmabuf_feedback_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwp_linux_dmabuf_feedback_v1::Event::FormatTable { fd, size } => {
+                state.format_table = read_format_table(File::from(fd), size as usize)
+                    .unwrap_or_else(|e| {
+                        tracing::warn!("Failed to read the DMA-BUF format table: {e}");
+                        Vec::new()
+                    });
+            }
+            zwp_linux_dmabuf_feedback_v1::Event::MainDevice { device } => {
+                state.pending_feedback.main_device = dev_t(&device);
+            }
+            zwp_linux_dmabuf_feedback_v1::Event::TrancheTargetDevice { device } => {
+                state.pending_tranche.target_device = dev_t(&device);
+            }
+            zwp_linux_dmabuf_feedback_v1::Event::TrancheFormats { indices } => {
+                let table = &state.format_table;
+                state.pending_tranche.formats.extend(
+                    indices
+                        .chunks_exact(2)
+                        .map(|index| u16::from_ne_bytes([index[0], index[1]]))
+                        .filter_map(|index| table.get(usize::from(index)).copied()),
+                );
+            }
+            zwp_linux_dmabuf_feedback_v1::Event::TrancheFlags { flags } => {
+                state.pending_tranche.scanout = matches!(
+                    flags,
+                    WEnum::Value(flags)
+                        if flags.contains(zwp_linux_dmabuf_feedback_v1::TrancheFlags::Scanout)
+                );
+            }
+            zwp_linux_dmabuf_feedback_v1::Event::TrancheDone => {
+                let tranche = mem::take(&mut state.pending_tranche);
+                state.pending_feedback.tranches.push(tranche);
+            }
+            zwp_linux_dmabuf_feedback_v1::Event::Done => {
+                state.feedback = Some(mem::take(&mut state.pending_feedback));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Read the `size` bytes of format table in `file`: 16 byte entries of a 32-bit fourcc, 4 bytes
+/// of padding and a 64-bit modifier, in native endianness.
+fn read_format_table(file: File, size: usize) -> io::Result<Vec<DrmFormat>> {
+    if size == 0 {
+        return Ok(Vec::new());
+    }
+    // SAFETY: the protocol forbids the compositor to change the table once it was sent, and the
+    // mapping is private and read-only, as the protocol requires. It is dropped before returning.
+    let table = unsafe { MmapOptions::new().len(size).map_copy_read_only(&file)? };
+    Ok(table
+        .chunks_exact(16)
+        .map(|entry| DrmFormat {
+            fourcc: u32::from_ne_bytes([entry[0], entry[1], entry[2], entry[3]]),
+            modifier: u64::from_ne_bytes([
+                entry[8], entry[9], entry[10], entry[11], entry[12], entry[13], entry[14],
+                entry[15],
+            ]),
+        })
+        .collect())
+}
+
+/// A `dev_t` sent as an array of native endian bytes.
+fn dev_t(device: &[u8]) -> u64 {
+    <[u8; 8]>::try_from(device).map_or(0, u64::from_ne_bytes)
+}
+
+impl WayshotConnection {
+    /// Query which protocols the compositor implements and which buffer formats it accepts.
+    ///
+    /// This does one roundtrip to the compositor to collect the formats.
+    pub fn capabilities(&self) -> Result<Capabilities> {
+        let advertised = |interface: &str| {
+            self.globals.contents().with_list(|globals| {
+                globals
+                    .iter()
+                    .filter(|global| global.interface == interface)
+                    .map(|global| global.version)
+                    .max()
+            })
+        };
+        let linux_dmabuf = advertised("zwp_linux_dmabuf_v1");
+
+        let mut state = CapabilitiesState::default();
+        let mut event_queue = self.conn.new_event_queue::<CapabilitiesState>();
+        let qh = event_queue.handle();
+        let _shm = self.globals.bind::<WlShm, _, _>(&qh, 1..=1, ())?;
+        // From version 4 on formats are only sent through feedback objects, before that they are
+        // listed as events of the global.
+        let dmabuf = match linux_dmabuf {
+            Some(_) => Some(
+                self.globals
+                    .bind::<ZwpLinuxDmabufV1, _, _>(&qh, 1..=5, ())?,
+            ),
+            None => None,
+        };
+        let feedback = dmabuf
+            .as_ref()
+            .filter(|dmabuf| dmabuf.version() >= 4)
+            .map(|dmabuf| dmabuf.get_default_feedback(&qh, ()));
+        event_queue.roundtrip(&mut state)?;
+        if let Some(feedback) = feedback {
+            feedback.destroy();
+        }
+        if let Some(dmabuf) = dmabuf {
+            dmabuf.destroy();
+        }
+        if let Some(feedback) = &state.feedback {
+            for format in feedback
+                .tranches
+                .iter()
+                .flat_map(|tranche| &tranche.formats)
+            {
+                if !state.drm_formats.contains(format) {
+                    state.drm_formats.push(*format);
+                }
+            }
+        }
+
+        let wlr_screencopy = advertised("zwlr_screencopy_manager_v1");
+        let versions = screencopy_versions();
+        let ext_image_copy_capture = advertised("ext_image_copy_capture_manager_v1");
+        Ok(Capabilities {
+            wlr_screencopy,
+            wlr_screencopy_bound: wlr_screencopy
+                .filter(|version| version >= versions.start())
+                .map(|version| version.min(*versions.end())),
+            ext_image_copy_capture,
+            ext_image_copy_capture_bound: ext_image_copy_capture
+                .map(|version| version.min(MAX_IMAGE_COPY_CAPTURE_VERSION)),
+            linux_dmabuf,
+            foreign_toplevel_list: advertised("ext_foreign_toplevel_list_v1"),
+            viewporter: advertised("wp_viewporter"),
+            layer_shell: advertised("zwlr_layer_shell_v1"),
+            shm_formats: state.shm_formats,
+            drm_formats: state.drm_formats,
+            dmabuf_feedback: state.feedback,
+        })
+    }
+
+    /// wl_shm buffer formats the compositor offers to copy frames of `output` into, along with
+    /// their size and stride. The list may include formats the screenshot functions cannot
+    /// convert.
+    ///
+    /// The frame is negotiated but never copied, no pixels are read.
+    pub fn supported_shm_formats(&self, output: &OutputInfo) -> Result<Vec<FrameFormat>> {
+        Ok(self.negotiate_frame(output)?.formats)
+    }
+
+    /// DMA-BUF formats the compositor offers to copy frames of `output` into, with every modifier
+    /// zwp_linux_dmabuf_v1 accepts them with, in the compositor's order of preference when it
+    /// sends feedback. Formats come with [`DRM_FORMAT_MOD_INVALID`] when the compositor does not
+    /// list modifiers, and the list is empty before zwlr_screencopy_v1 version 3.
+    ///
+    /// The frame is negotiated but never copied, no pixels are read.
+    pub fn supported_dmabuf_formats(&self, output: &OutputInfo) -> Result<Vec<DrmFormat>> {
+        let dmabuf_formats = self.negotiate_frame(output)?.dmabuf_formats;
+        let drm_formats = self.capabilities()?.drm_formats;
+        let offered = |fourcc: u32| {
+            dmabuf_formats
+                .iter()
+                .any(|dmabuf_format| dmabuf_format.format == fourcc)
+        };
+        let mut supported: Vec<DrmFormat> = drm_formats
+            .into_iter()
+            .filter(|drm_format| offered(drm_format.fourcc))
+            .collect();
+        for dmabuf_format in &dmabuf_formats {
+            if !supported
+                .iter()
+                .any(|drm_format| drm_format.fourcc == dmabuf_format.format)
+            {
+                supported.push(DrmFormat {
+                    fourcc: dmabuf_format.format,
+                    modifier: DRM_FORMAT_MOD_INVALID,
+                });
+            }
+        }
+        Ok(supported)
+    }
+
+    /// Ask for a frame of `output` and collect the buffers offered for it, then drop it.
+    fn negotiate_frame(&self, output: &OutputInfo) -> Result<CaptureFrameState> {
+        let (mut state, mut event_queue, frame) =
+            self.request_output_frame(0, &output.wl_output, None)?;
+        self.dispatch_frame(&mut event_queue, &mut state, |state| {
+            state.phase.is_buffer_done()
+        })?;
+        frame.destroy();
+        if state.phase.copied() == Some(FrameState::Failed) {
+            return Err(copy_failure(&state));
+        }
+        Ok(state)
+    }
+}
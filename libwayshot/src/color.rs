@@ -0,0 +1,141 @@
+//! Color corrections applied to captured images.
+
+use image::DynamicImage;
+
+/// Temperature night-light tools treat as neutral, in Kelvin.
+pub const NEUTRAL_TEMPERATURE: u32 = 6500;
+
+/// Undo the tint a night-light tool (gammastep, wlsunset, ...) applies through the gamma ramps.
+///
+/// Those tools scale every channel by the whitepoint of a black body at `temperature` Kelvin, so
+/// captures come out orange. Wayland does not let clients read the gamma ramps back, hence the
+/// temperature has to be supplied by the caller, usually the night temperature configured in the
+/// night-light tool.
+pub fn neutralize_color_temperature(image: &mut DynamicImage, temperature: u32) {
+    let tinted = whitepoint(temperature);
+    let neutral = whitepoint(NEUTRAL_TEMPERATURE);
+    // Dividing by the relative whitepoint restores what the compositor rendered before the ramp.
+    let factors: [f32; 3] = [
+        neutral[0] / tinted[0],
+        neutral[1] / tinted[1],
+        neutral[2] / tinted[2],
+    ];
+    if factors
+        .iter()
+        .all(|factor| (factor - 1.0).abs() < f32::EPSILON)
+    {
+        return;
+    }
+
+    let correct = |pixel: &mut [u8]| {
+        for (channel, factor) in pixel.iter_mut().zip(factors) {
+            *channel = (f32::from(*channel) * factor).round().min(255.0) as u8;
+        }
+    };
+    let correct16 = |pixel: &mut [u16]| {
+        for (channel, factor) in pixel.iter_mut().zip(factors) {
+            *channel = (f32::from(*channel) * factor).round().min(65535.0) as u16;
+        }
+    };
+    match image {
+        DynamicImage::ImageRgba8(buffer) => buffer.chunks_exact_mut(4).for_each(correct),
+        DynamicImage::ImageRgba16(buffer) => buffer.chunks_exact_mut(4).for_each(correct16),
+        DynamicImage::ImageRgb8(buffer) => buffer.chunks_exact_mut(3).for_each(correct),
+        _ => {
+            let mut buffer = image.to_rgba8();
+            buffer.chunks_exact_mut(4).for_each(correct);
+            *image = DynamicImage::ImageRgba8(buffer);
+        }
+    }
+}
+
+/// Approximate RGB whitepoint of a black body, each channel in `0.0..=1.0`.
+///
+/// Curve fit by Tanner Helland, accurate enough for the 1000K - 40000K range night-light tools use.
+fn whitepoint(temperature: u32) -> [f32; 3] {
+    let temperature = temperature.clamp(1000, 40000) as f32 / 100.0;
+
+    let red = if temperature <= 66.0 {
+        255.0
+    } else {
+        329.698_73 * (temperature - 60.0).powf(-0.133_204_76)
+    };
+    let green = if temperature <= 66.0 {
+        99.470_8 * temperature.ln() - 161.119_57
+    } else {
+        288.122_16 * (temperature - 60.0).powf(-0.075_514_85)
+    };
+    let blue = if temperature >= 66.0 {
+        255.0
+    } else if temperature <= 19.0 {
+        0.0
+    } else {
+        138.517_73 * (temperature - 10.0).ln() - 305.044_8
+    };
+
+    // Keep channels strictly positive so the inverse stays finite.
+    [red, green, blue].map(|channel| channel.clamp(1.0, 255.0) / 255.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{ImageBuffer, Rgba, RgbaImage};
+
+    use super::*;
+
+    const PIXEL: [u8; 4] = [200, 150, 100, 128];
+    const PIXEL16: [u16; 4] = [50_000, 40_000, 30_000, 32_768];
+
+    fn corrected(temperature: u32) -> [u8; 4] {
+        let mut image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba(PIXEL)));
+        neutralize_color_temperature(&mut image, temperature);
+        image.to_rgba8().get_pixel(0, 0).0
+    }
+
+    fn corrected16(temperature: u32) -> [u16; 4] {
+        let mut image = DynamicImage::ImageRgba16(ImageBuffer::from_pixel(1, 1, Rgba(PIXEL16)));
+        neutralize_color_temperature(&mut image, temperature);
+        let DynamicImage::ImageRgba16(buffer) = image else {
+            panic!("the image stays 16 bits per channel");
+        };
+        buffer.get_pixel(0, 0).0
+    }
+
+    #[test]
+    fn neutral_temperature_is_the_identity() {
+        assert_eq!(corrected(NEUTRAL_TEMPERATURE), PIXEL);
+        assert_eq!(corrected16(NEUTRAL_TEMPERATURE), PIXEL16);
+    }
+
+    #[test]
+    fn warm_temperatures_raise_blue_over_red() {
+        // Below 6600K the whitepoint keeps red at full scale, so red is left as it is while the
+        // dimmed green and blue are scaled back up.
+        let [red, green, blue, alpha] = corrected(3500);
+        assert!(red <= PIXEL[0]);
+        assert!(green > PIXEL[1]);
+        assert!(blue > PIXEL[2]);
+        assert_eq!(alpha, PIXEL[3]);
+
+        let [red, green, blue, alpha] = corrected16(3500);
+        assert!(red <= PIXEL16[0]);
+        assert!(green > PIXEL16[1]);
+        assert!(blue > PIXEL16[2]);
+        assert_eq!(alpha, PIXEL16[3]);
+        // Both depths scale blue by the same factor.
+        let factor = f32::from(blue) / f32::from(PIXEL16[2]);
+        let factor8 = f32::from(corrected(3500)[2]) / f32::from(PIXEL[2]);
+        assert!((factor - factor8).abs() < 0.01);
+    }
+
+    #[test]
+    fn cool_temperatures_raise_red_over_blue() {
+        let [red, _, blue, _] = corrected(10_000);
+        assert!(red > PIXEL[0]);
+        assert!(blue < PIXEL[2]);
+
+        let [red, _, blue, _] = corrected16(10_000);
+        assert!(red > PIXEL16[0]);
+        assert!(blue < PIXEL16[2]);
+    }
+}
@@ -1,9 +1,11 @@
 use image::ColorType;
 use wayland_client::protocol::wl_shm;
 
+use crate::strided::StridedView;
+
 pub trait Convert {
-    /// Convert raw image data into output type, return said type
-    fn convert_inplace(&self, data: &mut [u8]) -> ColorType;
+    /// Convert raw image data into output type, return said type. Row padding is left untouched.
+    fn convert_inplace(&self, view: &mut StridedView<&mut [u8]>) -> ColorType;
 }
 
 #[derive(Default)]
@@ -36,46 +38,143 @@ pub fn create_converter(format: wl_shm::Format) -> Option<Box<dyn Convert>> {
 }
 
 impl Convert for ConvertNone {
-    fn convert_inplace(&self, _data: &mut [u8]) -> ColorType {
+    fn convert_inplace(&self, _view: &mut StridedView<&mut [u8]>) -> ColorType {
         ColorType::Rgba8
     }
 }
 
 impl Convert for ConvertRGB8 {
-    fn convert_inplace(&self, data: &mut [u8]) -> ColorType {
-        for chunk in data.chunks_exact_mut(4) {
+    fn convert_inplace(&self, view: &mut StridedView<&mut [u8]>) -> ColorType {
+        for chunk in view.rows_mut().flat_map(|row| row.chunks_exact_mut(4)) {
             chunk.swap(0, 2);
         }
         ColorType::Rgba8
     }
 }
 
-/// Simple conversion from 10 to 8 bits for one channel
-fn convert10_to_8(color: u32) -> u8 {
-    ((color >> 2) & 255) as u8
-}
-
+/// 10-bit data does not fit 8-bit channels without losing precision, and 16-bit channels do not
+/// fit the buffer in place. The packed pixels are left as they are and expanded to
+/// [`ColorType::Rgba16`] when the image is built, see [`expand_bgr10`].
 impl Convert for ConvertBGR10 {
-    fn convert_inplace(&self, data: &mut [u8]) -> ColorType {
-        for chunk in data.chunks_exact_mut(4) {
-            let pixel = ((chunk[3] as u32) << 24)
-                | ((chunk[2] as u32) << 16)
-                | ((chunk[1] as u32) << 8)
-                | chunk[0] as u32;
-            let r = convert10_to_8(pixel >> SHIFT10BITS_1);
-            let g = convert10_to_8(pixel >> SHIFT10BITS_2);
-            let b = convert10_to_8(pixel);
-            chunk[0] = b;
-            chunk[1] = g;
-            chunk[2] = r;
-            chunk[3] = 255;
-        }
-        ColorType::Rgba8
+    fn convert_inplace(&self, _view: &mut StridedView<&mut [u8]>) -> ColorType {
+        ColorType::Rgba16
     }
 }
 
+/// Simple conversion from 10 to 16 bits for one channel, replicating the high bits into the low
+/// ones so that full intensity stays full intensity.
+fn convert10_to_16(color: u32) -> u16 {
+    let color = (color & 0x3ff) as u16;
+    (color << 6) | (color >> 4)
+}
+
+/// Expand packed Xbgr2101010 / Abgr2101010 pixels to 16-bit RGBA. The 2-bit alpha is ignored,
+/// as with the 8-bit formats the compositor hands out opaque output contents.
+pub(crate) fn expand_bgr10(view: &StridedView<&[u8]>) -> Vec<u16> {
+    view.rows()
+        .flat_map(|row| row.chunks_exact(4))
+        .flat_map(expand_bgr10_pixel)
+        .collect()
+}
+
+/// Expand one packed 10-bit pixel, see [`expand_bgr10`].
+pub(crate) fn expand_bgr10_pixel(chunk: &[u8]) -> [u16; 4] {
+    let pixel = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    [
+        convert10_to_16(pixel),
+        convert10_to_16(pixel >> SHIFT10BITS_2),
+        convert10_to_16(pixel >> SHIFT10BITS_1),
+        u16::MAX,
+    ]
+}
+
 impl Convert for ConvertBGR888 {
-    fn convert_inplace(&self, _data: &mut [u8]) -> ColorType {
+    fn convert_inplace(&self, _view: &mut StridedView<&mut [u8]>) -> ColorType {
         ColorType::Rgb8
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FrameFormat;
+
+    /// A 2x2 frame of `format` with rows 12 bytes apart, padded with `0xee`.
+    fn padded_frame(format: wl_shm::Format, pixels: [[u8; 4]; 4]) -> (FrameFormat, Vec<u8>) {
+        let frame_format = FrameFormat {
+            format,
+            width: 2,
+            height: 2,
+            stride: 12,
+        };
+        let data = pixels
+            .chunks(2)
+            .flat_map(|row| {
+                let mut bytes = row.concat();
+                bytes.resize(12, 0xee);
+                bytes
+            })
+            .collect();
+        (frame_format, data)
+    }
+
+    #[test]
+    fn xrgb_is_swapped_to_rgba_leaving_the_padding() {
+        let (frame_format, mut data) = padded_frame(
+            wl_shm::Format::Xrgb8888,
+            [
+                [1, 2, 3, 4],
+                [5, 6, 7, 8],
+                [9, 10, 11, 12],
+                [13, 14, 15, 16],
+            ],
+        );
+        let converter = create_converter(frame_format.format).expect("converter");
+        let mut view = StridedView::new(&mut data[..], &frame_format).expect("valid view");
+        assert_eq!(converter.convert_inplace(&mut view), ColorType::Rgba8);
+        assert_eq!(
+            data,
+            [
+                &[3, 2, 1, 4, 7, 6, 5, 8][..],
+                &[0xee; 4],
+                &[11, 10, 9, 12, 15, 14, 13, 16],
+                &[0xee; 4],
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn xbgr_and_bgr888_are_left_as_is() {
+        for (format, color_type) in [
+            (wl_shm::Format::Xbgr8888, ColorType::Rgba8),
+            (wl_shm::Format::Bgr888, ColorType::Rgb8),
+        ] {
+            let (frame_format, mut data) = padded_frame(format, [[1, 2, 3, 4]; 4]);
+            let original = data.clone();
+            let converter = create_converter(format).expect("converter");
+            let mut view = StridedView::new(&mut data[..], &frame_format).expect("valid view");
+            assert_eq!(converter.convert_inplace(&mut view), color_type);
+            assert_eq!(data, original);
+        }
+    }
+
+    #[test]
+    fn ten_bit_pixels_expand_to_full_range() {
+        // Red at full intensity, green at half and blue off, with the 2-bit alpha set.
+        let pixel = (0b11 << 30) | (0x200 << 10) | 0x3ff_u32;
+        let (frame_format, data) =
+            padded_frame(wl_shm::Format::Xbgr2101010, [pixel.to_le_bytes(); 4]);
+        let view = StridedView::new(&data[..], &frame_format).expect("valid view");
+        let expanded = expand_bgr10(&view);
+        assert_eq!(expanded.len(), 16);
+        for rgba in expanded.chunks_exact(4) {
+            assert_eq!(rgba, [u16::MAX, 0x8020, 0, u16::MAX]);
+        }
+    }
+
+    #[test]
+    fn unknown_formats_have_no_converter() {
+        assert!(create_converter(wl_shm::Format::Yuyv).is_none());
+    }
+}
@@ -1,30 +1,195 @@
 use std::{io, result};
 
 use thiserror::Error;
-use wayland_client::{globals::GlobalError, ConnectError, DispatchError};
+use wayland_client::{
+    globals::{BindError, GlobalError},
+    protocol::wl_shm,
+    ConnectError, DispatchError,
+};
+
+use crate::{CaptureRegion, SUPPORTED_FORMATS};
 
 pub type Result<T, E = Error> = result::Result<T, E>;
 
+/// Why a libwayshot call failed.
+///
+/// Failures are grouped by stage, so callers can tell a connection that broke apart from a
+/// compositor that lacks a protocol or a buffer format, or that failed a single capture. See
+/// [`Error::is_retryable`] and [`Error::is_unsupported`] to decide between retrying and falling
+/// back to another capture method.
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum Error {
     #[error("no outputs supplied")]
     NoOutputs,
     #[error("image buffer is not big enough")]
     BufferTooSmall,
+    #[error("buffer stride is smaller than a row of pixels")]
+    InvalidStride,
+    #[error("buffer is not aligned to the size of a pixel")]
+    MisalignedBuffer,
     #[error("image color type not supported")]
     InvalidColor,
     #[error("IO error: {0}")]
     Io(#[from] io::Error),
+    /// Talking to the compositor failed.
+    #[error("connection error: {0}")]
+    Connection(#[from] ConnectionError),
+    /// The compositor does not offer `protocol` at `version` or later.
+    #[error("{protocol} version {version} or later is not available")]
+    Protocol {
+        protocol: &'static str,
+        version: u32,
+    },
+    /// None of the buffer formats the compositor `advertised` is one of the `requested` formats
+    /// libwayshot can convert.
+    #[error("no supported buffer format: the compositor offered {advertised:?}, supported are {requested:?}")]
+    Format {
+        advertised: Vec<wl_shm::Format>,
+        requested: Vec<wl_shm::Format>,
+    },
+    /// The compositor failed a capture it accepted, eg: a frame copy ended with a `failed` event.
+    #[error("compositor failure: {reason}")]
+    CompositorFailure { reason: String },
+    #[error("timed out waiting for the compositor")]
+    Timeout,
+    #[error("compositor violated the protocol: {0}")]
+    ProtocolViolation(String),
+    /// A frame was asked for before one was captured.
+    #[error("no frame captured yet")]
+    NoFrame,
+    /// Every buffer of a [`crate::StreamingCaptureContext`] holds an acquired frame.
+    #[error("every stream buffer is acquired, release a frame first")]
+    NoFreeBuffer,
+    /// The compositor ended a capture session, eg: the captured window was closed.
+    #[error("the capture source went away")]
+    CaptureStopped,
+    /// A cursor capture needs a pointer, the seat has none, see
+    /// [`crate::WayshotConnection::start_ext_cursor_capture`].
+    #[error("the seat has no pointer")]
+    NoPointer,
+    /// The flag of [`crate::WayshotConnection::set_interrupt`] was set while waiting on the user.
+    #[error("interrupted")]
+    Interrupted,
+    #[error("capture region has an empty or negative size, or does not fit in i32: {0:?}")]
+    InvalidRegion(CaptureRegion),
+    #[error("capture region is too large: {0:?}")]
+    RegionTooLarge(CaptureRegion),
+    #[error("capture region does not intersect with any output: {0:?}")]
+    RegionOffScreen(CaptureRegion),
+    /// ext-image-copy-capture captures one output at a time, see
+    /// [`crate::WayshotConnection::screenshot_ext`].
+    #[error("capture region spans several outputs: {0:?}")]
+    RegionSpansOutputs(CaptureRegion),
+    #[cfg(feature = "testkit")]
+    #[error("failed to read or write golden image {0}")]
+    Golden(std::path::PathBuf),
+    /// A thread converting or rotating the frame of an output panicked, eg: inside the `image`
+    /// crate. The composite would lack that output.
+    #[error("converting a frame panicked: {0}")]
+    ConversionPanicked(String),
+    #[error("invalid output selector: {0}")]
+    InvalidOutputSelector(String),
+    #[error("output {0} has no logical size or mode yet, it may be switching modes")]
+    OutputNotReady(String),
+    #[cfg(feature = "overlay")]
+    #[error("failed to load the overlay font: {0}")]
+    Font(String),
+    #[cfg(feature = "write")]
+    #[error("failed to encode the image: {0}")]
+    Encode(String),
+    /// The image is larger than `format` can store, see [`crate::write::FormatLimits`].
+    #[cfg(feature = "write")]
+    #[error("a {width}x{height} image is too large for {format:?}")]
+    TooLargeForFormat {
+        format: image::ImageFormat,
+        width: u32,
+        height: u32,
+    },
+    #[cfg(feature = "wgpu")]
+    #[error("no wgpu texture format holds {0:?} pixels")]
+    TextureFormat(wl_shm::Format),
+}
+
+/// Failures of the Wayland connection itself or of its registry.
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum ConnectionError {
+    #[error("connect error: {0}")]
+    Connect(#[from] ConnectError),
     #[error("dispatch error: {0}")]
     Dispatch(#[from] DispatchError),
     #[error("global error: {0}")]
     Global(#[from] GlobalError),
-    #[error("connect error: {0}")]
-    Connect(#[from] ConnectError),
-    #[error("framecopy failed")]
-    FramecopyFailed,
-    #[error("No supported buffer format")]
-    NoSupportedBufferFormat,
-    #[error("Cannot find required wayland protocol")]
-    ProtocolNotFound(String),
+    /// A global is missing or too old, usually reported as [`Error::Protocol`] when libwayshot
+    /// knows which protocol it needed.
+    #[error("bind error: {0}")]
+    Bind(#[from] BindError),
+}
+
+impl Error {
+    /// Whether the same call may succeed when tried again: the compositor failed a single
+    /// capture, did not answer in time, or an output was switching modes, in which case
+    /// [`WayshotConnection::refresh_outputs`](crate::WayshotConnection::refresh_outputs) should
+    /// run first.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Error::CompositorFailure { .. } | Error::Timeout | Error::OutputNotReady(_)
+        )
+    }
+
+    /// Whether the compositor lacks a protocol or buffer format libwayshot needs. Retrying does
+    /// not help, another capture method such as the screenshot portal might.
+    pub fn is_unsupported(&self) -> bool {
+        matches!(
+            self,
+            Error::Protocol { .. }
+                | Error::Format { .. }
+                | Error::Connection(ConnectionError::Bind(_))
+        )
+    }
+
+    /// Whether the connection to the compositor failed, it is most likely unusable afterwards.
+    pub fn is_connection_error(&self) -> bool {
+        matches!(self, Error::Connection(_))
+    }
+}
+
+impl From<ConnectError> for Error {
+    fn from(error: ConnectError) -> Self {
+        Self::Connection(error.into())
+    }
+}
+
+impl From<DispatchError> for Error {
+    fn from(error: DispatchError) -> Self {
+        Self::Connection(error.into())
+    }
+}
+
+impl From<GlobalError> for Error {
+    fn from(error: GlobalError) -> Self {
+        Self::Connection(error.into())
+    }
+}
+
+impl From<BindError> for Error {
+    fn from(error: BindError) -> Self {
+        Self::Connection(error.into())
+    }
+}
+
+impl From<libwayshot_protocols::Error> for Error {
+    fn from(error: libwayshot_protocols::Error) -> Self {
+        match error {
+            libwayshot_protocols::Error::Io(e) => Self::Io(e),
+            libwayshot_protocols::Error::Dispatch(e) => e.into(),
+            libwayshot_protocols::Error::UnsupportedFormat(format) => Self::Format {
+                advertised: vec![format],
+                requested: SUPPORTED_FORMATS.to_vec(),
+            },
+            libwayshot_protocols::Error::Timeout => Self::Timeout,
+        }
+    }
 }
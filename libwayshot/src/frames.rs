@@ -0,0 +1,175 @@
+#[cfg(feature = "async")]
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "async")]
+use futures_core::Stream;
+
+use image::DynamicImage;
+
+use crate::{CaptureSource, Result, WayshotConnection};
+
+/// How often [`FrameIter`] captures, and for how long.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FramePacing {
+    /// Minimum time between the start of two captures. Captures taking longer than this are not
+    /// made up for, the next one simply starts right away.
+    pub interval: Duration,
+    /// Stop after this many frames, `None` to keep going forever.
+    pub limit: Option<usize>,
+}
+
+impl Default for FramePacing {
+    /// 30 frames per second, forever.
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1) / 30,
+            limit: None,
+        }
+    }
+}
+
+/// Blocking iterator over repeated captures of a [`CaptureSource`].
+///
+/// Created with [`WayshotConnection::frames`]. Errors are yielded rather than ending the iteration,
+/// so callers decide whether a failed frame is fatal.
+#[derive(Debug)]
+pub struct FrameIter<'a> {
+    wayshot_conn: &'a WayshotConnection,
+    source: CaptureSource,
+    cursor_overlay: bool,
+    pacing: FramePacing,
+    next_capture: Option<Instant>,
+    captured: usize,
+}
+
+impl<'a> FrameIter<'a> {
+    pub(crate) fn new(
+        wayshot_conn: &'a WayshotConnection,
+        source: CaptureSource,
+        cursor_overlay: bool,
+        pacing: FramePacing,
+    ) -> Self {
+        Self {
+            wayshot_conn,
+            source,
+            cursor_overlay,
+            pacing,
+            next_capture: None,
+            captured: 0,
+        }
+    }
+}
+
+impl Iterator for FrameIter<'_> {
+    type Item = Result<DynamicImage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self
+            .pacing
+            .limit
+            .is_some_and(|limit| self.captured >= limit)
+        {
+            return None;
+        }
+
+        if let Some(next_capture) = self.next_capture {
+            let now = Instant::now();
+            if next_capture > now {
+                thread::sleep(next_capture - now);
+            }
+        }
+        self.next_capture = Some(Instant::now() + self.pacing.interval);
+        self.captured += 1;
+
+        Some(self.wayshot_conn.capture(&self.source, self.cursor_overlay))
+    }
+}
+
+#[cfg(feature = "async")]
+type CaptureFuture<'a> = Pin<Box<dyn Future<Output = Result<DynamicImage>> + Send + 'a>>;
+
+/// Async counterpart of [`FrameIter`], a `Stream` of repeated captures of a [`CaptureSource`].
+///
+/// Created with [`WayshotConnection::frames_async`].
+#[cfg(feature = "async")]
+pub struct FrameStream<'a> {
+    wayshot_conn: &'a WayshotConnection,
+    source: CaptureSource,
+    cursor_overlay: bool,
+    pacing: FramePacing,
+    next_capture: Option<Instant>,
+    captured: usize,
+    pending: Option<CaptureFuture<'a>>,
+}
+
+#[cfg(feature = "async")]
+impl<'a> FrameStream<'a> {
+    pub(crate) fn new(
+        wayshot_conn: &'a WayshotConnection,
+        source: CaptureSource,
+        cursor_overlay: bool,
+        pacing: FramePacing,
+    ) -> Self {
+        Self {
+            wayshot_conn,
+            source,
+            cursor_overlay,
+            pacing,
+            next_capture: None,
+            captured: 0,
+            pending: None,
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl Stream for FrameStream<'_> {
+    type Item = Result<DynamicImage>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        if this.pending.is_none() {
+            if this
+                .pacing
+                .limit
+                .is_some_and(|limit| this.captured >= limit)
+            {
+                return Poll::Ready(None);
+            }
+
+            let start = this.next_capture.map_or_else(Instant::now, |next_capture| {
+                next_capture.max(Instant::now())
+            });
+            this.next_capture = Some(start + this.pacing.interval);
+            this.captured += 1;
+
+            let wayshot_conn = this.wayshot_conn;
+            let source = this.source.clone();
+            let cursor_overlay = this.cursor_overlay;
+            this.pending = Some(Box::pin(async move {
+                tokio::time::sleep_until(start.into()).await;
+                wayshot_conn.capture_async(&source, cursor_overlay).await
+            }));
+        }
+
+        let Some(pending) = this.pending.as_mut() else {
+            return Poll::Pending;
+        };
+        match pending.as_mut().poll(cx) {
+            Poll::Ready(result) => {
+                this.pending = None;
+                Poll::Ready(Some(result))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
@@ -0,0 +1,1095 @@
+//! Capturing through ext-image-copy-capture, from the windows ext_foreign_toplevel_list_v1 lists
+//! or from outputs, and capturing the pointer cursor over outputs apart from them.
+
+use std::mem;
+
+#[cfg(feature = "image")]
+use image::DynamicImage;
+use libwayshot_protocols::{
+    image_copy::{CopyOutcome, ImageCopyState},
+    screencopy::Damage,
+};
+use wayland_client::{
+    protocol::{
+        wl_output::{Transform, WlOutput},
+        wl_pointer::WlPointer,
+        wl_seat::WlSeat,
+    },
+    Dispatch, EventQueue, Proxy, QueueHandle,
+};
+use wayland_protocols::ext::{
+    foreign_toplevel_list::v1::client::ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1,
+    image_capture_source::v1::client::{
+        ext_foreign_toplevel_image_capture_source_manager_v1::ExtForeignToplevelImageCaptureSourceManagerV1,
+        ext_image_capture_source_v1::ExtImageCaptureSourceV1,
+        ext_output_image_capture_source_manager_v1::ExtOutputImageCaptureSourceManagerV1,
+    },
+    image_copy_capture::v1::client::{
+        ext_image_copy_capture_cursor_session_v1::ExtImageCopyCaptureCursorSessionV1,
+        ext_image_copy_capture_frame_v1::{ExtImageCopyCaptureFrameV1, FailureReason},
+        ext_image_copy_capture_manager_v1::{ExtImageCopyCaptureManagerV1, Options},
+        ext_image_copy_capture_session_v1::ExtImageCopyCaptureSessionV1,
+    },
+};
+
+#[cfg(feature = "image")]
+use crate::{
+    image_util, region_on_output, streaming::to_image, strided::StridedView, CaptureRegion,
+};
+use crate::{
+    output::OutputInfo,
+    screencopy::{FrameFormat, FrameMetadata},
+    streaming::StreamBuffer,
+    Error, Result, WayshotConnection, SUPPORTED_FORMATS,
+};
+
+/// Times a frame is captured again after the buffer constraints changed under it, eg: while the
+/// window is being resized.
+const CONSTRAINT_RETRIES: u32 = 3;
+
+/// Bytes rows of the buffer start at by default, the 32-bit boundaries pixman works with.
+const DEFAULT_STRIDE_ALIGNMENT: u32 = 4;
+
+/// Alignment rows are padded to once the compositor rejected a buffer matching its constraints.
+/// ext-image-copy-capture has no event telling the stride apart, and compositors importing shm
+/// buffers into the GPU commonly need 256 byte aligned rows.
+const FALLBACK_STRIDE_ALIGNMENT: u32 = 256;
+
+/// The newest ext_image_copy_capture_manager_v1 version libwayshot implements. Only raise it
+/// together with handling what the new version adds, gated on the version of the bound manager.
+pub(crate) const MAX_IMAGE_COPY_CAPTURE_VERSION: u32 = 1;
+/// The newest version of the ext-image-capture-source managers libwayshot implements.
+const MAX_IMAGE_CAPTURE_SOURCE_VERSION: u32 = 1;
+/// Newest wl_seat version cursor sessions bind, the first with `release`.
+const SEAT_VERSION: u32 = 5;
+
+/// The pointer cursor over an output, captured apart from the output, see
+/// [`WayshotConnection::capture_cursor`].
+#[cfg(feature = "image")]
+#[derive(Debug, Clone)]
+pub struct CursorInfo {
+    /// Position of the hotspot in buffer pixels of the output, the mode before its transform. It
+    /// may lie outside of the output while part of the cursor is over it.
+    pub position: (i32, i32),
+    /// Offset of the hotspot from the top left corner of `image`.
+    pub hotspot: (i32, i32),
+    /// The cursor image as the compositor copied it, with its alpha channel.
+    pub image: DynamicImage,
+}
+
+impl WayshotConnection {
+    /// Start capturing `output` repeatedly through ext-image-copy-capture, for compositors without
+    /// zwlr_screencopy_manager_v1. See [`ImageCopyCaptureContext::set_region`] to only capture
+    /// part of it.
+    pub fn start_ext_output_capture(
+        &self,
+        output: &OutputInfo,
+        cursor_overlay: bool,
+    ) -> Result<ImageCopyCaptureContext<'_>> {
+        output.check_ready()?;
+        ImageCopyCaptureContext::for_output(self, &output.wl_output, cursor_overlay)
+    }
+
+    /// Start capturing the image of the pointer cursor over `output` through an
+    /// ext-image-copy-capture cursor session, eg: for a remote desktop rendering the pointer
+    /// itself over captures without it. See [`ImageCopyCaptureContext::cursor_position`] and
+    /// [`ImageCopyCaptureContext::cursor_hotspot`] for where it is.
+    ///
+    /// Compositors may pause the session while the cursor is not over the output, captures then
+    /// wait for it to come back. Fails with [`Error::NoPointer`] when the seat has no pointer.
+    pub fn start_ext_cursor_capture(
+        &self,
+        output: &OutputInfo,
+    ) -> Result<ImageCopyCaptureContext<'_>> {
+        output.check_ready()?;
+        ImageCopyCaptureContext::for_cursor(self, &output.wl_output)
+    }
+
+    /// The pointer cursor over `output`, its image along with its position and hotspot, `None`
+    /// when the cursor is not over the output.
+    #[cfg(feature = "image")]
+    pub fn capture_cursor(&self, output: &OutputInfo) -> Result<Option<CursorInfo>> {
+        let mut context = self.start_ext_cursor_capture(output)?;
+        // The cursor session enters right away when the cursor is over the output.
+        context.event_queue.roundtrip(&mut context.state)?;
+        if !context.state.cursor.entered {
+            return Ok(None);
+        }
+        context.capture_frame_with_damage()?;
+        let (Some(stream_buffer), Some(position)) = (&context.buffer, context.cursor_position())
+        else {
+            return Ok(None);
+        };
+        // Not rotated upright, the hotspot is in buffer pixels of the cursor image.
+        let image = to_image(
+            stream_buffer.frame_format,
+            &stream_buffer.mmap,
+            context.transform,
+            context.metadata,
+        )?;
+        Ok(Some(CursorInfo {
+            position,
+            // A cursor image without a hotspot points with its top left corner.
+            hotspot: context.cursor_hotspot().unwrap_or_default(),
+            image,
+        }))
+    }
+
+    /// Take a screenshot of `capture_region` through ext-image-copy-capture, like
+    /// [`Self::screenshot`] does for compositors without zwlr_screencopy_manager_v1.
+    ///
+    /// ext-image-copy-capture copies whole outputs, so the region is handed to the compositor as
+    /// the only damage of the buffer and only its pixels are converted. A region on a 4K output
+    /// is not copied and converted as a whole, unless the compositor chooses to. The region has
+    /// to lie on a single output, [`Error::RegionSpansOutputs`] otherwise.
+    #[cfg(feature = "image")]
+    pub fn screenshot_ext(
+        &self,
+        capture_region: CaptureRegion,
+        cursor_overlay: bool,
+    ) -> Result<DynamicImage> {
+        let mut context = self.start_ext_region_capture(capture_region, cursor_overlay)?;
+        context.capture_frame_with_damage()?;
+        context.image()
+    }
+
+    /// A context capturing `capture_region` of the single output it lies on, see
+    /// [`Self::screenshot_ext`].
+    #[cfg(feature = "image")]
+    pub(crate) fn start_ext_region_capture(
+        &self,
+        capture_region: CaptureRegion,
+        cursor_overlay: bool,
+    ) -> Result<ImageCopyCaptureContext<'_>> {
+        capture_region.validate()?;
+        let mut on_outputs = self.get_all_outputs().iter().filter_map(|output| {
+            Some((
+                output,
+                region_on_output(capture_region, &output.dimensions)?,
+            ))
+        });
+        let Some((output, region)) = on_outputs.next() else {
+            return Err(Error::RegionOffScreen(capture_region));
+        };
+        if on_outputs.next().is_some() {
+            return Err(Error::RegionSpansOutputs(capture_region));
+        }
+
+        // Outputs are copied in the orientation of their mode, before the transform.
+        let buffer_size = (output.mode.width as u32, output.mode.height as u32);
+        let logical_size = (output.dimensions.width, output.dimensions.height);
+        let mut context = self.start_ext_output_capture(output, cursor_overlay)?;
+        context.set_region(buffer_region(
+            region,
+            logical_size,
+            buffer_size,
+            output.transform,
+        ));
+        Ok(context)
+    }
+}
+
+/// The manager a capture source was created with, destroyed along with the session.
+#[derive(Debug)]
+enum SourceManager {
+    Toplevel(ExtForeignToplevelImageCaptureSourceManagerV1),
+    Output(ExtOutputImageCaptureSourceManagerV1),
+}
+
+impl SourceManager {
+    fn destroy(&self) {
+        match self {
+            Self::Toplevel(manager) => manager.destroy(),
+            Self::Output(manager) => manager.destroy(),
+        }
+    }
+}
+
+/// The wl_pointer a cursor session follows, along with its seat.
+#[derive(Debug)]
+struct Pointer {
+    seat: WlSeat,
+    pointer: WlPointer,
+}
+
+impl Pointer {
+    fn release(&self) {
+        if self.pointer.version() >= 3 {
+            self.pointer.release();
+        }
+        release_seat(&self.seat);
+    }
+}
+
+/// `release` came with wl_seat version 5, older seats stay bound until the connection closes.
+fn release_seat(seat: &WlSeat) {
+    if seat.version() >= 5 {
+        seat.release();
+    }
+}
+
+/// What a session copies.
+enum SessionKind {
+    /// The source itself, with or without the cursor painted in.
+    Source { cursor_overlay: bool },
+    /// The image of the cursor of `Pointer` over the source.
+    Cursor(Pointer),
+}
+
+/// A cursor session, the session of the context copies its cursor image.
+#[derive(Debug)]
+struct CursorSession {
+    session: ExtImageCopyCaptureCursorSessionV1,
+    pointer: Pointer,
+}
+
+/// Repeated captures of a window or an output into a reused buffer, eg: to mirror or record it.
+///
+/// Created with [`WayshotConnection::start_toplevel_streaming_capture`],
+/// [`WayshotConnection::start_ext_output_capture`] or, to capture the cursor image alone,
+/// [`WayshotConnection::start_ext_cursor_capture`].
+///
+/// ```no_run
+/// use libwayshot::WayshotConnection;
+///
+/// let wayshot_connection = WayshotConnection::new().unwrap();
+/// let toplevels = wayshot_connection.get_all_toplevels().unwrap();
+/// let mut context = wayshot_connection
+///     .start_toplevel_streaming_capture(&toplevels[0], false)
+///     .unwrap();
+/// loop {
+///     let damage = context.capture_frame_with_damage().unwrap();
+///     let frame_format = context.frame_format().unwrap();
+///     println!("{}x{}, {damage:?}", frame_format.width, frame_format.height);
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ImageCopyCaptureContext<'a> {
+    wayshot_conn: &'a WayshotConnection,
+    event_queue: EventQueue<ImageCopyState>,
+    state: ImageCopyState,
+    copy_manager: ExtImageCopyCaptureManagerV1,
+    source_manager: SourceManager,
+    source: ExtImageCaptureSourceV1,
+    session: ExtImageCopyCaptureSessionV1,
+    cursor_session: Option<CursorSession>,
+    buffer: Option<StreamBuffer>,
+    /// The buffer does not hold a complete frame, so all of it must be copied.
+    full_damage: bool,
+    /// Part of the buffer captured, see [`Self::set_region`].
+    region: Option<Damage>,
+    /// The latest frame has another size than the one before it.
+    resized: bool,
+    /// Bytes the rows of the buffer are aligned to, see [`Self::set_stride_alignment`].
+    stride_alignment: u32,
+    metadata: FrameMetadata,
+    transform: Transform,
+}
+
+impl<'a> ImageCopyCaptureContext<'a> {
+    pub(crate) fn for_toplevel(
+        wayshot_conn: &'a WayshotConnection,
+        handle: &ExtForeignToplevelHandleV1,
+        cursor_overlay: bool,
+    ) -> Result<Self> {
+        let event_queue = wayshot_conn.conn.new_event_queue::<ImageCopyState>();
+        let qh = event_queue.handle();
+        let source_manager: ExtForeignToplevelImageCaptureSourceManagerV1 =
+            bind(wayshot_conn, &qh, MAX_IMAGE_CAPTURE_SOURCE_VERSION)?;
+        let source = source_manager.create_source(handle, &qh, ());
+        Self::new(
+            wayshot_conn,
+            event_queue,
+            ImageCopyState::default(),
+            SourceManager::Toplevel(source_manager),
+            source,
+            SessionKind::Source { cursor_overlay },
+        )
+    }
+
+    pub(crate) fn for_output(
+        wayshot_conn: &'a WayshotConnection,
+        output: &WlOutput,
+        cursor_overlay: bool,
+    ) -> Result<Self> {
+        let event_queue = wayshot_conn.conn.new_event_queue::<ImageCopyState>();
+        let qh = event_queue.handle();
+        let source_manager: ExtOutputImageCaptureSourceManagerV1 =
+            bind(wayshot_conn, &qh, MAX_IMAGE_CAPTURE_SOURCE_VERSION)?;
+        let source = source_manager.create_source(output, &qh, ());
+        Self::new(
+            wayshot_conn,
+            event_queue,
+            ImageCopyState::default(),
+            SourceManager::Output(source_manager),
+            source,
+            SessionKind::Source { cursor_overlay },
+        )
+    }
+
+    fn for_cursor(wayshot_conn: &'a WayshotConnection, output: &WlOutput) -> Result<Self> {
+        let mut event_queue = wayshot_conn.conn.new_event_queue::<ImageCopyState>();
+        let qh = event_queue.handle();
+        let mut state = ImageCopyState::default();
+        let seat: WlSeat = bind(wayshot_conn, &qh, SEAT_VERSION)?;
+        // Asking a seat that never had a pointer for one is a protocol error.
+        if let Err(e) = event_queue.roundtrip(&mut state) {
+            release_seat(&seat);
+            return Err(e.into());
+        }
+        if !state.seat_has_pointer {
+            release_seat(&seat);
+            return Err(Error::NoPointer);
+        }
+        let pointer = Pointer {
+            pointer: seat.get_pointer(&qh, ()),
+            seat,
+        };
+        let source_manager: ExtOutputImageCaptureSourceManagerV1 =
+            match bind(wayshot_conn, &qh, MAX_IMAGE_CAPTURE_SOURCE_VERSION) {
+                Ok(source_manager) => source_manager,
+                Err(e) => {
+                    pointer.release();
+                    return Err(e);
+                }
+            };
+        let source = source_manager.create_source(output, &qh, ());
+        Self::new(
+            wayshot_conn,
+            event_queue,
+            state,
+            SourceManager::Output(source_manager),
+            source,
+            SessionKind::Cursor(pointer),
+        )
+    }
+
+    fn new(
+        wayshot_conn: &'a WayshotConnection,
+        event_queue: EventQueue<ImageCopyState>,
+        state: ImageCopyState,
+        source_manager: SourceManager,
+        source: ExtImageCaptureSourceV1,
+        kind: SessionKind,
+    ) -> Result<Self> {
+        let qh = event_queue.handle();
+        let copy_manager: ExtImageCopyCaptureManagerV1 =
+            match bind(wayshot_conn, &qh, MAX_IMAGE_COPY_CAPTURE_VERSION) {
+                Ok(copy_manager) => copy_manager,
+                Err(e) => {
+                    source.destroy();
+                    source_manager.destroy();
+                    if let SessionKind::Cursor(pointer) = kind {
+                        pointer.release();
+                    }
+                    return Err(e);
+                }
+            };
+        let (session, cursor_session) = match kind {
+            SessionKind::Source { cursor_overlay } => {
+                let options = if cursor_overlay {
+                    Options::PaintCursors
+                } else {
+                    Options::empty()
+                };
+                (copy_manager.create_session(&source, options, &qh, ()), None)
+            }
+            SessionKind::Cursor(pointer) => {
+                let cursor_session =
+                    copy_manager.create_pointer_cursor_session(&source, &pointer.pointer, &qh, ());
+                let session = cursor_session.get_capture_session(&qh, ());
+                (
+                    session,
+                    Some(CursorSession {
+                        session: cursor_session,
+                        pointer,
+                    }),
+                )
+            }
+        };
+        Ok(Self {
+            wayshot_conn,
+            event_queue,
+            state,
+            copy_manager,
+            source_manager,
+            source,
+            session,
+            cursor_session,
+            buffer: None,
+            full_damage: false,
+            region: None,
+            resized: false,
+            stride_alignment: DEFAULT_STRIDE_ALIGNMENT,
+            metadata: FrameMetadata::default(),
+            transform: Transform::Normal,
+        })
+    }
+
+    /// Copy the next frame into the reused buffer and return the regions that changed.
+    ///
+    /// Blocks until the source has changed, except for the first frame. The first frame, and
+    /// every frame after the source was resized, is reported as damaged as a whole. Fails with
+    /// [`Error::CaptureStopped`] once the source went away, and sets [`Self::resized`] when the
+    /// new frame has another size. With a region set, the damage is cropped to it and may be
+    /// empty. Failed copies are retried by the [`RetryPolicy`](crate::RetryPolicy) of the
+    /// connection.
+    pub fn capture_frame_with_damage(&mut self) -> Result<Vec<Damage>> {
+        let previous_format = self.frame_format();
+        let (damage, attempts) = self
+            .wayshot_conn
+            .retry_policy
+            .run(|| self.try_capture_frame_with_damage(previous_format))?;
+        self.metadata.attempts = attempts;
+        Ok(damage)
+    }
+
+    /// A single attempt of [`Self::capture_frame_with_damage`], only retrying copies turned down
+    /// for their buffer constraints.
+    fn try_capture_frame_with_damage(
+        &mut self,
+        previous_format: Option<FrameFormat>,
+    ) -> Result<Vec<Damage>> {
+        let mut attempt = 0;
+        loop {
+            self.prepare_buffer()?;
+            let outcome = self.copy_frame()?;
+            if let Some(damage) = self.frame_damage(outcome, previous_format, &mut attempt)? {
+                return Ok(damage);
+            }
+        }
+    }
+
+    /// Async version of a single attempt of [`Self::capture_frame_with_damage`], retried by the
+    /// caller.
+    #[cfg(all(feature = "async", feature = "image"))]
+    pub(crate) async fn try_capture_frame_with_damage_async(&mut self) -> Result<Vec<Damage>> {
+        let previous_format = self.frame_format();
+        let mut attempt = 0;
+        loop {
+            self.prepare_buffer()?;
+            let outcome = self.copy_frame_async().await?;
+            if let Some(damage) = self.frame_damage(outcome, previous_format, &mut attempt)? {
+                return Ok(damage);
+            }
+        }
+    }
+
+    /// The damage of a copy that ended with `outcome`, `None` when it was turned down for its
+    /// buffer constraints and should be copied again.
+    fn frame_damage(
+        &mut self,
+        outcome: CopyOutcome,
+        previous_format: Option<FrameFormat>,
+        attempt: &mut u32,
+    ) -> Result<Option<Vec<Damage>>> {
+        match outcome {
+            CopyOutcome::Ready => {
+                let Some(frame_format) = self.frame_format() else {
+                    return Err(Error::NoFrame);
+                };
+                self.resized = previous_format.is_some_and(|previous| {
+                    (previous.width, previous.height) != (frame_format.width, frame_format.height)
+                });
+                let captured = self.captured_region(frame_format);
+                let damage = mem::take(&mut self.state.frame.damage);
+                if mem::take(&mut self.full_damage) || damage.is_empty() {
+                    return Ok(Some(vec![captured]));
+                }
+                Ok(Some(
+                    damage
+                        .into_iter()
+                        .filter_map(|damage| intersection(damage, captured))
+                        .collect(),
+                ))
+            }
+            CopyOutcome::Failed(FailureReason::BufferConstraints)
+                if *attempt < CONSTRAINT_RETRIES =>
+            {
+                if self.constraints_unchanged() && self.stride_alignment < FALLBACK_STRIDE_ALIGNMENT
+                {
+                    tracing::debug!(
+                        "Compositor rejected a buffer matching its constraints, retrying with \
+                         rows aligned to {FALLBACK_STRIDE_ALIGNMENT} bytes"
+                    );
+                    self.stride_alignment = FALLBACK_STRIDE_ALIGNMENT;
+                } else {
+                    tracing::debug!("Buffer constraints changed during the copy, retrying");
+                }
+                *attempt += 1;
+                Ok(None)
+            }
+            CopyOutcome::Failed(FailureReason::Stopped) => Err(Error::CaptureStopped),
+            CopyOutcome::Failed(_) => Err(Error::CompositorFailure {
+                reason: format!(
+                    "frame copy failed, events: {}",
+                    self.state.frame.event_log()
+                ),
+            }),
+        }
+    }
+
+    /// Only capture `region` of the buffer, in buffer pixels, `None` to capture all of it again.
+    ///
+    /// ext-image-copy-capture has no way to copy part of a source, so the region is sent as the
+    /// only damage of buffers that do not hold a frame yet, and the compositor is free to leave
+    /// the rest of them alone. Pixels outside of the region are undefined, the damage of
+    /// [`Self::capture_frame_with_damage`] and [`Self::image`] are cropped to it.
+    pub fn set_region(&mut self, region: Option<Damage>) {
+        if self.region.is_some() && region.is_none() {
+            self.full_damage = true;
+        }
+        self.region = region;
+    }
+
+    /// Part of a `frame_format` buffer [`Self::set_region`] captures.
+    fn captured_region(&self, frame_format: FrameFormat) -> Damage {
+        let whole = Damage {
+            x: 0,
+            y: 0,
+            width: frame_format.width,
+            height: frame_format.height,
+        };
+        self.region
+            .and_then(|region| intersection(region, whole))
+            .unwrap_or(whole)
+    }
+
+    /// Wait for the buffer constraints of the session and make sure the reused buffer matches
+    /// them.
+    fn prepare_buffer(&mut self) -> Result<()> {
+        self.wayshot_conn
+            .dispatch_frame(&mut self.event_queue, &mut self.state, |state| {
+                state.constraints.done || state.stopped
+            })?;
+        if self.state.stopped {
+            return Err(Error::CaptureStopped);
+        }
+        let frame_format = self.constrained_frame_format()?;
+        if self
+            .buffer
+            .as_ref()
+            .is_some_and(|stream_buffer| stream_buffer.frame_format == frame_format)
+        {
+            return Ok(());
+        }
+        tracing::debug!("Allocating capture buffer for {frame_format:?}");
+        if let Some(stale) = self.buffer.take() {
+            stale.destroy();
+        }
+        self.buffer = Some(StreamBuffer::new(
+            self.wayshot_conn,
+            &self.event_queue.handle(),
+            frame_format,
+        )?);
+        self.full_damage = true;
+        Ok(())
+    }
+
+    /// Buffer layout satisfying the latest session constraints, in the first format libwayshot
+    /// can convert, with rows aligned to [`Self::set_stride_alignment`].
+    fn constrained_frame_format(&self) -> Result<FrameFormat> {
+        let constraints = &self.state.constraints;
+        let Some((width, height)) = constraints.buffer_size else {
+            return Err(Error::ProtocolViolation(
+                "capture session constraints without a buffer size".to_string(),
+            ));
+        };
+        let Some(format) = constraints
+            .shm_formats
+            .iter()
+            .copied()
+            .find(|format| SUPPORTED_FORMATS.contains(format))
+        else {
+            return Err(Error::Format {
+                advertised: constraints.shm_formats.clone(),
+                requested: SUPPORTED_FORMATS.to_vec(),
+            });
+        };
+        let mut frame_format = FrameFormat {
+            format,
+            width,
+            height,
+            stride: 0,
+        };
+        frame_format.stride = aligned_stride(
+            width,
+            frame_format.bytes_per_pixel()? as u32,
+            self.stride_alignment,
+        )?;
+        Ok(frame_format)
+    }
+
+    /// Whether the reused buffer still matches the session constraints, so a copy failing on
+    /// them was turned down for its stride.
+    fn constraints_unchanged(&self) -> bool {
+        self.state.constraints.done
+            && self
+                .constrained_frame_format()
+                .is_ok_and(|frame_format| self.frame_format() == Some(frame_format))
+    }
+
+    /// Pad the rows of the buffer to a multiple of `alignment` bytes, for compositors that
+    /// only accept aligned strides. Rows are 4 byte aligned by default, and 256 byte aligned
+    /// after the compositor rejected a buffer that matched its constraints. The buffer is
+    /// reallocated before the next frame when its stride changes.
+    ///
+    /// Fails with [`Error::InvalidStride`] when `alignment` is 0.
+    pub fn set_stride_alignment(&mut self, alignment: u32) -> Result<()> {
+        if alignment == 0 {
+            return Err(Error::InvalidStride);
+        }
+        self.stride_alignment = alignment;
+        Ok(())
+    }
+
+    /// Capture a frame into the reused buffer, damaging the captured region of it when it does
+    /// not hold a frame yet.
+    fn copy_frame(&mut self) -> Result<CopyOutcome> {
+        let frame = self.request_frame()?;
+        let copied =
+            self.wayshot_conn
+                .dispatch_frame(&mut self.event_queue, &mut self.state, |state| {
+                    state.frame.outcome.is_some()
+                });
+        self.finish_frame(frame, copied)
+    }
+
+    /// Async version of [`Self::copy_frame`].
+    #[cfg(all(feature = "async", feature = "image"))]
+    async fn copy_frame_async(&mut self) -> Result<CopyOutcome> {
+        let frame = self.request_frame()?;
+        let copied = self
+            .wayshot_conn
+            .dispatch_frame_async(&mut self.event_queue, &mut self.state, |state| {
+                state.frame.outcome.is_some()
+            })
+            .await;
+        self.finish_frame(frame, copied)
+    }
+
+    /// Ask the compositor to copy a frame into the reused buffer.
+    fn request_frame(&mut self) -> Result<ExtImageCopyCaptureFrameV1> {
+        let Some(stream_buffer) = &self.buffer else {
+            return Err(Error::NoFrame);
+        };
+        self.state.frame = Default::default();
+        let frame = self.session.create_frame(&self.event_queue.handle(), ());
+        frame.attach_buffer(&stream_buffer.buffer);
+        if self.full_damage {
+            let damage = self.captured_region(stream_buffer.frame_format);
+            frame.damage_buffer(
+                damage.x as i32,
+                damage.y as i32,
+                damage.width as i32,
+                damage.height as i32,
+            );
+        }
+        frame.capture();
+        Ok(frame)
+    }
+
+    /// Destroy `frame` once the wait for it ended with `copied`, and return how the copy went.
+    fn finish_frame(
+        &mut self,
+        frame: ExtImageCopyCaptureFrameV1,
+        copied: Result<()>,
+    ) -> Result<CopyOutcome> {
+        // Also when the copy timed out, so the compositor stops writing into the buffer.
+        frame.destroy();
+        copied?;
+
+        let outcome = self.state.frame.outcome.ok_or(Error::NoFrame)?;
+        if outcome == CopyOutcome::Ready {
+            self.transform = self.state.frame.transform.unwrap_or(Transform::Normal);
+            self.metadata = FrameMetadata {
+                y_invert: false,
+                presentation_time: self.state.frame.presentation_time.unwrap_or_default(),
+                attempts: 1,
+            };
+        }
+        Ok(outcome)
+    }
+
+    /// Format of the pixels in [`Self::buffer`], `None` before the first capture. Its size
+    /// changes with the source.
+    pub fn frame_format(&self) -> Option<FrameFormat> {
+        self.buffer
+            .as_ref()
+            .map(|stream_buffer| stream_buffer.frame_format)
+    }
+
+    /// Presentation time of the latest frame, eg: to timestamp it in a recording.
+    pub fn metadata(&self) -> FrameMetadata {
+        self.metadata
+    }
+
+    /// Whether the latest frame has another size than the frame before it, because the window
+    /// was resized or moved to an output of another scale.
+    pub fn resized(&self) -> bool {
+        self.resized
+    }
+
+    /// Transform the compositor applied to the latest frame.
+    pub fn transform(&self) -> Transform {
+        self.transform
+    }
+
+    /// Position of the hotspot of the cursor over the source, in buffer pixels of the source,
+    /// for contexts capturing the cursor. `None` while the cursor is elsewhere.
+    pub fn cursor_position(&self) -> Option<(i32, i32)> {
+        self.state.cursor.position
+    }
+
+    /// Offset of the hotspot from the top left corner of the latest cursor image, for contexts
+    /// capturing the cursor.
+    pub fn cursor_hotspot(&self) -> Option<(i32, i32)> {
+        self.state.cursor.hotspot
+    }
+
+    /// Version of ext_image_copy_capture_manager_v1 the session was created with. Features added
+    /// after version 1 are only available when it is high enough.
+    pub fn copy_capture_version(&self) -> u32 {
+        self.copy_manager.version()
+    }
+
+    /// Raw pixels of the latest frame, laid out as described by [`Self::frame_format`]. Rows are
+    /// [`FrameFormat::stride`] bytes apart and may be padded, see [`crate::StridedView`].
+    pub fn buffer(&self) -> Option<&[u8]> {
+        self.buffer
+            .as_ref()
+            .map(|stream_buffer| &stream_buffer.mmap[..])
+    }
+
+    /// The latest frame, or its region, converted and rotated upright, leaving the reused buffer
+    /// as is.
+    #[cfg(feature = "image")]
+    pub fn image(&self) -> Result<DynamicImage> {
+        let Some(stream_buffer) = &self.buffer else {
+            return Err(Error::NoFrame);
+        };
+        let frame_format = stream_buffer.frame_format;
+        let image = match self.region {
+            None => to_image(
+                frame_format,
+                &stream_buffer.mmap,
+                self.transform,
+                self.metadata,
+            )?,
+            Some(_) => {
+                let region = self.captured_region(frame_format);
+                let pixels = StridedView::new(&stream_buffer.mmap[..], &frame_format)?
+                    .crop_packed(region)
+                    .ok_or(Error::BufferTooSmall)?;
+                let cropped_format = FrameFormat {
+                    width: region.width,
+                    height: region.height,
+                    stride: region.width * frame_format.bytes_per_pixel()? as u32,
+                    ..frame_format
+                };
+                to_image(cropped_format, &pixels, self.transform, self.metadata)?
+            }
+        };
+        let (width, height) = upright_size(image.width(), image.height(), self.transform);
+        Ok(image_util::rotate_image_buffer(
+            image,
+            self.transform,
+            width,
+            height,
+        ))
+    }
+}
+
+impl Drop for ImageCopyCaptureContext<'_> {
+    fn drop(&mut self) {
+        self.session.destroy();
+        if let Some(cursor_session) = &self.cursor_session {
+            cursor_session.session.destroy();
+        }
+        self.source.destroy();
+        if let Some(stream_buffer) = self.buffer.take() {
+            stream_buffer.destroy();
+        }
+        self.source_manager.destroy();
+        self.copy_manager.destroy();
+        if let Some(cursor_session) = &self.cursor_session {
+            cursor_session.pointer.release();
+        }
+    }
+}
+
+/// Bytes between the rows of a buffer `width` pixels wide, padded to a multiple of `alignment`.
+fn aligned_stride(width: u32, bytes_per_pixel: u32, alignment: u32) -> Result<u32> {
+    width
+        .checked_mul(bytes_per_pixel)
+        .and_then(|row_bytes| row_bytes.checked_next_multiple_of(alignment))
+        .ok_or(Error::InvalidStride)
+}
+
+/// The part of `damage` inside `bounds`, `None` when they do not overlap.
+fn intersection(damage: Damage, bounds: Damage) -> Option<Damage> {
+    let right = |damage: Damage| u64::from(damage.x) + u64::from(damage.width);
+    let bottom = |damage: Damage| u64::from(damage.y) + u64::from(damage.height);
+    let x = damage.x.max(bounds.x);
+    let y = damage.y.max(bounds.y);
+    let x2 = right(damage).min(right(bounds));
+    let y2 = bottom(damage).min(bottom(bounds));
+    if x2 <= u64::from(x) || y2 <= u64::from(y) {
+        return None;
+    }
+    Some(Damage {
+        x,
+        y,
+        width: (x2 - u64::from(x)) as u32,
+        height: (y2 - u64::from(y)) as u32,
+    })
+}
+
+/// Whether `transform` turns the buffer by a quarter, swapping its width and height.
+#[cfg(feature = "image")]
+fn is_quarter_turn(transform: Transform) -> bool {
+    matches!(
+        transform,
+        Transform::_90 | Transform::_270 | Transform::Flipped90 | Transform::Flipped270
+    )
+}
+
+/// Size of a `width`x`height` buffer once rotated upright by `transform`.
+#[cfg(feature = "image")]
+fn upright_size(width: u32, height: u32, transform: Transform) -> (u32, u32) {
+    if is_quarter_turn(transform) {
+        (height, width)
+    } else {
+        (width, height)
+    }
+}
+
+/// The pixels of a `buffer_size` buffer showing `region` of an output once rotated upright by
+/// `transform`. `region` is in the logical coordinates of the output, which is `logical_size`
+/// large. `None` when the region lies outside of the output.
+#[cfg(feature = "image")]
+fn buffer_region(
+    region: CaptureRegion,
+    logical_size: (i32, i32),
+    buffer_size: (u32, u32),
+    transform: Transform,
+) -> Option<Damage> {
+    let (width, height) = buffer_size;
+    let (upright_width, upright_height) = upright_size(width, height, transform);
+    let (logical_width, logical_height) = logical_size;
+    if logical_width <= 0 || logical_height <= 0 {
+        return None;
+    }
+    // Scale to upright pixels, rounding outwards so fractional scales do not cut off an edge.
+    let scale = |position: i32, logical: i32, physical: u32, round: fn(f64) -> f64| {
+        let scaled = round(f64::from(position) * f64::from(physical) / f64::from(logical));
+        scaled.clamp(0.0, f64::from(physical)) as u32
+    };
+    let x1 = scale(
+        region.x_coordinate,
+        logical_width,
+        upright_width,
+        f64::floor,
+    );
+    let y1 = scale(
+        region.y_coordinate,
+        logical_height,
+        upright_height,
+        f64::floor,
+    );
+    let x2 = scale(
+        region.x_coordinate.checked_add(region.width)?,
+        logical_width,
+        upright_width,
+        f64::ceil,
+    );
+    let y2 = scale(
+        region.y_coordinate.checked_add(region.height)?,
+        logical_height,
+        upright_height,
+        f64::ceil,
+    );
+    if x2 <= x1 || y2 <= y1 {
+        return None;
+    }
+
+    // Undo the rotation the image is turned upright with, see `image_util::rotate_image_buffer`,
+    // then the horizontal flip it starts with.
+    let (x1, y1, x2, y2) = match transform {
+        Transform::_90 | Transform::Flipped90 => (y1, height - x2, y2, height - x1),
+        Transform::_180 | Transform::Flipped180 => {
+            (width - x2, height - y2, width - x1, height - y1)
+        }
+        Transform::_270 | Transform::Flipped270 => (width - y2, x1, width - y1, x2),
+        _ => (x1, y1, x2, y2),
+    };
+    let (x1, x2) = match transform {
+        Transform::Flipped
+        | Transform::Flipped90
+        | Transform::Flipped180
+        | Transform::Flipped270 => (width - x2, width - x1),
+        _ => (x1, x2),
+    };
+    Some(Damage {
+        x: x1,
+        y: y1,
+        width: x2 - x1,
+        height: y2 - y1,
+    })
+}
+
+/// Bind the global `I`, at the newest version up to `max_version` the compositor offers,
+/// reporting a missing one as [`Error::Protocol`].
+fn bind<I>(
+    wayshot_conn: &WayshotConnection,
+    qh: &QueueHandle<ImageCopyState>,
+    max_version: u32,
+) -> Result<I>
+where
+    I: Proxy + 'static,
+    ImageCopyState: Dispatch<I, ()>,
+{
+    let proxy = wayshot_conn
+        .globals
+        .bind::<I, _, _>(qh, 1..=max_version, ())
+        .map_err(|_| Error::Protocol {
+            protocol: I::interface().name,
+            version: 1,
+        })?;
+    tracing::debug!("Bound {} v{}", I::interface().name, proxy.version());
+    Ok(proxy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packed_rows_keep_their_length() {
+        assert_eq!(aligned_stride(1920, 4, 4).expect("stride"), 7680);
+        assert_eq!(aligned_stride(1920, 4, 1).expect("stride"), 7680);
+    }
+
+    #[test]
+    fn rows_are_padded_to_the_alignment() {
+        assert_eq!(aligned_stride(1366, 4, 256).expect("stride"), 5632);
+        assert_eq!(aligned_stride(101, 3, 4).expect("stride"), 304);
+    }
+
+    #[test]
+    fn invalid_strides_are_rejected() {
+        assert!(matches!(
+            aligned_stride(1920, 4, 0),
+            Err(Error::InvalidStride)
+        ));
+        assert!(matches!(
+            aligned_stride(u32::MAX, 4, 4),
+            Err(Error::InvalidStride)
+        ));
+    }
+
+    fn damage(x: u32, y: u32, width: u32, height: u32) -> Damage {
+        Damage {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn damage_is_cropped_to_the_region() {
+        let region = damage(100, 100, 200, 200);
+        assert_eq!(
+            intersection(damage(0, 150, 150, 10), region),
+            Some(damage(100, 150, 50, 10))
+        );
+        assert_eq!(intersection(damage(0, 0, 100, 100), region), None);
+        assert_eq!(
+            intersection(damage(u32::MAX, 0, u32::MAX, 1), damage(0, 0, u32::MAX, 1)),
+            None
+        );
+    }
+
+    #[cfg(feature = "image")]
+    fn capture_region(x: i32, y: i32, width: i32, height: i32) -> CaptureRegion {
+        CaptureRegion {
+            x_coordinate: x,
+            y_coordinate: y,
+            width,
+            height,
+        }
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn regions_are_scaled_to_buffer_pixels() {
+        // A 4K output at scale 2.
+        let region = capture_region(100, 50, 200, 100);
+        assert_eq!(
+            buffer_region(region, (1920, 1080), (3840, 2160), Transform::Normal),
+            Some(damage(200, 100, 400, 200))
+        );
+        // Fractional scales round outwards.
+        assert_eq!(
+            buffer_region(
+                capture_region(1, 1, 1, 1),
+                (2, 2),
+                (3, 3),
+                Transform::Normal
+            ),
+            Some(damage(1, 1, 2, 2))
+        );
+        assert_eq!(
+            buffer_region(
+                capture_region(-10, 0, 5, 5),
+                (100, 100),
+                (100, 100),
+                Transform::Normal
+            ),
+            None
+        );
+    }
+
+    /// Marking the buffer pixels of a region and rotating the buffer upright marks the region.
+    #[cfg(feature = "image")]
+    #[test]
+    fn regions_follow_the_output_transform() {
+        let region = capture_region(10, 5, 30, 20);
+        for transform in [
+            Transform::Normal,
+            Transform::_90,
+            Transform::_180,
+            Transform::_270,
+            Transform::Flipped,
+            Transform::Flipped90,
+            Transform::Flipped180,
+            Transform::Flipped270,
+        ] {
+            let (width, height) = upright_size(100, 50, transform);
+            let Damage {
+                x,
+                y,
+                width: marked_width,
+                height: marked_height,
+            } = buffer_region(region, (100, 50), (width, height), transform)
+                .expect("the region is on the output");
+            let mut buffer = image::GrayImage::new(width, height);
+            for pixel_y in y..y + marked_height {
+                for pixel_x in x..x + marked_width {
+                    buffer.put_pixel(pixel_x, pixel_y, image::Luma([255]));
+                }
+            }
+            let upright =
+                image_util::rotate_image_buffer(buffer.into(), transform, 100, 50).into_luma8();
+            for (pixel_x, pixel_y, pixel) in upright.enumerate_pixels() {
+                let inside = (10..40).contains(&pixel_x) && (5..25).contains(&pixel_y);
+                assert_eq!(
+                    pixel.0[0] == 255,
+                    inside,
+                    "{transform:?} at {pixel_x},{pixel_y}"
+                );
+            }
+        }
+    }
+}
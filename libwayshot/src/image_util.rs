@@ -1,41 +1,76 @@
-use image::{DynamicImage, GenericImageView};
+use image::{imageops, DynamicImage, GenericImageView, ImageBuffer, Pixel};
 use wayland_client::protocol::wl_output::Transform;
 
+/// Turn `image` upside down, for frames the compositor copied with the y_invert flag.
+pub(crate) fn flip_vertical(image: DynamicImage) -> DynamicImage {
+    match image {
+        DynamicImage::ImageRgb8(mut buffer) => {
+            imageops::flip_vertical_in_place(&mut buffer);
+            buffer.into()
+        }
+        DynamicImage::ImageRgba8(mut buffer) => {
+            imageops::flip_vertical_in_place(&mut buffer);
+            buffer.into()
+        }
+        DynamicImage::ImageRgba16(mut buffer) => {
+            imageops::flip_vertical_in_place(&mut buffer);
+            buffer.into()
+        }
+        image => image.flipv(),
+    }
+}
+
 pub(crate) fn rotate_image_buffer(
     image: DynamicImage,
     transform: Transform,
     width: u32,
     height: u32,
 ) -> DynamicImage {
+    // The pixel accessors of DynamicImage are 8-bit, so 16-bit captures go through their own
+    // buffer type to keep their precision.
+    match image {
+        DynamicImage::ImageRgba16(buffer) => transform_image(buffer, transform, width, height),
+        image => transform_image(image, transform, width, height),
+    }
+}
+
+fn transform_image<I, P>(image: I, transform: Transform, width: u32, height: u32) -> DynamicImage
+where
+    I: GenericImageView<Pixel = P> + Into<DynamicImage>,
+    P: Pixel + 'static,
+    ImageBuffer<P, Vec<P::Subpixel>>: Into<DynamicImage>,
+{
     let final_image = match transform {
-        Transform::_90 => image::imageops::rotate90(&image).into(),
-        Transform::_180 => image::imageops::rotate180(&image).into(),
-        Transform::_270 => image::imageops::rotate270(&image).into(),
-        Transform::Flipped => image::imageops::flip_horizontal(&image).into(),
+        Transform::_90 => imageops::rotate90(&image),
+        Transform::_180 => imageops::rotate180(&image),
+        Transform::_270 => imageops::rotate270(&image),
+        Transform::Flipped => imageops::flip_horizontal(&image),
         Transform::Flipped90 => {
-            let flipped_buffer = image::imageops::flip_horizontal(&image);
-            image::imageops::rotate90(&flipped_buffer).into()
+            let flipped_buffer = imageops::flip_horizontal(&image);
+            imageops::rotate90(&flipped_buffer)
         }
         Transform::Flipped180 => {
-            let flipped_buffer = image::imageops::flip_horizontal(&image);
-            image::imageops::rotate180(&flipped_buffer).into()
+            let flipped_buffer = imageops::flip_horizontal(&image);
+            imageops::rotate180(&flipped_buffer)
         }
         Transform::Flipped270 => {
-            let flipped_buffer = image::imageops::flip_horizontal(&image);
-            image::imageops::rotate270(&flipped_buffer).into()
+            let flipped_buffer = imageops::flip_horizontal(&image);
+            imageops::rotate270(&flipped_buffer)
         }
-        _ => image,
+        _ => return resize_image(image, width, height),
     };
+    resize_image(final_image, width, height)
+}
 
-    if final_image.dimensions() == (width, height) {
-        return final_image;
+fn resize_image<I, P>(image: I, width: u32, height: u32) -> DynamicImage
+where
+    I: GenericImageView<Pixel = P> + Into<DynamicImage>,
+    P: Pixel + 'static,
+    ImageBuffer<P, Vec<P::Subpixel>>: Into<DynamicImage>,
+{
+    if image.dimensions() == (width, height) {
+        return image.into();
     }
 
-    image::imageops::resize(
-        &final_image,
-        width,
-        height,
-        image::imageops::FilterType::Gaussian,
-    )
-    .into()
+    imageops::resize(&image, width, height, imageops::FilterType::Gaussian).into()
 }
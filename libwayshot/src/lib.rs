@@ -2,55 +2,140 @@
 //! that provides a simple API to take screenshots with.
 //!
 //! To get started, look at [`WayshotConnection`].
+//!
+//! With the default `image` feature disabled the crate does not depend on `image`, and captures
+//! are only available as [`RawFrame`]s through [`WayshotConnection::capture_raw_frame`].
+//!
+//! The library never panics on purpose: every failure on the capture path is surfaced as an
+//! [`Error`], so callers can decide how to report it.
+#![deny(clippy::unwrap_used, clippy::undocumented_unsafe_blocks)]
 
+#[cfg(feature = "async")]
+mod async_capture;
+mod builder;
+mod capabilities;
+#[cfg(feature = "image")]
+pub mod color;
+#[cfg(feature = "image")]
 mod convert;
-mod dispatch;
 mod error;
+#[cfg(feature = "image")]
+mod frames;
+mod image_copy;
+#[cfg(feature = "image")]
 mod image_util;
 pub mod output;
+#[cfg(feature = "overlay")]
+pub mod overlay;
+#[cfg(feature = "write")]
+mod png_encoder;
+mod pool;
+mod retry;
 mod screencopy;
+#[cfg(feature = "selector")]
+mod selector;
+mod streaming;
+#[cfg(feature = "image")]
+mod strided;
+#[cfg(feature = "testkit")]
+pub mod testkit;
+mod toplevel;
+mod watcher;
+#[cfg(feature = "wgpu")]
+mod wgpu_texture;
+#[cfg(feature = "write")]
+pub mod write;
 
+#[cfg(feature = "image")]
+use std::{any::Any, cmp, mem};
 use std::{
-    cmp,
     fs::File,
+    ops::RangeInclusive,
     os::fd::AsFd,
-    process::exit,
-    sync::atomic::{AtomicBool, Ordering},
+    sync::{atomic::AtomicBool, Arc},
     thread,
+    time::{Duration, Instant},
 };
 
-use image::{imageops::overlay, DynamicImage};
-use memmap2::MmapMut;
+#[cfg(feature = "image")]
+use image::{imageops::overlay, ColorType, DynamicImage};
+#[cfg(feature = "image")]
+use libwayshot_protocols::screencopy::CaptureFramesState;
+use libwayshot_protocols::{
+    dispatch_until, dispatch_until_deadline,
+    output::OutputCaptureState,
+    screencopy::{CaptureFrameState, FrameState},
+};
 use wayland_client::{
-    globals::{registry_queue_init, GlobalList},
+    globals::GlobalList,
     protocol::{
+        wl_buffer::WlBuffer,
         wl_output::{Transform, WlOutput},
         wl_shm::{self, WlShm},
+        wl_shm_pool::WlShmPool,
     },
-    Connection, EventQueue,
+    Connection, Dispatch, EventQueue, Proxy, QueueHandle,
 };
-use wayland_protocols::xdg::xdg_output::zv1::client::{
-    zxdg_output_manager_v1::ZxdgOutputManagerV1, zxdg_output_v1::ZxdgOutputV1,
+use wayland_protocols::{
+    wp::color_management::v1::client::wp_color_manager_v1::WpColorManagerV1,
+    xdg::xdg_output::zv1::client::{
+        zxdg_output_manager_v1::ZxdgOutputManagerV1, zxdg_output_v1::ZxdgOutputV1,
+    },
 };
 use wayland_protocols_wlr::screencopy::v1::client::{
     zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1,
     zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1,
 };
 
+#[cfg(feature = "image")]
 use crate::{
-    convert::create_converter,
-    dispatch::{CaptureFrameState, FrameState, OutputCaptureState, WayshotState},
-    output::OutputInfo,
-    screencopy::{create_shm_fd, FrameCopy, FrameFormat},
+    convert::{create_converter, expand_bgr10_pixel},
+    screencopy::FrameCopy,
+    strided::StridedView,
+};
+use crate::{
+    output::{ColorDescription, OutputInfo, OutputPositioning, OutputSelector},
+    pool::{BufferLease, ShmBuffer},
+    screencopy::shm_pool_size,
 };
 
-pub use crate::error::{Error, Result};
+pub use crate::{
+    builder::WayshotConnectionBuilder,
+    capabilities::{
+        Capabilities, DmabufFeedback, DmabufTranche, DrmFormat, DRM_FORMAT_MOD_INVALID,
+    },
+    error::{ConnectionError, Error, Result},
+    image_copy::ImageCopyCaptureContext,
+    pool::BufferPool,
+    retry::RetryPolicy,
+    screencopy::{create_shm_fd, FrameFormat, FrameMetadata, RawFrame},
+    streaming::{
+        AcquiredFrame, Damage, SliceLayout, StreamingCaptureContext, ViewportOptions,
+        DEFAULT_MAX_BUFFERS,
+    },
+    toplevel::TopLevel,
+    watcher::{OutputEvent, OutputWatcher},
+};
+
+#[cfg(feature = "image")]
+pub use crate::frames::{FrameIter, FramePacing};
+#[cfg(feature = "image")]
+pub use crate::image_copy::CursorInfo;
+#[cfg(feature = "selector")]
+pub use crate::selector::{Selection, SelectionConfirm};
+
+#[cfg(all(feature = "async", feature = "image"))]
+pub use crate::frames::FrameStream;
 
 pub mod reexport {
     use wayland_client::protocol::wl_output;
+    pub use wayland_protocols::wp::color_management::v1::client::wp_color_manager_v1::{
+        Primaries, TransferFunction,
+    };
     pub use wl_output::{Transform, WlOutput};
 }
 
+#[cfg(feature = "image")]
 type Frame = (Vec<FrameCopy>, (i32, i32));
 
 /// Struct to store region capture details.
@@ -66,47 +151,263 @@ pub struct CaptureRegion {
     pub height: i32,
 }
 
+/// Largest area, in pixels, a single capture may cover. Anything above this is almost certainly a
+/// bogus region and would only end in a huge allocation.
+pub const MAX_CAPTURE_PIXELS: i64 = 256 * 1024 * 1024;
+
+impl CaptureRegion {
+    /// Flip regions with a negative width or height, as produced by dragging a selection up or to
+    /// the left, into the equivalent region with a positive size.
+    ///
+    /// Fails with [`Error::InvalidRegion`] when the flipped region does not fit in `i32`
+    /// coordinates, eg: a width of `i32::MIN`.
+    pub fn normalized(self) -> Result<Self> {
+        let flip = |position: i32, length: i32| {
+            if length < 0 {
+                Some((position.checked_add(length)?, length.checked_neg()?))
+            } else {
+                Some((position, length))
+            }
+        };
+        let invalid = || Error::InvalidRegion(self);
+        let (x_coordinate, width) = flip(self.x_coordinate, self.width).ok_or_else(invalid)?;
+        let (y_coordinate, height) = flip(self.y_coordinate, self.height).ok_or_else(invalid)?;
+        Ok(Self {
+            x_coordinate,
+            y_coordinate,
+            width,
+            height,
+        })
+    }
+
+    /// Reject empty, negative and absurdly large regions, and regions whose far edges do not fit
+    /// in `i32` coordinates.
+    pub fn validate(&self) -> Result<()> {
+        if self.width <= 0
+            || self.height <= 0
+            || self.x_coordinate.checked_add(self.width).is_none()
+            || self.y_coordinate.checked_add(self.height).is_none()
+        {
+            return Err(Error::InvalidRegion(*self));
+        }
+        if i64::from(self.width) * i64::from(self.height) > MAX_CAPTURE_PIXELS {
+            return Err(Error::RegionTooLarge(*self));
+        }
+        Ok(())
+    }
+
+    /// The smallest region covering both, `None` when its edges or size do not fit in `i32`.
+    fn union(&self, other: &Self) -> Option<Self> {
+        let x1 = self.x_coordinate.min(other.x_coordinate);
+        let y1 = self.y_coordinate.min(other.y_coordinate);
+        let x2 = (self.x_coordinate.checked_add(self.width)?)
+            .max(other.x_coordinate.checked_add(other.width)?);
+        let y2 = (self.y_coordinate.checked_add(self.height)?)
+            .max(other.y_coordinate.checked_add(other.height)?);
+        Some(Self {
+            x_coordinate: x1,
+            y_coordinate: y1,
+            width: x2.checked_sub(x1)?,
+            height: y2.checked_sub(y1)?,
+        })
+    }
+
+    fn intersects(&self, dimensions: &OutputPositioning) -> bool {
+        i64::from(self.x_coordinate) < i64::from(dimensions.x) + i64::from(dimensions.width)
+            && i64::from(dimensions.x) < i64::from(self.x_coordinate) + i64::from(self.width)
+            && i64::from(self.y_coordinate) < i64::from(dimensions.y) + i64::from(dimensions.height)
+            && i64::from(dimensions.y) < i64::from(self.y_coordinate) + i64::from(self.height)
+    }
+}
+
+/// A region given as fractions of a reference area, such as an output or the whole desktop, so it
+/// covers the same part of the screen whatever the resolution.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RelativeRegion {
+    /// Offset from the left of the reference area, `0.5` is halfway.
+    pub x: f64,
+    /// Offset from the top of the reference area.
+    pub y: f64,
+    /// Width as a fraction of the reference area's width.
+    pub width: f64,
+    /// Height as a fraction of the reference area's height.
+    pub height: f64,
+}
+
+impl RelativeRegion {
+    /// Convert to logical pixels inside `area`, see [`OutputInfo::region`] and
+    /// [`WayshotConnection::desktop_region`].
+    ///
+    /// Fails with [`Error::InvalidRegion`] when the offset region does not fit in `i32`.
+    pub fn resolve(&self, area: CaptureRegion) -> Result<CaptureRegion> {
+        let scale = |fraction: f64, length: i32| (fraction * f64::from(length)).round() as i32;
+        let offset = |start: i32, fraction: f64, length: i32| {
+            start
+                .checked_add(scale(fraction, length))
+                .ok_or(Error::InvalidRegion(area))
+        };
+        Ok(CaptureRegion {
+            x_coordinate: offset(area.x_coordinate, self.x, area.width)?,
+            y_coordinate: offset(area.y_coordinate, self.y, area.height)?,
+            width: scale(self.width, area.width),
+            height: scale(self.height, area.height),
+        })
+    }
+}
+
+/// What to capture.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CaptureSource {
+    /// Every output, composed together.
+    All,
+    /// A single output, by connector name or [`output::OutputId`].
+    Output(String),
+    /// A region of the global compositor space.
+    Region(CaptureRegion),
+}
+
+/// Which frame a capture copies.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum FrameTiming {
+    /// The frame the compositor drew last, right away.
+    #[default]
+    LastDrawn,
+    /// The next frame the compositor draws, waiting for the output to change. Captures an
+    /// animation at a frame boundary, but blocks for as long as the output is idle.
+    NextRedraw,
+}
+
+#[cfg(feature = "image")]
 #[derive(Debug)]
 struct IntersectingOutput {
     output: WlOutput,
     region: CaptureRegion,
     transform: Transform,
+    scale: f64,
 }
 
 /// Struct to store wayland connection and globals list.
 /// # Example usage
 ///
-/// ```
+/// ```no_run
+/// use libwayshot::WayshotConnection;
+///
 /// let wayshot_connection = WayshotConnection::new().unwrap();
-/// let image_buffer = wayshot_connection.screenshot_all().unwrap();
+/// let image_buffer = wayshot_connection.screenshot_all(false).unwrap();
 /// ```
 #[derive(Debug)]
 pub struct WayshotConnection {
     pub conn: Connection,
     pub globals: GlobalList,
     output_infos: Vec<OutputInfo>,
+    retry_policy: RetryPolicy,
+    capture_timeout: Option<Duration>,
+    frame_timing: FrameTiming,
+    buffer_pool: Arc<BufferPool>,
+    interrupt: Option<Arc<AtomicBool>>,
 }
 
 impl WayshotConnection {
     pub fn new() -> Result<Self> {
-        let conn = Connection::connect_to_env()?;
+        Self::builder().build()
+    }
+
+    /// Configure the connection before creating it, see [`WayshotConnectionBuilder`].
+    pub fn builder() -> WayshotConnectionBuilder {
+        WayshotConnectionBuilder::default()
+    }
 
-        Self::from_connection(conn)
+    /// Connect to the compositor listening on the given Wayland socket instead of the one named by
+    /// `WAYLAND_DISPLAY`. Relative names such as `wayland-1` are looked up in
+    /// `XDG_RUNTIME_DIR`, absolute paths are used as is.
+    pub fn from_display(display: &str) -> Result<Self> {
+        Self::builder().display(display).build()
     }
 
     /// Recommended if you already have a [`wayland_client::Connection`].
     pub fn from_connection(conn: Connection) -> Result<Self> {
-        let (globals, _) = registry_queue_init::<WayshotState>(&conn)?;
+        Self::builder().connection(conn).build()
+    }
 
-        let mut initial_state = Self {
-            conn,
-            globals,
-            output_infos: Vec::new(),
-        };
+    /// Buffers kept for reuse by the next captures, see [`BufferPool`].
+    pub fn buffer_pool(&self) -> &BufferPool {
+        &self.buffer_pool
+    }
+
+    /// Change how failed frame copies are retried, see [`RetryPolicy`].
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) {
+        self.retry_policy = retry_policy;
+    }
 
-        initial_state.refresh_outputs()?;
+    /// Give up with [`Error::Timeout`] when the compositor takes longer than `capture_timeout` to
+    /// answer a step of a capture, `None` waits forever.
+    pub fn set_capture_timeout(&mut self, capture_timeout: Option<Duration>) {
+        self.capture_timeout = capture_timeout;
+    }
+
+    /// Give up waiting on the user with [`Error::Interrupted`] once `interrupt` is set, eg: from a
+    /// SIGINT handler. Checked by [`Self::select_region`] and [`Self::pick_output`], whose
+    /// overlays are removed before they return.
+    pub fn set_interrupt(&mut self, interrupt: Option<Arc<AtomicBool>>) {
+        self.interrupt = interrupt;
+    }
+
+    /// Whether the flag of [`Self::set_interrupt`] was set.
+    #[cfg(feature = "selector")]
+    fn interrupted(&self) -> bool {
+        self.interrupt
+            .as_ref()
+            .is_some_and(|interrupt| interrupt.load(std::sync::atomic::Ordering::SeqCst))
+    }
+
+    /// Choose which frame captures copy, see [`FrameTiming`]. Combine [`FrameTiming::NextRedraw`]
+    /// with a capture timeout to not wait forever on an idle output.
+    pub fn set_frame_timing(&mut self, frame_timing: FrameTiming) {
+        self.frame_timing = frame_timing;
+    }
+
+    /// Ask the compositor to copy `frame` into `buffer` according to the frame timing.
+    fn copy_frame(&self, frame: &ZwlrScreencopyFrameV1, buffer: &WlBuffer) {
+        match self.frame_timing {
+            FrameTiming::LastDrawn => frame.copy(buffer),
+            FrameTiming::NextRedraw => frame.copy_with_damage(buffer),
+        }
+    }
+
+    /// Dispatch the events of a frame until `done`, within the capture timeout if there is one.
+    fn dispatch_frame<S>(
+        &self,
+        event_queue: &mut EventQueue<S>,
+        state: &mut S,
+        done: impl Fn(&S) -> bool,
+    ) -> Result<()> {
+        match self.capture_timeout {
+            Some(capture_timeout) => Ok(dispatch_until_deadline(
+                event_queue,
+                state,
+                done,
+                Instant::now() + capture_timeout,
+            )?),
+            None => Ok(dispatch_until(event_queue, state, done)?),
+        }
+    }
+
+    /// Bounding box of all outputs in logical pixels, the area [`Self::screenshot_all`] captures.
+    pub fn desktop_region(&self) -> Result<CaptureRegion> {
+        outputs_region(&self.output_infos)
+    }
 
-        Ok(initial_state)
+    /// Color description shared by every output `region` covers, to tag an image captured from it
+    /// with. `None` when an output has none or they differ, as one image carries one description.
+    pub fn color_description(&self, region: CaptureRegion) -> Option<&ColorDescription> {
+        let mut outputs = self
+            .output_infos
+            .iter()
+            .filter(|output| region.intersects(&output.dimensions));
+        let color = outputs.next()?.color.as_ref()?;
+        outputs
+            .all(|output| output.color.as_ref() == Some(color))
+            .then_some(color)
     }
 
     /// Fetch all accessible wayland outputs.
@@ -114,7 +415,20 @@ impl WayshotConnection {
         &self.output_infos
     }
 
+    /// Find an output either by its connector name (eg: "DP-1") or by its stable
+    /// [`output::OutputId`].
+    pub fn find_output(&self, name_or_id: &str) -> Option<&OutputInfo> {
+        self.select_output(&OutputSelector::Name(name_or_id.to_string()))
+    }
+
+    /// Pick a single output using the given [`OutputSelector`] strategy.
+    pub fn select_output(&self, selector: &OutputSelector) -> Option<&OutputInfo> {
+        selector.select(&self.output_infos)
+    }
+
     /// refresh the outputs, to get new outputs
+    ///
+    /// To keep them up to date as monitors are plugged and unplugged, see [`Self::watch_outputs`].
     pub fn refresh_outputs(&mut self) -> Result<()> {
         // Connecting to wayland environment.
         let mut state = OutputCaptureState {
@@ -132,7 +446,11 @@ impl WayshotConnection {
             Ok(x) => x,
             Err(e) => {
                 tracing::error!("Failed to create ZxdgOutputManagerV1 version 3. Does your compositor implement ZxdgOutputManagerV1?");
-                panic!("{:#?}", e);
+                tracing::error!("err: {e}");
+                return Err(Error::Protocol {
+                    protocol: ZxdgOutputManagerV1::interface().name,
+                    version: 3,
+                });
             }
         };
 
@@ -141,32 +459,168 @@ impl WayshotConnection {
         event_queue.roundtrip(&mut state)?;
         event_queue.roundtrip(&mut state)?;
 
-        // We loop over each output and request its position data.
-        let xdg_outputs: Vec<ZxdgOutputV1> = state
-            .outputs
-            .iter()
-            .enumerate()
-            .map(|(index, output)| {
-                zxdg_output_manager.get_xdg_output(&output.wl_output, &qh, index)
-            })
-            .collect();
+        // We loop over each output and request its position data. Compositors may briefly
+        // report a 0x0 logical size while switching modes, so ask again until all have one.
+        for attempt in 1..=OUTPUT_READY_ATTEMPTS {
+            let xdg_outputs: Vec<ZxdgOutputV1> = state
+                .outputs
+                .iter()
+                .enumerate()
+                .map(|(index, output)| {
+                    zxdg_output_manager.get_xdg_output(&output.wl_output, &qh, index)
+                })
+                .collect();
 
-        event_queue.roundtrip(&mut state)?;
+            event_queue.roundtrip(&mut state)?;
+
+            for xdg_output in xdg_outputs {
+                xdg_output.destroy();
+            }
 
-        for xdg_output in xdg_outputs {
-            xdg_output.destroy();
+            let not_ready: Vec<&str> = state
+                .outputs
+                .iter()
+                .filter(|output| output.dimensions.width <= 0 || output.dimensions.height <= 0)
+                .map(|output| output.name.as_str())
+                .collect();
+            if not_ready.is_empty() {
+                break;
+            }
+            if attempt == OUTPUT_READY_ATTEMPTS {
+                tracing::warn!(
+                    "Outputs without a logical size, capturing them fails: {not_ready:?}"
+                );
+            } else {
+                tracing::debug!("Outputs without a logical size yet: {not_ready:?}");
+                thread::sleep(OUTPUT_READY_DELAY);
+            }
+        }
+
+        // Optional, outputs are taken as sRGB without it.
+        if let Ok(color_manager) = self.globals.bind::<WpColorManagerV1, _, _>(&qh, 1..=1, ()) {
+            let image_descriptions: Vec<_> = state
+                .outputs
+                .iter()
+                .enumerate()
+                .map(|(index, output)| {
+                    let color_output = color_manager.get_output(&output.wl_output, &qh, ());
+                    let image_description = color_output.get_image_description(&qh, index);
+                    (color_output, image_description)
+                })
+                .collect();
+            // Descriptions become ready first, their information is requested from there.
+            event_queue.roundtrip(&mut state)?;
+            event_queue.roundtrip(&mut state)?;
+
+            for (color_output, image_description) in image_descriptions {
+                image_description.destroy();
+                color_output.destroy();
+            }
+            color_manager.destroy();
         }
 
         if state.outputs.is_empty() {
             tracing::error!("Compositor did not advertise any wl_output devices!");
-            exit(1);
+            return Err(Error::NoOutputs);
         }
         tracing::debug!("Outputs detected: {:#?}", state.outputs);
-        self.output_infos = state.outputs;
+        self.output_infos = state.outputs.into_iter().map(OutputInfo::from).collect();
+        // Modes may have changed and outputs gone, along with the buffers sized for them.
+        self.buffer_pool.clear();
 
         Ok(())
     }
 
+    /// Capture `output_info` and write its pixels into `dest`, converted and turned upright when
+    /// y-inverted like [`Self::screenshot_single_output`], but not rotated by the output
+    /// transform.
+    ///
+    /// This skips the intermediate [`DynamicImage`], for consumers that manage their own ring
+    /// buffers. The pixels are laid out as the returned [`ColorType`] describes, [`ColorType::Rgba16`]
+    /// channels in native endianness. `dest` must hold `height` rows of `layout`'s stride, and be
+    /// aligned to `u16` for [`ColorType::Rgba16`]. The returned [`FrameFormat`] is the one the
+    /// compositor picked, with the stride of `dest`.
+    ///
+    /// ```no_run
+    /// use libwayshot::{SliceLayout, WayshotConnection};
+    ///
+    /// let wayshot_connection = WayshotConnection::new().unwrap();
+    /// let output = &wayshot_connection.get_all_outputs()[0];
+    /// // Large enough for 16-bit RGBA pixels.
+    /// let frame_bytes = 8 * output.mode.width as usize * output.mode.height as usize;
+    /// let mut ring = vec![vec![0u8; frame_bytes]; 3];
+    /// for frame in 0..30 {
+    ///     let (frame_format, color_type) = wayshot_connection
+    ///         .capture_output_frame_into(output, false, &mut ring[frame % 3], SliceLayout::Packed)
+    ///         .unwrap();
+    ///     println!("{}x{} {color_type:?}", frame_format.width, frame_format.height);
+    /// }
+    /// ```
+    #[cfg(feature = "image")]
+    pub fn capture_output_frame_into(
+        &self,
+        output_info: &OutputInfo,
+        cursor_overlay: bool,
+        dest: &mut [u8],
+        layout: SliceLayout,
+    ) -> Result<(FrameFormat, ColorType)> {
+        output_info.check_ready()?;
+        // The lease is kept until the pixels are copied, the buffer is reused after that.
+        let FrameCopy {
+            frame_format,
+            frame_color_type,
+            frame_mmap,
+            metadata,
+            _lease,
+            ..
+        } = self.capture_output_frame(
+            cursor_overlay,
+            &output_info.wl_output,
+            output_info.transform,
+            None,
+        )?;
+        let row_bytes = frame_format.width as usize * frame_color_type.bytes_per_pixel() as usize;
+        let stride = layout.stride(&frame_format, row_bytes)?;
+        let height = frame_format.height as usize;
+        // The last row does not need to carry padding.
+        if height > 0 && dest.len() < stride * (height - 1) + row_bytes {
+            return Err(Error::BufferTooSmall);
+        }
+        if frame_color_type == ColorType::Rgba16
+            && dest.as_ptr().align_offset(mem::align_of::<u16>()) != 0
+        {
+            return Err(Error::MisalignedBuffer);
+        }
+
+        let view = StridedView::new(&frame_mmap[..], &frame_format)?;
+        let mut rows: Vec<_> = view.rows().collect();
+        if metadata.y_invert {
+            rows.reverse();
+        }
+        for (dest_row, row) in dest.chunks_mut(stride).zip(rows) {
+            let dest_row = &mut dest_row[..row_bytes];
+            if frame_color_type == ColorType::Rgba16 {
+                for (dest_pixel, pixel) in dest_row.chunks_exact_mut(8).zip(row.chunks_exact(4)) {
+                    for (dest_channel, channel) in dest_pixel
+                        .chunks_exact_mut(2)
+                        .zip(expand_bgr10_pixel(pixel))
+                    {
+                        dest_channel.copy_from_slice(&channel.to_ne_bytes());
+                    }
+                }
+            } else {
+                dest_row.copy_from_slice(row);
+            }
+        }
+        Ok((
+            FrameFormat {
+                stride: stride as u32,
+                ..frame_format
+            },
+            frame_color_type,
+        ))
+    }
+
     /// Get a FrameCopy instance with screenshot pixel data for any wl_output object.
     ///  Data will be written to fd.
     pub fn capture_output_frame_shm_fd<T: AsFd>(
@@ -176,12 +630,24 @@ impl WayshotConnection {
         fd: T,
         capture_region: Option<CaptureRegion>,
     ) -> Result<FrameFormat> {
-        let (state, event_queue, frame, frame_format) =
-            self.capture_output_frame_get_state(cursor_overlay, output, capture_region)?;
-        self.capture_output_frame_inner(state, event_queue, frame, frame_format, fd)
+        if let Some(capture_region) = capture_region {
+            capture_region.validate()?;
+        }
+        let fd = fd.as_fd();
+        let (frame_format, _) = self.retry_policy.run(|| {
+            let (mut state, mut event_queue, frame) =
+                self.request_output_frame(cursor_overlay, output, capture_region)?;
+            self.dispatch_frame(&mut event_queue, &mut state, |state| {
+                state.phase.is_buffer_done()
+            })?;
+            let frame_format = select_frame_format(&state)?;
+            self.copy_output_frame(state, event_queue, frame, frame_format, fd)
+        })?;
+        Ok(frame_format)
     }
 
-    fn capture_output_frame_get_state(
+    /// Ask the compositor for a frame of `output`, the returned state collects its buffer formats.
+    fn request_output_frame(
         &self,
         cursor_overlay: i32,
         output: &WlOutput,
@@ -190,88 +656,147 @@ impl WayshotConnection {
         CaptureFrameState,
         EventQueue<CaptureFrameState>,
         ZwlrScreencopyFrameV1,
-        FrameFormat,
     )> {
-        let mut state = CaptureFrameState {
-            formats: Vec::new(),
-            state: None,
-            buffer_done: AtomicBool::new(false),
-        };
-        let mut event_queue = self.conn.new_event_queue::<CaptureFrameState>();
+        let state = CaptureFrameState::default();
+        let event_queue = self.conn.new_event_queue::<CaptureFrameState>();
         let qh = event_queue.handle();
+        let screencopy_manager = self.bind_screencopy_manager(&qh)?;
 
-        // Instantiating screencopy manager.
-        let screencopy_manager = match self.globals.bind::<ZwlrScreencopyManagerV1, _, _>(
+        let frame = request_frame(
+            &screencopy_manager,
             &qh,
-            3..=3,
+            cursor_overlay,
+            output,
+            capture_region,
             (),
-        ) {
-            Ok(x) => x,
-            Err(e) => {
-                tracing::error!("Failed to create screencopy manager. Does your compositor implement ZwlrScreencopy?");
-                tracing::error!("err: {e}");
-                return Err(Error::ProtocolNotFound(
-                    "ZwlrScreencopy Manager not found".to_string(),
-                ));
-            }
-        };
+        );
+        Ok((state, event_queue, frame))
+    }
 
-        // Capture output.
-        let frame: ZwlrScreencopyFrameV1 = if let Some(region) = capture_region {
-            screencopy_manager.capture_output_region(
-                cursor_overlay,
-                output,
-                region.x_coordinate,
-                region.y_coordinate,
-                region.width,
-                region.height,
-                &qh,
-                (),
-            )
-        } else {
-            screencopy_manager.capture_output(cursor_overlay, output, &qh, ())
+    /// Ask the compositor for a frame of every output in `outputs` on one event queue, the state
+    /// collects the buffer formats of frame `i` at index `i`.
+    #[cfg(feature = "image")]
+    fn request_output_frames(
+        &self,
+        cursor_overlay: bool,
+        outputs: &[(WlOutput, Option<CaptureRegion>)],
+    ) -> Result<(
+        CaptureFramesState,
+        EventQueue<CaptureFramesState>,
+        Vec<ZwlrScreencopyFrameV1>,
+    )> {
+        let state = CaptureFramesState {
+            frames: outputs
+                .iter()
+                .map(|_| CaptureFrameState::default())
+                .collect(),
         };
+        let event_queue = self.conn.new_event_queue::<CaptureFramesState>();
+        let qh = event_queue.handle();
+        let screencopy_manager = self.bind_screencopy_manager(&qh)?;
+        let frames = outputs
+            .iter()
+            .enumerate()
+            .map(|(index, (output, capture_region))| {
+                request_frame(
+                    &screencopy_manager,
+                    &qh,
+                    cursor_overlay as i32,
+                    output,
+                    *capture_region,
+                    index,
+                )
+            })
+            .collect();
+        Ok((state, event_queue, frames))
+    }
 
-        // Empty internal event buffer until buffer_done is set to true which is when the Buffer done
-        // event is fired, aka the capture from the compositor is succesful.
-        while !state.buffer_done.load(Ordering::SeqCst) {
-            event_queue.blocking_dispatch(&mut state)?;
+    /// Copy a frame of every output in `outputs` into buffers of [`Self::buffer_pool`].
+    ///
+    /// All copies are requested before waiting for any of them, so they complete in about the
+    /// time of one frame rather than one frame per output.
+    #[cfg(feature = "image")]
+    fn capture_output_frames_pooled(
+        &self,
+        cursor_overlay: bool,
+        outputs: &[(WlOutput, Option<CaptureRegion>)],
+    ) -> Result<Vec<(FrameFormat, FrameMetadata, ShmBuffer)>> {
+        let (mut state, mut event_queue, frames) =
+            self.request_output_frames(cursor_overlay, outputs)?;
+        let copied = self
+            .dispatch_frame(&mut event_queue, &mut state, all_buffers_done)
+            .and_then(|()| self.copy_output_frames(&event_queue, &state, &frames, outputs))
+            .and_then(|shm_buffers| {
+                self.dispatch_frame(&mut event_queue, &mut state, all_copied)?;
+                check_frame_copies(&state, shm_buffers)
+            });
+        for frame in frames {
+            frame.destroy();
         }
+        copied
+    }
 
-        tracing::debug!(
-            "Received compositor frame buffer formats: {:#?}",
-            state.formats
-        );
-        // Filter advertised wl_shm formats and select the first one that matches.
-        let frame_format = state
-            .formats
+    /// Ask the compositor to copy each of `frames` into a buffer of the format it selected.
+    #[cfg(feature = "image")]
+    fn copy_output_frames(
+        &self,
+        event_queue: &EventQueue<CaptureFramesState>,
+        state: &CaptureFramesState,
+        frames: &[ZwlrScreencopyFrameV1],
+        outputs: &[(WlOutput, Option<CaptureRegion>)],
+    ) -> Result<Vec<(FrameFormat, ShmBuffer)>> {
+        // Nothing is copied when one of the frames already failed, the capture is retried as a
+        // whole.
+        if let Some(failed) = state
+            .frames
             .iter()
-            .find(|frame| {
-                matches!(
-                    frame.format,
-                    wl_shm::Format::Xbgr2101010
-                        | wl_shm::Format::Abgr2101010
-                        | wl_shm::Format::Argb8888
-                        | wl_shm::Format::Xrgb8888
-                        | wl_shm::Format::Xbgr8888
-                        | wl_shm::Format::Bgr888
-                )
+            .find(|frame_state| frame_state.phase.copied() == Some(FrameState::Failed))
+        {
+            return Err(copy_failure(failed));
+        }
+        frames
+            .iter()
+            .zip(&state.frames)
+            .zip(outputs)
+            .map(|((frame, frame_state), (output, _))| {
+                let frame_format = select_frame_format(frame_state)?;
+                let shm_buffer = self.pooled_buffer(&event_queue.handle(), output, frame_format)?;
+                self.copy_frame(frame, &shm_buffer.buffer);
+                Ok((frame_format, shm_buffer))
             })
-            .copied();
-        tracing::debug!("Selected frame buffer format: {:#?}", frame_format);
-
-        // Check if frame format exists.
-        let frame_format = match frame_format {
-            Some(format) => format,
-            None => {
-                tracing::error!("No suitable frame format found");
-                return Err(Error::NoSupportedBufferFormat);
+            .collect()
+    }
+
+    /// Whether the compositor offers a global of `interface`.
+    #[cfg(feature = "image")]
+    fn advertises(&self, interface: &str) -> bool {
+        self.globals
+            .contents()
+            .with_list(|globals| globals.iter().any(|global| global.interface == interface))
+    }
+
+    fn bind_screencopy_manager<S>(&self, qh: &QueueHandle<S>) -> Result<ZwlrScreencopyManagerV1>
+    where
+        S: Dispatch<ZwlrScreencopyManagerV1, ()> + 'static,
+    {
+        match self
+            .globals
+            .bind::<ZwlrScreencopyManagerV1, _, _>(qh, screencopy_versions(), ())
+        {
+            Ok(x) => Ok(x),
+            Err(e) => {
+                tracing::error!("Failed to create screencopy manager. Does your compositor implement ZwlrScreencopy?");
+                tracing::error!("err: {e}");
+                Err(Error::Protocol {
+                    protocol: ZwlrScreencopyManagerV1::interface().name,
+                    version: MIN_SCREENCOPY_VERSION,
+                })
             }
-        };
-        Ok((state, event_queue, frame, frame_format))
+        }
     }
 
-    fn capture_output_frame_inner<T: AsFd>(
+    /// Copy the frame into `fd` and wait for the compositor to finish writing it.
+    fn copy_output_frame<T: AsFd>(
         &self,
         mut state: CaptureFrameState,
         mut event_queue: EventQueue<CaptureFrameState>,
@@ -279,66 +804,143 @@ impl WayshotConnection {
         frame_format: FrameFormat,
         fd: T,
     ) -> Result<FrameFormat> {
-        // Connecting to wayland environment.
-        let qh = event_queue.handle();
-
-        // Bytes of data in the frame = stride * height.
-        let frame_bytes = frame_format.stride * frame_format.height;
+        let (buffer, shm_pool) =
+            self.attach_frame_buffer(&event_queue, &frame, &frame_format, fd)?;
+        // On copy the Ready / Failed events are fired by the frame object, so wait for either.
+        self.dispatch_frame(&mut event_queue, &mut state, |state| {
+            state.phase.copied().is_some()
+        })?;
+        finish_frame_copy(&state, frame, buffer, shm_pool, frame_format)
+    }
 
-        // Instantiate shm global.
-        let shm = self.globals.bind::<WlShm, _, _>(&qh, 1..=1, ()).unwrap();
-        let shm_pool = shm.create_pool(fd.as_fd(), frame_bytes as i32, &qh, ());
-        let buffer = shm_pool.create_buffer(
-            0,
-            frame_format.width as i32,
-            frame_format.height as i32,
-            frame_format.stride as i32,
-            frame_format.format,
-            &qh,
-            (),
-        );
+    /// Create a buffer backed by `fd` and ask the compositor to copy the frame into it.
+    fn attach_frame_buffer<T: AsFd>(
+        &self,
+        event_queue: &EventQueue<CaptureFrameState>,
+        frame: &ZwlrScreencopyFrameV1,
+        frame_format: &FrameFormat,
+        fd: T,
+    ) -> Result<(WlBuffer, WlShmPool)> {
+        let (buffer, shm_pool) =
+            self.create_frame_buffer(&event_queue.handle(), frame_format, fd)?;
 
         // Copy the pixel data advertised by the compositor into the buffer we just created.
-        frame.copy(&buffer);
-        // On copy the Ready / Failed events are fired by the frame object, so here we check for them.
-        loop {
-            // Basically reads, if frame state is not None then...
-            if let Some(state) = state.state {
-                match state {
-                    FrameState::Failed => {
-                        tracing::error!("Frame copy failed");
-                        return Err(Error::FramecopyFailed);
-                    }
-                    FrameState::Finished => {
-                        buffer.destroy();
-                        shm_pool.destroy();
-                        return Ok(frame_format);
-                    }
-                }
-            }
+        self.copy_frame(frame, &buffer);
+        Ok((buffer, shm_pool))
+    }
 
-            event_queue.blocking_dispatch(&mut state)?;
-        }
+    /// Create a `frame_format` sized buffer backed by `fd`.
+    fn create_frame_buffer<S: ShmDispatch, T: AsFd>(
+        &self,
+        qh: &QueueHandle<S>,
+        frame_format: &FrameFormat,
+        fd: T,
+    ) -> Result<(WlBuffer, WlShmPool)> {
+        let frame_bytes = shm_pool_size(frame_format)?;
+        let stride = i32::try_from(frame_format.stride).map_err(|_| Error::InvalidStride)?;
+        let height = i32::try_from(frame_format.height).map_err(|_| Error::BufferTooSmall)?;
+        let width = i32::try_from(frame_format.width).map_err(|_| Error::BufferTooSmall)?;
+
+        // Instantiate shm global.
+        let shm = self.globals.bind::<WlShm, _, _>(qh, 1..=1, ())?;
+        let shm_pool = shm.create_pool(fd.as_fd(), frame_bytes, qh, ());
+        let buffer = shm_pool.create_buffer(0, width, height, stride, frame_format.format, qh, ());
+        Ok((buffer, shm_pool))
     }
 
-    fn capture_output_frame_shm_from_file(
+    /// Copy a frame of `output` into a buffer of [`Self::buffer_pool`], or into a new one when
+    /// none fits.
+    fn capture_output_frame_pooled(
         &self,
         cursor_overlay: bool,
         output: &WlOutput,
-        file: &File,
         capture_region: Option<CaptureRegion>,
-    ) -> Result<FrameFormat> {
-        let (state, event_queue, frame, frame_format) =
-            self.capture_output_frame_get_state(cursor_overlay as i32, output, capture_region)?;
+    ) -> Result<(FrameFormat, FrameMetadata, ShmBuffer)> {
+        let (mut state, mut event_queue, frame) =
+            self.request_output_frame(cursor_overlay as i32, output, capture_region)?;
+        self.dispatch_frame(&mut event_queue, &mut state, |state| {
+            state.phase.is_buffer_done()
+        })?;
+        let frame_format = select_frame_format(&state)?;
 
-        // Bytes of data in the frame = stride * height.
-        let frame_bytes = frame_format.stride * frame_format.height;
+        let shm_buffer = self.pooled_buffer(&event_queue.handle(), output, frame_format)?;
+        self.copy_frame(&frame, &shm_buffer.buffer);
+        self.dispatch_frame(&mut event_queue, &mut state, |state| {
+            state.phase.copied().is_some()
+        })?;
+        frame.destroy();
+        let metadata = check_frame_copy(&state)?;
+        Ok((frame_format, metadata, shm_buffer))
+    }
+
+    /// A buffer for a `frame_format` frame of `output`, taken from [`Self::buffer_pool`] when it
+    /// holds one.
+    fn pooled_buffer<S: ShmDispatch>(
+        &self,
+        qh: &QueueHandle<S>,
+        output: &WlOutput,
+        frame_format: FrameFormat,
+    ) -> Result<ShmBuffer> {
+        if let Some(shm_buffer) = self.buffer_pool.take(output, &frame_format) {
+            return Ok(shm_buffer);
+        }
+        let frame_bytes = shm_pool_size(&frame_format)?;
+        let file = File::from(create_shm_fd()?);
         file.set_len(frame_bytes as u64)?;
+        let (buffer, shm_pool) = self.create_frame_buffer(qh, &frame_format, &file)?;
+        Ok(ShmBuffer {
+            output: output.clone(),
+            frame_format,
+            file,
+            buffer,
+            shm_pool,
+        })
+    }
 
-        self.capture_output_frame_inner(state, event_queue, frame, frame_format, file)
+    /// Map the frame copied into `shm_buffer`, which goes back to the pool once the frame is
+    /// dropped.
+    fn raw_frame_from_buffer(
+        &self,
+        frame_format: FrameFormat,
+        metadata: FrameMetadata,
+        shm_buffer: ShmBuffer,
+        transform: Transform,
+    ) -> Result<RawFrame> {
+        Ok(RawFrame {
+            frame_format,
+            frame_mmap: screencopy::map_shm(&shm_buffer.file)?,
+            transform,
+            metadata,
+            _lease: Some(BufferLease::new(
+                shm_buffer,
+                Arc::downgrade(&self.buffer_pool),
+            )),
+        })
+    }
+
+    /// Capture `output_info` without converting its pixels.
+    ///
+    /// `capture_region` is in the logical coordinates of the output, `None` captures all of it.
+    pub fn capture_raw_frame(
+        &self,
+        output_info: &OutputInfo,
+        cursor_overlay: bool,
+        capture_region: Option<CaptureRegion>,
+    ) -> Result<RawFrame> {
+        output_info.check_ready()?;
+        if let Some(capture_region) = capture_region {
+            capture_region.validate()?;
+        }
+        self.capture_output_raw_frame(
+            cursor_overlay,
+            &output_info.wl_output,
+            output_info.transform,
+            capture_region,
+        )
     }
 
     /// Get a FrameCopy instance with screenshot pixel data for any wl_output object.
+    #[cfg(feature = "image")]
     fn capture_output_frame(
         &self,
         cursor_overlay: bool,
@@ -346,161 +948,182 @@ impl WayshotConnection {
         transform: Transform,
         capture_region: Option<CaptureRegion>,
     ) -> Result<FrameCopy> {
-        // Create an in memory file and return it's file descriptor.
-        let fd = create_shm_fd()?;
-        // Create a writeable memory map backed by a mem_file.
-        let mem_file = File::from(fd);
-
-        let frame_format = self.capture_output_frame_shm_from_file(
+        convert_frame(self.capture_output_raw_frame(
             cursor_overlay,
             output,
-            &mem_file,
+            transform,
             capture_region,
-        )?;
+        )?)
+    }
 
-        let mut frame_mmap = unsafe { MmapMut::map_mut(&mem_file)? };
-        let data = &mut *frame_mmap;
-        let frame_color_type = if let Some(converter) = create_converter(frame_format.format) {
-            converter.convert_inplace(data)
-        } else {
-            tracing::error!("Unsupported buffer format: {:?}", frame_format.format);
-            tracing::error!("You can send a feature request for the above format to the mailing list for wayshot over at https://sr.ht/~shinyzenith/wayshot.");
-            return Err(Error::NoSupportedBufferFormat);
-        };
-        Ok(FrameCopy {
-            frame_format,
-            frame_color_type,
-            frame_mmap,
-            transform,
-        })
+    fn capture_output_raw_frame(
+        &self,
+        cursor_overlay: bool,
+        output: &WlOutput,
+        transform: Transform,
+        capture_region: Option<CaptureRegion>,
+    ) -> Result<RawFrame> {
+        let ((frame_format, mut metadata, shm_buffer), attempts) = self
+            .retry_policy
+            .run(|| self.capture_output_frame_pooled(cursor_overlay, output, capture_region))?;
+        metadata.attempts = attempts;
+        self.raw_frame_from_buffer(frame_format, metadata, shm_buffer, transform)
     }
 
+    /// Outputs overlapping `capture_region`, with the region translated into their coordinates.
+    #[cfg(feature = "image")]
+    fn intersecting_outputs(&self, capture_region: CaptureRegion) -> Vec<IntersectingOutput> {
+        self.get_all_outputs()
+            .iter()
+            .filter_map(|output| {
+                let true_region = region_on_output(capture_region, &output.dimensions)?;
+                Some(IntersectingOutput {
+                    output: output.wl_output.clone(),
+                    region: true_region,
+                    transform: output.transform,
+                    scale: output.scale(),
+                })
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "image")]
     fn create_frame_copy(
         &self,
         capture_region: CaptureRegion,
         cursor_overlay: bool,
     ) -> Result<Frame> {
+        let intersecting_outputs = self.intersecting_outputs(capture_region);
+        let size = composed_size(capture_region, &intersecting_outputs);
+        let requests: Vec<_> = intersecting_outputs
+            .iter()
+            .map(|intersecting_output| {
+                (
+                    intersecting_output.output.clone(),
+                    Some(intersecting_output.region),
+                )
+            })
+            .collect();
+        let (shm_buffers, attempts) = self
+            .retry_policy
+            .run(|| self.capture_output_frames_pooled(cursor_overlay, &requests))?;
+
         let frame_copies = thread::scope(|scope| -> Result<_> {
-            let join_handles = self
-                .get_all_outputs()
+            let join_handles = shm_buffers
                 .into_iter()
-                .filter_map(|output| {
-                    let x1: i32 = cmp::max(output.dimensions.x, capture_region.x_coordinate);
-                    let y1: i32 = cmp::max(output.dimensions.y, capture_region.y_coordinate);
-                    let x2: i32 = cmp::min(
-                        output.dimensions.x + output.dimensions.width,
-                        capture_region.x_coordinate + capture_region.width,
-                    );
-                    let y2: i32 = cmp::min(
-                        output.dimensions.y + output.dimensions.height,
-                        capture_region.y_coordinate + capture_region.height,
-                    );
-
-                    let width = x2 - x1;
-                    let height = y2 - y1;
-
-                    if width <= 0 || height <= 0 {
-                        return None;
-                    }
-
-                    let true_x = capture_region.x_coordinate - output.dimensions.x;
-                    let true_y = capture_region.y_coordinate - output.dimensions.y;
-                    let true_region = CaptureRegion {
-                        x_coordinate: true_x,
-                        y_coordinate: true_y,
-                        width: capture_region.width,
-                        height: capture_region.height,
-                    };
-                    Some(IntersectingOutput {
-                        output: output.wl_output.clone(),
-                        region: true_region,
-                        transform: output.transform,
-                    })
-                })
-                .map(|intersecting_output| {
-                    scope.spawn(move || {
-                        self.capture_output_frame(
-                            cursor_overlay,
-                            &intersecting_output.output,
-                            intersecting_output.transform,
-                            Some(intersecting_output.region),
-                        )
-                    })
-                })
+                .zip(&intersecting_outputs)
+                .map(
+                    |((frame_format, mut metadata, shm_buffer), intersecting_output)| {
+                        metadata.attempts = attempts;
+                        scope.spawn(move || {
+                            convert_frame(self.raw_frame_from_buffer(
+                                frame_format,
+                                metadata,
+                                shm_buffer,
+                                intersecting_output.transform,
+                            )?)
+                        })
+                    },
+                )
                 .collect::<Vec<_>>();
 
-            join_handles
-                .into_iter()
-                .map(|join_handle| join_handle.join())
-                .flatten()
-                .collect::<Result<_>>()
+            join_handles.into_iter().map(joined).collect::<Result<_>>()
         })?;
 
-        Ok((frame_copies, (capture_region.width, capture_region.height)))
+        Ok((frame_copies, size))
+    }
+
+    /// Reject regions that are invalid or do not overlap any output.
+    #[cfg(feature = "image")]
+    fn check_capture_region(&self, capture_region: CaptureRegion) -> Result<()> {
+        capture_region.validate()?;
+        if !self
+            .output_infos
+            .iter()
+            .any(|output| capture_region.intersects(&output.dimensions))
+        {
+            tracing::error!("Provided capture region doesn't intersect with any outputs!");
+            return Err(Error::RegionOffScreen(capture_region));
+        }
+        Ok(())
     }
 
     /// Take a screenshot from the specified region.
+    ///
+    /// The region is validated before talking to the compositor: empty, negative, absurdly large
+    /// and entirely off-screen regions are rejected.
+    ///
+    /// The image has the highest [`OutputInfo::scale`] of the outputs the region covers, so on
+    /// scaled outputs it is larger than the region in logical pixels.
+    ///
+    /// Compositors without zwlr_screencopy_manager_v1 are captured through
+    /// ext-image-copy-capture instead, see [`Self::screenshot_ext`].
+    #[cfg(feature = "image")]
     pub fn screenshot(
         &self,
         capture_region: CaptureRegion,
         cursor_overlay: bool,
     ) -> Result<DynamicImage> {
+        self.check_capture_region(capture_region)?;
+        if !self.advertises(ZwlrScreencopyManagerV1::interface().name) {
+            tracing::debug!(
+                "No zwlr_screencopy_manager_v1, capturing through ext-image-copy-capture"
+            );
+            return self.screenshot_ext(capture_region, cursor_overlay);
+        }
         let (frame_copies, (width, height)) =
             self.create_frame_copy(capture_region, cursor_overlay)?;
+        compose_frame_copies(frame_copies, width, height)
+    }
 
-        thread::scope(|scope| {
-            let rotate_join_handles = frame_copies
-                .into_iter()
-                .map(|frame_copy| {
-                    scope.spawn(move || {
-                        let transform = frame_copy.transform;
-                        let image = frame_copy.try_into()?;
-                        Ok(image_util::rotate_image_buffer(
-                            image,
-                            transform,
-                            width as u32,
-                            height as u32,
-                        ))
-                    })
-                })
-                .collect::<Vec<_>>();
+    /// Take a screenshot of several regions at once, so they all show the same moment.
+    ///
+    /// The bounding box of the regions is captured once and cropped into one image per region,
+    /// in the order given.
+    #[cfg(feature = "image")]
+    pub fn screenshot_regions(
+        &self,
+        capture_regions: &[CaptureRegion],
+        cursor_overlay: bool,
+    ) -> Result<Vec<DynamicImage>> {
+        for capture_region in capture_regions {
+            capture_region.validate()?;
+        }
+        let Some(first) = capture_regions.first() else {
+            return Ok(Vec::new());
+        };
+        // Regions far apart may each be valid while the area covering them does not fit in i32.
+        let bounds = capture_regions[1..]
+            .iter()
+            .try_fold(*first, |bounds, region| {
+                bounds.union(region).ok_or(Error::RegionTooLarge(*region))
+            })?;
 
-            rotate_join_handles
-                .into_iter()
-                .map(|join_handle| join_handle.join())
-                .flatten()
-                .fold(
-                    None,
-                    |possible_overlayed_image_or_error: Option<Result<_>>, image: Result<_>| {
-                        if let Some(overlayed_image_or_error) = possible_overlayed_image_or_error {
-                            if let Ok(mut overlayed_image) = overlayed_image_or_error {
-                                if let Ok(image) = image {
-                                    overlay(&mut overlayed_image, &image, 0, 0);
-                                    Some(Ok(overlayed_image))
-                                } else {
-                                    Some(image)
-                                }
-                            } else {
-                                Some(image)
-                            }
-                        } else {
-                            Some(image)
-                        }
-                    },
+        let image = self.screenshot(bounds, cursor_overlay)?;
+        // The image is at the scale of the outputs, the regions are in logical pixels.
+        let scale = f64::from(image.width()) / f64::from(bounds.width);
+        let scaled = |length: i64| (length as f64 * scale).round() as u32;
+        Ok(capture_regions
+            .iter()
+            .map(|region| {
+                image.crop_imm(
+                    scaled(i64::from(region.x_coordinate) - i64::from(bounds.x_coordinate)),
+                    scaled(i64::from(region.y_coordinate) - i64::from(bounds.y_coordinate)),
+                    scaled(region.width.into()),
+                    scaled(region.height.into()),
                 )
-                .ok_or_else(|| {
-                    tracing::error!("Provided capture region doesn't intersect with any outputs!");
-                    Error::NoOutputs
-                })?
-        })
+            })
+            .collect())
     }
 
     /// shot one ouput
+    #[cfg(feature = "image")]
     pub fn screenshot_single_output(
         &self,
         output_info: &OutputInfo,
         cursor_overlay: bool,
     ) -> Result<DynamicImage> {
+        output_info.check_ready()?;
         let frame_copy = self.capture_output_frame(
             cursor_overlay,
             &output_info.wl_output,
@@ -511,46 +1134,672 @@ impl WayshotConnection {
     }
 
     /// Take a screenshot from all of the specified outputs.
+    #[cfg(feature = "image")]
     pub fn screenshot_outputs(
         &self,
-        outputs: &Vec<OutputInfo>,
+        outputs: &[OutputInfo],
         cursor_overlay: bool,
     ) -> Result<DynamicImage> {
-        if outputs.is_empty() {
-            return Err(Error::NoOutputs);
-        }
-
-        let x1 = outputs
-            .iter()
-            .map(|output| output.dimensions.x)
-            .min()
-            .unwrap();
-        let y1 = outputs
-            .iter()
-            .map(|output| output.dimensions.y)
-            .min()
-            .unwrap();
-        let x2 = outputs
-            .iter()
-            .map(|output| output.dimensions.x + output.dimensions.width)
-            .max()
-            .unwrap();
-        let y2 = outputs
-            .iter()
-            .map(|output| output.dimensions.y + output.dimensions.height)
-            .max()
-            .unwrap();
-        let capture_region = CaptureRegion {
-            x_coordinate: x1,
-            y_coordinate: y1,
-            width: x2 - x1,
-            height: y2 - y1,
-        };
-        self.screenshot(capture_region, cursor_overlay)
+        self.screenshot(outputs_region(outputs)?, cursor_overlay)
     }
 
     /// Take a screenshot from all accessible outputs.
+    #[cfg(feature = "image")]
     pub fn screenshot_all(&self, cursor_overlay: bool) -> Result<DynamicImage> {
         self.screenshot_outputs(self.get_all_outputs(), cursor_overlay)
     }
+
+    /// Take a screenshot of the given [`CaptureSource`].
+    #[cfg(feature = "image")]
+    pub fn capture(&self, source: &CaptureSource, cursor_overlay: bool) -> Result<DynamicImage> {
+        match source {
+            CaptureSource::All => self.screenshot_all(cursor_overlay),
+            CaptureSource::Output(name_or_id) => {
+                let output = self.find_output(name_or_id).ok_or(Error::NoOutputs)?;
+                self.screenshot_single_output(output, cursor_overlay)
+            }
+            CaptureSource::Region(region) => self.screenshot(*region, cursor_overlay),
+        }
+    }
+
+    /// Wait for `delay`, then take a screenshot of `source`, eg: to leave the user time to open a
+    /// menu or hover a tooltip that would close as soon as the focus changes.
+    #[cfg(feature = "image")]
+    pub fn screenshot_after(
+        &self,
+        delay: Duration,
+        source: &CaptureSource,
+        cursor_overlay: bool,
+    ) -> Result<DynamicImage> {
+        thread::sleep(delay);
+        self.capture(source, cursor_overlay)
+    }
+
+    /// Start capturing `output` repeatedly into a single reused buffer, tracking which parts of it
+    /// changed between frames.
+    pub fn start_streaming_capture(
+        &self,
+        output: &OutputInfo,
+        cursor_overlay: bool,
+    ) -> Result<StreamingCaptureContext<'_>> {
+        output.check_ready()?;
+        StreamingCaptureContext::new(
+            self,
+            output.wl_output.clone(),
+            output.transform,
+            cursor_overlay,
+        )
+    }
+
+    /// Capture `source` over and over, paced by `pacing`.
+    ///
+    /// ```no_run
+    /// use libwayshot::{CaptureSource, FramePacing, WayshotConnection};
+    ///
+    /// let wayshot_connection = WayshotConnection::new().unwrap();
+    /// for frame in wayshot_connection.frames(CaptureSource::All, false, FramePacing::default()) {
+    ///     let frame = frame.unwrap();
+    ///     println!("{}x{}", frame.width(), frame.height());
+    /// }
+    /// ```
+    #[cfg(feature = "image")]
+    pub fn frames(
+        &self,
+        source: CaptureSource,
+        cursor_overlay: bool,
+        pacing: FramePacing,
+    ) -> FrameIter<'_> {
+        FrameIter::new(self, source, cursor_overlay, pacing)
+    }
+}
+
+/// States able to create the wl_shm objects frames are copied into.
+trait ShmDispatch:
+    Dispatch<WlShm, ()> + Dispatch<WlShmPool, ()> + Dispatch<WlBuffer, ()> + 'static
+{
+}
+
+impl<S> ShmDispatch for S where
+    S: Dispatch<WlShm, ()> + Dispatch<WlShmPool, ()> + Dispatch<WlBuffer, ()> + 'static
+{
+}
+
+/// zwlr_screencopy_manager_v1 versions libwayshot binds.
+fn screencopy_versions() -> RangeInclusive<u32> {
+    MIN_SCREENCOPY_VERSION..=MAX_SCREENCOPY_VERSION
+}
+
+/// Version 3 brings the `buffer_done` event the format negotiation relies on.
+const MIN_SCREENCOPY_VERSION: u32 = 3;
+/// The newest zwlr_screencopy_manager_v1 version libwayshot implements. Only raise it together
+/// with handling what the new version adds, gated on the version of the bound manager.
+const MAX_SCREENCOPY_VERSION: u32 = 3;
+
+/// wl_shm formats libwayshot can convert, frames are copied in the first of them the compositor
+/// offers.
+pub(crate) const SUPPORTED_FORMATS: [wl_shm::Format; 6] = [
+    wl_shm::Format::Xbgr2101010,
+    wl_shm::Format::Abgr2101010,
+    wl_shm::Format::Argb8888,
+    wl_shm::Format::Xrgb8888,
+    wl_shm::Format::Xbgr8888,
+    wl_shm::Format::Bgr888,
+];
+
+/// Times the logical size of outputs is asked for while some report 0x0, and the wait in between.
+const OUTPUT_READY_ATTEMPTS: u32 = 5;
+const OUTPUT_READY_DELAY: Duration = Duration::from_millis(50);
+
+/// Pick the first advertised buffer format we know how to convert.
+fn select_frame_format(state: &CaptureFrameState) -> Result<FrameFormat> {
+    // A failed event ends the negotiation too, possibly before any buffer was announced. That is
+    // a capture the compositor failed, which may succeed when retried.
+    if state.phase.copied() == Some(FrameState::Failed) {
+        return Err(copy_failure(state));
+    }
+    tracing::debug!(
+        "Received compositor frame buffer formats: {:#?}",
+        state.formats
+    );
+    check_buffer_events(state)?;
+    // Filter advertised wl_shm formats and select the first one that matches.
+    let frame_format = state
+        .formats
+        .iter()
+        .find(|frame| SUPPORTED_FORMATS.contains(&frame.format))
+        .copied();
+    tracing::debug!("Selected frame buffer format: {:#?}", frame_format);
+
+    // Check if frame format exists.
+    match frame_format {
+        Some(format) => Ok(format),
+        None => {
+            tracing::error!("No suitable frame format found");
+            Err(Error::Format {
+                advertised: state
+                    .formats
+                    .iter()
+                    .map(|frame_format| frame_format.format)
+                    .collect(),
+                requested: SUPPORTED_FORMATS.to_vec(),
+            })
+        }
+    }
+}
+
+/// Ask `screencopy_manager` for a frame of `output`, or of `capture_region` of it.
+fn request_frame<S, U>(
+    screencopy_manager: &ZwlrScreencopyManagerV1,
+    qh: &QueueHandle<S>,
+    cursor_overlay: i32,
+    output: &WlOutput,
+    capture_region: Option<CaptureRegion>,
+    udata: U,
+) -> ZwlrScreencopyFrameV1
+where
+    S: Dispatch<ZwlrScreencopyFrameV1, U> + 'static,
+    U: Send + Sync + 'static,
+{
+    match capture_region {
+        Some(region) => screencopy_manager.capture_output_region(
+            cursor_overlay,
+            output,
+            region.x_coordinate,
+            region.y_coordinate,
+            region.width,
+            region.height,
+            qh,
+            udata,
+        ),
+        None => screencopy_manager.capture_output(cursor_overlay, output, qh, udata),
+    }
+}
+
+#[cfg(feature = "image")]
+fn all_buffers_done(state: &CaptureFramesState) -> bool {
+    state
+        .frames
+        .iter()
+        .all(|frame_state| frame_state.phase.is_buffer_done())
+}
+
+#[cfg(feature = "image")]
+fn all_copied(state: &CaptureFramesState) -> bool {
+    state
+        .frames
+        .iter()
+        .all(|frame_state| frame_state.phase.copied().is_some())
+}
+
+/// Fail with [`Error::CompositorFailure`] unless the compositor copied every frame.
+#[cfg(feature = "image")]
+fn check_frame_copies(
+    state: &CaptureFramesState,
+    shm_buffers: Vec<(FrameFormat, ShmBuffer)>,
+) -> Result<Vec<(FrameFormat, FrameMetadata, ShmBuffer)>> {
+    state
+        .frames
+        .iter()
+        .zip(shm_buffers)
+        .map(|(frame_state, (frame_format, shm_buffer))| {
+            Ok((frame_format, check_frame_copy(frame_state)?, shm_buffer))
+        })
+        .collect()
+}
+
+/// Reject buffer announcements no buffer could be allocated for, rather than failing later on
+/// with a misleading error or copying into a buffer of the wrong size.
+fn check_buffer_events(state: &CaptureFrameState) -> Result<()> {
+    let violation = |detail: &str| {
+        let detail = format!("{detail}, events: {}", state.event_log());
+        tracing::error!("Compositor violated zwlr_screencopy_v1: {detail}");
+        Err(Error::ProtocolViolation(detail))
+    };
+    if state.formats.is_empty() && state.dmabuf_formats.is_empty() {
+        return violation("buffer_done without any buffer event");
+    }
+    for frame_format in &state.formats {
+        if frame_format.width == 0 || frame_format.height == 0 {
+            return violation(&format!("empty buffer offered: {frame_format:?}"));
+        }
+        // Formats of unknown size are skipped when selecting one anyway.
+        let Ok(bytes_per_pixel) = frame_format.bytes_per_pixel() else {
+            continue;
+        };
+        if u64::from(frame_format.stride) < u64::from(frame_format.width) * bytes_per_pixel as u64 {
+            return violation(&format!(
+                "stride smaller than a row of pixels: {frame_format:?}"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Turn the Ready / Failed event of a copied frame into a result, releasing the frame and its
+/// buffer.
+fn finish_frame_copy(
+    state: &CaptureFrameState,
+    frame: ZwlrScreencopyFrameV1,
+    buffer: WlBuffer,
+    shm_pool: WlShmPool,
+    frame_format: FrameFormat,
+) -> Result<FrameFormat> {
+    frame.destroy();
+    buffer.destroy();
+    shm_pool.destroy();
+    check_frame_copy(state)?;
+    Ok(frame_format)
+}
+
+/// Whether the compositor copied the frame, reported as [`Error::CompositorFailure`] when it did
+/// not, along with what it reported about the frame.
+fn check_frame_copy(state: &CaptureFrameState) -> Result<FrameMetadata> {
+    match state.phase.copied() {
+        Some(FrameState::Finished) => Ok(FrameMetadata::from_state(state)),
+        Some(FrameState::Failed) | None => Err(copy_failure(state)),
+    }
+}
+
+/// Error for a frame copy the compositor failed, with its events as the reason.
+pub(crate) fn copy_failure(state: &CaptureFrameState) -> Error {
+    tracing::error!("Frame copy failed");
+    Error::CompositorFailure {
+        reason: format!("frame copy failed, events: {}", state.event_log()),
+    }
+}
+
+/// Convert the pixels of a captured frame in place.
+#[cfg(feature = "image")]
+fn convert_frame(raw_frame: RawFrame) -> Result<FrameCopy> {
+    let RawFrame {
+        frame_format,
+        mut frame_mmap,
+        transform,
+        metadata,
+        _lease,
+    } = raw_frame;
+    let mut view = StridedView::new(&mut frame_mmap[..], &frame_format)?;
+    let frame_color_type = if let Some(converter) = create_converter(frame_format.format) {
+        converter.convert_inplace(&mut view)
+    } else {
+        tracing::error!("Unsupported buffer format: {:?}", frame_format.format);
+        tracing::error!("You can send a feature request for the above format to the mailing list for wayshot over at https://sr.ht/~shinyzenith/wayshot.");
+        return Err(Error::Format {
+            advertised: vec![frame_format.format],
+            requested: SUPPORTED_FORMATS.to_vec(),
+        });
+    };
+    Ok(FrameCopy {
+        frame_format,
+        frame_color_type,
+        frame_mmap,
+        transform,
+        metadata,
+        _lease,
+    })
+}
+
+/// The result of a conversion thread, reporting a panic in it as
+/// [`Error::ConversionPanicked`] rather than dropping the output it converted.
+#[cfg(feature = "image")]
+fn joined<T>(join_handle: thread::ScopedJoinHandle<'_, Result<T>>) -> Result<T> {
+    join_handle
+        .join()
+        .unwrap_or_else(|payload| Err(conversion_panicked(payload)))
+}
+
+/// [`Error::ConversionPanicked`] with the message of a conversion thread's panic.
+#[cfg(feature = "image")]
+fn conversion_panicked(payload: Box<dyn Any + Send>) -> Error {
+    let message = if let Some(message) = payload.downcast_ref::<&str>() {
+        (*message).to_owned()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_owned()
+    };
+    Error::ConversionPanicked(message)
+}
+
+/// Rotate every frame copy and overlay them into a single `width` x `height` image.
+#[cfg(feature = "image")]
+fn compose_frame_copies(
+    frame_copies: Vec<FrameCopy>,
+    width: i32,
+    height: i32,
+) -> Result<DynamicImage> {
+    thread::scope(|scope| {
+        let rotate_join_handles = frame_copies
+            .into_iter()
+            .map(|frame_copy| {
+                scope.spawn(move || {
+                    let transform = frame_copy.transform;
+                    let image = frame_copy.try_into()?;
+                    Ok(image_util::rotate_image_buffer(
+                        image,
+                        transform,
+                        width as u32,
+                        height as u32,
+                    ))
+                })
+            })
+            .collect::<Vec<_>>();
+
+        rotate_join_handles
+            .into_iter()
+            .map(joined)
+            .fold(
+                None,
+                |possible_overlayed_image_or_error: Option<Result<_>>, image: Result<_>| match (
+                    possible_overlayed_image_or_error,
+                    image,
+                ) {
+                    (Some(Ok(mut overlayed_image)), Ok(image)) => {
+                        overlay_image(&mut overlayed_image, &image);
+                        Some(Ok(overlayed_image))
+                    }
+                    (_, image) => Some(image),
+                },
+            )
+            .ok_or_else(|| {
+                tracing::error!("Provided capture region doesn't intersect with any outputs!");
+                Error::NoOutputs
+            })?
+    })
+}
+
+/// Size of the image composed from `outputs` for `capture_region`. It is rendered at the highest
+/// scale among them, so no output loses detail and outputs at that scale are not resampled.
+#[cfg(feature = "image")]
+fn composed_size(capture_region: CaptureRegion, outputs: &[IntersectingOutput]) -> (i32, i32) {
+    let scale = outputs
+        .iter()
+        .map(|output| output.scale)
+        .reduce(f64::max)
+        .unwrap_or(1.0);
+    let scaled = |length: i32| (f64::from(length) * scale).round() as i32;
+    (scaled(capture_region.width), scaled(capture_region.height))
+}
+
+/// Draw `top` over `bottom`. As soon as one of them is a 16-bit capture the result is 16-bit, the
+/// DynamicImage pixel accessors would otherwise reduce it to 8 bits.
+#[cfg(feature = "image")]
+fn overlay_image(bottom: &mut DynamicImage, top: &DynamicImage) {
+    if top.color() == ColorType::Rgba16 && bottom.color() != ColorType::Rgba16 {
+        *bottom = DynamicImage::ImageRgba16(bottom.to_rgba16());
+    }
+    match bottom {
+        DynamicImage::ImageRgba16(bottom) => match top {
+            DynamicImage::ImageRgba16(top) => overlay(bottom, top, 0, 0),
+            top => overlay(bottom, &top.to_rgba16(), 0, 0),
+        },
+        bottom => overlay(bottom, top, 0, 0),
+    }
+}
+
+/// `capture_region` translated into the coordinates of the output at `dimensions`, `None` when
+/// they do not overlap or the translated region does not fit in `i32` coordinates.
+#[cfg(feature = "image")]
+fn region_on_output(
+    capture_region: CaptureRegion,
+    dimensions: &OutputPositioning,
+) -> Option<CaptureRegion> {
+    let x1 = cmp::max(dimensions.x, capture_region.x_coordinate);
+    let y1 = cmp::max(dimensions.y, capture_region.y_coordinate);
+    let x2 = cmp::min(
+        dimensions.x.checked_add(dimensions.width)?,
+        capture_region
+            .x_coordinate
+            .checked_add(capture_region.width)?,
+    );
+    let y2 = cmp::min(
+        dimensions.y.checked_add(dimensions.height)?,
+        capture_region
+            .y_coordinate
+            .checked_add(capture_region.height)?,
+    );
+    if x2 <= x1 || y2 <= y1 {
+        return None;
+    }
+    Some(CaptureRegion {
+        x_coordinate: capture_region.x_coordinate.checked_sub(dimensions.x)?,
+        y_coordinate: capture_region.y_coordinate.checked_sub(dimensions.y)?,
+        width: capture_region.width,
+        height: capture_region.height,
+    })
+}
+
+/// Bounding box of `outputs` in the global compositor space.
+fn outputs_region(outputs: &[OutputInfo]) -> Result<CaptureRegion> {
+    for output in outputs {
+        output.check_ready()?;
+    }
+    outputs
+        .iter()
+        .map(OutputInfo::region)
+        .try_fold(None, |covered: Option<CaptureRegion>, region| {
+            covered
+                .unwrap_or(region)
+                .union(&region)
+                .map(Some)
+                .ok_or(Error::InvalidRegion(region))
+        })?
+        .ok_or(Error::NoOutputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use libwayshot_protocols::screencopy::FramePhase;
+
+    use super::*;
+
+    fn region(x_coordinate: i32, y_coordinate: i32, width: i32, height: i32) -> CaptureRegion {
+        CaptureRegion {
+            x_coordinate,
+            y_coordinate,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn normalized_flips_negative_sizes() {
+        assert_eq!(
+            region(100, 50, -30, -20).normalized().expect("region"),
+            region(70, 30, 30, 20)
+        );
+        assert_eq!(
+            region(100, 50, 30, 20).normalized().expect("region"),
+            region(100, 50, 30, 20)
+        );
+    }
+
+    #[test]
+    fn normalized_rejects_overflowing_regions() {
+        for invalid in [
+            region(0, 0, i32::MIN, 10),
+            region(0, 0, 10, i32::MIN),
+            region(i32::MIN, 0, -1, 10),
+            region(0, i32::MIN + 5, 10, -6),
+        ] {
+            assert!(matches!(
+                invalid.normalized(),
+                Err(Error::InvalidRegion(region)) if region == invalid
+            ));
+        }
+    }
+
+    #[test]
+    fn validate_rejects_empty_and_negative_regions() {
+        for invalid in [
+            region(0, 0, 0, 10),
+            region(0, 0, 10, 0),
+            region(0, 0, -10, 10),
+            region(0, 0, 10, i32::MIN),
+        ] {
+            assert!(matches!(invalid.validate(), Err(Error::InvalidRegion(_))));
+        }
+        assert!(region(-1920, 0, 1920, 1080).validate().is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_regions_past_i32() {
+        assert!(matches!(
+            region(i32::MAX - 10, 0, 20, 20).validate(),
+            Err(Error::InvalidRegion(_))
+        ));
+        assert!(matches!(
+            region(0, i32::MAX, 1, 1).validate(),
+            Err(Error::InvalidRegion(_))
+        ));
+    }
+
+    #[test]
+    fn validate_rejects_oversize_regions() {
+        assert!(matches!(
+            region(0, 0, 65536, 65536).validate(),
+            Err(Error::RegionTooLarge(_))
+        ));
+        assert!(region(0, 0, 16384, 16384).validate().is_ok());
+    }
+
+    #[test]
+    fn union_covers_both_regions() {
+        assert_eq!(
+            region(0, 0, 10, 10).union(&region(-20, 30, 5, 5)),
+            Some(region(-20, 0, 30, 35))
+        );
+    }
+
+    #[test]
+    fn union_rejects_regions_too_far_apart() {
+        assert_eq!(
+            region(i32::MIN, 0, 10, 10).union(&region(i32::MAX - 10, 0, 10, 10)),
+            None
+        );
+    }
+
+    #[test]
+    fn shm_pool_size_must_fit_in_i32() {
+        let frame_format = |stride, height| FrameFormat {
+            format: wl_shm::Format::Xrgb8888,
+            width: stride / 4,
+            height,
+            stride,
+        };
+        assert_eq!(
+            shm_pool_size(&frame_format(7680, 1080)).ok(),
+            Some(7680 * 1080)
+        );
+        assert!(matches!(
+            shm_pool_size(&frame_format(65536, 65536)),
+            Err(Error::BufferTooSmall)
+        ));
+        assert!(matches!(
+            shm_pool_size(&frame_format(65536, 32768)),
+            Err(Error::BufferTooSmall)
+        ));
+    }
+
+    #[test]
+    fn resolving_relative_regions_checks_for_overflow() {
+        let relative = RelativeRegion {
+            x: 0.5,
+            y: 0.0,
+            width: 0.5,
+            height: 1.0,
+        };
+        let resolved = relative
+            .resolve(region(100, 50, 200, 100))
+            .expect("the region fits in i32");
+        assert_eq!(resolved, region(200, 50, 100, 100));
+        assert!(matches!(
+            relative.resolve(region(i32::MAX - 10, 0, 200, 100)),
+            Err(Error::InvalidRegion(_))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn panicking_conversion_threads_are_reported() {
+        let result: Result<()> =
+            thread::scope(|scope| joined(scope.spawn(|| panic!("conversion failed"))));
+        assert!(matches!(
+            result,
+            Err(Error::ConversionPanicked(message)) if message == "conversion failed"
+        ));
+    }
+
+    #[test]
+    fn failing_before_buffer_done_is_retryable() {
+        let state = CaptureFrameState {
+            phase: FramePhase::Copied(FrameState::Failed),
+            events: vec!["Failed".to_string()],
+            ..Default::default()
+        };
+        assert!(state.phase.is_buffer_done());
+        let error = select_frame_format(&state).expect_err("the frame failed");
+        assert!(matches!(error, Error::CompositorFailure { .. }));
+        assert!(error.is_retryable());
+    }
+
+    #[test]
+    fn retry_policy_retries_retryable_errors_and_counts_attempts() {
+        let retry_policy = RetryPolicy {
+            attempts: 3,
+            backoff: Duration::ZERO,
+        };
+        let mut failures = vec![Error::OutputNotReady("DP-1".to_string()), Error::Timeout];
+        let (value, attempts) = retry_policy
+            .run(|| failures.pop().map_or(Ok(7), Err))
+            .expect("the third attempt succeeds");
+        assert_eq!((value, attempts), (7, 3));
+
+        let mut calls = 0;
+        let result: Result<((), u32)> = retry_policy.run(|| {
+            calls += 1;
+            Err(Error::NoOutputs)
+        });
+        assert!(matches!(result, Err(Error::NoOutputs)));
+        assert_eq!(calls, 1);
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn region_on_output_translates_overlapping_regions() {
+        let output = OutputPositioning {
+            x: 1920,
+            y: 0,
+            width: 2560,
+            height: 1440,
+        };
+        assert_eq!(
+            region_on_output(region(1800, 100, 400, 300), &output),
+            Some(region(-120, 100, 400, 300))
+        );
+        assert_eq!(region_on_output(region(0, 0, 1920, 1080), &output), None);
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn region_on_output_ignores_overflowing_regions() {
+        let output = OutputPositioning {
+            x: 0,
+            y: 0,
+            width: 1920,
+            height: 1080,
+        };
+        assert_eq!(region_on_output(region(i32::MAX, 0, 10, 10), &output), None);
+        assert_eq!(
+            region_on_output(region(0, 0, i32::MAX, 10), &output),
+            Some(region(0, 0, i32::MAX, 10))
+        );
+        let far_output = OutputPositioning {
+            x: i32::MAX - 100,
+            y: 0,
+            width: 200,
+            height: 1080,
+        };
+        assert_eq!(region_on_output(region(0, 0, 100, 100), &far_output), None);
+    }
 }
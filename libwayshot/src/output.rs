@@ -1,5 +1,16 @@
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use libwayshot_protocols::output::OutputProperties;
+pub use libwayshot_protocols::output::{
+    Chromaticities, ColorDescription, OutputPositioning, WlOutputMode,
+};
 use wayland_client::protocol::{wl_output, wl_output::WlOutput};
 
+use crate::{CaptureRegion, Error, Result};
+
 /// Represents an accessible wayland output.
 ///
 /// Do not instantiate, instead use [`crate::WayshotConnection::get_all_outputs`].
@@ -8,21 +19,384 @@ pub struct OutputInfo {
     pub wl_output: WlOutput,
     pub name: String,
     pub description: String,
+    pub make: String,
+    pub model: String,
     pub transform: wl_output::Transform,
     pub dimensions: OutputPositioning,
     pub mode: WlOutputMode,
+    /// What the output's pixel values mean, `None` when the compositor does not implement
+    /// wp_color_management_v1. Outputs without it are assumed to be sRGB.
+    pub color: Option<ColorDescription>,
+}
+
+impl OutputInfo {
+    /// Area of this output in the global compositor space, in logical pixels.
+    pub fn region(&self) -> CaptureRegion {
+        CaptureRegion {
+            x_coordinate: self.dimensions.x,
+            y_coordinate: self.dimensions.y,
+            width: self.dimensions.width,
+            height: self.dimensions.height,
+        }
+    }
+
+    /// Stable identifier of the monitor behind this output.
+    pub fn id(&self) -> OutputId {
+        OutputId::new(&self.make, &self.model, self.serial())
+    }
+
+    /// Serial number of the monitor, when the compositor puts it in the description.
+    ///
+    /// wl_output has no serial event, but wlroots based compositors describe outputs as
+    /// `"<make> <model> <serial> (<name>)"`.
+    pub fn serial(&self) -> Option<&str> {
+        let description = self.description.trim();
+        let description = description
+            .strip_suffix(&format!("({})", self.name))
+            .unwrap_or(description);
+        let serial = description
+            .trim()
+            .strip_prefix(self.make.trim())?
+            .trim_start()
+            .strip_prefix(self.model.trim())?
+            .trim();
+        (!serial.is_empty() && serial != "Unknown").then_some(serial)
+    }
+
+    /// Whether the compositor reported a logical size and a mode for the output. Some briefly
+    /// report 0x0 while switching modes.
+    pub fn is_ready(&self) -> bool {
+        self.dimensions.width > 0
+            && self.dimensions.height > 0
+            && self.mode.width > 0
+            && self.mode.height > 0
+    }
+
+    /// Fail with [`Error::OutputNotReady`] unless [`Self::is_ready`].
+    pub(crate) fn check_ready(&self) -> Result<()> {
+        if self.is_ready() {
+            Ok(())
+        } else {
+            tracing::error!(
+                "Output {} has a {}x{} logical size and a {}x{} mode",
+                self.name,
+                self.dimensions.width,
+                self.dimensions.height,
+                self.mode.width,
+                self.mode.height
+            );
+            Err(Error::OutputNotReady(self.name.clone()))
+        }
+    }
+
+    /// [`Self::scale`], failing with [`Error::OutputNotReady`] while the output has no logical
+    /// size or mode instead of assuming `1.0`.
+    pub fn checked_scale(&self) -> Result<f64> {
+        self.check_ready()?;
+        Ok(self.scale())
+    }
+
+    /// Physical pixels per logical pixel, eg: `1.25`.
+    ///
+    /// wp_fractional_scale_v1 only reports the scale of surfaces, so this is derived from the mode
+    /// and the logical size, snapped to the 1/120 steps that protocol expresses scales in. `1.0`
+    /// while the output is not [ready](Self::is_ready), see [`Self::checked_scale`].
+    pub fn scale(&self) -> f64 {
+        let physical_width = match self.transform {
+            wl_output::Transform::_90
+            | wl_output::Transform::_270
+            | wl_output::Transform::Flipped90
+            | wl_output::Transform::Flipped270 => self.mode.height,
+            _ => self.mode.width,
+        };
+        if physical_width <= 0 || self.dimensions.width <= 0 {
+            return 1.0;
+        }
+        let scale = f64::from(physical_width) / f64::from(self.dimensions.width);
+        (scale * 120.0).round() / 120.0
+    }
+}
+
+impl From<OutputProperties> for OutputInfo {
+    fn from(output: OutputProperties) -> Self {
+        Self {
+            wl_output: output.wl_output,
+            name: output.name,
+            description: output.description,
+            make: output.make,
+            model: output.model,
+            transform: output.transform,
+            dimensions: output.dimensions,
+            mode: output.mode,
+            color: output.color,
+        }
+    }
+}
+
+/// Identifier of an output derived from the make, model and, when known, serial number of the
+/// monitor.
+///
+/// Unlike connector names, which may shuffle around when a dock is replugged, this stays the same
+/// for as long as the same monitor is connected. Identical monitors without a known serial number
+/// share an identifier, which then matches none of them, see [`OutputSelector::Name`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OutputId(String);
+
+impl OutputId {
+    pub fn new(make: &str, model: &str, serial: Option<&str>) -> Self {
+        let id = [make, model, serial.unwrap_or_default()]
+            .iter()
+            .map(|part| part.trim())
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        Self(id)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for OutputId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Eq)]
-pub struct WlOutputMode {
-    pub width: i32,
-    pub height: i32,
+/// Strategy used to pick a single output when the user did not name one explicitly.
+///
+/// Parses from `"largest"`, `"primary"`, `"focused"` or `"name:<name or id>"`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputSelector {
+    /// The output with the largest logical area.
+    Largest,
+    /// The output positioned at the origin of the global compositor space, which is where
+    /// compositors place the primary display.
+    Primary,
+    /// The output holding keyboard focus. Wayland does not expose focus to clients, so
+    /// [`Self::select`] matches nothing: resolve it through the compositor's IPC first and select
+    /// the output by [name](Self::Name), as `wayshot` does on sway and Hyprland.
+    Focused,
+    /// The output with the given connector name or [`OutputId`]. An identifier shared by several
+    /// outputs selects none, rather than an arbitrary one of them.
+    Name(String),
+}
+
+impl OutputSelector {
+    /// Pick an output from `outputs` according to this strategy.
+    pub fn select<'a>(&self, outputs: &'a [OutputInfo]) -> Option<&'a OutputInfo> {
+        match self {
+            Self::Largest => outputs.iter().reduce(|largest, output| {
+                let area = |output: &OutputInfo| {
+                    i64::from(output.dimensions.width) * i64::from(output.dimensions.height)
+                };
+                if area(output) > area(largest) {
+                    output
+                } else {
+                    largest
+                }
+            }),
+            Self::Focused => None,
+            Self::Primary => outputs
+                .iter()
+                .find(|output| output.dimensions.x == 0 && output.dimensions.y == 0)
+                .or_else(|| outputs.first()),
+            Self::Name(name_or_id) => {
+                if let Some(output) = outputs.iter().find(|output| &output.name == name_or_id) {
+                    return Some(output);
+                }
+                let mut matches = outputs
+                    .iter()
+                    .filter(|output| output.id().as_str() == name_or_id);
+                let output = matches.next()?;
+                if matches.next().is_some() {
+                    tracing::error!(
+                        "Several outputs are identified as {name_or_id:?}, select one by its connector name instead"
+                    );
+                    return None;
+                }
+                Some(output)
+            }
+        }
+    }
+}
+
+impl FromStr for OutputSelector {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s {
+            "largest" => Ok(Self::Largest),
+            "primary" => Ok(Self::Primary),
+            "focused" => Ok(Self::Focused),
+            _ => match s.strip_prefix("name:") {
+                Some(name) if !name.is_empty() => Ok(Self::Name(name.to_string())),
+                _ => Err(Error::InvalidOutputSelector(s.to_string())),
+            },
+        }
+    }
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Eq)]
-pub struct OutputPositioning {
-    pub x: i32,
-    pub y: i32,
-    pub width: i32,
-    pub height: i32,
+// The outputs are backed by a socket pair, which miri cannot create.
+#[cfg(all(test, not(miri)))]
+mod tests {
+    use std::os::unix::net::UnixStream;
+
+    use wayland_client::{backend::Backend, Proxy};
+
+    use super::*;
+
+    fn output(name: &str, description: &str, region: (i32, i32, i32, i32)) -> OutputInfo {
+        let (stream, _) = UnixStream::pair().expect("socket pair");
+        let backend = Backend::connect(stream).expect("backend");
+        let (x, y, width, height) = region;
+        OutputInfo {
+            wl_output: WlOutput::inert(backend.downgrade()),
+            name: name.to_string(),
+            description: description.to_string(),
+            make: "Dell Inc.".to_string(),
+            model: "DELL U2720Q".to_string(),
+            transform: wl_output::Transform::Normal,
+            dimensions: OutputPositioning {
+                x,
+                y,
+                width,
+                height,
+            },
+            mode: WlOutputMode { width, height },
+            color: None,
+        }
+    }
+
+    #[test]
+    fn id_includes_the_serial_from_the_description() {
+        let output = output(
+            "DP-1",
+            "Dell Inc. DELL U2720Q 5KC0J83 (DP-1)",
+            (0, 0, 1920, 1080),
+        );
+        assert_eq!(output.serial(), Some("5KC0J83"));
+        assert_eq!(output.id().as_str(), "Dell Inc. DELL U2720Q 5KC0J83");
+    }
+
+    #[test]
+    fn id_without_serial_is_make_and_model() {
+        for description in ["Dell Inc. DELL U2720Q Unknown (DP-1)", "", "Some monitor"] {
+            let output = output("DP-1", description, (0, 0, 1920, 1080));
+            assert_eq!(output.serial(), None);
+            assert_eq!(output.id().as_str(), "Dell Inc. DELL U2720Q");
+        }
+    }
+
+    #[test]
+    fn identical_monitors_are_told_apart_by_serial() {
+        let outputs = [
+            output(
+                "DP-1",
+                "Dell Inc. DELL U2720Q AAA (DP-1)",
+                (0, 0, 1920, 1080),
+            ),
+            output(
+                "DP-2",
+                "Dell Inc. DELL U2720Q BBB (DP-2)",
+                (1920, 0, 1920, 1080),
+            ),
+        ];
+        let selected = OutputSelector::Name("Dell Inc. DELL U2720Q BBB".to_string())
+            .select(&outputs)
+            .map(|output| output.name.as_str());
+        assert_eq!(selected, Some("DP-2"));
+    }
+
+    #[test]
+    fn ambiguous_id_selects_nothing() {
+        let outputs = [
+            output("DP-1", "", (0, 0, 1920, 1080)),
+            output("DP-2", "", (1920, 0, 1920, 1080)),
+        ];
+        assert!(OutputSelector::Name("Dell Inc. DELL U2720Q".to_string())
+            .select(&outputs)
+            .is_none());
+        let selected = OutputSelector::Name("DP-2".to_string())
+            .select(&outputs)
+            .map(|output| output.name.as_str());
+        assert_eq!(selected, Some("DP-2"));
+    }
+
+    fn selected<'a>(selector: &str, outputs: &'a [OutputInfo]) -> Option<&'a str> {
+        selector
+            .parse::<OutputSelector>()
+            .expect("valid selector")
+            .select(outputs)
+            .map(|output| output.name.as_str())
+    }
+
+    #[test]
+    fn largest_picks_the_largest_area() {
+        let outputs = [
+            output("eDP-1", "", (0, 0, 1920, 1200)),
+            output("DP-1", "", (1920, 0, 2560, 1440)),
+            output("DP-2", "", (4480, 0, 3440, 1000)),
+        ];
+        assert_eq!(selected("largest", &outputs), Some("DP-1"));
+        assert_eq!(selected("largest", &[]), None);
+    }
+
+    #[test]
+    fn primary_is_the_output_at_the_origin() {
+        let outputs = [
+            output("DP-1", "", (-2560, 0, 2560, 1440)),
+            output("eDP-1", "", (0, 0, 1920, 1200)),
+        ];
+        assert_eq!(selected("primary", &outputs), Some("eDP-1"));
+        let outputs = [
+            output("DP-1", "", (100, 0, 2560, 1440)),
+            output("DP-2", "", (2660, 0, 1920, 1080)),
+        ];
+        assert_eq!(selected("primary", &outputs), Some("DP-1"));
+    }
+
+    #[test]
+    fn focused_needs_to_be_resolved_by_the_caller() {
+        let outputs = [output("eDP-1", "", (0, 0, 1920, 1200))];
+        assert_eq!(selected("focused", &outputs), None);
+    }
+
+    #[test]
+    fn name_matches_connector_names_and_ids() {
+        let outputs = [
+            output(
+                "eDP-1",
+                "Dell Inc. DELL U2720Q AAA (eDP-1)",
+                (0, 0, 1920, 1200),
+            ),
+            output(
+                "DP-1",
+                "Dell Inc. DELL U2720Q BBB (DP-1)",
+                (1920, 0, 2560, 1440),
+            ),
+        ];
+        assert_eq!(selected("name:DP-1", &outputs), Some("DP-1"));
+        assert_eq!(selected(" name:eDP-1 ", &outputs), Some("eDP-1"));
+        assert_eq!(
+            selected("name:Dell Inc. DELL U2720Q BBB", &outputs),
+            Some("DP-1")
+        );
+        assert_eq!(selected("name:HDMI-A-1", &outputs), None);
+    }
+
+    #[test]
+    fn invalid_selectors_are_rejected() {
+        for selector in ["", "name:", "Largest", "biggest", "DP-1"] {
+            assert!(
+                matches!(
+                    selector.parse::<OutputSelector>(),
+                    Err(Error::InvalidOutputSelector(_))
+                ),
+                "{selector:?} parsed"
+            );
+        }
+    }
 }
@@ -0,0 +1,68 @@
+//! Drawing on the shm buffers of overlay surfaces, such as the selector or a countdown.
+//!
+//! Buffers are premultiplied `Argb8888`, stored as little endian B, G, R, A, with rows of
+//! `width * 4` bytes.
+
+pub mod text;
+
+/// Pixels of an overlay buffer to draw on.
+#[derive(Debug)]
+pub struct Canvas<'a> {
+    pub pixels: &'a mut [u8],
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Canvas<'_> {
+    /// Set every pixel to `color`.
+    pub fn fill(&mut self, color: [u8; 4]) {
+        for pixel in self.pixels.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&color);
+        }
+    }
+
+    /// Set the pixels of the `width` x `height` rectangle at `x`, `y` to `color`, clipped to the
+    /// canvas.
+    pub fn fill_rect(&mut self, x: i32, y: i32, width: u32, height: u32, color: [u8; 4]) {
+        let (x0, x1) = clip(x, width, self.width);
+        let (y0, y1) = clip(y, height, self.height);
+        for row in y0..y1 {
+            let start = (row * self.width + x0) as usize * 4;
+            let end = (row * self.width + x1) as usize * 4;
+            let Some(pixels) = self.pixels.get_mut(start..end) else {
+                return;
+            };
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.copy_from_slice(&color);
+            }
+        }
+    }
+
+    /// Draw `color` over the pixel at `x`, `y` with `coverage` out of 255, eg: an antialiased
+    /// glyph edge. Pixels off the canvas are ignored.
+    pub fn blend(&mut self, x: i32, y: i32, color: [u8; 4], coverage: u8) {
+        if x < 0 || y < 0 || x as u32 >= self.width || y as u32 >= self.height {
+            return;
+        }
+        let start = (y as u32 * self.width + x as u32) as usize * 4;
+        let Some(pixel) = self.pixels.get_mut(start..start + 4) else {
+            return;
+        };
+        let coverage = u32::from(coverage);
+        let source = color.map(|channel| u32::from(channel) * coverage / 255);
+        // Premultiplied "over": the source plus what its alpha lets through.
+        let remaining = 255 - source[3];
+        for (destination, source) in pixel.iter_mut().zip(source) {
+            *destination = (source + u32::from(*destination) * remaining / 255) as u8;
+        }
+    }
+}
+
+/// The part of `start..start + length` within `0..limit`.
+fn clip(start: i32, length: u32, limit: u32) -> (u32, u32) {
+    let end = (i64::from(start) + i64::from(length)).clamp(0, i64::from(limit));
+    (
+        i64::from(start).clamp(0, i64::from(limit)) as u32,
+        end as u32,
+    )
+}
@@ -0,0 +1,126 @@
+//! Text labels for overlays, rendered with an embedded DejaVu Sans Mono Bold so they look the
+//! same on every system, whatever fonts are installed.
+//!
+//! The font covers Latin and Cyrillic. Text is laid out on a single line, left to right, with the
+//! kerning of the font. Characters the font lacks are drawn as its replacement box.
+
+use std::sync::OnceLock;
+
+use fontdue::{Font, FontSettings};
+
+use super::Canvas;
+use crate::{Error, Result};
+
+static FONT_DATA: &[u8] = include_bytes!("../../assets/DejaVuSansMono-Bold.ttf");
+
+/// How a label is drawn.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LabelStyle {
+    /// Font size in pixels.
+    pub size: f32,
+    pub foreground: [u8; 4],
+    /// Box drawn behind the text, `None` to draw the text alone.
+    pub background: Option<[u8; 4]>,
+    /// Space between the text and the edges of the background box.
+    pub padding: u32,
+}
+
+/// Size of `text` drawn with `style`, including the padding.
+pub fn label_size(text: &str, style: &LabelStyle) -> Result<(u32, u32)> {
+    let layout = Layout::new(font()?, text, style.size);
+    Ok((
+        layout.width + 2 * style.padding,
+        layout.height + 2 * style.padding,
+    ))
+}
+
+/// Draw `text` centered on `canvas`, over a box of `style.background`.
+pub fn draw_label(canvas: &mut Canvas<'_>, text: &str, style: &LabelStyle) -> Result<()> {
+    let font = font()?;
+    let layout = Layout::new(font, text, style.size);
+    let (width, height) = (
+        layout.width + 2 * style.padding,
+        layout.height + 2 * style.padding,
+    );
+    let x = (i64::from(canvas.width) - i64::from(width)) / 2;
+    let y = (i64::from(canvas.height) - i64::from(height)) / 2;
+    let (x, y) = (x as i32, y as i32);
+    if let Some(background) = style.background {
+        canvas.fill_rect(x, y, width, height, background);
+    }
+
+    let baseline = y + style.padding as i32 + layout.ascent;
+    for glyph in &layout.glyphs {
+        let (metrics, coverage) = font.rasterize(glyph.character, style.size);
+        let glyph_x = x + style.padding as i32 + glyph.x + metrics.xmin;
+        // `ymin` is the offset of the bottom of the bitmap from the baseline, upwards.
+        let glyph_y = baseline - metrics.ymin - metrics.height as i32;
+        for (row, coverage_row) in coverage.chunks(metrics.width.max(1)).enumerate() {
+            for (column, &coverage) in coverage_row.iter().enumerate() {
+                if coverage > 0 {
+                    canvas.blend(
+                        glyph_x + column as i32,
+                        glyph_y + row as i32,
+                        style.foreground,
+                        coverage,
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The embedded font, parsed on first use.
+fn font() -> Result<&'static Font> {
+    static FONT: OnceLock<Result<Font, &'static str>> = OnceLock::new();
+    FONT.get_or_init(|| Font::from_bytes(FONT_DATA, FontSettings::default()))
+        .as_ref()
+        .map_err(|e| Error::Font(e.to_string()))
+}
+
+/// A glyph placed on the line, `x` pixels right of its start.
+struct PlacedGlyph {
+    character: char,
+    x: i32,
+}
+
+/// Glyph positions of a line of text.
+struct Layout {
+    glyphs: Vec<PlacedGlyph>,
+    width: u32,
+    height: u32,
+    /// Distance from the top of the line to the baseline.
+    ascent: i32,
+}
+
+impl Layout {
+    fn new(font: &Font, text: &str, size: f32) -> Self {
+        let (ascent, descent) = font
+            .horizontal_line_metrics(size)
+            .map_or((size, 0.0), |line| (line.ascent, line.descent));
+        let mut glyphs = Vec::new();
+        let mut pen = 0.0;
+        let mut previous = None;
+        for character in text.chars().filter(|character| !character.is_control()) {
+            if let Some(kern) =
+                previous.and_then(|previous| font.horizontal_kern(previous, character, size))
+            {
+                pen += kern;
+            }
+            glyphs.push(PlacedGlyph {
+                character,
+                x: pen.round() as i32,
+            });
+            pen += font.metrics(character, size).advance_width;
+            previous = Some(character);
+        }
+        Self {
+            glyphs,
+            width: pen.ceil().max(0.0) as u32,
+            // `descent` is negative, below the baseline.
+            height: (ascent - descent).ceil().max(0.0) as u32,
+            ascent: ascent.round() as i32,
+        }
+    }
+}
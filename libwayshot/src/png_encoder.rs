@@ -0,0 +1,120 @@
+use std::{borrow::Cow, io::Write};
+
+use image::DynamicImage;
+
+use crate::{
+    output::ColorDescription,
+    reexport::{Primaries, TransferFunction},
+    Error, Result,
+};
+
+/// Write `image` as PNG with `compression`, tagged with `color`, the color description of the
+/// outputs it was captured from, so viewers do not take captures of wide-gamut or HDR outputs for
+/// sRGB.
+///
+/// The description ends up as a cICP chunk when the compositor names its primaries and transfer
+/// function, and as iCCP, cHRM and gAMA chunks otherwise.
+pub(crate) fn write_png<W: Write>(
+    image: &DynamicImage,
+    color: Option<&ColorDescription>,
+    compression: png::Compression,
+    writer: W,
+) -> Result<()> {
+    let (color_type, bit_depth, data): (_, _, Cow<[u8]>) = match image {
+        DynamicImage::ImageRgb8(buffer) => (
+            png::ColorType::Rgb,
+            png::BitDepth::Eight,
+            Cow::Borrowed(buffer.as_raw()),
+        ),
+        DynamicImage::ImageRgba8(buffer) => (
+            png::ColorType::Rgba,
+            png::BitDepth::Eight,
+            Cow::Borrowed(buffer.as_raw()),
+        ),
+        // PNG stores 16-bit samples big endian.
+        DynamicImage::ImageRgba16(buffer) => (
+            png::ColorType::Rgba,
+            png::BitDepth::Sixteen,
+            Cow::Owned(
+                buffer
+                    .as_raw()
+                    .iter()
+                    .flat_map(|sample| sample.to_be_bytes())
+                    .collect(),
+            ),
+        ),
+        image => (
+            png::ColorType::Rgba,
+            png::BitDepth::Eight,
+            Cow::Owned(image.to_rgba8().into_raw()),
+        ),
+    };
+
+    let mut info = png::Info::with_size(image.width(), image.height());
+    info.color_type = color_type;
+    info.bit_depth = bit_depth;
+    info.compression = compression;
+    if let Some(color) = color {
+        info.icc_profile = color.icc_profile.as_deref().map(Cow::Borrowed);
+        if let Some(primaries) = color.primaries {
+            let xy = |(x, y): (f64, f64)| (x as f32, y as f32);
+            info.source_chromaticities = Some(png::SourceChromaticities::new(
+                xy(primaries.white),
+                xy(primaries.red),
+                xy(primaries.green),
+                xy(primaries.blue),
+            ));
+        }
+        if let Some(exponent) = color.transfer_function_power {
+            // gAMA holds the encoding exponent, the inverse of the display one.
+            info.source_gamma = Some(png::ScaledFloat::new((1.0 / exponent) as f32));
+        }
+    }
+
+    let encode_error = |e: png::EncodingError| Error::Encode(e.to_string());
+    let mut writer = png::Encoder::with_info(writer, info)
+        .and_then(png::Encoder::write_header)
+        .map_err(encode_error)?;
+    if let Some(code_points) = color.and_then(code_points) {
+        writer
+            .write_chunk(png::chunk::ChunkType(*b"cICP"), &code_points)
+            .map_err(encode_error)?;
+    }
+    writer.write_image_data(&data).map_err(encode_error)?;
+    writer.finish().map_err(encode_error)
+}
+
+/// cICP payload for the named primaries and transfer function, using the ITU-T H.273 code points.
+fn code_points(color: &ColorDescription) -> Option<[u8; 4]> {
+    let primaries = match color.primaries_named? {
+        Primaries::Srgb => 1,
+        Primaries::PalM => 4,
+        Primaries::Pal => 5,
+        Primaries::Ntsc => 6,
+        Primaries::GenericFilm => 8,
+        Primaries::Bt2020 => 9,
+        Primaries::Cie1931Xyz => 10,
+        Primaries::DciP3 => 11,
+        Primaries::DisplayP3 => 12,
+        _ => return None,
+    };
+    let transfer_function = match color.transfer_function? {
+        TransferFunction::Bt1886 => 1,
+        TransferFunction::Gamma22 => 4,
+        TransferFunction::Gamma28 => 5,
+        TransferFunction::St240 => 7,
+        TransferFunction::ExtLinear => 8,
+        TransferFunction::Log100 => 9,
+        TransferFunction::Log316 => 10,
+        TransferFunction::Xvycc => 11,
+        TransferFunction::Srgb | TransferFunction::ExtSrgb | TransferFunction::CompoundPower24 => {
+            13
+        }
+        TransferFunction::St2084Pq => 16,
+        TransferFunction::St428 => 17,
+        TransferFunction::Hlg => 18,
+        _ => return None,
+    };
+    // RGB without matrix coefficients, full range.
+    Some([primaries, transfer_function, 0, 1])
+}
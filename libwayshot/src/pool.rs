@@ -0,0 +1,119 @@
+use std::{
+    fs::File,
+    sync::{Mutex, MutexGuard, PoisonError, Weak},
+};
+
+use wayland_client::protocol::{wl_buffer::WlBuffer, wl_output::WlOutput, wl_shm_pool::WlShmPool};
+
+use crate::screencopy::FrameFormat;
+
+/// Buffers kept by default, enough for repeated captures of a few outputs.
+pub(crate) const DEFAULT_BUFFER_POOL_CAPACITY: usize = 4;
+
+/// A wl_shm buffer along with the memfd backing it, destroyed when dropped.
+#[derive(Debug)]
+pub(crate) struct ShmBuffer {
+    pub(crate) output: WlOutput,
+    pub(crate) frame_format: FrameFormat,
+    pub(crate) file: File,
+    pub(crate) buffer: WlBuffer,
+    pub(crate) shm_pool: WlShmPool,
+}
+
+impl Drop for ShmBuffer {
+    fn drop(&mut self) {
+        self.buffer.destroy();
+        self.shm_pool.destroy();
+    }
+}
+
+/// wl_shm buffers kept around by a [`WayshotConnection`](crate::WayshotConnection) after a
+/// capture, so the next capture of the same output in the same format and size reuses them
+/// instead of creating a new memfd, shm pool and buffer.
+///
+/// A buffer only goes back to the pool once the [`RawFrame`](crate::RawFrame) or image built from
+/// it is dropped, so frames are never overwritten while in use. The least recently returned
+/// buffers are dropped first once the pool is full.
+#[derive(Debug)]
+pub struct BufferPool {
+    capacity: usize,
+    buffers: Mutex<Vec<ShmBuffer>>,
+}
+
+impl BufferPool {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            buffers: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Number of buffers kept at most, `0` when pooling is disabled.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of buffers waiting to be reused.
+    pub fn len(&self) -> usize {
+        self.buffers().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffers().is_empty()
+    }
+
+    /// Destroy every buffer waiting to be reused, eg: after outputs changed mode.
+    pub fn clear(&self) {
+        self.buffers().clear();
+    }
+
+    /// Take out a buffer previously used for `output` in `frame_format`.
+    pub(crate) fn take(&self, output: &WlOutput, frame_format: &FrameFormat) -> Option<ShmBuffer> {
+        let mut buffers = self.buffers();
+        let index = buffers
+            .iter()
+            .position(|buffer| &buffer.output == output && &buffer.frame_format == frame_format)?;
+        Some(buffers.remove(index))
+    }
+
+    fn put(&self, buffer: ShmBuffer) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut buffers = self.buffers();
+        if buffers.len() >= self.capacity {
+            buffers.remove(0);
+        }
+        buffers.push(buffer);
+    }
+
+    /// A panic while holding the lock cannot leave the list inconsistent, so poisoning is
+    /// ignored.
+    fn buffers(&self) -> MutexGuard<'_, Vec<ShmBuffer>> {
+        self.buffers.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A buffer taken out of a [`BufferPool`], handed back to it when dropped.
+#[derive(Debug)]
+pub(crate) struct BufferLease {
+    buffer: Option<ShmBuffer>,
+    pool: Weak<BufferPool>,
+}
+
+impl BufferLease {
+    pub(crate) fn new(buffer: ShmBuffer, pool: Weak<BufferPool>) -> Self {
+        Self {
+            buffer: Some(buffer),
+            pool,
+        }
+    }
+}
+
+impl Drop for BufferLease {
+    fn drop(&mut self) {
+        if let (Some(buffer), Some(pool)) = (self.buffer.take(), self.pool.upgrade()) {
+            pool.put(buffer);
+        }
+    }
+}
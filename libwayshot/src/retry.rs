@@ -0,0 +1,106 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    thread,
+    time::Duration,
+};
+
+use crate::{Error, Result};
+
+/// How often to retry a frame copy that failed with a retryable error, see
+/// [`Error::is_retryable`].
+///
+/// Some compositors intermittently fail copies right after a mode change, a short wait is enough
+/// for the next copy to succeed. The attempts a frame took are reported in
+/// [`FrameMetadata::attempts`](crate::FrameMetadata::attempts).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one. `1` disables retrying.
+    pub attempts: u32,
+    /// Delay before the first retry, doubled for every further retry and jittered by up to 50%.
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Fail on the first failed copy.
+    pub const NEVER: Self = Self {
+        attempts: 1,
+        backoff: Duration::ZERO,
+    };
+
+    /// Delay before retry number `retry`, starting at 1.
+    fn delay(&self, retry: u32) -> Duration {
+        let delay = self
+            .backoff
+            .saturating_mul(1 << retry.saturating_sub(1).min(16));
+        // RandomState is seeded randomly, good enough to spread out retries without a rand crate.
+        let jitter = RandomState::new().build_hasher().finish() % 1000;
+        delay + delay.mul_f64(jitter as f64 / 2000.0)
+    }
+
+    /// Log a failed attempt and return how long to wait, `None` if there are no attempts left.
+    fn next_delay(&self, attempt: u32, error: &Error) -> Option<Duration> {
+        if !error.is_retryable() || attempt >= self.attempts {
+            return None;
+        }
+        let delay = self.delay(attempt);
+        tracing::warn!(
+            "Frame copy failed, retrying in {delay:?} (attempt {attempt}/{})",
+            self.attempts
+        );
+        Some(delay)
+    }
+
+    /// Run `capture` until it succeeds or the attempts run out, along with the attempts it took.
+    pub(crate) fn run<T>(&self, mut capture: impl FnMut() -> Result<T>) -> Result<(T, u32)> {
+        let mut attempt = 1;
+        loop {
+            match capture() {
+                Err(e) => match self.next_delay(attempt, &e) {
+                    Some(delay) => thread::sleep(delay),
+                    None => return Err(e),
+                },
+                Ok(value) => {
+                    if attempt > 1 {
+                        tracing::info!("Frame copied after {attempt} attempts");
+                    }
+                    return Ok((value, attempt));
+                }
+            }
+            attempt += 1;
+        }
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) async fn run_async<T, F>(&self, mut capture: impl FnMut() -> F) -> Result<(T, u32)>
+    where
+        F: std::future::Future<Output = Result<T>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match capture().await {
+                Err(e) => match self.next_delay(attempt, &e) {
+                    Some(delay) => tokio::time::sleep(delay).await,
+                    None => return Err(e),
+                },
+                Ok(value) => {
+                    if attempt > 1 {
+                        tracing::info!("Frame copied after {attempt} attempts");
+                    }
+                    return Ok((value, attempt));
+                }
+            }
+            attempt += 1;
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    /// Three attempts, 20ms apart.
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            backoff: Duration::from_millis(20),
+        }
+    }
+}
@@ -1,30 +1,119 @@
 use std::{
-    ffi::CStr,
+    fs::File,
+    io,
     os::fd::{AsRawFd, IntoRawFd, OwnedFd},
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
+#[cfg(feature = "image")]
 use image::{ColorType, DynamicImage, ImageBuffer, Pixel};
+use libwayshot_protocols::screencopy::CaptureFrameState;
 use memmap2::MmapMut;
 use nix::{
     fcntl,
     sys::{memfd, mman, stat},
     unistd,
 };
-use wayland_client::protocol::{wl_output, wl_shm::Format};
-
-use crate::{Error, Result};
-
-/// Type of frame supported by the compositor. For now we only support Argb8888, Xrgb8888, and
-/// Xbgr8888.
-#[derive(Debug, Copy, Clone, PartialEq)]
-pub struct FrameFormat {
-    pub format: Format,
-    pub width: u32,
-    pub height: u32,
-    pub stride: u32,
+use wayland_client::protocol::wl_output;
+use wayland_protocols_wlr::screencopy::v1::client::zwlr_screencopy_frame_v1;
+
+pub use libwayshot_protocols::screencopy::FrameFormat;
+
+#[cfg(feature = "image")]
+use crate::{convert, image_util, strided::StridedView};
+use crate::{pool::BufferLease, Error, Result};
+
+/// What the compositor reported along with a copied frame, eg: for a recorder to timestamp it.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct FrameMetadata {
+    /// The frame is upside down, its first row is the bottom of the output. Images built from
+    /// the frame are flipped back, raw pixels are left as copied, eg: for GL consumers to flip
+    /// their texture coordinates instead.
+    pub y_invert: bool,
+    /// When the copied content was presented, on the compositor's presentation clock, usually
+    /// CLOCK_MONOTONIC. Zero when the compositor did not send a valid time.
+    pub presentation_time: Duration,
+    /// Copies the frame took, more than 1 when failed copies were retried, see
+    /// [`RetryPolicy`](crate::RetryPolicy). Zero before a frame was copied.
+    pub attempts: u32,
+}
+
+impl FrameMetadata {
+    pub(crate) fn from_state(state: &CaptureFrameState) -> Self {
+        Self {
+            y_invert: state
+                .flags
+                .is_some_and(|flags| flags.contains(zwlr_screencopy_frame_v1::Flags::YInvert)),
+            presentation_time: state.presentation_time.unwrap_or_default(),
+            attempts: 1,
+        }
+    }
+}
+
+/// A frame as the compositor copied it: the pixels are left in `frame_format.format` and are not
+/// rotated by `transform` yet, for callers that do their own pixel handling.
+///
+/// Dropping the frame hands its shm buffer back to the [`BufferPool`](crate::BufferPool) of the
+/// connection.
+#[derive(Debug)]
+pub struct RawFrame {
+    pub frame_format: FrameFormat,
+    /// The shm buffer the frame was copied into, rows are `frame_format.stride` bytes apart.
+    pub frame_mmap: MmapMut,
+    /// Transform of the output the frame was captured from.
+    pub transform: wl_output::Transform,
+    pub metadata: FrameMetadata,
+    /// Declared last, so the buffer is only reused once `frame_mmap` is unmapped.
+    pub(crate) _lease: Option<BufferLease>,
 }
 
+impl RawFrame {
+    /// Pixels of the frame, in place in the shm buffer. Rows are [`Self::stride`] bytes apart and
+    /// may carry padding past `width * bytes_per_pixel`.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.frame_mmap
+    }
+
+    /// Mutable pixels of the frame, see [`Self::as_bytes`].
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.frame_mmap
+    }
+
+    /// Bytes between the start of two rows.
+    pub fn stride(&self) -> u32 {
+        self.frame_format.stride
+    }
+
+    /// Format, size and stride the compositor copied the frame with.
+    pub fn format(&self) -> FrameFormat {
+        self.frame_format
+    }
+
+    /// The mapping of the shm buffer itself, eg: to keep it around without copying it.
+    pub fn map(&self) -> &MmapMut {
+        &self.frame_mmap
+    }
+
+    /// Iterate over the pixel bytes of every row, skipping the padding.
+    pub fn rows(&self) -> Result<impl Iterator<Item = &[u8]>> {
+        let row_len = self.frame_format.width as usize * self.frame_format.bytes_per_pixel()?;
+        let stride = self.frame_format.stride as usize;
+        let height = self.frame_format.height as usize;
+        if stride == 0 || stride < row_len {
+            return Err(Error::InvalidStride);
+        }
+        if height > 0 && self.frame_mmap.len() < stride * (height - 1) + row_len {
+            return Err(Error::BufferTooSmall);
+        }
+        Ok(self
+            .frame_mmap
+            .chunks(stride)
+            .take(height)
+            .map(move |row| &row[..row_len]))
+    }
+}
+
+#[cfg(feature = "image")]
 fn create_image_buffer<P>(
     frame_format: &FrameFormat,
     frame_mmap: &MmapMut,
@@ -32,46 +121,95 @@ fn create_image_buffer<P>(
 where
     P: Pixel<Subpixel = u8>,
 {
-    ImageBuffer::from_vec(frame_format.width, frame_format.height, frame_mmap.to_vec())
-        .ok_or(Error::BufferTooSmall)
+    let view = StridedView::new(&frame_mmap[..], frame_format)?;
+    ImageBuffer::from_vec(
+        frame_format.width,
+        frame_format.height,
+        view.to_packed_vec(),
+    )
+    .ok_or(Error::BufferTooSmall)
 }
 
-/// The copied frame comprising of the FrameFormat, ColorType (Rgba8), and a memory backed shm
-/// file that holds the image data in it.
+/// The copied frame comprising of the FrameFormat, ColorType, and a memory backed shm file that
+/// holds the image data in it. For [`ColorType::Rgba16`] the file still holds the packed 10-bit
+/// pixels, they are widened when converting to a [`DynamicImage`].
+#[cfg(feature = "image")]
 #[derive(Debug)]
 pub struct FrameCopy {
     pub frame_format: FrameFormat,
     pub frame_color_type: ColorType,
     pub frame_mmap: MmapMut,
     pub transform: wl_output::Transform,
+    pub metadata: FrameMetadata,
+    pub(crate) _lease: Option<BufferLease>,
 }
 
+#[cfg(feature = "image")]
 impl TryFrom<FrameCopy> for DynamicImage {
     type Error = Error;
 
     fn try_from(value: FrameCopy) -> Result<Self> {
-        Ok(match value.frame_color_type {
+        let image = match value.frame_color_type {
             ColorType::Rgb8 => {
                 Self::ImageRgb8(create_image_buffer(&value.frame_format, &value.frame_mmap)?)
             }
             ColorType::Rgba8 => {
                 Self::ImageRgba8(create_image_buffer(&value.frame_format, &value.frame_mmap)?)
             }
+            ColorType::Rgba16 => {
+                let view = StridedView::new(&value.frame_mmap[..], &value.frame_format)?;
+                Self::ImageRgba16(
+                    ImageBuffer::from_vec(
+                        value.frame_format.width,
+                        value.frame_format.height,
+                        convert::expand_bgr10(&view),
+                    )
+                    .ok_or(Error::BufferTooSmall)?,
+                )
+            }
             _ => return Err(Error::InvalidColor),
+        };
+        Ok(if value.metadata.y_invert {
+            image_util::flip_vertical(image)
+        } else {
+            image
         })
     }
 }
 
+/// Bytes of a wl_shm pool holding a `frame_format` frame, which wl_shm sizes with an `i32`.
+///
+/// Fails with [`Error::BufferTooSmall`] when `stride * height` does not fit.
+pub(crate) fn shm_pool_size(frame_format: &FrameFormat) -> Result<i32> {
+    frame_format
+        .stride
+        .checked_mul(frame_format.height)
+        .and_then(|bytes| i32::try_from(bytes).ok())
+        .ok_or(Error::BufferTooSmall)
+}
+
+/// Map a shm file created with [`create_shm_fd`] for reading and writing.
+///
+/// This is the only way libwayshot maps memory shared with the compositor.
+pub(crate) fn map_shm(mem_file: &File) -> io::Result<MmapMut> {
+    // SAFETY: the mapping is only undefined behaviour if another process truncates the file or
+    // writes to it while it is read. The file is an anonymous memfd or an unlinked shm object, so
+    // only the compositor holds it, and it is sealed against shrinking where memfd is available.
+    // The compositor only writes while a copy is in flight, before the frame's ready event is
+    // dispatched, and nothing reads the mapping before then.
+    unsafe { MmapMut::map_mut(mem_file) }
+}
+
 /// Return a RawFd to a shm file. We use memfd create on linux and shm_open for BSD support.
-/// You don't need to mess around with this function, it is only used by
-/// capture_output_frame.
+/// Useful to back the fd passed to [`crate::WayshotConnection::capture_output_frame_shm_fd`], or
+/// any other wl_shm buffer.
 pub fn create_shm_fd() -> std::io::Result<OwnedFd> {
     // Only try memfd on linux and freebsd.
     #[cfg(any(target_os = "linux", target_os = "freebsd"))]
     loop {
         // Create a file that closes on succesful execution and seal it's operations.
         match memfd::memfd_create(
-            CStr::from_bytes_with_nul(b"libwayshot\0").unwrap(),
+            c"libwayshot",
             memfd::MemFdCreateFlag::MFD_CLOEXEC | memfd::MemFdCreateFlag::MFD_ALLOW_SEALING,
         ) {
             Ok(fd) => {
@@ -96,7 +234,10 @@ pub fn create_shm_fd() -> std::io::Result<OwnedFd> {
     let sys_time = SystemTime::now();
     let mut mem_file_handle = format!(
         "/libwayshot-{}",
-        sys_time.duration_since(UNIX_EPOCH).unwrap().subsec_nanos()
+        sys_time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .subsec_nanos()
     );
     loop {
         match mman::shm_open(
@@ -124,7 +265,10 @@ pub fn create_shm_fd() -> std::io::Result<OwnedFd> {
                 // If a file with that handle exists then change the handle
                 mem_file_handle = format!(
                     "/libwayshot-{}",
-                    sys_time.duration_since(UNIX_EPOCH).unwrap().subsec_nanos()
+                    sys_time
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .subsec_nanos()
                 );
                 continue;
             }
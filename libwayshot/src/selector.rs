@@ -0,0 +1,963 @@
+use std::{fs::File, os::fd::AsFd, time::Duration};
+
+use libwayshot_protocols::dispatch_timeout;
+use memmap2::MmapMut;
+use wayland_client::{
+    delegate_noop,
+    protocol::{
+        wl_buffer::{self, WlBuffer},
+        wl_compositor::WlCompositor,
+        wl_keyboard::{self, KeyState, WlKeyboard},
+        wl_pointer::{self, ButtonState, WlPointer},
+        wl_seat::{self, Capability, WlSeat},
+        wl_shm::{self, WlShm},
+        wl_shm_pool::WlShmPool,
+        wl_surface::WlSurface,
+    },
+    Connection, Dispatch, DispatchError, Proxy, QueueHandle, WEnum,
+};
+use wayland_protocols::wp::cursor_shape::v1::client::{
+    wp_cursor_shape_device_v1::{Shape, WpCursorShapeDeviceV1},
+    wp_cursor_shape_manager_v1::WpCursorShapeManagerV1,
+};
+use wayland_protocols_wlr::layer_shell::v1::client::{
+    zwlr_layer_shell_v1::{Layer, ZwlrLayerShellV1},
+    zwlr_layer_surface_v1::{self, Anchor, KeyboardInteractivity, ZwlrLayerSurfaceV1},
+};
+
+use crate::{
+    output::OutputInfo,
+    overlay::{
+        text::{draw_label, label_size, LabelStyle},
+        Canvas,
+    },
+    screencopy::{create_shm_fd, map_shm},
+    CaptureRegion, Error, Result, WayshotConnection,
+};
+
+/// How often the overlays check whether they were interrupted, see
+/// [`WayshotConnection::set_interrupt`].
+const INTERRUPT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Left mouse button, from linux/input-event-codes.h.
+const BTN_LEFT: u32 = 0x110;
+/// Right mouse button, from linux/input-event-codes.h.
+const BTN_RIGHT: u32 = 0x111;
+/// Escape key, from linux/input-event-codes.h.
+const KEY_ESC: u32 = 1;
+/// Enter and keypad Enter keys, from linux/input-event-codes.h.
+const KEY_ENTER: u32 = 28;
+const KEY_KPENTER: u32 = 96;
+/// Keys 1 to 9 of the top row, from linux/input-event-codes.h.
+const KEY_1: u32 = 2;
+const KEY_9: u32 = 10;
+const BORDER_WIDTH: i32 = 2;
+/// Half the side of the square resize handles, and how far from a handle it can be grabbed.
+const HANDLE_RADIUS: i32 = 5;
+// Premultiplied Argb8888, stored as little endian B, G, R, A.
+const DIM: [u8; 4] = [0, 0, 0, 0x80];
+const BORDER: [u8; 4] = [0xff, 0xff, 0xff, 0xff];
+const CLEAR: [u8; 4] = [0, 0, 0, 0];
+const LABEL_BACKGROUND: [u8; 4] = [22, 22, 22, 0xe0];
+const LABEL_FOREGROUND: [u8; 4] = [0xe6, 0xe6, 0xe6, 0xff];
+/// Labels are at most an eighth of the output height.
+const LABEL_HEIGHT_DIVISOR: f32 = 8.0;
+
+/// A region the user selected with [`WayshotConnection::select_region`].
+#[derive(Debug, Clone)]
+pub struct Selection {
+    /// Selected area in the global compositor space, in logical pixels. It may extend past
+    /// `output` when the drag crossed into another output.
+    pub region: CaptureRegion,
+    /// Output the top left corner of `region` is on.
+    pub output: OutputInfo,
+}
+
+/// When a region dragged out with [`WayshotConnection::select_region_with`] is selected.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum SelectionConfirm {
+    /// As soon as the mouse button is released.
+    #[default]
+    OnRelease,
+    /// Once Enter is pressed. Until then the region shows resize handles: dragging a handle
+    /// moves its edges, dragging inside the region moves it, and dragging outside of it starts
+    /// over. Clicking without dragging selects the whole output.
+    Explicit,
+}
+
+/// Edges of a region a resize handle moves.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+struct Edges {
+    left: bool,
+    right: bool,
+    top: bool,
+    bottom: bool,
+}
+
+impl Edges {
+    fn cursor_shape(self) -> Shape {
+        match (self.left || self.right, self.top || self.bottom) {
+            (true, true) if self.left == self.top => Shape::NwseResize,
+            (true, true) => Shape::NeswResize,
+            (true, false) => Shape::EwResize,
+            _ => Shape::NsResize,
+        }
+    }
+}
+
+/// What dragging with the left button held does.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Drag {
+    /// Drag out a new region from `anchor`.
+    New { anchor: (i32, i32) },
+    /// Move `region` along with the pointer, which grabbed it at `grab`.
+    Move {
+        region: CaptureRegion,
+        grab: (i32, i32),
+    },
+    /// Move the `edges` of `region` to the pointer.
+    Resize { region: CaptureRegion, edges: Edges },
+}
+
+impl Drag {
+    /// The region with the pointer at `pointer`, `None` when it does not fit in `i32`.
+    fn apply(self, (x, y): (i32, i32)) -> Option<CaptureRegion> {
+        match self {
+            Drag::New { anchor: (x0, y0) } => CaptureRegion {
+                x_coordinate: x0,
+                y_coordinate: y0,
+                width: x.checked_sub(x0)?,
+                height: y.checked_sub(y0)?,
+            }
+            .normalized()
+            .ok(),
+            Drag::Move {
+                region,
+                grab: (grab_x, grab_y),
+            } => Some(CaptureRegion {
+                x_coordinate: region.x_coordinate.checked_add(x.checked_sub(grab_x)?)?,
+                y_coordinate: region.y_coordinate.checked_add(y.checked_sub(grab_y)?)?,
+                ..region
+            }),
+            Drag::Resize { region, edges } => {
+                let mut x1 = region.x_coordinate;
+                let mut y1 = region.y_coordinate;
+                let mut x2 = x1.checked_add(region.width)?;
+                let mut y2 = y1.checked_add(region.height)?;
+                if edges.left {
+                    x1 = x;
+                }
+                if edges.right {
+                    x2 = x;
+                }
+                if edges.top {
+                    y1 = y;
+                }
+                if edges.bottom {
+                    y2 = y;
+                }
+                // Dragging an edge past the opposite one flips the region.
+                CaptureRegion {
+                    x_coordinate: x1,
+                    y_coordinate: y1,
+                    width: x2.checked_sub(x1)?,
+                    height: y2.checked_sub(y1)?,
+                }
+                .normalized()
+                .ok()
+            }
+        }
+    }
+}
+
+/// Centers of the resize handles of `region`, on its corners and the middle of its edges, along
+/// with the edges each one moves.
+fn handles(region: CaptureRegion) -> [((i32, i32), Edges); 8] {
+    let (x1, y1) = (region.x_coordinate, region.y_coordinate);
+    let x2 = x1.saturating_add(region.width);
+    let y2 = y1.saturating_add(region.height);
+    let x_mid = x1.saturating_add(region.width / 2);
+    let y_mid = y1.saturating_add(region.height / 2);
+    let edges = |left, right, top, bottom| Edges {
+        left,
+        right,
+        top,
+        bottom,
+    };
+    [
+        ((x1, y1), edges(true, false, true, false)),
+        ((x_mid, y1), edges(false, false, true, false)),
+        ((x2, y1), edges(false, true, true, false)),
+        ((x2, y_mid), edges(false, true, false, false)),
+        ((x2, y2), edges(false, true, false, true)),
+        ((x_mid, y2), edges(false, false, false, true)),
+        ((x1, y2), edges(true, false, false, true)),
+        ((x1, y_mid), edges(true, false, false, false)),
+    ]
+}
+
+/// Edges moved by the handle of `region` at `(x, y)`, if there is one.
+fn handle_at(region: CaptureRegion, (x, y): (i32, i32)) -> Option<Edges> {
+    handles(region)
+        .into_iter()
+        .find(|((handle_x, handle_y), _)| {
+            x.abs_diff(*handle_x) <= HANDLE_RADIUS as u32
+                && y.abs_diff(*handle_y) <= HANDLE_RADIUS as u32
+        })
+        .map(|(_, edges)| edges)
+}
+
+/// What the overlays let the user select.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Mode {
+    /// Drag out a region.
+    Region,
+    /// Click an output, or press its number.
+    Output,
+}
+
+/// Pixels shared with the compositor for one overlay surface.
+struct OverlayBuffer {
+    buffer: WlBuffer,
+    pool: WlShmPool,
+    mmap: MmapMut,
+    width: i32,
+    height: i32,
+}
+
+/// Full screen overlay on one output.
+struct OverlaySurface {
+    /// Area of the output, the overlay's surface coordinates start at its top left corner.
+    area: CaptureRegion,
+    surface: WlSurface,
+    layer_surface: ZwlrLayerSurfaceV1,
+    /// Number and name of the output, shown when picking an output.
+    label: String,
+    buffer: Option<OverlayBuffer>,
+    /// The compositor still reads from the buffer, it cannot be redrawn yet.
+    busy: bool,
+    dirty: bool,
+}
+
+struct SelectorState {
+    mode: Mode,
+    confirm: SelectionConfirm,
+    shm: WlShm,
+    cursor_shape_manager: Option<WpCursorShapeManagerV1>,
+    cursor_shape_device: Option<WpCursorShapeDeviceV1>,
+    wl_pointer: Option<WlPointer>,
+    wl_keyboard: Option<WlKeyboard>,
+    overlays: Vec<OverlaySurface>,
+    /// Index of the overlay the pointer is on, its coordinates are relative to it.
+    pointer_focus: Option<usize>,
+    /// Pointer position in the global compositor space.
+    pointer: Option<(i32, i32)>,
+    /// Serial of the latest pointer enter, to change the cursor shape with.
+    enter_serial: Option<u32>,
+    /// What the held button does, while dragging.
+    drag: Option<Drag>,
+    /// With [`SelectionConfirm::Explicit`], the region waiting for Enter.
+    selected: Option<CaptureRegion>,
+    /// `Some(None)` once the selection was cancelled.
+    outcome: Option<Option<CaptureRegion>>,
+    error: Option<Error>,
+}
+
+impl WayshotConnection {
+    /// Let the user select a region by dragging over any output, without an external program
+    /// such as slurp. Clicking without dragging selects the whole output.
+    ///
+    /// Every output is dimmed by a zwlr_layer_shell_v1 overlay while selecting, which is gone
+    /// when this returns. Returns `None` when the user cancelled with Escape or a right click.
+    pub fn select_region(&self) -> Result<Option<Selection>> {
+        self.select_region_with(SelectionConfirm::OnRelease)
+    }
+
+    /// Let the user select a region like [`Self::select_region`] does, selected as `confirm`
+    /// says, eg: to adjust it with resize handles until Enter is pressed.
+    pub fn select_region_with(&self, confirm: SelectionConfirm) -> Result<Option<Selection>> {
+        let Some(region) = self.run_selector(Mode::Region, confirm)? else {
+            return Ok(None);
+        };
+        let Some(output) = self
+            .output_infos
+            .iter()
+            .find(|output| contains(&output.region(), region.x_coordinate, region.y_coordinate))
+        else {
+            return Err(Error::RegionOffScreen(region));
+        };
+        let region = if region.width == 0 || region.height == 0 {
+            output.region()
+        } else {
+            region
+        };
+        Ok(Some(Selection {
+            region,
+            output: output.clone(),
+        }))
+    }
+
+    /// Let the user pick an output on the screen: every output is dimmed and labelled with its
+    /// number and name, clicking an output or pressing its number picks it.
+    ///
+    /// Returns `None` when the user cancelled with Escape or a right click.
+    pub fn pick_output(&self) -> Result<Option<OutputInfo>> {
+        let Some(area) = self.run_selector(Mode::Output, SelectionConfirm::OnRelease)? else {
+            return Ok(None);
+        };
+        Ok(self
+            .output_infos
+            .iter()
+            .find(|output| output.region() == area)
+            .cloned())
+    }
+
+    /// Show the overlays until the user selected something in `mode`, the selected region or
+    /// the area of the picked output.
+    fn run_selector(&self, mode: Mode, confirm: SelectionConfirm) -> Result<Option<CaptureRegion>> {
+        let mut event_queue = self.conn.new_event_queue::<SelectorState>();
+        let qh = event_queue.handle();
+
+        let compositor = self.globals.bind::<WlCompositor, _, _>(&qh, 4..=6, ())?;
+        let layer_shell = self
+            .globals
+            .bind::<ZwlrLayerShellV1, _, _>(&qh, 1..=4, ())
+            .map_err(|_| Error::Protocol {
+                protocol: ZwlrLayerShellV1::interface().name,
+                version: 1,
+            })?;
+        let shm = self.globals.bind::<WlShm, _, _>(&qh, 1..=1, ())?;
+        let seat = self.globals.bind::<WlSeat, _, _>(&qh, 1..=7, ())?;
+        let cursor_shape_manager = self
+            .globals
+            .bind::<WpCursorShapeManagerV1, _, _>(&qh, 1..=1, ())
+            .ok();
+
+        let overlays = self
+            .output_infos
+            .iter()
+            .enumerate()
+            .map(|(index, output_info)| {
+                let surface = compositor.create_surface(&qh, ());
+                let layer_surface = layer_shell.get_layer_surface(
+                    &surface,
+                    Some(&output_info.wl_output),
+                    Layer::Overlay,
+                    "wayshot-selector".to_string(),
+                    &qh,
+                    index,
+                );
+                layer_surface
+                    .set_anchor(Anchor::Top | Anchor::Bottom | Anchor::Left | Anchor::Right);
+                layer_surface.set_exclusive_zone(-1);
+                layer_surface.set_keyboard_interactivity(KeyboardInteractivity::Exclusive);
+                surface.commit();
+                OverlaySurface {
+                    area: output_info.region(),
+                    surface,
+                    layer_surface,
+                    label: format!("{} {}", index + 1, output_info.name),
+                    buffer: None,
+                    busy: false,
+                    dirty: true,
+                }
+            })
+            .collect();
+
+        let mut state = SelectorState {
+            mode,
+            confirm,
+            shm,
+            cursor_shape_manager,
+            cursor_shape_device: None,
+            wl_pointer: None,
+            wl_keyboard: None,
+            overlays,
+            pointer_focus: None,
+            pointer: None,
+            enter_serial: None,
+            drag: None,
+            selected: None,
+            outcome: None,
+            error: None,
+        };
+        let result = loop {
+            if self.interrupted() {
+                break Err(Error::Interrupted);
+            }
+            if let Err(e) = dispatch_timeout(&mut event_queue, &mut state, INTERRUPT_POLL_INTERVAL)
+            {
+                break Err(e.into());
+            }
+            if let Some(e) = state.error.take() {
+                break Err(e);
+            }
+            if let Some(outcome) = state.outcome {
+                break Ok(outcome);
+            }
+            state.redraw();
+            if let Err(e) = self.conn.flush() {
+                break Err(DispatchError::from(e).into());
+            }
+        };
+
+        for overlay in state.overlays.drain(..) {
+            overlay.layer_surface.destroy();
+            overlay.surface.destroy();
+            if let Some(overlay_buffer) = overlay.buffer {
+                overlay_buffer.buffer.destroy();
+                overlay_buffer.pool.destroy();
+            }
+        }
+        if let Some(cursor_shape_device) = state.cursor_shape_device.take() {
+            cursor_shape_device.destroy();
+        }
+        // Release requests exist from wl_seat version 3 and 5 on.
+        if seat.version() >= 3 {
+            if let Some(wl_pointer) = state.wl_pointer.take() {
+                wl_pointer.release();
+            }
+            if let Some(wl_keyboard) = state.wl_keyboard.take() {
+                wl_keyboard.release();
+            }
+        }
+        if seat.version() >= 5 {
+            seat.release();
+        }
+        // Make sure the overlays are gone before anything is captured.
+        event_queue.roundtrip(&mut state)?;
+        result
+    }
+}
+
+fn contains(area: &CaptureRegion, x: i32, y: i32) -> bool {
+    x >= area.x_coordinate
+        && x < area.x_coordinate + area.width
+        && y >= area.y_coordinate
+        && y < area.y_coordinate + area.height
+}
+
+impl SelectorState {
+    /// Current selection in the global compositor space.
+    fn selection(&self) -> Option<CaptureRegion> {
+        match self.drag {
+            Some(drag) => drag.apply(self.pointer?),
+            None => self.selected,
+        }
+    }
+
+    /// Start dragging at the pointer: a handle or the inside of a selection waiting for Enter
+    /// adjusts it, anywhere else starts a new one.
+    fn press(&mut self) {
+        let Some(pointer) = self.pointer else {
+            return;
+        };
+        self.drag = Some(match self.selected {
+            Some(region) => match handle_at(region, pointer) {
+                Some(edges) => Drag::Resize { region, edges },
+                None if contains(&region, pointer.0, pointer.1) => Drag::Move {
+                    region,
+                    grab: pointer,
+                },
+                None => Drag::New { anchor: pointer },
+            },
+            None => Drag::New { anchor: pointer },
+        });
+        self.mark_dirty();
+    }
+
+    /// Finish dragging, selecting the region or, with [`SelectionConfirm::Explicit`], keeping it
+    /// for adjustments until Enter is pressed.
+    fn release(&mut self) {
+        let selection = self.selection();
+        self.drag = None;
+        match self.confirm {
+            SelectionConfirm::OnRelease => self.outcome = Some(selection),
+            SelectionConfirm::Explicit => {
+                // A click selects the output it is on, as it would once confirmed.
+                let focused = self
+                    .pointer_focus
+                    .and_then(|index| self.overlays.get(index))
+                    .map(|overlay| overlay.area);
+                self.selected = match selection {
+                    Some(region) if region.width == 0 || region.height == 0 => focused,
+                    Some(region) => Some(region),
+                    None => self.selected,
+                };
+                self.mark_dirty();
+            }
+        }
+    }
+
+    /// Cursor shape telling what pressing the button at the pointer would do.
+    fn cursor_shape(&self) -> Shape {
+        match self.mode {
+            Mode::Output => Shape::Pointer,
+            Mode::Region => match (self.selected, self.pointer, self.drag) {
+                (Some(region), Some(pointer), None) => match handle_at(region, pointer) {
+                    Some(edges) => edges.cursor_shape(),
+                    None if contains(&region, pointer.0, pointer.1) => Shape::Move,
+                    None => Shape::Crosshair,
+                },
+                (_, _, Some(Drag::Move { .. })) => Shape::Move,
+                (_, _, Some(Drag::Resize { edges, .. })) => edges.cursor_shape(),
+                _ => Shape::Crosshair,
+            },
+        }
+    }
+
+    fn update_cursor_shape(&self) {
+        if let (Some(cursor_shape_device), Some(serial)) =
+            (&self.cursor_shape_device, self.enter_serial)
+        {
+            cursor_shape_device.set_shape(serial, self.cursor_shape());
+        }
+    }
+
+    /// Redraw the overlays that changed and that the compositor released.
+    fn redraw(&mut self) {
+        let selection = self.selection();
+        for (index, overlay) in self.overlays.iter_mut().enumerate() {
+            let Some(overlay_buffer) = &mut overlay.buffer else {
+                continue;
+            };
+            if !overlay.dirty || overlay.busy {
+                continue;
+            }
+            match self.mode {
+                Mode::Region => {
+                    let with_handles = self.confirm == SelectionConfirm::Explicit;
+                    draw(overlay_buffer, overlay.area, selection, with_handles)
+                }
+                Mode::Output => {
+                    let hovered = self.pointer_focus == Some(index);
+                    if let Err(e) = draw_output_label(overlay_buffer, &overlay.label, hovered) {
+                        self.error = Some(e);
+                        return;
+                    }
+                }
+            }
+            overlay.surface.attach(Some(&overlay_buffer.buffer), 0, 0);
+            overlay
+                .surface
+                .damage_buffer(0, 0, overlay_buffer.width, overlay_buffer.height);
+            overlay.surface.commit();
+            overlay.busy = true;
+            overlay.dirty = false;
+        }
+    }
+
+    /// Track the pointer at `surface_x`, `surface_y` on the focused overlay.
+    fn move_pointer(&mut self, surface_x: f64, surface_y: f64) {
+        let Some(overlay) = self
+            .pointer_focus
+            .and_then(|index| self.overlays.get(index))
+        else {
+            return;
+        };
+        self.pointer = Some((
+            overlay
+                .area
+                .x_coordinate
+                .saturating_add(surface_x.floor() as i32),
+            overlay
+                .area
+                .y_coordinate
+                .saturating_add(surface_y.floor() as i32),
+        ));
+        if self.drag.is_some() {
+            self.mark_dirty();
+        }
+    }
+
+    /// Pick the output of the overlay at `index`, if there is one.
+    fn pick(&mut self, index: usize) {
+        if let Some(overlay) = self.overlays.get(index) {
+            self.outcome = Some(Some(overlay.area));
+        }
+    }
+
+    fn mark_dirty(&mut self) {
+        for overlay in &mut self.overlays {
+            overlay.dirty = true;
+        }
+    }
+
+    fn create_buffer(
+        &self,
+        index: usize,
+        width: i32,
+        height: i32,
+        qh: &QueueHandle<Self>,
+    ) -> Result<OverlayBuffer> {
+        let stride = width * 4;
+        let mem_file = File::from(create_shm_fd()?);
+        mem_file.set_len((stride * height) as u64)?;
+        let mmap = map_shm(&mem_file)?;
+        let pool = self
+            .shm
+            .create_pool(mem_file.as_fd(), stride * height, qh, ());
+        let buffer = pool.create_buffer(
+            0,
+            width,
+            height,
+            stride,
+            wl_shm::Format::Argb8888,
+            qh,
+            index,
+        );
+        Ok(OverlayBuffer {
+            buffer,
+            pool,
+            mmap,
+            width,
+            height,
+        })
+    }
+}
+
+/// Dim the part of `area` outside `selection` and draw a border around it, along with its resize
+/// handles when `with_handles`.
+fn draw(
+    overlay_buffer: &mut OverlayBuffer,
+    area: CaptureRegion,
+    selection: Option<CaptureRegion>,
+    with_handles: bool,
+) {
+    let handle_centers = selection
+        .filter(|_| with_handles)
+        .map(|selection| handles(selection).map(|(center, _)| center));
+    let row_bytes = overlay_buffer.width as usize * 4;
+    for (y, row) in overlay_buffer.mmap.chunks_exact_mut(row_bytes).enumerate() {
+        let y = area.y_coordinate + y as i32;
+        for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+            let x = area.x_coordinate + x as i32;
+            let on_handle = handle_centers.iter().flatten().any(|(handle_x, handle_y)| {
+                x.abs_diff(*handle_x) <= HANDLE_RADIUS as u32
+                    && y.abs_diff(*handle_y) <= HANDLE_RADIUS as u32
+            });
+            let color = match selection {
+                _ if on_handle => BORDER,
+                Some(selection) => {
+                    let inside = |inset: i32| {
+                        x >= selection.x_coordinate - inset
+                            && x < selection.x_coordinate + selection.width + inset
+                            && y >= selection.y_coordinate - inset
+                            && y < selection.y_coordinate + selection.height + inset
+                    };
+                    if inside(0) {
+                        CLEAR
+                    } else if inside(BORDER_WIDTH) {
+                        BORDER
+                    } else {
+                        DIM
+                    }
+                }
+                None => DIM,
+            };
+            pixel.copy_from_slice(&color);
+        }
+    }
+}
+
+/// Dim the whole output and draw `label` in its middle, with a border around the output when
+/// `hovered`.
+fn draw_output_label(overlay_buffer: &mut OverlayBuffer, label: &str, hovered: bool) -> Result<()> {
+    let (width, height) = (overlay_buffer.width as u32, overlay_buffer.height as u32);
+    let mut canvas = Canvas {
+        pixels: &mut overlay_buffer.mmap,
+        width,
+        height,
+    };
+    if hovered {
+        canvas.fill(BORDER);
+        let inset = 2 * BORDER_WIDTH as u32;
+        canvas.fill_rect(
+            BORDER_WIDTH,
+            BORDER_WIDTH,
+            width.saturating_sub(inset),
+            height.saturating_sub(inset),
+            DIM,
+        );
+    } else {
+        canvas.fill(DIM);
+    }
+
+    let mut style = LabelStyle {
+        size: height as f32 / LABEL_HEIGHT_DIVISOR,
+        foreground: LABEL_FOREGROUND,
+        background: Some(LABEL_BACKGROUND),
+        padding: 0,
+    };
+    // Shrink long names until they fit on the output.
+    let (label_width, _) = label_size(label, &style)?;
+    if label_width > width * 9 / 10 {
+        style.size *= (width * 9 / 10) as f32 / label_width as f32;
+    }
+    style.padding = (style.size / 2.0) as u32;
+    draw_label(&mut canvas, label, &style)
+}
+
+impl Dispatch<ZwlrLayerSurfaceV1, usize> for SelectorState {
+    fn event(
+        state: &mut Self,
+        layer_surface: &ZwlrLayerSurfaceV1,
+        event: zwlr_layer_surface_v1::Event,
+        index: &usize,
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_layer_surface_v1::Event::Configure {
+                serial,
+                width,
+                height,
+            } => {
+                layer_surface.ack_configure(serial);
+                let (width, height) = (width as i32, height as i32);
+                let Some(overlay) = state.overlays.get(*index) else {
+                    return;
+                };
+                if overlay
+                    .buffer
+                    .as_ref()
+                    .is_some_and(|buffer| (buffer.width, buffer.height) == (width, height))
+                {
+                    return;
+                }
+                match state.create_buffer(*index, width, height, qh) {
+                    Ok(overlay_buffer) => {
+                        let overlay = &mut state.overlays[*index];
+                        if let Some(old) = overlay.buffer.replace(overlay_buffer) {
+                            old.buffer.destroy();
+                            old.pool.destroy();
+                        }
+                        overlay.busy = false;
+                        overlay.dirty = true;
+                    }
+                    Err(e) => state.error = Some(e),
+                }
+            }
+            zwlr_layer_surface_v1::Event::Closed => {
+                tracing::debug!("Selection overlay closed by the compositor");
+                state.outcome = Some(None);
+            }
+            _ => (),
+        }
+    }
+}
+
+impl Dispatch<WlBuffer, usize> for SelectorState {
+    fn event(
+        state: &mut Self,
+        _: &WlBuffer,
+        event: wl_buffer::Event,
+        index: &usize,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wl_buffer::Event::Release = event {
+            if let Some(overlay) = state.overlays.get_mut(*index) {
+                overlay.busy = false;
+            }
+        }
+    }
+}
+
+impl Dispatch<WlSeat, ()> for SelectorState {
+    fn event(
+        state: &mut Self,
+        seat: &WlSeat,
+        event: wl_seat::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let wl_seat::Event::Capabilities {
+            capabilities: WEnum::Value(capabilities),
+        } = event
+        {
+            if capabilities.contains(Capability::Pointer) {
+                let wl_pointer = seat.get_pointer(qh, ());
+                state.cursor_shape_device =
+                    state
+                        .cursor_shape_manager
+                        .as_ref()
+                        .map(|cursor_shape_manager| {
+                            cursor_shape_manager.get_pointer(&wl_pointer, qh, ())
+                        });
+                state.wl_pointer = Some(wl_pointer);
+            }
+            if capabilities.contains(Capability::Keyboard) {
+                state.wl_keyboard = Some(seat.get_keyboard(qh, ()));
+            }
+        }
+    }
+}
+
+impl Dispatch<WlPointer, ()> for SelectorState {
+    fn event(
+        state: &mut Self,
+        _: &WlPointer,
+        event: wl_pointer::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_pointer::Event::Enter {
+                serial,
+                surface,
+                surface_x,
+                surface_y,
+            } => {
+                state.pointer_focus = state
+                    .overlays
+                    .iter()
+                    .position(|overlay| overlay.surface == surface);
+                state.move_pointer(surface_x, surface_y);
+                state.enter_serial = Some(serial);
+                state.update_cursor_shape();
+                if state.mode == Mode::Output {
+                    state.mark_dirty();
+                }
+            }
+            wl_pointer::Event::Leave { .. } => {
+                state.pointer_focus = None;
+                if state.mode == Mode::Output {
+                    state.mark_dirty();
+                }
+            }
+            // While a button is held the pointer stays on the overlay it was pressed on, even
+            // when moved past its edges onto another output.
+            wl_pointer::Event::Motion {
+                surface_x,
+                surface_y,
+                ..
+            } => {
+                let shape = state.cursor_shape();
+                state.move_pointer(surface_x, surface_y);
+                if state.cursor_shape() != shape {
+                    state.update_cursor_shape();
+                }
+            }
+            wl_pointer::Event::Button {
+                button,
+                state: WEnum::Value(button_state),
+                ..
+            } => match (button, button_state) {
+                (BTN_LEFT, ButtonState::Pressed) if state.mode == Mode::Output => {
+                    if let Some(index) = state.pointer_focus {
+                        state.pick(index);
+                    }
+                }
+                (BTN_LEFT, ButtonState::Pressed) => state.press(),
+                (BTN_LEFT, ButtonState::Released) if state.drag.is_some() => state.release(),
+                (BTN_RIGHT, ButtonState::Pressed) => state.outcome = Some(None),
+                _ => (),
+            },
+            _ => (),
+        }
+    }
+}
+
+impl Dispatch<WlKeyboard, ()> for SelectorState {
+    fn event(
+        state: &mut Self,
+        _: &WlKeyboard,
+        event: wl_keyboard::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let wl_keyboard::Event::Key {
+            key,
+            state: WEnum::Value(KeyState::Pressed),
+            ..
+        } = event
+        else {
+            return;
+        };
+        match key {
+            KEY_ESC => state.outcome = Some(None),
+            KEY_ENTER | KEY_KPENTER if state.drag.is_none() && state.selected.is_some() => {
+                state.outcome = Some(state.selected);
+            }
+            KEY_1..=KEY_9 if state.mode == Mode::Output => state.pick((key - KEY_1) as usize),
+            _ => (),
+        }
+    }
+}
+
+delegate_noop!(SelectorState: ignore WlCompositor);
+delegate_noop!(SelectorState: ignore WlSurface);
+delegate_noop!(SelectorState: ignore WlShm);
+delegate_noop!(SelectorState: ignore WlShmPool);
+delegate_noop!(SelectorState: ignore ZwlrLayerShellV1);
+delegate_noop!(SelectorState: ignore WpCursorShapeManagerV1);
+delegate_noop!(SelectorState: ignore WpCursorShapeDeviceV1);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(x_coordinate: i32, y_coordinate: i32, width: i32, height: i32) -> CaptureRegion {
+        CaptureRegion {
+            x_coordinate,
+            y_coordinate,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn handles_are_grabbed_near_corners_and_edges() {
+        let selected = region(100, 100, 200, 100);
+        let bottom_right = handle_at(selected, (303, 198)).expect("bottom right handle");
+        assert!(bottom_right.right && bottom_right.bottom);
+        assert!(!bottom_right.left && !bottom_right.top);
+        let top = handle_at(selected, (200, 100)).expect("top handle");
+        assert_eq!(
+            top,
+            Edges {
+                top: true,
+                ..Edges::default()
+            }
+        );
+        assert_eq!(handle_at(selected, (150, 150)), None);
+    }
+
+    #[test]
+    fn dragging_moves_and_resizes_the_selection() {
+        let selected = region(100, 100, 200, 100);
+        let moved = Drag::Move {
+            region: selected,
+            grab: (150, 150),
+        };
+        assert_eq!(moved.apply((170, 140)), Some(region(120, 90, 200, 100)));
+
+        let resized = Drag::Resize {
+            region: selected,
+            edges: Edges {
+                right: true,
+                bottom: true,
+                ..Edges::default()
+            },
+        };
+        assert_eq!(resized.apply((400, 250)), Some(region(100, 100, 300, 150)));
+        // Past the opposite edges, the selection flips.
+        assert_eq!(resized.apply((50, 60)), Some(region(50, 60, 50, 40)));
+
+        let new = Drag::New { anchor: (10, 10) };
+        assert_eq!(new.apply((0, 5)), Some(region(0, 5, 10, 5)));
+    }
+
+    #[test]
+    fn dragging_past_i32_is_rejected() {
+        let moved = Drag::Move {
+            region: region(i32::MAX - 10, 0, 5, 5),
+            grab: (0, 0),
+        };
+        assert_eq!(moved.apply((100, 0)), None);
+    }
+}
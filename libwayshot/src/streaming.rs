@@ -0,0 +1,624 @@
+use std::{fs::File, mem, sync::Arc};
+
+pub use libwayshot_protocols::screencopy::Damage;
+use libwayshot_protocols::screencopy::{CaptureFrameState, FrameState};
+use memmap2::MmapMut;
+use wayland_client::{
+    protocol::{
+        wl_buffer::WlBuffer,
+        wl_output::{Transform, WlOutput},
+        wl_shm_pool::WlShmPool,
+        wl_surface::WlSurface,
+    },
+    DispatchError, EventQueue, QueueHandle,
+};
+use wayland_protocols::wp::viewporter::client::wp_viewport::WpViewport;
+use wayland_protocols_wlr::screencopy::v1::client::{
+    zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1,
+    zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1,
+};
+
+#[cfg(feature = "image")]
+use image::DynamicImage;
+
+#[cfg(feature = "image")]
+use crate::{convert_frame, screencopy::RawFrame};
+use crate::{
+    copy_failure,
+    screencopy::{create_shm_fd, map_shm, shm_pool_size, FrameFormat, FrameMetadata},
+    select_frame_format, Error, Result, ShmDispatch, WayshotConnection,
+};
+
+/// Frames a [`StreamingCaptureContext`] captures into at most, unless changed with
+/// [`StreamingCaptureContext::set_max_buffers`]: one being written by the compositor, one held by
+/// a consumer and a spare.
+pub const DEFAULT_MAX_BUFFERS: usize = 3;
+
+/// A shm buffer frames are copied into, reused as long as the frame format does not change.
+#[derive(Debug)]
+pub(crate) struct StreamBuffer {
+    pub(crate) frame_format: FrameFormat,
+    /// Shared with the [`AcquiredFrame`]s of the buffer.
+    pub(crate) mmap: Arc<MmapMut>,
+    pub(crate) buffer: WlBuffer,
+    shm_pool: WlShmPool,
+}
+
+impl StreamBuffer {
+    pub(crate) fn new<S: ShmDispatch>(
+        wayshot_conn: &WayshotConnection,
+        qh: &QueueHandle<S>,
+        frame_format: FrameFormat,
+    ) -> Result<Self> {
+        let frame_bytes = shm_pool_size(&frame_format)?;
+        let mem_file = File::from(create_shm_fd()?);
+        mem_file.set_len(frame_bytes as u64)?;
+        let (buffer, shm_pool) = wayshot_conn.create_frame_buffer(qh, &frame_format, &mem_file)?;
+        let mmap = Arc::new(map_shm(&mem_file)?);
+        Ok(Self {
+            frame_format,
+            mmap,
+            buffer,
+            shm_pool,
+        })
+    }
+
+    /// Whether no [`AcquiredFrame`] holds the buffer, so the next frame can be copied into it.
+    fn is_free(&self) -> bool {
+        Arc::strong_count(&self.mmap) == 1
+    }
+
+    pub(crate) fn destroy(self) {
+        self.buffer.destroy();
+        self.shm_pool.destroy();
+    }
+}
+
+/// Row layout of the buffer [`StreamingCaptureContext::capture_into_slice`] copies into.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum SliceLayout {
+    /// Rows as far apart as the compositor lays them out, see [`FrameFormat::stride`].
+    #[default]
+    Native,
+    /// Rows right after one another, without any padding.
+    Packed,
+    /// Rows this many bytes apart, eg: to match the padding of a video frame pool.
+    Stride(usize),
+}
+
+impl SliceLayout {
+    /// Bytes between two rows of `row_bytes` pixel data in a frame of `frame_format`.
+    pub(crate) fn stride(self, frame_format: &FrameFormat, row_bytes: usize) -> Result<usize> {
+        let stride = match self {
+            Self::Native => frame_format.stride as usize,
+            Self::Packed => row_bytes,
+            Self::Stride(stride) => stride,
+        };
+        if stride < row_bytes {
+            return Err(Error::InvalidStride);
+        }
+        Ok(stride)
+    }
+}
+
+/// Destination of a frame shown with [`StreamingCaptureContext::present_frame`].
+#[derive(Debug, Copy, Clone)]
+pub struct ViewportOptions<'a> {
+    /// Viewport of the surface the frame is shown on.
+    pub viewport: &'a WpViewport,
+    /// Size of the frame on the surface, in surface-local coordinates.
+    pub width: i32,
+    pub height: i32,
+}
+
+/// A frame held with [`StreamingCaptureContext::acquire`]. The context captures into other
+/// buffers until it is released or dropped, so it can be read, eg: encoded on another thread,
+/// while the next frames are captured.
+#[derive(Debug, Clone)]
+pub struct AcquiredFrame {
+    frame_format: FrameFormat,
+    metadata: FrameMetadata,
+    transform: Transform,
+    mmap: Arc<MmapMut>,
+}
+
+impl AcquiredFrame {
+    pub fn frame_format(&self) -> FrameFormat {
+        self.frame_format
+    }
+
+    pub fn metadata(&self) -> FrameMetadata {
+        self.metadata
+    }
+
+    /// Transform of the captured output, the frame is not rotated by it.
+    pub fn transform(&self) -> Transform {
+        self.transform
+    }
+
+    /// Raw pixels of the frame, laid out as described by [`Self::frame_format`].
+    pub fn pixels(&self) -> &[u8] {
+        &self.mmap[..]
+    }
+
+    /// The frame converted and rotated like [`WayshotConnection::screenshot_single_output`]
+    /// would.
+    #[cfg(feature = "image")]
+    pub fn image(&self) -> Result<DynamicImage> {
+        to_image(self.frame_format, &self.mmap, self.transform, self.metadata)
+    }
+}
+
+/// Repeated captures of one output into reused buffers, for consumers such as PipeWire or VNC
+/// servers that only want to send the parts of the screen that changed.
+///
+/// Frames are captured into a single buffer, unless the latest one is held with
+/// [`Self::acquire`]: then the next frame goes to another buffer, up to
+/// [`DEFAULT_MAX_BUFFERS`], so a consumer can read one frame while the next is captured.
+///
+/// ```no_run
+/// use std::{sync::mpsc, thread};
+///
+/// use libwayshot::{AcquiredFrame, WayshotConnection};
+///
+/// let wayshot_connection = WayshotConnection::new().unwrap();
+/// let output = &wayshot_connection.get_all_outputs()[0];
+/// let mut context = wayshot_connection
+///     .start_streaming_capture(output, false)
+///     .unwrap();
+/// let (sender, receiver) = mpsc::sync_channel::<AcquiredFrame>(1);
+/// let encoder = thread::spawn(move || {
+///     for frame in receiver {
+///         let _image = frame.image().unwrap();
+///         // Encode the image. Dropping the frame gives its buffer back to the context.
+///     }
+/// });
+/// for _ in 0..100 {
+///     context.capture_frame_with_damage().unwrap();
+///     sender.send(context.acquire().unwrap()).unwrap();
+/// }
+/// drop(sender);
+/// encoder.join().unwrap();
+/// ```
+///
+/// Created with [`WayshotConnection::start_streaming_capture`].
+#[derive(Debug)]
+pub struct StreamingCaptureContext<'a> {
+    wayshot_conn: &'a WayshotConnection,
+    output: WlOutput,
+    transform: Transform,
+    cursor_overlay: bool,
+    event_queue: EventQueue<CaptureFrameState>,
+    screencopy_manager: ZwlrScreencopyManagerV1,
+    buffers: Vec<StreamBuffer>,
+    max_buffers: usize,
+    /// Index of the buffer holding the latest frame in `buffers`.
+    latest: Option<usize>,
+    metadata: FrameMetadata,
+    /// The latest frame has another size than the one before it.
+    resized: bool,
+    accumulated_damage: Vec<Damage>,
+}
+
+impl<'a> StreamingCaptureContext<'a> {
+    pub(crate) fn new(
+        wayshot_conn: &'a WayshotConnection,
+        output: WlOutput,
+        transform: Transform,
+        cursor_overlay: bool,
+    ) -> Result<Self> {
+        let event_queue = wayshot_conn.conn.new_event_queue::<CaptureFrameState>();
+        let screencopy_manager = wayshot_conn.bind_screencopy_manager(&event_queue.handle())?;
+        Ok(Self {
+            wayshot_conn,
+            output,
+            transform,
+            cursor_overlay,
+            event_queue,
+            screencopy_manager,
+            buffers: Vec::new(),
+            max_buffers: DEFAULT_MAX_BUFFERS,
+            latest: None,
+            metadata: FrameMetadata::default(),
+            resized: false,
+            accumulated_damage: Vec::new(),
+        })
+    }
+
+    /// Capture into at most `count` buffers. Buffers beyond it are only freed once their frames
+    /// are released, and with every buffer held, captures fail with [`Error::NoFreeBuffer`].
+    pub fn set_max_buffers(&mut self, count: usize) {
+        self.max_buffers = count.max(1);
+    }
+
+    /// Hold on to the latest frame, so the following captures do not overwrite it. Its buffer
+    /// is reused once the frame was given back with [`Self::release`] or dropped.
+    pub fn acquire(&self) -> Result<AcquiredFrame> {
+        let Some(stream_buffer) = self.latest_buffer() else {
+            return Err(Error::NoFrame);
+        };
+        Ok(AcquiredFrame {
+            frame_format: stream_buffer.frame_format,
+            metadata: self.metadata,
+            transform: self.transform,
+            mmap: Arc::clone(&stream_buffer.mmap),
+        })
+    }
+
+    /// Give the buffer of `frame` back for the following captures, the same as dropping it.
+    pub fn release(&self, frame: AcquiredFrame) {
+        drop(frame);
+    }
+
+    /// Copy the next frame into a reused buffer and return the regions that changed.
+    ///
+    /// Blocks until the compositor has damage to report. The first frame, and every frame after
+    /// the buffer format changed, is reported as damaged as a whole. When the output mode or scale
+    /// changed the size of the frames, new buffers are allocated, [`Self::resized`] is set and the
+    /// damage accumulated at the previous size is dropped.
+    pub fn capture_frame_with_damage(&mut self) -> Result<Vec<Damage>> {
+        let (state, format_changed) = self.copy_frame(true)?;
+        let Some(frame_format) = self.frame_format() else {
+            return Err(Error::NoFrame);
+        };
+
+        let damage = if format_changed || state.damage.is_empty() {
+            vec![Damage {
+                x: 0,
+                y: 0,
+                width: frame_format.width,
+                height: frame_format.height,
+            }]
+        } else {
+            if let Some(outside) = state.damage.iter().find(|damage| {
+                u64::from(damage.x) + u64::from(damage.width) > u64::from(frame_format.width)
+                    || u64::from(damage.y) + u64::from(damage.height)
+                        > u64::from(frame_format.height)
+            }) {
+                return Err(Error::ProtocolViolation(format!(
+                    "damage {outside:?} outside of the {}x{} buffer, events: {}",
+                    frame_format.width,
+                    frame_format.height,
+                    state.event_log()
+                )));
+            }
+            state.damage
+        };
+        if self.resized {
+            self.accumulated_damage.clear();
+        }
+        self.accumulated_damage.extend_from_slice(&damage);
+        Ok(damage)
+    }
+
+    /// Capture the current frame right away with as little latency as possible, for callers that
+    /// sample the screen often such as color pickers or motion detection.
+    ///
+    /// The globals, event queue and buffers of this context are reused and the pixels are returned
+    /// as the compositor copied them, rows [`FrameFormat::stride`] bytes apart and usually
+    /// `Xrgb8888`/`Argb8888`, which is B, G, R, A in memory. Once the first call allocated the
+    /// buffer, a 1080p output should take well under 10ms. Damage is not tracked.
+    ///
+    /// ```no_run
+    /// use std::time::Instant;
+    ///
+    /// use libwayshot::WayshotConnection;
+    ///
+    /// let wayshot_connection = WayshotConnection::new().unwrap();
+    /// let output = &wayshot_connection.get_all_outputs()[0];
+    /// let mut context = wayshot_connection
+    ///     .start_streaming_capture(output, false)
+    ///     .unwrap();
+    /// for _ in 0..100 {
+    ///     let start = Instant::now();
+    ///     let (frame_format, pixels) = context.screenshot_fast().unwrap();
+    ///     println!("{:?} {} bytes in {:?}", frame_format.format, pixels.len(), start.elapsed());
+    /// }
+    /// ```
+    pub fn screenshot_fast(&mut self) -> Result<(FrameFormat, &[u8])> {
+        self.copy_frame(false)?;
+        let Some(stream_buffer) = self.latest_buffer() else {
+            return Err(Error::NoFrame);
+        };
+        Ok((stream_buffer.frame_format, &stream_buffer.mmap[..]))
+    }
+
+    /// Capture the current frame right away and copy its pixels into `dest`, a buffer the caller
+    /// owns such as one from a video frame pool.
+    ///
+    /// wl_shm can only copy into memory shared through a file descriptor, so the frame still goes
+    /// through a reused buffer of this context, but no memory is mapped or allocated per
+    /// capture. Damage is not tracked.
+    ///
+    /// `dest` must hold `height` rows of `layout`'s stride and be aligned to the pixel size of
+    /// 4 bytes per pixel formats, so callers can read the pixels as `u32`s.
+    pub fn capture_into_slice(
+        &mut self,
+        dest: &mut [u8],
+        layout: SliceLayout,
+    ) -> Result<FrameFormat> {
+        let (frame_format, pixels) = self.screenshot_fast()?;
+        let bytes_per_pixel = frame_format.bytes_per_pixel()?;
+        let row_bytes = frame_format.width as usize * bytes_per_pixel;
+        let stride = layout.stride(&frame_format, row_bytes)?;
+        let needed = stride
+            .checked_mul(frame_format.height as usize)
+            .ok_or(Error::BufferTooSmall)?;
+        if dest.len() < needed {
+            return Err(Error::BufferTooSmall);
+        }
+        if bytes_per_pixel == 4 && dest.as_ptr().align_offset(mem::align_of::<u32>()) != 0 {
+            return Err(Error::MisalignedBuffer);
+        }
+
+        // A stride or mapping the compositor got wrong is an error, not a panic in the host
+        // application.
+        let source_stride = frame_format.stride as usize;
+        let height = frame_format.height as usize;
+        if source_stride < row_bytes {
+            return Err(Error::InvalidStride);
+        }
+        if height > 0 && pixels.len() < source_stride * (height - 1) + row_bytes {
+            return Err(Error::BufferTooSmall);
+        }
+
+        for (dest_row, row) in dest
+            .chunks_mut(stride)
+            .zip(pixels.chunks(source_stride))
+            .take(height)
+        {
+            dest_row[..row_bytes].copy_from_slice(&row[..row_bytes]);
+        }
+        Ok(FrameFormat {
+            stride: stride as u32,
+            ..frame_format
+        })
+    }
+
+    /// Request a frame and copy it into a free buffer, reallocating one when the format changed.
+    /// Returns the frame events and whether the format changed since the previous frame.
+    fn copy_frame(&mut self, with_damage: bool) -> Result<(CaptureFrameState, bool)> {
+        let frame = self.screencopy_manager.capture_output(
+            self.cursor_overlay as i32,
+            &self.output,
+            &self.event_queue.handle(),
+            (),
+        );
+        // Also when the copy timed out, so the compositor stops writing into the buffer.
+        let copied = self.copy_into_buffer(&frame, with_damage);
+        frame.destroy();
+        copied
+    }
+
+    fn copy_into_buffer(
+        &mut self,
+        frame: &ZwlrScreencopyFrameV1,
+        with_damage: bool,
+    ) -> Result<(CaptureFrameState, bool)> {
+        let qh = self.event_queue.handle();
+        let mut state = CaptureFrameState::default();
+        self.wayshot_conn
+            .dispatch_frame(&mut self.event_queue, &mut state, |state| {
+                state.phase.is_buffer_done()
+            })?;
+        let frame_format = select_frame_format(&state)?;
+
+        let previous_format = self.frame_format();
+        let format_changed = previous_format != Some(frame_format);
+        let index = self.free_buffer(&qh, frame_format)?;
+        let stream_buffer = &self.buffers[index];
+
+        if with_damage {
+            frame.copy_with_damage(&stream_buffer.buffer);
+        } else {
+            frame.copy(&stream_buffer.buffer);
+        }
+        self.wayshot_conn
+            .dispatch_frame(&mut self.event_queue, &mut state, |state| {
+                state.phase.copied().is_some()
+            })?;
+        if state.phase.copied() != Some(FrameState::Finished) {
+            return Err(copy_failure(&state));
+        }
+        self.metadata = FrameMetadata::from_state(&state);
+        self.latest = Some(index);
+        self.resized = previous_format.is_some_and(|previous| {
+            (previous.width, previous.height) != (frame_format.width, frame_format.height)
+        });
+        Ok((state, format_changed))
+    }
+
+    /// Index of a buffer of `frame_format` no [`AcquiredFrame`] holds, preferably the latest one
+    /// so consumers that never acquire frames use a single buffer.
+    fn free_buffer(
+        &mut self,
+        qh: &QueueHandle<CaptureFrameState>,
+        frame_format: FrameFormat,
+    ) -> Result<usize> {
+        let reusable = |stream_buffer: &StreamBuffer| {
+            stream_buffer.frame_format == frame_format && stream_buffer.is_free()
+        };
+        if let Some(index) = self
+            .latest
+            .filter(|&index| reusable(&self.buffers[index]))
+            .or_else(|| self.buffers.iter().position(reusable))
+        {
+            return Ok(index);
+        }
+
+        // Free buffers of another format are replaced first, then the ring grows.
+        let stale = self.buffers.iter().position(StreamBuffer::is_free);
+        if stale.is_none() && self.buffers.len() >= self.max_buffers {
+            return Err(Error::NoFreeBuffer);
+        }
+        tracing::debug!("Allocating stream buffer for {frame_format:?}");
+        let new_buffer = StreamBuffer::new(self.wayshot_conn, qh, frame_format)?;
+        let Some(index) = stale else {
+            self.buffers.push(new_buffer);
+            return Ok(self.buffers.len() - 1);
+        };
+        if self.latest == Some(index) {
+            self.latest = None;
+        }
+        mem::replace(&mut self.buffers[index], new_buffer).destroy();
+        Ok(index)
+    }
+
+    fn latest_buffer(&self) -> Option<&StreamBuffer> {
+        self.latest.and_then(|index| self.buffers.get(index))
+    }
+
+    /// Format of the pixels in [`Self::buffer`], `None` before the first capture.
+    pub fn frame_format(&self) -> Option<FrameFormat> {
+        self.latest_buffer()
+            .map(|stream_buffer| stream_buffer.frame_format)
+    }
+
+    /// Flags and presentation time of the latest frame, eg: to timestamp it in a recording.
+    pub fn metadata(&self) -> FrameMetadata {
+        self.metadata
+    }
+
+    /// Whether the latest frame has another size than the frame before it, eg: because the output
+    /// mode or scale changed. Consumers sizing their own buffers, such as encoders, should
+    /// reconfigure them from [`Self::frame_format`].
+    pub fn resized(&self) -> bool {
+        self.resized
+    }
+
+    /// Transform of the captured output, the frames in [`Self::buffer`] are not rotated by it.
+    pub fn transform(&self) -> Transform {
+        self.transform
+    }
+
+    /// Raw pixels of the latest frame, laid out as described by [`Self::frame_format`].
+    pub fn buffer(&self) -> Option<&[u8]> {
+        self.latest_buffer()
+            .map(|stream_buffer| &stream_buffer.mmap[..])
+    }
+
+    /// The latest frame converted and rotated like
+    /// [`WayshotConnection::screenshot_single_output`] would, leaving the reused buffer as is.
+    #[cfg(feature = "image")]
+    pub fn image(&self) -> Result<DynamicImage> {
+        let Some(stream_buffer) = self.latest_buffer() else {
+            return Err(Error::NoFrame);
+        };
+        to_image(
+            stream_buffer.frame_format,
+            &stream_buffer.mmap,
+            self.transform,
+            self.metadata,
+        )
+    }
+
+    /// Show the latest frame on `surface` without copying it, eg: for a mirror or a preview
+    /// window on the same connection.
+    ///
+    /// The buffer of the latest frame is attached as is, and the output transform, flipped for y-inverted
+    /// frames, is set as the buffer transform so the frame shows upright. With `viewport`, the frame is scaled to its
+    /// destination size. The surface is committed, call this again after every capture to show
+    /// the new frame, as the buffer is written to in place.
+    ///
+    /// ```no_run
+    /// use libwayshot::WayshotConnection;
+    /// # use wayland_client::protocol::wl_surface::WlSurface;
+    /// # fn mirror(wayshot_connection: &WayshotConnection, surface: &WlSurface) {
+    ///
+    /// let output = &wayshot_connection.get_all_outputs()[0];
+    /// let mut context = wayshot_connection
+    ///     .start_streaming_capture(output, true)
+    ///     .unwrap();
+    /// loop {
+    ///     context.capture_frame_with_damage().unwrap();
+    ///     context.present_frame(surface, None).unwrap();
+    /// }
+    /// # }
+    /// ```
+    pub fn present_frame(
+        &self,
+        surface: &WlSurface,
+        viewport: Option<ViewportOptions<'_>>,
+    ) -> Result<()> {
+        let Some(stream_buffer) = self.latest_buffer() else {
+            return Err(Error::NoFrame);
+        };
+        surface.attach(Some(&stream_buffer.buffer), 0, 0);
+        let transform = if self.metadata.y_invert {
+            y_inverted(self.transform)
+        } else {
+            self.transform
+        };
+        surface.set_buffer_transform(transform);
+        surface.damage_buffer(0, 0, i32::MAX, i32::MAX);
+        if let Some(ViewportOptions {
+            viewport,
+            width,
+            height,
+        }) = viewport
+        {
+            viewport.set_destination(width, height);
+        }
+        surface.commit();
+        self.wayshot_conn
+            .conn
+            .flush()
+            .map_err(DispatchError::from)?;
+        Ok(())
+    }
+
+    /// Damage accumulated over every capture since the last [`Self::take_damage`].
+    pub fn accumulated_damage(&self) -> &[Damage] {
+        &self.accumulated_damage
+    }
+
+    /// Return and reset the accumulated damage, typically once a consumer sent an update.
+    pub fn take_damage(&mut self) -> Vec<Damage> {
+        mem::take(&mut self.accumulated_damage)
+    }
+}
+
+/// Convert a copy of `pixels`, leaving the buffer they are in as is.
+#[cfg(feature = "image")]
+pub(crate) fn to_image(
+    frame_format: FrameFormat,
+    pixels: &[u8],
+    transform: Transform,
+    metadata: FrameMetadata,
+) -> Result<DynamicImage> {
+    let mut frame_mmap = MmapMut::map_anon(pixels.len())?;
+    frame_mmap.copy_from_slice(pixels);
+    convert_frame(RawFrame {
+        frame_format,
+        frame_mmap,
+        transform,
+        metadata,
+        _lease: None,
+    })?
+    .try_into()
+}
+
+/// Buffer transform showing an upside down copy of a `transform` output upright: flipping
+/// vertically is flipping horizontally and rotating by 180°.
+fn y_inverted(transform: Transform) -> Transform {
+    match transform {
+        Transform::Normal => Transform::Flipped180,
+        Transform::_90 => Transform::Flipped270,
+        Transform::_180 => Transform::Flipped,
+        Transform::_270 => Transform::Flipped90,
+        Transform::Flipped => Transform::_180,
+        Transform::Flipped90 => Transform::_270,
+        Transform::Flipped180 => Transform::Normal,
+        Transform::Flipped270 => Transform::_90,
+        transform => transform,
+    }
+}
+
+impl Drop for StreamingCaptureContext<'_> {
+    fn drop(&mut self) {
+        for stream_buffer in self.buffers.drain(..) {
+            stream_buffer.destroy();
+        }
+        self.screencopy_manager.destroy();
+    }
+}
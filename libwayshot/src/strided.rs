@@ -0,0 +1,224 @@
+use crate::{screencopy::FrameFormat, Damage, Error, Result};
+
+/// View over a frame buffer whose rows may be padded past `width * bytes_per_pixel`.
+///
+/// Compositors are free to advertise a stride larger than the packed row size, so anything
+/// walking over pixel data has to go through this instead of assuming tightly packed rows.
+pub(crate) struct StridedView<B> {
+    data: B,
+    height: usize,
+    stride: usize,
+    row_len: usize,
+    bytes_per_pixel: usize,
+}
+
+impl<B: AsRef<[u8]>> StridedView<B> {
+    /// Wrap `data`, checking that every row advertised by `frame_format` fits inside it.
+    pub(crate) fn new(data: B, frame_format: &FrameFormat) -> Result<Self> {
+        let bytes_per_pixel = frame_format.bytes_per_pixel()?;
+        let height = frame_format.height as usize;
+        let stride = frame_format.stride as usize;
+        let row_len = frame_format.width as usize * bytes_per_pixel;
+
+        if stride == 0 || stride < row_len {
+            tracing::error!("Stride {stride} is smaller than a row of {row_len} bytes");
+            return Err(Error::InvalidStride);
+        }
+        // The last row does not need to carry padding.
+        let needed = match height {
+            0 => 0,
+            height => stride * (height - 1) + row_len,
+        };
+        if data.as_ref().len() < needed {
+            return Err(Error::BufferTooSmall);
+        }
+
+        Ok(Self {
+            data,
+            height,
+            stride,
+            row_len,
+            bytes_per_pixel,
+        })
+    }
+
+    /// Iterate over the pixel bytes of every row, skipping the padding.
+    pub(crate) fn rows(&self) -> impl Iterator<Item = &[u8]> {
+        self.data
+            .as_ref()
+            .chunks(self.stride)
+            .take(self.height)
+            .map(|row| &row[..self.row_len])
+    }
+
+    /// Copy the pixel data into a tightly packed buffer.
+    pub(crate) fn to_packed_vec(&self) -> Vec<u8> {
+        if self.stride == self.row_len {
+            return self.data.as_ref()[..self.row_len * self.height].to_vec();
+        }
+        let mut packed = Vec::with_capacity(self.row_len * self.height);
+        for row in self.rows() {
+            packed.extend_from_slice(row);
+        }
+        packed
+    }
+
+    /// Copy the pixels of `region` into a tightly packed buffer, `None` when it reaches past the
+    /// rows of the view.
+    pub(crate) fn crop_packed(&self, region: Damage) -> Option<Vec<u8>> {
+        let x = region.x as usize;
+        let width = region.width as usize;
+        let start = x.checked_mul(self.bytes_per_pixel)?;
+        let end = x.checked_add(width)?.checked_mul(self.bytes_per_pixel)?;
+        if end > self.row_len || region.y.checked_add(region.height)? as usize > self.height {
+            return None;
+        }
+        let mut packed = Vec::with_capacity((end - start) * region.height as usize);
+        for row in self
+            .rows()
+            .skip(region.y as usize)
+            .take(region.height as usize)
+        {
+            packed.extend_from_slice(&row[start..end]);
+        }
+        Some(packed)
+    }
+}
+
+impl<B: AsRef<[u8]> + AsMut<[u8]>> StridedView<B> {
+    /// Iterate mutably over the pixel bytes of every row, skipping the padding.
+    pub(crate) fn rows_mut(&mut self) -> impl Iterator<Item = &mut [u8]> {
+        let row_len = self.row_len;
+        self.data
+            .as_mut()
+            .chunks_mut(self.stride)
+            .take(self.height)
+            .map(move |row| &mut row[..row_len])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wayland_client::protocol::wl_shm;
+
+    use super::*;
+
+    /// 2x3 Xrgb8888 frame with rows `stride` bytes apart.
+    fn frame_format(stride: u32) -> FrameFormat {
+        FrameFormat {
+            format: wl_shm::Format::Xrgb8888,
+            width: 2,
+            height: 3,
+            stride,
+        }
+    }
+
+    /// Rows of `row + 1` repeated over the pixels, followed by `0xee` padding.
+    fn padded_frame(stride: usize) -> Vec<u8> {
+        (0..3u8)
+            .flat_map(|row| {
+                let mut bytes = vec![row + 1; 8];
+                bytes.resize(stride, 0xee);
+                bytes
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rejects_a_stride_shorter_than_a_row() {
+        for stride in [0, 4, 7] {
+            assert!(matches!(
+                StridedView::new(vec![0; 64], &frame_format(stride)),
+                Err(Error::InvalidStride)
+            ));
+        }
+    }
+
+    #[test]
+    fn rejects_a_buffer_missing_rows() {
+        // Two padded rows and the first byte of the last one.
+        let data = vec![0; 12 * 2 + 7];
+        assert!(matches!(
+            StridedView::new(data, &frame_format(12)),
+            Err(Error::BufferTooSmall)
+        ));
+    }
+
+    #[test]
+    fn last_row_needs_no_padding() {
+        let mut data = padded_frame(12);
+        data.truncate(12 * 2 + 8);
+        let view = StridedView::new(data, &frame_format(12)).expect("valid view");
+        assert_eq!(view.rows().count(), 3);
+    }
+
+    #[test]
+    fn rows_skip_the_padding() {
+        let view = StridedView::new(padded_frame(12), &frame_format(12)).expect("valid view");
+        let rows: Vec<&[u8]> = view.rows().collect();
+        assert_eq!(rows, [[1; 8], [2; 8], [3; 8]]);
+    }
+
+    #[test]
+    fn rows_mut_leave_the_padding_alone() {
+        let mut view = StridedView::new(padded_frame(12), &frame_format(12)).expect("valid view");
+        for row in view.rows_mut() {
+            assert_eq!(row.len(), 8);
+            row.fill(0);
+        }
+        let data = view.data;
+        for row in data.chunks(12) {
+            assert_eq!(row[..8], [0; 8]);
+            assert_eq!(row[8..], [0xee; 4]);
+        }
+    }
+
+    #[test]
+    fn to_packed_vec_drops_the_padding() {
+        let view = StridedView::new(padded_frame(16), &frame_format(16)).expect("valid view");
+        let mut expected = vec![1; 8];
+        expected.extend([2; 8]);
+        expected.extend([3; 8]);
+        assert_eq!(view.to_packed_vec(), expected);
+    }
+
+    #[test]
+    fn crop_packed_keeps_the_region_only() {
+        let mut data = padded_frame(12);
+        // Tell the second pixel of every row apart from the first.
+        for row in data.chunks_mut(12) {
+            let second = row[0] + 10;
+            row[4..8].fill(second);
+        }
+        let view = StridedView::new(data, &frame_format(12)).expect("valid view");
+        let region = Damage {
+            x: 1,
+            y: 1,
+            width: 1,
+            height: 2,
+        };
+        assert_eq!(view.crop_packed(region), Some([[12; 4], [13; 4]].concat()));
+    }
+
+    #[test]
+    fn crop_packed_rejects_regions_past_the_frame() {
+        let view = StridedView::new(padded_frame(12), &frame_format(12)).expect("valid view");
+        for (x, y, width, height) in [(1, 0, 2, 1), (0, 2, 1, 2), (u32::MAX, 0, 2, 1)] {
+            let region = Damage {
+                x,
+                y,
+                width,
+                height,
+            };
+            assert_eq!(view.crop_packed(region), None);
+        }
+    }
+
+    #[test]
+    fn to_packed_vec_of_packed_rows_ignores_trailing_bytes() {
+        let mut data = padded_frame(8);
+        data.extend([0xff; 5]);
+        let view = StridedView::new(data, &frame_format(8)).expect("valid view");
+        assert_eq!(view.to_packed_vec().len(), 24);
+    }
+}
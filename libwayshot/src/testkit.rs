@@ -0,0 +1,220 @@
+//! Golden screenshot assertions for testing Wayland applications.
+//!
+//! Capture what is currently on screen and compare it against a reference image checked into the
+//! repository, using a structural similarity metric so tiny rendering differences (antialiasing,
+//! dithering) do not fail the test.
+//!
+//! Set `WAYSHOT_UPDATE_GOLDEN=1` to (re)write the reference images instead of comparing.
+//!
+//! ```no_run
+//! use libwayshot::{testkit::{assert_matches_golden, CaptureSource}, WayshotConnection};
+//!
+//! let wayshot_connection = WayshotConnection::new().unwrap();
+//! assert_matches_golden(
+//!     &wayshot_connection,
+//!     &CaptureSource::Output("HEADLESS-1".to_string()),
+//!     "tests/golden/main_window.png",
+//!     0.01,
+//! );
+//! ```
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use image::{DynamicImage, GrayImage, ImageFormat, Rgb, RgbImage};
+
+pub use crate::CaptureSource;
+use crate::{Error, Result, WayshotConnection};
+
+/// Side of the square windows SSIM is computed over.
+const WINDOW_SIZE: u32 = 8;
+// Stabilizing constants from the SSIM paper, for 8 bit channels.
+const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+/// Result of comparing a capture against a golden image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GoldenComparison {
+    /// Mean structural similarity, `1.0` for identical images.
+    pub similarity: f64,
+    /// Whether `1.0 - similarity` is within the tolerance.
+    pub matches: bool,
+    /// Capture and diff images written next to the golden image when the comparison failed.
+    pub artifacts: Option<(PathBuf, PathBuf)>,
+}
+
+/// Capture `source` and compare it against the PNG at `golden_path`.
+///
+/// `tolerance` is the largest accepted dissimilarity, `1.0 - SSIM`. On mismatch the capture and a
+/// heatmap of the differences are written next to the golden image as `<name>.actual.png` and
+/// `<name>.diff.png`.
+pub fn compare_with_golden(
+    wayshot_conn: &WayshotConnection,
+    source: &CaptureSource,
+    golden_path: impl AsRef<Path>,
+    tolerance: f64,
+) -> Result<GoldenComparison> {
+    let golden_path = golden_path.as_ref();
+    let actual = wayshot_conn.capture(source, false)?;
+
+    if env::var_os("WAYSHOT_UPDATE_GOLDEN").is_some_and(|value| value != "0") {
+        tracing::info!("Updating golden image {}", golden_path.display());
+        save_png(&actual, golden_path)?;
+        return Ok(GoldenComparison {
+            similarity: 1.0,
+            matches: true,
+            artifacts: None,
+        });
+    }
+
+    let golden = image::open(golden_path).map_err(|e| {
+        tracing::error!("Failed to open golden image {}: {e}", golden_path.display());
+        Error::Golden(golden_path.to_path_buf())
+    })?;
+
+    let similarity = if golden.width() == actual.width() && golden.height() == actual.height() {
+        ssim(&golden.to_luma8(), &actual.to_luma8())
+    } else {
+        tracing::error!(
+            "Golden image is {}x{} but the capture is {}x{}",
+            golden.width(),
+            golden.height(),
+            actual.width(),
+            actual.height()
+        );
+        0.0
+    };
+    let matches = 1.0 - similarity <= tolerance;
+
+    let artifacts = if matches {
+        None
+    } else {
+        let actual_path = golden_path.with_extension("actual.png");
+        let diff_path = golden_path.with_extension("diff.png");
+        save_png(&actual, &actual_path)?;
+        save_png(&diff_heatmap(&golden, &actual).into(), &diff_path)?;
+        Some((actual_path, diff_path))
+    };
+
+    Ok(GoldenComparison {
+        similarity,
+        matches,
+        artifacts,
+    })
+}
+
+/// Like [`compare_with_golden`], but panics when the capture does not match, for use in `#[test]`
+/// functions.
+pub fn assert_matches_golden(
+    wayshot_conn: &WayshotConnection,
+    source: &CaptureSource,
+    golden_path: impl AsRef<Path>,
+    tolerance: f64,
+) {
+    let golden_path = golden_path.as_ref();
+    match compare_with_golden(wayshot_conn, source, golden_path, tolerance) {
+        Ok(GoldenComparison { matches: true, .. }) => {}
+        Ok(GoldenComparison {
+            similarity,
+            artifacts,
+            ..
+        }) => {
+            let artifacts = artifacts
+                .map(|(actual, diff)| format!(", see {} and {}", actual.display(), diff.display()))
+                .unwrap_or_default();
+            panic!(
+                "capture does not match golden image {}: dissimilarity {:.4} > tolerance {tolerance}{artifacts}",
+                golden_path.display(),
+                1.0 - similarity,
+            );
+        }
+        Err(e) => panic!(
+            "failed to compare against golden image {}: {e}",
+            golden_path.display()
+        ),
+    }
+}
+
+fn save_png(image: &DynamicImage, path: &Path) -> Result<()> {
+    image.save_with_format(path, ImageFormat::Png).map_err(|e| {
+        tracing::error!("Failed to write {}: {e}", path.display());
+        Error::Golden(path.to_path_buf())
+    })
+}
+
+/// Mean SSIM over non-overlapping windows of two equally sized grayscale images.
+fn ssim(a: &GrayImage, b: &GrayImage) -> f64 {
+    let mut total = 0.0;
+    let mut windows = 0u32;
+
+    for window_y in (0..a.height()).step_by(WINDOW_SIZE as usize) {
+        for window_x in (0..a.width()).step_by(WINDOW_SIZE as usize) {
+            let width = WINDOW_SIZE.min(a.width() - window_x);
+            let height = WINDOW_SIZE.min(a.height() - window_y);
+            let count = f64::from(width * height);
+
+            let (mut sum_a, mut sum_b) = (0.0, 0.0);
+            let (mut sum_aa, mut sum_bb, mut sum_ab) = (0.0, 0.0, 0.0);
+            for y in window_y..window_y + height {
+                for x in window_x..window_x + width {
+                    let pa = f64::from(a.get_pixel(x, y)[0]);
+                    let pb = f64::from(b.get_pixel(x, y)[0]);
+                    sum_a += pa;
+                    sum_b += pb;
+                    sum_aa += pa * pa;
+                    sum_bb += pb * pb;
+                    sum_ab += pa * pb;
+                }
+            }
+
+            let mean_a = sum_a / count;
+            let mean_b = sum_b / count;
+            let variance_a = sum_aa / count - mean_a * mean_a;
+            let variance_b = sum_bb / count - mean_b * mean_b;
+            let covariance = sum_ab / count - mean_a * mean_b;
+
+            total += ((2.0 * mean_a * mean_b + C1) * (2.0 * covariance + C2))
+                / ((mean_a * mean_a + mean_b * mean_b + C1) * (variance_a + variance_b + C2));
+            windows += 1;
+        }
+    }
+
+    if windows == 0 {
+        1.0
+    } else {
+        total / f64::from(windows)
+    }
+}
+
+/// Dimmed golden image with every differing pixel painted red, brighter for larger differences.
+fn diff_heatmap(golden: &DynamicImage, actual: &DynamicImage) -> RgbImage {
+    let golden = golden.to_rgb8();
+    let actual = actual.to_rgb8();
+    let mut heatmap = RgbImage::new(golden.width(), golden.height());
+
+    for (x, y, pixel) in heatmap.enumerate_pixels_mut() {
+        let expected = golden.get_pixel(x, y);
+        let difference = actual
+            .get_pixel_checked(x, y)
+            .map(|actual| {
+                expected
+                    .0
+                    .iter()
+                    .zip(actual.0.iter())
+                    .map(|(e, a)| e.abs_diff(*a))
+                    .max()
+                    .unwrap_or(0)
+            })
+            .unwrap_or(u8::MAX);
+        *pixel = if difference == 0 {
+            let Rgb([r, g, b]) = *expected;
+            Rgb([r / 4, g / 4, b / 4])
+        } else {
+            Rgb([difference.saturating_mul(4).max(64), 0, 0])
+        };
+    }
+
+    heatmap
+}
@@ -0,0 +1,86 @@
+//! Listing the windows of compositors implementing ext_foreign_toplevel_list_v1, to capture them
+//! through ext-image-copy-capture.
+
+#[cfg(feature = "image")]
+use image::DynamicImage;
+use libwayshot_protocols::image_copy::ToplevelListState;
+use wayland_client::Proxy;
+use wayland_protocols::ext::foreign_toplevel_list::v1::client::{
+    ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1,
+    ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1,
+};
+
+use crate::{image_copy::ImageCopyCaptureContext, Error, Result, WayshotConnection};
+
+/// A window of the compositor, see [`WayshotConnection::get_all_toplevels`].
+#[derive(Debug, Clone)]
+pub struct TopLevel {
+    pub handle: ExtForeignToplevelHandleV1,
+    pub title: String,
+    pub app_id: String,
+    /// Stable identifier of the window, it does not change when it is listed again.
+    pub identifier: String,
+}
+
+impl WayshotConnection {
+    /// List the windows of the compositor through ext_foreign_toplevel_list_v1.
+    pub fn get_all_toplevels(&self) -> Result<Vec<TopLevel>> {
+        let mut event_queue = self.conn.new_event_queue::<ToplevelListState>();
+        let qh = event_queue.handle();
+        let list = self
+            .globals
+            .bind::<ExtForeignToplevelListV1, _, _>(&qh, 1..=1, ())
+            .map_err(|_| Error::Protocol {
+                protocol: ExtForeignToplevelListV1::interface().name,
+                version: 1,
+            })?;
+
+        let mut state = ToplevelListState::default();
+        // The toplevels are announced first, then their properties.
+        event_queue.roundtrip(&mut state)?;
+        event_queue.roundtrip(&mut state)?;
+        if !state.is_done() {
+            tracing::debug!("Some toplevels did not send all their properties");
+        }
+        list.stop();
+
+        Ok(state
+            .toplevels
+            .into_iter()
+            .filter(|(_, properties)| !properties.closed)
+            .map(|(handle, properties)| TopLevel {
+                handle,
+                title: properties.title,
+                app_id: properties.app_id,
+                identifier: properties.identifier,
+            })
+            .collect())
+    }
+
+    /// Start capturing `toplevel` repeatedly into a reused buffer, tracking which parts of it
+    /// changed between frames. The buffer follows the size of the window as it is resized.
+    pub fn start_toplevel_streaming_capture(
+        &self,
+        toplevel: &TopLevel,
+        cursor_overlay: bool,
+    ) -> Result<ImageCopyCaptureContext<'_>> {
+        ImageCopyCaptureContext::for_toplevel(self, &toplevel.handle, cursor_overlay)
+    }
+
+    /// Take a screenshot of `toplevel`, converted and rotated upright like the output
+    /// screenshots. The image is as large as the window's buffer, in physical pixels.
+    ///
+    /// Fails with [`Error::Protocol`] when the compositor lacks
+    /// ext_foreign_toplevel_image_capture_source_manager_v1 or ext_image_copy_capture_manager_v1,
+    /// and with [`Error::CaptureStopped`] when the window was closed.
+    #[cfg(feature = "image")]
+    pub fn screenshot_toplevel(
+        &self,
+        toplevel: &TopLevel,
+        cursor_overlay: bool,
+    ) -> Result<DynamicImage> {
+        let mut context = self.start_toplevel_streaming_capture(toplevel, cursor_overlay)?;
+        context.capture_frame_with_damage()?;
+        context.image()
+    }
+}
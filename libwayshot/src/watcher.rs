@@ -0,0 +1,133 @@
+use std::io::ErrorKind;
+
+use libwayshot_protocols::output::OutputWatchState;
+use wayland_client::{backend::WaylandError, DispatchError, EventQueue};
+
+use crate::{output::OutputInfo, Error, Result, WayshotConnection};
+
+/// A change to the outputs of a [`WayshotConnection`], reported by [`OutputWatcher`].
+#[derive(Debug, Clone)]
+pub enum OutputEvent {
+    /// An output was plugged in.
+    Added(OutputInfo),
+    /// An output was unplugged, this is how it was last seen.
+    Removed(OutputInfo),
+    /// An output was moved, resized, rotated or got a new mode or color description.
+    Changed(OutputInfo),
+}
+
+/// Keeps listening to the compositor for outputs being plugged, unplugged or reconfigured, so
+/// long-running captures survive monitor hot-plug.
+///
+/// Created with [`WayshotConnection::watch_outputs`]. Every dispatch refreshes the outputs of the
+/// connection when something changed, and reports what did.
+///
+/// ```no_run
+/// use libwayshot::{OutputEvent, WayshotConnection};
+///
+/// let mut wayshot_connection = WayshotConnection::new().unwrap();
+/// let mut watcher = wayshot_connection.watch_outputs().unwrap();
+/// loop {
+///     for event in watcher.blocking_dispatch(&mut wayshot_connection).unwrap() {
+///         if let OutputEvent::Added(output) = event {
+///             println!("{} plugged in", output.name);
+///         }
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct OutputWatcher {
+    event_queue: EventQueue<OutputWatchState>,
+    state: OutputWatchState,
+}
+
+impl WayshotConnection {
+    /// Start watching the outputs of this connection, see [`OutputWatcher`].
+    pub fn watch_outputs(&self) -> Result<OutputWatcher> {
+        let mut state = OutputWatchState::default();
+        let mut event_queue = self.conn.new_event_queue::<OutputWatchState>();
+        let _ = self.conn.display().get_registry(&event_queue.handle(), ());
+        // The current outputs are announced first, and are already known.
+        event_queue.roundtrip(&mut state)?;
+        event_queue.roundtrip(&mut state)?;
+        state.changed = false;
+        Ok(OutputWatcher { event_queue, state })
+    }
+}
+
+impl OutputWatcher {
+    /// Handle whatever the compositor sent so far without blocking, and report the changes to the
+    /// outputs of `wayshot_conn`.
+    ///
+    /// To wait for changes in an event loop, poll the file descriptor of the connection,
+    /// `wayshot_conn.conn.backend().poll_fd()`.
+    pub fn dispatch(&mut self, wayshot_conn: &mut WayshotConnection) -> Result<Vec<OutputEvent>> {
+        self.event_queue.flush().map_err(DispatchError::from)?;
+        if let Some(guard) = self.event_queue.prepare_read() {
+            match guard.read() {
+                Ok(_) => {}
+                Err(WaylandError::Io(e)) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(DispatchError::from(e).into()),
+            }
+        }
+        self.event_queue.dispatch_pending(&mut self.state)?;
+        self.update(wayshot_conn)
+    }
+
+    /// Wait for the compositor to send something, then report the changes to the outputs of
+    /// `wayshot_conn`. The result is empty when what arrived did not change any output.
+    pub fn blocking_dispatch(
+        &mut self,
+        wayshot_conn: &mut WayshotConnection,
+    ) -> Result<Vec<OutputEvent>> {
+        self.event_queue.blocking_dispatch(&mut self.state)?;
+        self.update(wayshot_conn)
+    }
+
+    fn update(&mut self, wayshot_conn: &mut WayshotConnection) -> Result<Vec<OutputEvent>> {
+        if !self.state.changed {
+            return Ok(Vec::new());
+        }
+        self.state.changed = false;
+
+        let previous = wayshot_conn.output_infos.clone();
+        match wayshot_conn.refresh_outputs() {
+            Ok(()) => {}
+            // Every output is gone, eg: the only monitor was unplugged.
+            Err(Error::NoOutputs) => wayshot_conn.output_infos.clear(),
+            Err(e) => return Err(e),
+        }
+        Ok(diff_outputs(&previous, &wayshot_conn.output_infos))
+    }
+}
+
+/// Outputs are matched by connector name, their wl_output objects are new after every refresh.
+fn diff_outputs(previous: &[OutputInfo], current: &[OutputInfo]) -> Vec<OutputEvent> {
+    let removed = previous
+        .iter()
+        .filter(|output| !current.iter().any(|current| current.name == output.name))
+        .map(|output| OutputEvent::Removed(output.clone()));
+    let added_or_changed = current.iter().filter_map(|output| {
+        match previous
+            .iter()
+            .find(|previous| previous.name == output.name)
+        {
+            None => Some(OutputEvent::Added(output.clone())),
+            Some(previous) if !same_configuration(previous, output) => {
+                Some(OutputEvent::Changed(output.clone()))
+            }
+            Some(_) => None,
+        }
+    });
+    removed.chain(added_or_changed).collect()
+}
+
+fn same_configuration(a: &OutputInfo, b: &OutputInfo) -> bool {
+    a.description == b.description
+        && a.make == b.make
+        && a.model == b.model
+        && a.transform == b.transform
+        && a.dimensions == b.dimensions
+        && a.mode == b.mode
+        && a.color == b.color
+}
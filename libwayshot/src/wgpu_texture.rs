@@ -0,0 +1,117 @@
+//! Uploading captures into wgpu textures, for GUI toolkits rendering with wgpu such as iced or
+//! egui.
+//!
+//! Frames are copied through wl_shm buffers and uploaded with [`wgpu::Queue::write_texture`], so
+//! no EGL or DMA-BUF handling is involved.
+
+use wayland_client::protocol::wl_shm;
+
+use crate::{output::OutputInfo, screencopy::RawFrame, Error, Result, WayshotConnection};
+
+impl WayshotConnection {
+    /// Capture `output` and upload it into a new texture on `device`, ready to be sampled.
+    ///
+    /// The texture is `Bgra8Unorm` or `Rgba8Unorm` for 8-bit outputs and `Rgb10a2Unorm` for
+    /// 10-bit ones, and can be copied from and written to. Like [`RawFrame`], it is not rotated by
+    /// the output transform.
+    ///
+    /// To show a live capture, keep the texture and update it with
+    /// [`RawFrame::write_wgpu_texture`] instead of creating one per frame.
+    pub fn capture_to_wgpu_texture(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        output: &OutputInfo,
+        cursor_overlay: bool,
+    ) -> Result<wgpu::Texture> {
+        let mut frame = self.capture_raw_frame(output, cursor_overlay, None)?;
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("wayshot capture"),
+            size: frame.wgpu_extent(),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: frame.wgpu_texture_format()?,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        frame.write_wgpu_texture(queue, &texture)?;
+        Ok(texture)
+    }
+}
+
+impl RawFrame {
+    /// Texture format holding the pixels of the frame as they are, or
+    /// [`Error::TextureFormat`] when wgpu has none, eg: for 24-bit formats.
+    pub fn wgpu_texture_format(&self) -> Result<wgpu::TextureFormat> {
+        // wl_shm formats are named from the most significant bits of a little endian word.
+        match self.frame_format.format {
+            wl_shm::Format::Xrgb8888 | wl_shm::Format::Argb8888 => {
+                Ok(wgpu::TextureFormat::Bgra8Unorm)
+            }
+            wl_shm::Format::Xbgr8888 | wl_shm::Format::Abgr8888 => {
+                Ok(wgpu::TextureFormat::Rgba8Unorm)
+            }
+            wl_shm::Format::Xbgr2101010 | wl_shm::Format::Abgr2101010 => {
+                Ok(wgpu::TextureFormat::Rgb10a2Unorm)
+            }
+            format => Err(Error::TextureFormat(format)),
+        }
+    }
+
+    /// Size of the frame as a texture extent.
+    pub fn wgpu_extent(&self) -> wgpu::Extent3d {
+        wgpu::Extent3d {
+            width: self.frame_format.width,
+            height: self.frame_format.height,
+            depth_or_array_layers: 1,
+        }
+    }
+
+    /// Upload the frame into `texture` through `queue`. The texture needs the size of
+    /// [`RawFrame::wgpu_extent`], the format of [`RawFrame::wgpu_texture_format`] and
+    /// `COPY_DST` usage.
+    ///
+    /// Formats without alpha leave it undefined, so it is set to opaque in the frame first. Frames
+    /// with [`crate::FrameMetadata::y_invert`] set are uploaded upside down, flip the texture
+    /// coordinates to show them.
+    pub fn write_wgpu_texture(
+        &mut self,
+        queue: &wgpu::Queue,
+        texture: &wgpu::Texture,
+    ) -> Result<()> {
+        self.wgpu_texture_format()?;
+        // Checks the stride against the buffer, write_texture would panic otherwise.
+        let _ = self.rows()?;
+
+        let opaque_bits = match self.frame_format.format {
+            wl_shm::Format::Xrgb8888 | wl_shm::Format::Xbgr8888 => Some(0xff),
+            wl_shm::Format::Xbgr2101010 => Some(0xc0),
+            _ => None,
+        };
+        if let Some(opaque_bits) = opaque_bits {
+            for pixel in self.frame_mmap.chunks_exact_mut(4) {
+                pixel[3] |= opaque_bits;
+            }
+        }
+
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &self.frame_mmap,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(self.frame_format.stride),
+                rows_per_image: Some(self.frame_format.height),
+            },
+            self.wgpu_extent(),
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,318 @@
+//! Encoding captures and writing them out, the way the `wayshot` command line does: to files, to
+//! file descriptors or to any writer.
+//!
+//! PNGs are tagged with the color description of the outputs the capture comes from, so viewers
+//! do not take captures of wide-gamut or HDR outputs for sRGB.
+//!
+//! ```no_run
+//! use std::path::Path;
+//!
+//! use libwayshot::{
+//!     write::{self, Capture, EncodeOptions, WriteTarget},
+//!     WayshotConnection,
+//! };
+//!
+//! let wayshot_connection = WayshotConnection::new().unwrap();
+//! let image = wayshot_connection.screenshot_all(false).unwrap();
+//! let region = wayshot_connection.desktop_region().unwrap();
+//! let capture = Capture::new(&image).with_color(wayshot_connection.color_description(region));
+//! write::save(
+//!     &capture,
+//!     WriteTarget::File(Path::new("screenshot.png")),
+//!     EncodeOptions::default(),
+//! )
+//! .unwrap();
+//! ```
+
+use std::{
+    borrow::Cow,
+    ffi::OsString,
+    fs::{self, File},
+    io::{BufWriter, Cursor, Seek, Write},
+    os::fd::BorrowedFd,
+    path::Path,
+};
+
+use image::{DynamicImage, ImageFormat};
+
+use crate::{output::ColorDescription, png_encoder, Error, Result};
+
+/// Pixel count above which [`SpeedVsSize::Auto`] trades size for speed: one 4K output.
+///
+/// At the default level, zlib style deflate takes around 1.4 s for a 4K desktop and 4.5 s for
+/// three side by side, while the fdeflate based fast level takes a twentieth of that for files
+/// less than twice as large. Re-measure with `cargo run --release --features write --example
+/// png_speed`.
+const FAST_PNG_PIXELS: u64 = 3840 * 2160;
+
+/// Largest image a format can store, eg: to split captures of very large desktops into tiles
+/// ahead of encoding them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FormatLimits {
+    pub max_width: u32,
+    pub max_height: u32,
+    pub max_pixels: u64,
+}
+
+impl FormatLimits {
+    /// Limits of `format`, `None` for formats [`save`] cannot write.
+    pub fn of(format: ImageFormat) -> Option<Self> {
+        match format {
+            // Dimensions are stored in 31 bits.
+            ImageFormat::Png => Some(Self {
+                max_width: i32::MAX as u32,
+                max_height: i32::MAX as u32,
+                max_pixels: u64::MAX,
+            }),
+            ImageFormat::Jpeg => Some(Self {
+                max_width: u16::MAX.into(),
+                max_height: u16::MAX.into(),
+                max_pixels: u64::MAX,
+            }),
+            ImageFormat::Pnm => Some(Self {
+                max_width: u32::MAX,
+                max_height: u32::MAX,
+                max_pixels: u64::MAX,
+            }),
+            // The reference decoder rejects anything larger, so does the qoi crate.
+            ImageFormat::Qoi => Some(Self {
+                max_width: u32::MAX,
+                max_height: u32::MAX,
+                max_pixels: 400_000_000,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn fits(&self, width: u32, height: u32) -> bool {
+        width <= self.max_width
+            && height <= self.max_height
+            && u64::from(width) * u64::from(height) <= self.max_pixels
+    }
+
+    /// Split a `width` x `height` image into as few tiles as fit, left to right then top to
+    /// bottom, as `(x, y, width, height)`.
+    ///
+    /// ```
+    /// use image::ImageFormat;
+    /// use libwayshot::write::FormatLimits;
+    ///
+    /// let jpeg = FormatLimits::of(ImageFormat::Jpeg).unwrap();
+    /// assert_eq!(
+    ///     jpeg.tiles(100_000, 3000),
+    ///     [(0, 0, 65535, 3000), (65535, 0, 34465, 3000)]
+    /// );
+    /// ```
+    pub fn tiles(&self, width: u32, height: u32) -> Vec<(u32, u32, u32, u32)> {
+        let tile_width = width.min(self.max_width).max(1);
+        let rows_per_pixels = (self.max_pixels / u64::from(tile_width)).max(1);
+        let tile_height = height
+            .min(self.max_height)
+            .min(u32::try_from(rows_per_pixels).unwrap_or(u32::MAX))
+            .max(1);
+        let mut tiles = Vec::new();
+        for y in (0..height).step_by(tile_height as usize) {
+            for x in (0..width).step_by(tile_width as usize) {
+                tiles.push((x, y, tile_width.min(width - x), tile_height.min(height - y)));
+            }
+        }
+        tiles
+    }
+}
+
+/// An image to write, with the color description of the outputs it was captured from.
+#[derive(Debug, Copy, Clone)]
+pub struct Capture<'a> {
+    pub image: &'a DynamicImage,
+    /// See [`crate::WayshotConnection::color_description`], `None` for untagged sRGB.
+    pub color: Option<&'a ColorDescription>,
+}
+
+impl<'a> Capture<'a> {
+    pub fn new(image: &'a DynamicImage) -> Self {
+        Self { image, color: None }
+    }
+
+    pub fn with_color(self, color: Option<&'a ColorDescription>) -> Self {
+        Self { color, ..self }
+    }
+}
+
+/// Where [`save`] writes the encoded image.
+pub enum WriteTarget<'a> {
+    /// Written to a temporary file in the same directory and renamed into place, so an
+    /// interrupted or failed write never leaves a truncated image behind.
+    File(&'a Path),
+    /// An open file descriptor, eg: a pipe or a file handed over by a portal. It stays open.
+    Fd(BorrowedFd<'a>),
+    Writer(&'a mut dyn Write),
+}
+
+/// How [`save`] encodes the image.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct EncodeOptions {
+    /// PNG, JPEG, PNM or QOI. `None` picks it from the extension of a file target, and PNG for
+    /// the other targets.
+    pub format: Option<ImageFormat>,
+    /// How hard PNGs are compressed, the other formats ignore it.
+    pub speed_vs_size: SpeedVsSize,
+}
+
+impl EncodeOptions {
+    /// Encode as `format`, with the defaults for everything else.
+    pub fn with_format(format: ImageFormat) -> Self {
+        Self {
+            format: Some(format),
+            ..Self::default()
+        }
+    }
+}
+
+/// Trade-off between encoding time and file size of PNGs.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum SpeedVsSize {
+    /// [`SpeedVsSize::Balanced`] for images up to the size of a 4K output, [`SpeedVsSize::Speed`]
+    /// for larger ones, such as captures of several outputs.
+    #[default]
+    Auto,
+    /// Fastest encoding, files around twice as large as balanced ones.
+    Speed,
+    /// The default zlib level.
+    Balanced,
+    /// Slightly smaller files than balanced ones, in about four times the time.
+    Size,
+}
+
+impl SpeedVsSize {
+    fn compression(self, image: &DynamicImage) -> png::Compression {
+        match self {
+            Self::Auto
+                if u64::from(image.width()) * u64::from(image.height()) > FAST_PNG_PIXELS =>
+            {
+                png::Compression::Fast
+            }
+            Self::Auto | Self::Balanced => png::Compression::Default,
+            Self::Speed => png::Compression::Fast,
+            Self::Size => png::Compression::Best,
+        }
+    }
+}
+
+/// Encode `capture` with `options` and write it to `target`.
+pub fn save(capture: &Capture<'_>, target: WriteTarget<'_>, options: EncodeOptions) -> Result<()> {
+    match target {
+        WriteTarget::File(path) => {
+            let format = match options.format {
+                Some(format) => format,
+                None => ImageFormat::from_path(path).map_err(|e| Error::Encode(e.to_string()))?,
+            };
+            save_atomically(capture, path, format, options.speed_vs_size)
+        }
+        WriteTarget::Fd(fd) => {
+            let encoded = encode(capture, options)?;
+            // A duplicate, so dropping the file leaves the caller's descriptor open.
+            File::from(fd.try_clone_to_owned()?).write_all(&encoded)?;
+            Ok(())
+        }
+        WriteTarget::Writer(writer) => {
+            let encoded = encode(capture, options)?;
+            writer.write_all(&encoded)?;
+            Ok(())
+        }
+    }
+}
+
+/// Encode `capture` with `options` in memory, eg: to offer it on the clipboard. Without a format
+/// it is encoded as PNG.
+pub fn encode(capture: &Capture<'_>, options: EncodeOptions) -> Result<Vec<u8>> {
+    let mut buffer = Cursor::new(Vec::new());
+    encode_to(
+        capture,
+        options.format.unwrap_or(ImageFormat::Png),
+        options.speed_vs_size,
+        &mut buffer,
+    )?;
+    Ok(buffer.into_inner())
+}
+
+fn encode_to<W: Write + Seek>(
+    capture: &Capture<'_>,
+    format: ImageFormat,
+    speed_vs_size: SpeedVsSize,
+    mut writer: W,
+) -> Result<()> {
+    let (width, height) = (capture.image.width(), capture.image.height());
+    if FormatLimits::of(format).is_some_and(|limits| !limits.fits(width, height)) {
+        return Err(Error::TooLargeForFormat {
+            format,
+            width,
+            height,
+        });
+    }
+    if format == ImageFormat::Png {
+        return png_encoder::write_png(
+            capture.image,
+            capture.color,
+            speed_vs_size.compression(capture.image),
+            writer,
+        );
+    }
+    with_encodable_depth(capture.image)
+        .write_to(&mut writer, format)
+        .map_err(|e| Error::Encode(e.to_string()))
+}
+
+/// Replace the file at `path` with `contents` the way [`WriteTarget::File`] does: through a
+/// temporary file renamed into place, so readers see either the old or the new contents. Meant
+/// for files read while they are updated, eg: metadata next to a capture.
+pub fn replace_file(path: &Path, contents: &[u8]) -> Result<()> {
+    write_atomically(path, |writer| Ok(writer.write_all(contents)?))
+}
+
+fn save_atomically(
+    capture: &Capture<'_>,
+    path: &Path,
+    format: ImageFormat,
+    speed_vs_size: SpeedVsSize,
+) -> Result<()> {
+    write_atomically(path, |writer| {
+        encode_to(capture, format, speed_vs_size, writer)
+    })
+}
+
+fn write_atomically(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> Result<()>,
+) -> Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| Error::Encode(format!("{} is not a file path", path.display())))?;
+    let mut temp_name = OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(".part");
+    let temp_path = path.with_file_name(temp_name);
+
+    let result = File::create(&temp_path)
+        .map_err(Error::from)
+        .and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            write(&mut writer)?;
+            Ok(writer.flush()?)
+        })
+        .and_then(|()| Ok(fs::rename(&temp_path, path)?));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// `image` reduced to 8 bits per channel for the formats other than PNG, which cannot store the
+/// 16-bit channels of 10-bit captures.
+fn with_encodable_depth(image: &DynamicImage) -> Cow<'_, DynamicImage> {
+    let color = image.color();
+    if color.bytes_per_pixel() == color.channel_count() {
+        Cow::Borrowed(image)
+    } else {
+        Cow::Owned(DynamicImage::ImageRgba8(image.to_rgba8()))
+    }
+}
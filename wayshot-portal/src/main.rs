@@ -0,0 +1,181 @@
+use std::{
+    collections::HashMap,
+    env,
+    error::Error,
+    process::Command,
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use libwayshot::{CaptureRegion, WayshotConnection};
+use tracing::Level;
+use zbus::{
+    blocking::connection,
+    interface,
+    zvariant::{ObjectPath, OwnedValue, Value},
+};
+
+const BUS_NAME: &str = "org.freedesktop.impl.portal.desktop.wayshot";
+const OBJECT_PATH: &str = "/org/freedesktop/portal/desktop";
+
+/// Response codes of the portal Request interface.
+const RESPONSE_SUCCESS: u32 = 0;
+const RESPONSE_CANCELLED: u32 = 1;
+const RESPONSE_OTHER: u32 = 2;
+
+type Results = HashMap<String, OwnedValue>;
+
+/// Why a request did not produce a result, mapped onto the portal response codes.
+enum Failure {
+    Cancelled,
+    Other(Box<dyn Error>),
+}
+
+impl<E: Into<Box<dyn Error>>> From<E> for Failure {
+    fn from(error: E) -> Self {
+        Failure::Other(error.into())
+    }
+}
+
+fn respond(result: Result<Results, Failure>) -> (u32, Results) {
+    match result {
+        Ok(results) => (RESPONSE_SUCCESS, results),
+        Err(Failure::Cancelled) => (RESPONSE_CANCELLED, Results::new()),
+        Err(Failure::Other(e)) => {
+            tracing::error!("{e}");
+            (RESPONSE_OTHER, Results::new())
+        }
+    }
+}
+
+/// Run slurp with `args`, `None` if the user cancelled the selection.
+fn slurp(args: &[&str]) -> Result<Option<CaptureRegion>, Box<dyn Error>> {
+    let output = Command::new("slurp")
+        .args(args)
+        .output()
+        .map_err(|e| format!("Failed to run slurp, is it installed? {e}"))?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    let geometry = String::from_utf8(output.stdout)?;
+    // slurp prints `X,Y WxH`.
+    let mut numbers = geometry
+        .split(|c: char| c == ',' || c == 'x' || c.is_whitespace())
+        .filter(|part| !part.is_empty())
+        .map(str::parse::<i32>);
+    let mut next = || -> Result<i32, Box<dyn Error>> {
+        Ok(numbers
+            .next()
+            .ok_or_else(|| format!("slurp returned {geometry:?}"))??)
+    };
+    Ok(Some(CaptureRegion {
+        x_coordinate: next()?,
+        y_coordinate: next()?,
+        width: next()?,
+        height: next()?,
+    }))
+}
+
+/// The xdg-desktop-portal backend, implementing the portal `impl` interfaces.
+struct Screenshot;
+
+impl Screenshot {
+    fn take_screenshot(&self, interactive: bool) -> Result<Results, Failure> {
+        let wayshot_conn = WayshotConnection::new()?;
+        let image = if interactive {
+            let region = slurp(&[])?.ok_or(Failure::Cancelled)?;
+            wayshot_conn.screenshot(region, false)?
+        } else {
+            wayshot_conn.screenshot_all(false)?
+        };
+
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_millis());
+        let path = env::temp_dir().join(format!("wayshot-portal-{time}.png"));
+        image.save(&path)?;
+        tracing::debug!("Saved screenshot to {}", path.display());
+
+        let uri = format!("file://{}", path.display());
+        Ok(Results::from([(
+            "uri".to_string(),
+            OwnedValue::try_from(Value::from(uri))?,
+        )]))
+    }
+
+    fn take_color(&self) -> Result<Results, Failure> {
+        let wayshot_conn = WayshotConnection::new()?;
+        let mut point = slurp(&["-p"])?.ok_or(Failure::Cancelled)?;
+        point.width = 1;
+        point.height = 1;
+        let pixel = wayshot_conn.screenshot(point, false)?.to_rgb8();
+        let [red, green, blue] = pixel
+            .get_pixel(0, 0)
+            .0
+            .map(|channel| f64::from(channel) / 255.0);
+
+        Ok(Results::from([(
+            "color".to_string(),
+            OwnedValue::try_from(Value::from((red, green, blue)))?,
+        )]))
+    }
+}
+
+#[interface(name = "org.freedesktop.impl.portal.Screenshot")]
+impl Screenshot {
+    #[zbus(out_args("response", "results"))]
+    fn screenshot(
+        &self,
+        _handle: ObjectPath<'_>,
+        app_id: &str,
+        _parent_window: &str,
+        options: HashMap<String, OwnedValue>,
+    ) -> (u32, Results) {
+        let interactive = options
+            .get("interactive")
+            .and_then(|value| bool::try_from(value).ok())
+            .unwrap_or(false);
+        tracing::info!("Screenshot requested by {app_id:?}, interactive: {interactive}");
+        respond(self.take_screenshot(interactive))
+    }
+
+    #[zbus(out_args("response", "results"))]
+    fn pick_color(
+        &self,
+        _handle: ObjectPath<'_>,
+        app_id: &str,
+        _parent_window: &str,
+        _options: HashMap<String, OwnedValue>,
+    ) -> (u32, Results) {
+        tracing::info!("Color pick requested by {app_id:?}");
+        respond(self.take_color())
+    }
+
+    #[zbus(property)]
+    fn version(&self) -> u32 {
+        2
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let level = if env::args().any(|arg| arg == "-d" || arg == "--debug") {
+        Level::TRACE
+    } else {
+        Level::INFO
+    };
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(std::io::stderr)
+        .init();
+
+    let _connection = connection::Builder::session()?
+        .name(BUS_NAME)?
+        .serve_at(OBJECT_PATH, Screenshot)?
+        .build()?;
+    tracing::info!("Serving {BUS_NAME}");
+
+    // Requests are handled on the connection's executor thread.
+    loop {
+        thread::park();
+    }
+}
@@ -25,6 +25,7 @@ fn main() {
         let output = OpenOptions::new()
             .write(true)
             .create(true)
+            .truncate(true)
             .open(Path::new(&man_page.1))
             .unwrap();
         _ = Command::new("scdoc")
@@ -41,6 +42,7 @@ fn main() {
         let output = OpenOptions::new()
             .write(true)
             .create(true)
+            .truncate(true)
             .open(Path::new(&scdoc_output.1))
             .unwrap();
         let mut encoder = GzEncoder::new(output, Compression::default());
@@ -0,0 +1,166 @@
+use std::{
+    error::Error,
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    imageops, Delay, Frame, RgbaImage,
+};
+
+use crate::progress::Progress;
+
+/// Animated image formats `wayshot record` can write without ffmpeg.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AnimationFormat {
+    Gif,
+    Apng,
+}
+
+impl AnimationFormat {
+    /// The animated format matching the extension of `path`, `None` for video files.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        match extension.as_str() {
+            "gif" => Some(Self::Gif),
+            "png" | "apng" => Some(Self::Apng),
+            _ => None,
+        }
+    }
+}
+
+/// The part of a frame that changed since the previous one.
+struct Patch {
+    image: RgbaImage,
+    left: u32,
+    top: u32,
+    captured: Instant,
+}
+
+/// Collects frames and writes them as an animation once recording stopped.
+///
+/// Only the bounding box of the pixels that changed since the previous frame is kept, and frames
+/// without any change just extend how long the previous one is shown, which keeps recordings of
+/// mostly static screens small.
+pub struct AnimationEncoder {
+    format: AnimationFormat,
+    file: PathBuf,
+    width: u32,
+    height: u32,
+    previous: Option<RgbaImage>,
+    patches: Vec<Patch>,
+    duplicates: usize,
+}
+
+impl AnimationEncoder {
+    pub fn new(format: AnimationFormat, file: PathBuf, width: u32, height: u32) -> Self {
+        Self {
+            format,
+            file,
+            width,
+            height,
+            previous: None,
+            patches: Vec::new(),
+            duplicates: 0,
+        }
+    }
+
+    pub fn push(&mut self, frame: RgbaImage) {
+        let captured = Instant::now();
+        let changed = match &self.previous {
+            Some(previous) => changed_region(previous, &frame),
+            None => Some((0, 0, self.width, self.height)),
+        };
+        match changed {
+            Some((left, top, width, height)) => self.patches.push(Patch {
+                image: imageops::crop_imm(&frame, left, top, width, height).to_image(),
+                left,
+                top,
+                captured,
+            }),
+            None => self.duplicates += 1,
+        }
+        self.previous = Some(frame);
+    }
+
+    /// Write the animation, every patch shown until the next one was captured, reporting the
+    /// encoded frames to `progress`.
+    pub fn finish(self, progress: &dyn Progress) -> Result<(), Box<dyn Error>> {
+        tracing::debug!(
+            "Encoding {} frames, {} unchanged frames dropped",
+            self.patches.len(),
+            self.duplicates
+        );
+        let ended = Instant::now();
+        let delays: Vec<Duration> = self
+            .patches
+            .iter()
+            .enumerate()
+            .map(|(index, patch)| {
+                let next = self
+                    .patches
+                    .get(index + 1)
+                    .map_or(ended, |next| next.captured);
+                next - patch.captured
+            })
+            .collect();
+
+        progress.start("Encoding", Some(self.patches.len() as u64));
+        let writer = BufWriter::new(File::create(&self.file)?);
+        match self.format {
+            AnimationFormat::Gif => {
+                let mut encoder = GifEncoder::new(writer);
+                encoder.set_repeat(Repeat::Infinite)?;
+                encoder.encode_frames(self.patches.into_iter().zip(delays).enumerate().map(
+                    |(index, (patch, delay))| {
+                        progress.update(index as u64);
+                        Frame::from_parts(
+                            patch.image,
+                            patch.left,
+                            patch.top,
+                            Delay::from_saturating_duration(delay),
+                        )
+                    },
+                ))?;
+            }
+            AnimationFormat::Apng => {
+                let mut encoder = png::Encoder::new(writer, self.width, self.height);
+                encoder.set_color(png::ColorType::Rgba);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder.set_animated(self.patches.len() as u32, 0)?;
+                let mut writer = encoder.write_header()?;
+                for (index, (patch, delay)) in self.patches.into_iter().zip(delays).enumerate() {
+                    progress.update(index as u64);
+                    // Move to the origin first, the new size may not fit at the old position.
+                    writer.reset_frame_position()?;
+                    writer.set_frame_dimension(patch.image.width(), patch.image.height())?;
+                    writer.set_frame_position(patch.left, patch.top)?;
+                    let millis = delay.as_millis().min(u128::from(u16::MAX)) as u16;
+                    writer.set_frame_delay(millis, 1000)?;
+                    writer.write_image_data(&patch.image)?;
+                }
+                writer.finish()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Bounding box `(x, y, width, height)` of the pixels that differ between two equally sized
+/// frames, `None` if they are identical.
+fn changed_region(previous: &RgbaImage, frame: &RgbaImage) -> Option<(u32, u32, u32, u32)> {
+    let (mut min_x, mut min_y) = (u32::MAX, u32::MAX);
+    let (mut max_x, mut max_y) = (0, 0);
+    for (x, y, pixel) in frame.enumerate_pixels() {
+        if previous.get_pixel(x, y) != pixel {
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+        }
+    }
+    (min_x != u32::MAX).then(|| (min_x, min_y, max_x - min_x + 1, max_y - min_y + 1))
+}
@@ -0,0 +1,189 @@
+use std::{env, fs, path::Path, process::Command};
+
+use font8x8::legacy::BASIC_LEGACY;
+use image::{imageops::overlay, DynamicImage, Rgba, RgbaImage};
+use libwayshot::WayshotConnection;
+
+/// Globals worth mentioning in a bug report, everything else is noise for wayshot.
+const RELEVANT_PROTOCOLS: &[&str] = &[
+    "wl_shm",
+    "wl_output",
+    "zxdg_output_manager_v1",
+    "zwlr_screencopy_manager_v1",
+    "zwlr_layer_shell_v1",
+    "zwlr_data_control_manager_v1",
+    "zwp_linux_dmabuf_v1",
+    "wl_data_device_manager",
+];
+
+/// Compositors known to print their version on `--version` without starting a session.
+const VERSIONED_COMPOSITORS: &[&str] = &["sway", "Hyprland", "river", "niri", "labwc", "wayfire"];
+
+/// Every glyph is an 8x8 bitmap, drawn at this many pixels per bitmap pixel.
+const GLYPH_SCALE: u32 = 2;
+pub const GLYPH_SIZE: u32 = 8 * GLYPH_SCALE;
+const LINE_SPACING: u32 = 4;
+const PADDING: u32 = 8;
+
+const FOOTER_BACKGROUND: Rgba<u8> = Rgba([30, 30, 30, 255]);
+const FOOTER_FOREGROUND: Rgba<u8> = Rgba([230, 230, 230, 255]);
+
+/// Describe the environment wayshot is running in, one line per fact.
+pub fn collect_system_info(wayshot_conn: &WayshotConnection) -> Vec<String> {
+    let mut lines = vec![format!("wayshot {}", env!("CARGO_PKG_VERSION"))];
+
+    let compositor = env::var("XDG_CURRENT_DESKTOP").unwrap_or_else(|_| "unknown".to_string());
+    match compositor_version(&compositor) {
+        Some(version) => lines.push(format!("compositor: {compositor} ({version})")),
+        None => lines.push(format!("compositor: {compositor}")),
+    }
+
+    let gpus = gpus();
+    lines.push(format!(
+        "gpu: {}",
+        if gpus.is_empty() {
+            "unknown".to_string()
+        } else {
+            gpus.join(", ")
+        }
+    ));
+
+    let mut protocols: Vec<String> = wayshot_conn
+        .globals
+        .contents()
+        .clone_list()
+        .into_iter()
+        .filter(|global| RELEVANT_PROTOCOLS.contains(&global.interface.as_str()))
+        .map(|global| format!("{} v{}", global.interface, global.version))
+        .collect();
+    protocols.sort();
+    protocols.dedup();
+    lines.push(format!("protocols: {}", protocols.join(", ")));
+    if let Ok(capabilities) = wayshot_conn.capabilities() {
+        if let Some(version) = capabilities.wlr_screencopy_bound {
+            lines.push(format!("screencopy: bound v{version}"));
+        }
+        if let Some(version) = capabilities.ext_image_copy_capture_bound {
+            lines.push(format!("image copy capture: bound v{version}"));
+        }
+    }
+
+    for output in wayshot_conn.get_all_outputs() {
+        lines.push(format!(
+            "output {} ({}): {}x{} mode, {}x{}+{}+{} logical, {:?}",
+            output.name,
+            output.id(),
+            output.mode.width,
+            output.mode.height,
+            output.dimensions.width,
+            output.dimensions.height,
+            output.dimensions.x,
+            output.dimensions.y,
+            output.transform,
+        ));
+    }
+
+    lines
+}
+
+fn compositor_version(compositor: &str) -> Option<String> {
+    let binary = VERSIONED_COMPOSITORS
+        .iter()
+        .find(|known| known.eq_ignore_ascii_case(compositor))?;
+    let output = Command::new(binary).arg("--version").output().ok()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout
+        .lines()
+        .next()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+}
+
+/// Kernel driver and PCI id of every DRM card, eg: `i915 (8086:9A49)`.
+fn gpus() -> Vec<String> {
+    let Ok(entries) = fs::read_dir("/sys/class/drm") else {
+        return Vec::new();
+    };
+    let mut cards: Vec<String> = entries
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        // Skip connectors such as card0-DP-1.
+        .filter(|name| name.starts_with("card") && !name.contains('-'))
+        .collect();
+    cards.sort();
+
+    cards
+        .into_iter()
+        .filter_map(|card| {
+            let uevent =
+                fs::read_to_string(Path::new("/sys/class/drm").join(card).join("device/uevent"))
+                    .ok()?;
+            let field = |key: &str| {
+                uevent
+                    .lines()
+                    .find_map(|line| line.strip_prefix(key))
+                    .map(str::to_string)
+            };
+            match (field("DRIVER="), field("PCI_ID=")) {
+                (Some(driver), Some(pci_id)) => Some(format!("{driver} ({pci_id})")),
+                (Some(driver), None) => Some(driver),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Append a strip below `image` rendering `lines`, cutting lines that do not fit the width.
+pub fn append_footer(image: DynamicImage, lines: &[String]) -> DynamicImage {
+    let width = image.width();
+    let footer_height = (PADDING * 2 + lines.len() as u32 * (GLYPH_SIZE + LINE_SPACING))
+        .saturating_sub(LINE_SPACING);
+
+    let mut composed =
+        RgbaImage::from_pixel(width, image.height() + footer_height, FOOTER_BACKGROUND);
+    overlay(&mut composed, &image.to_rgba8(), 0, 0);
+
+    let max_chars = (width.saturating_sub(PADDING * 2) / GLYPH_SIZE) as usize;
+    for (index, line) in lines.iter().enumerate() {
+        let y = image.height() + PADDING + index as u32 * (GLYPH_SIZE + LINE_SPACING);
+        let line: String = line.chars().take(max_chars).collect();
+        draw_text(&mut composed, &line, PADDING, y);
+    }
+
+    composed.into()
+}
+
+/// Draw `text` on a dark label with its top left corner at `x`, `y`, clipped to the image.
+pub fn draw_text(image: &mut RgbaImage, text: &str, x: u32, y: u32) {
+    let label_width = text.chars().count() as u32 * GLYPH_SIZE;
+    for label_y in y..(y + GLYPH_SIZE).min(image.height()) {
+        for label_x in x..x.saturating_add(label_width).min(image.width()) {
+            image.put_pixel(label_x, label_y, FOOTER_BACKGROUND);
+        }
+    }
+    for (column, character) in text.chars().enumerate() {
+        draw_glyph(image, character, x + column as u32 * GLYPH_SIZE, y);
+    }
+}
+
+fn draw_glyph(image: &mut RgbaImage, character: char, x: u32, y: u32) {
+    let glyph = BASIC_LEGACY
+        .get(character as usize)
+        .unwrap_or(&BASIC_LEGACY[b'?' as usize]);
+    for (row, bits) in glyph.iter().enumerate() {
+        for column in 0..8 {
+            if bits & (1 << column) == 0 {
+                continue;
+            }
+            for dy in 0..GLYPH_SCALE {
+                for dx in 0..GLYPH_SCALE {
+                    let pixel_x = x + column * GLYPH_SCALE + dx;
+                    let pixel_y = y + row as u32 * GLYPH_SCALE + dy;
+                    if pixel_x < image.width() && pixel_y < image.height() {
+                        image.put_pixel(pixel_x, pixel_y, FOOTER_FOREGROUND);
+                    }
+                }
+            }
+        }
+    }
+}
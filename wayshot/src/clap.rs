@@ -1,4 +1,11 @@
-use clap::{arg, ArgAction, Command};
+use std::path::PathBuf;
+
+use clap::{arg, value_parser, ArgAction, Command};
+
+use crate::{
+    scale::FrameSize,
+    utils::{self, AspectRatio},
+};
 
 pub fn set_flags() -> Command {
     Command::new("wayshot")
@@ -13,9 +20,88 @@ pub fn set_flags() -> Command {
         )
         .arg(
             arg!(-s --slurp <GEOMETRY>)
+                .required(false)
+                .visible_short_alias('g')
+                .visible_alias("geometry")
+                .action(ArgAction::Set)
+                .help("Choose a portion of your display to screenshot using slurp, or in percentages like \"10%,10% 80%x50%\""),
+        )
+        .arg(
+            arg!(--aspect <RATIO>)
                 .required(false)
                 .action(ArgAction::Set)
-                .help("Choose a portion of your display to screenshot using slurp"),
+                .value_parser(|ratio: &str| ratio.parse::<AspectRatio>())
+                .help("Constrain --slurp and --gui selections to an aspect ratio, eg: 16:9"),
+        )
+        .arg(
+            arg!(--flash)
+                .required(false)
+                .action(ArgAction::SetTrue)
+                .requires("slurp")
+                .conflicts_with("nested")
+                .help("Briefly highlight the captured --slurp region on screen"),
+        )
+        .arg(
+            arg!(--delay <SECONDS>)
+                .required(false)
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(u64))
+                .help("Wait this many seconds before capturing, eg: to open a menu first"),
+        )
+        .arg(
+            arg!(--countdown)
+                .required(false)
+                .action(ArgAction::SetTrue)
+                .requires("delay")
+                .help("Show the seconds left of --delay on screen"),
+        )
+        .arg(
+            arg!(--"next-frame")
+                .id("nextframe")
+                .required(false)
+                .action(ArgAction::SetTrue)
+                .help("Capture the next frame the compositor draws instead of the last one, eg: to catch an animation at a frame boundary"),
+        )
+        .arg(
+            arg!(--interval <DURATION>)
+                .required(false)
+                .action(ArgAction::Set)
+                .value_parser(|value: &str| utils::parse_duration(value))
+                .conflicts_with_all(["slurp", "stdout", "clipboard", "dnd", "allworkspaces", "chooseoutput", "pickoutput", "gui"])
+                .help("Timelapse: save a numbered shot of --output every DURATION, eg: 5s, until --count or Ctrl-C"),
+        )
+        .arg(
+            arg!(--count <COUNT>)
+                .required(false)
+                .action(ArgAction::Set)
+                .requires("interval")
+                .value_parser(value_parser!(u64).range(1..))
+                .help("Stop the --interval timelapse after this many shots"),
+        )
+        .arg(
+            arg!(--"skip-unchanged")
+                .id("skipunchanged")
+                .required(false)
+                .action(ArgAction::SetTrue)
+                .requires("interval")
+                .help("In --interval mode, only take a shot once the screen changed since the previous one"),
+        )
+        .arg(
+            arg!(--when <WHEN>)
+                .required(false)
+                .action(ArgAction::Set)
+                .requires("interval")
+                .value_parser(["always", "active"])
+                .default_value("always")
+                .help("In --interval mode, \"active\" pauses while the session is idle to save battery"),
+        )
+        .arg(
+            arg!(--latest <PATH>)
+                .required(false)
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(PathBuf))
+                .requires("interval")
+                .help("In --interval mode, also keep the newest shot at PATH and its metadata next to it as JSON, eg: latest.png and latest.json"),
         )
         .arg(
             arg!(-f - -file <FILE_PATH>)
@@ -24,6 +110,20 @@ pub fn set_flags() -> Command {
                 .action(ArgAction::Set)
                 .help("Mention a custom file path"),
         )
+        .arg(
+            arg!(--"auto-tile")
+                .id("autotile")
+                .required(false)
+                .action(ArgAction::SetTrue)
+                .help("Split captures too large for the file format into numbered tiles instead of failing"),
+        )
+        .arg(
+            arg!(--"verify-write")
+                .id("verifywrite")
+                .required(false)
+                .action(ArgAction::SetTrue)
+                .help("Decode saved files back and exit with 74 if they do not hold the capture"),
+        )
         .arg(
             arg!(-c - -cursor)
                 .required(false)
@@ -53,8 +153,7 @@ pub fn set_flags() -> Command {
             arg!(-o --output <OUTPUT>)
                 .required(false)
                 .action(ArgAction::Set)
-                .conflicts_with("slurp")
-                .help("Choose a particular display to screenshot"),
+                .help("Choose a particular display to screenshot, by name or stable identifier, --slurp regions are then relative to it"),
         )
         .arg(
             arg!(--chooseoutput)
@@ -64,4 +163,287 @@ pub fn set_flags() -> Command {
                 .conflicts_with("output")
                 .help("Present a fuzzy selector for outputs"),
         )
+        .arg(
+            arg!(--"pick-output")
+                .id("pickoutput")
+                .required(false)
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["slurp", "output", "chooseoutput", "interaction"])
+                .help("Pick the output on the screen: click it or press its number, like --chooseoutput --interaction gui"),
+        )
+        .arg(
+            arg!(--"all-workspaces")
+                .id("allworkspaces")
+                .required(false)
+                .action(ArgAction::SetTrue)
+                .requires("output")
+                .conflicts_with_all(["slurp", "stdout", "chooseoutput", "pickoutput"])
+                .help("Capture every workspace of the output given with --output"),
+        )
+        .arg(
+            arg!(--clipboard)
+                .required(false)
+                .action(ArgAction::SetTrue)
+                .conflicts_with("allworkspaces")
+                .help(
+                    "Copy the screenshot to the clipboard, and only save it when --file is given",
+                ),
+        )
+        .arg(
+            arg!(--"clipboard-timeout" <SECONDS>)
+                .required(false)
+                .action(ArgAction::Set)
+                .requires("clipboard")
+                .value_parser(value_parser!(u64))
+                .help("Stop serving the clipboard after this many seconds"),
+        )
+        .arg(
+            arg!(--"clipboard-handoff" [SOCKET])
+                .required(false)
+                .action(ArgAction::Set)
+                .num_args(0..=1)
+                .requires("clipboard")
+                .value_parser(value_parser!(PathBuf))
+                .help("Hand the clipboard over to `wayshot clipboard-helper` and exit right away (socket defaults to $XDG_RUNTIME_DIR/wayshot-clipboard-$WAYLAND_DISPLAY.sock)"),
+        )
+        .arg(
+            arg!(--dnd)
+                .required(false)
+                .action(ArgAction::SetTrue)
+                .conflicts_with("allworkspaces")
+                .help("Drag the screenshot into another window, and only save it when --file is given"),
+        )
+        .arg(
+            arg!(--display <DISPLAY>)
+                .required(false)
+                .action(ArgAction::Set)
+                .help("Connect to this Wayland socket instead of $WAYLAND_DISPLAY"),
+        )
+        .arg(
+            arg!(--nested <COMMAND>)
+                .required(false)
+                .action(ArgAction::Set)
+                .conflicts_with_all(["display", "clipboard", "dnd", "allworkspaces"])
+                .help("Run a command inside a headless sway session and screenshot it"),
+        )
+        .arg(
+            arg!(--"neutralize-nightlight" [KELVIN])
+                .required(false)
+                .action(ArgAction::Set)
+                .num_args(0..=1)
+                .default_missing_value("4500")
+                .value_parser(value_parser!(u32).range(1000..=40000))
+                .help("Undo the tint of a night-light tool running at this temperature (4500K by default)"),
+        )
+        .arg(
+            arg!(--interaction <MODE>)
+                .required(false)
+                .action(ArgAction::Set)
+                .value_parser(["auto", "tty", "gui", "none"])
+                .default_value("auto")
+                .help("How to prompt for choices such as --chooseoutput"),
+        )
+        .arg(
+            arg!(--gui)
+                .required(false)
+                .action(ArgAction::SetTrue)
+                .help("Launcher mode: pick a region with slurp, prompt graphically and report through notifications"),
+        )
+        .arg(
+            arg!(--multiple)
+                .required(false)
+                .action(ArgAction::SetTrue)
+                .requires("gui")
+                .help("In --gui mode, keep selecting regions until Escape and save one file per region"),
+        )
+        .arg(
+            arg!(--"no-plugins")
+                .required(false)
+                .action(ArgAction::SetTrue)
+                .help("Do not run the processor plugins from the plugin directory"),
+        )
+        .arg(
+            arg!(--"log-file" <PATH>)
+                .required(false)
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(PathBuf))
+                .help("Also write logs to PATH, rotated daily with the date appended"),
+        )
+        .arg(
+            arg!(--config <CONFIG_PATH>)
+                .required(false)
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(PathBuf))
+                .help("Path to the config file (defaults to $XDG_CONFIG_HOME/wayshot/config.toml)"),
+        )
+        .subcommand(Command::new("bugreport").about(
+            "Capture all outputs with a footer describing the system, to attach to bug reports",
+        ))
+        .subcommand(daemon_command())
+        .subcommand(
+            Command::new("clipboard-helper")
+                .about("Serve the clipboard contents handed over with --clipboard-handoff, so wayshot can exit right after copying")
+                .arg(
+                    arg!(--socket <PATH>)
+                        .required(false)
+                        .action(ArgAction::Set)
+                        .value_parser(value_parser!(PathBuf))
+                        .help("Listen on this Unix socket (defaults to $XDG_RUNTIME_DIR/wayshot-clipboard-$WAYLAND_DISPLAY.sock)"),
+                ),
+        )
+        .subcommand(
+            Command::new("client")
+                .about("Have a running `wayshot daemon` take the --slurp, --output or full capture, and save it to --file or --stdout")
+                .arg(
+                    arg!(--socket <PATH>)
+                        .required(false)
+                        .action(ArgAction::Set)
+                        .value_parser(value_parser!(PathBuf))
+                        .help("Unix socket of the daemon (defaults to $XDG_RUNTIME_DIR/wayshot-$WAYLAND_DISPLAY.sock)"),
+                ),
+        )
+        .subcommand(record_command())
+}
+
+/// `wayshot daemon`, with `--metrics` when built with the `metrics` feature.
+fn daemon_command() -> Command {
+    let command = Command::new("daemon")
+        .about("Keep a connection to the compositor open and take the captures requested with `wayshot client`")
+        .arg(
+            arg!(--socket <PATH>)
+                .required(false)
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(PathBuf))
+                .help("Listen on this Unix socket (defaults to $XDG_RUNTIME_DIR/wayshot-$WAYLAND_DISPLAY.sock)"),
+        )
+        .arg(
+            arg!(--"also-display" <DISPLAY>)
+                .required(false)
+                .action(ArgAction::Append)
+                .help("Also connect to the compositor on this Wayland socket, eg: a nested one, for `wayshot --display <DISPLAY> client` to capture from (repeatable)"),
+        )
+        .arg(
+            arg!(--dbus)
+                .required(false)
+                .action(ArgAction::SetTrue)
+                .help("Also serve captures as org.waycrate.Wayshot on the session bus"),
+        )
+        .arg(
+            arg!(--latest <PATH>)
+                .required(false)
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(PathBuf))
+                .help("Also keep the newest capture at PATH and its metadata next to it as JSON, eg: latest.png and latest.json"),
+        );
+    #[cfg(feature = "metrics")]
+    let command = command.arg(
+        arg!(--metrics <ADDRESS>)
+            .required(false)
+            .action(ArgAction::Set)
+            .value_parser(value_parser!(std::net::SocketAddr))
+            .help("Serve Prometheus metrics of the captures on http://ADDRESS/metrics, eg: 127.0.0.1:9185"),
+    );
+    command
+}
+
+/// `wayshot record`, with `--input-log` when built with the `input-log` feature.
+fn record_command() -> Command {
+    let command = Command::new("record")
+        .about("Record a video of the outputs, --output or --slurp region using ffmpeg")
+        .arg(
+            arg!([FILE_PATH])
+                .value_parser(value_parser!(PathBuf))
+                .help("Where to save the recording, the extension picks the format (WebM is default)"),
+        )
+        .arg(
+            arg!(--format <FORMAT>)
+                .required(false)
+                .action(ArgAction::Set)
+                .value_parser(["video", "gif", "apng"])
+                .help("Write a video or an animated image, guessed from the file extension by default"),
+        )
+        .arg(
+            arg!(--fps <FPS>)
+                .required(false)
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(u32).range(1..=240))
+                .default_value("30")
+                .help("Frames captured per second"),
+        )
+        .arg(
+            arg!(--duration <SECONDS>)
+                .required(false)
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(u64))
+                .help("Stop recording after this many seconds instead of on Ctrl-C"),
+        )
+        .arg(
+            arg!(--audio [SOURCE])
+                .required(false)
+                .action(ArgAction::Set)
+                .num_args(0..=1)
+                .default_missing_value("default")
+                .help("Also record this PulseAudio/PipeWire source (the default source if none is given)"),
+        )
+        .arg(
+            arg!(--"max-memory" <MIB>)
+                .id("maxmemory")
+                .required(false)
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(usize))
+                .default_value("256")
+                .help("MiB of frames kept in memory while ffmpeg falls behind, later ones are spooled to the temporary directory"),
+        )
+        .arg(
+            arg!(--replay <SECONDS>)
+                .required(false)
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(u64).range(1..))
+                .help("Only keep the last SECONDS, saved to FILE_PATH-1, FILE_PATH-2... on SIGUSR1"),
+        )
+        .arg(
+            arg!(--scale <SIZE>)
+                .required(false)
+                .action(ArgAction::Set)
+                .value_parser(|size: &str| size.parse::<FrameSize>())
+                .help("Scale the frames to SIZE before encoding them, eg: 1920x1080"),
+        )
+        .arg(
+            arg!(--"min-fps" <FPS>)
+                .required(false)
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(u32).range(1..=240))
+                .help("Capture fewer frames, down to FPS, while ffmpeg falls behind instead of spooling them"),
+        )
+        .arg(
+            arg!(--timestamp)
+                .required(false)
+                .action(ArgAction::SetTrue)
+                .help("Burn the UTC time each frame was captured into its bottom left corner"),
+        )
+        .arg(
+            arg!(--subtitles <FILE>)
+                .required(false)
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(PathBuf))
+                .conflicts_with("replay")
+                .help("Write the UTC time of every second of the recording to FILE as SRT subtitles"),
+        );
+    #[cfg(feature = "input-log")]
+    let command = command
+        .arg(
+            arg!(--"input-log" <FILE>)
+                .required(false)
+                .action(ArgAction::Set)
+                .value_parser(value_parser!(PathBuf))
+                .help("Log the timings of pointer and keyboard events to FILE as JSON lines, read from /dev/input"),
+        )
+        .arg(
+            arg!(--"input-log-keys")
+                .required(false)
+                .action(ArgAction::SetTrue)
+                .requires("input-log")
+                .help("Also log which keys are pressed, redacted by default so typed text is not logged"),
+        );
+    command
 }
@@ -0,0 +1,503 @@
+use std::{
+    error::Error,
+    fmt::Write as _,
+    fs::{self, File},
+    io::{BufRead, BufReader, Read, Write},
+    os::unix::{
+        ffi::OsStrExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use image::{DynamicImage, ImageFormat};
+use libwayshot::{
+    output::ColorDescription,
+    write::{self, EncodeOptions},
+};
+use nix::{
+    errno::Errno,
+    poll::{poll, PollFd, PollFlags},
+};
+use serde::{Deserialize, Serialize};
+use wayland_client::{
+    delegate_noop, event_created_child,
+    globals::{registry_queue_init, GlobalListContents},
+    protocol::{
+        wl_registry::{self, WlRegistry},
+        wl_seat::WlSeat,
+    },
+    Connection, Dispatch, EventQueue, QueueHandle,
+};
+use wayland_protocols_wlr::data_control::v1::client::{
+    zwlr_data_control_device_v1::{self, ZwlrDataControlDeviceV1},
+    zwlr_data_control_manager_v1::ZwlrDataControlManagerV1,
+    zwlr_data_control_offer_v1::ZwlrDataControlOfferV1,
+    zwlr_data_control_source_v1::{self, ZwlrDataControlSourceV1},
+};
+
+use crate::{
+    daemon, signals,
+    utils::{self, EncodingFormat},
+};
+
+/// How long a handoff may take to send its offers before the helper moves on.
+const HANDOFF_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Sent by `--clipboard-handoff` as one line of JSON, followed by the data of every offer in
+/// order.
+#[derive(Debug, Serialize, Deserialize)]
+struct HandoffRequest {
+    offers: Vec<HandoffOffer>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct HandoffOffer {
+    mime_type: String,
+    length: usize,
+}
+
+/// Answer of the helper as one line of JSON, once it owns the clipboard or failed to.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum HandoffResponse {
+    Copied,
+    Error { message: String },
+}
+
+/// A single representation of the clipboard contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Offer {
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+impl Offer {
+    pub fn new(mime_type: &str, data: Vec<u8>) -> Self {
+        Self {
+            mime_type: mime_type.to_string(),
+            data,
+        }
+    }
+
+    /// A `text/uri-list` offer pointing at `path`, for paste targets that prefer files.
+    pub fn uri_list(path: &Path) -> Self {
+        let mut uri = String::from("file://");
+        for &byte in path.as_os_str().as_bytes() {
+            if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+                uri.push(byte as char);
+            } else {
+                let _ = write!(uri, "%{byte:02X}");
+            }
+        }
+        uri.push_str("\r\n");
+        Self::new("text/uri-list", uri.into_bytes())
+    }
+}
+
+/// Every representation of `image_buffer` offered to other applications: the chosen encoding, PNG,
+/// and a text/uri-list pointing at the saved file when there is one.
+pub fn image_offers(
+    image_buffer: &DynamicImage,
+    extension: EncodingFormat,
+    file_path: Option<&Path>,
+    color: Option<&ColorDescription>,
+) -> Result<Vec<Offer>, Box<dyn Error>> {
+    let capture = write::Capture::new(image_buffer).with_color(color);
+    let mut offers = vec![Offer::new(
+        extension.mime_type(),
+        write::encode(&capture, EncodeOptions::with_format(extension.into()))?,
+    )];
+
+    // PNG is the lingua franca of image pastes, always offer it.
+    if extension != EncodingFormat::Png {
+        offers.push(Offer::new(
+            EncodingFormat::Png.mime_type(),
+            write::encode(&capture, EncodeOptions::with_format(ImageFormat::Png))?,
+        ));
+    }
+
+    if let Some(file_path) = file_path {
+        offers.push(Offer::uri_list(&fs::canonicalize(file_path)?));
+    }
+
+    Ok(offers)
+}
+
+/// Offer every representation in `offers` on the clipboard of the compositor behind `conn` and
+/// keep serving paste requests from this process. Paste targets pick whichever MIME type suits
+/// them best; duplicate MIME types keep the first offer.
+///
+/// Returns once another client takes over the clipboard, once `timeout` elapses or once
+/// interrupted, whichever comes first.
+pub fn copy_to_clipboard(
+    conn: &Connection,
+    offers: Vec<Offer>,
+    timeout: Option<Duration>,
+) -> Result<(), Box<dyn Error>> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    Selection::set(conn, offers)?.serve(|| {
+        if signals::received().is_some() {
+            tracing::debug!("Interrupted, no longer serving paste requests");
+            return true;
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            tracing::debug!("Clipboard timeout reached, no longer serving paste requests");
+            return true;
+        }
+        false
+    })
+}
+
+/// Clipboard contents set through wlr-data-control, served from its own event queue so several
+/// selections can share a connection.
+struct Selection {
+    event_queue: EventQueue<SelectionState>,
+    state: SelectionState,
+    device: ZwlrDataControlDeviceV1,
+    source: ZwlrDataControlSourceV1,
+}
+
+struct SelectionState {
+    offers: Vec<Offer>,
+    /// Another client took over the clipboard.
+    cancelled: bool,
+}
+
+impl Selection {
+    /// Take over the clipboard of the first seat with `offers`. Setup failures, eg: a compositor
+    /// without wlr-data-control, surface here rather than while serving.
+    fn set(conn: &Connection, offers: Vec<Offer>) -> Result<Self, Box<dyn Error>> {
+        let offers = dedup_offers(offers);
+        tracing::debug!(
+            "Offering clipboard MIME types: {:?}",
+            offers
+                .iter()
+                .map(|offer| &offer.mime_type)
+                .collect::<Vec<_>>()
+        );
+
+        let (globals, mut event_queue) = registry_queue_init::<SelectionState>(conn)?;
+        let qh = event_queue.handle();
+        let manager = globals
+            .bind::<ZwlrDataControlManagerV1, _, _>(&qh, 1..=2, ())
+            .map_err(|_| "the compositor does not implement wlr-data-control")?;
+        let seat = globals.bind::<WlSeat, _, _>(&qh, 1..=7, ())?;
+        let device = manager.get_data_device(&seat, &qh, ());
+        let source = manager.create_data_source(&qh, ());
+        for offer in &offers {
+            source.offer(offer.mime_type.clone());
+        }
+        device.set_selection(Some(&source));
+        manager.destroy();
+
+        let mut state = SelectionState {
+            offers,
+            cancelled: false,
+        };
+        event_queue.roundtrip(&mut state)?;
+        Ok(Self {
+            event_queue,
+            state,
+            device,
+            source,
+        })
+    }
+
+    /// Serve paste requests until another client takes over the clipboard or `stop` returns
+    /// true, checked every [`signals::POLL_INTERVAL_MS`].
+    fn serve(mut self, mut stop: impl FnMut() -> bool) -> Result<(), Box<dyn Error>> {
+        let result = loop {
+            if self.state.cancelled {
+                tracing::debug!("Clipboard ownership lost, no longer serving paste requests");
+                break Ok(());
+            }
+            if stop() {
+                break Ok(());
+            }
+            if let Err(e) = utils::dispatch_interruptible(&mut self.event_queue, &mut self.state) {
+                break Err(e);
+            }
+        };
+        if !self.state.cancelled {
+            self.source.destroy();
+        }
+        self.device.destroy();
+        self.event_queue.flush()?;
+        result
+    }
+}
+
+/// `offers` in order, keeping the first of several offers of the same MIME type.
+fn dedup_offers(offers: Vec<Offer>) -> Vec<Offer> {
+    let mut unique: Vec<Offer> = Vec::with_capacity(offers.len());
+    for offer in offers {
+        if !unique.iter().any(|kept| kept.mime_type == offer.mime_type) {
+            unique.push(offer);
+        }
+    }
+    unique
+}
+
+/// `$XDG_RUNTIME_DIR/wayshot-clipboard-$WAYLAND_DISPLAY.sock`, where `wayshot clipboard-helper`
+/// listens by default.
+pub fn default_helper_socket_path() -> PathBuf {
+    daemon::session_socket_path("wayshot-clipboard")
+}
+
+/// Hand `offers` over to the `wayshot clipboard-helper` listening on `socket_path`, which serves
+/// paste requests from then on so this process can exit right away.
+///
+/// Returns once the helper owns the clipboard.
+pub fn hand_off(socket_path: &Path, offers: &[Offer]) -> Result<(), Box<dyn Error>> {
+    let mut stream = UnixStream::connect(socket_path).map_err(|e| {
+        format!(
+            "cannot reach a wayshot clipboard helper on {}: {e}",
+            socket_path.display()
+        )
+    })?;
+    let request = HandoffRequest {
+        offers: offers
+            .iter()
+            .map(|offer| HandoffOffer {
+                mime_type: offer.mime_type.clone(),
+                length: offer.data.len(),
+            })
+            .collect(),
+    };
+    serde_json::to_writer(&mut stream, &request)?;
+    stream.write_all(b"\n")?;
+    for offer in offers {
+        stream.write_all(&offer.data)?;
+    }
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    match serde_json::from_str(&line)? {
+        HandoffResponse::Copied => {
+            tracing::debug!("Clipboard handed off to {}", socket_path.display());
+            Ok(())
+        }
+        HandoffResponse::Error { message } => Err(message.into()),
+    }
+}
+
+/// `wayshot clipboard-helper`: take the clipboard contents handed off on `socket_path` and serve
+/// paste requests for them on `conn` until interrupted, the latest handoff replacing the ones
+/// before it.
+pub fn run_helper(conn: &Connection, socket_path: &Path) -> Result<(), Box<dyn Error>> {
+    if UnixStream::connect(socket_path).is_ok() {
+        return Err(format!(
+            "a wayshot clipboard helper is already listening on {}",
+            socket_path.display()
+        )
+        .into());
+    }
+    // Left behind by a helper that did not exit cleanly.
+    let _ = fs::remove_file(socket_path);
+    let listener = UnixListener::bind(socket_path)?;
+    tracing::info!("Serving clipboard handoffs on {}", socket_path.display());
+
+    let result = serve_handoffs(conn, &listener);
+    let _ = fs::remove_file(socket_path);
+    result
+}
+
+fn serve_handoffs(conn: &Connection, listener: &UnixListener) -> Result<(), Box<dyn Error>> {
+    while signals::received().is_none() {
+        let mut fds = [PollFd::new(listener, PollFlags::POLLIN)];
+        match poll(&mut fds, signals::POLL_INTERVAL_MS) {
+            Ok(0) | Err(Errno::EINTR) => continue,
+            Ok(_) => {}
+            Err(e) => return Err(e.into()),
+        }
+        let (stream, _) = listener.accept()?;
+        // One failed handoff should not take the helper down.
+        if let Err(e) = take_handoff(conn, stream) {
+            tracing::warn!("Failed to take over a clipboard handoff: {e}");
+        }
+    }
+    tracing::info!("Interrupted, exiting");
+    Ok(())
+}
+
+/// Read the offers sent on `stream` and serve them from a thread of their own. Serving the
+/// previous handoff stops once this one takes over the clipboard.
+fn take_handoff(conn: &Connection, mut stream: UnixStream) -> Result<(), Box<dyn Error>> {
+    stream.set_read_timeout(Some(HANDOFF_TIMEOUT))?;
+    let copied = read_offers(&stream).and_then(|offers| Selection::set(conn, offers));
+    let response = match copied {
+        Ok(selection) => {
+            thread::spawn(move || {
+                if let Err(e) = selection.serve(|| signals::received().is_some()) {
+                    tracing::warn!("Failed to serve the clipboard: {e}");
+                }
+            });
+            HandoffResponse::Copied
+        }
+        Err(e) => HandoffResponse::Error {
+            message: e.to_string(),
+        },
+    };
+    serde_json::to_writer(&mut stream, &response)?;
+    stream.write_all(b"\n")?;
+    Ok(())
+}
+
+fn read_offers(stream: &UnixStream) -> Result<Vec<Offer>, Box<dyn Error>> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let request: HandoffRequest = serde_json::from_str(&line)?;
+    request
+        .offers
+        .into_iter()
+        .map(|offer| {
+            let mut data = vec![0; offer.length];
+            reader.read_exact(&mut data)?;
+            Ok(Offer::new(&offer.mime_type, data))
+        })
+        .collect()
+}
+
+impl Dispatch<ZwlrDataControlSourceV1, ()> for SelectionState {
+    fn event(
+        state: &mut Self,
+        source: &ZwlrDataControlSourceV1,
+        event: zwlr_data_control_source_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_data_control_source_v1::Event::Send { mime_type, fd } => {
+                let Some(offer) = state
+                    .offers
+                    .iter()
+                    .find(|offer| offer.mime_type == mime_type)
+                else {
+                    return;
+                };
+                tracing::debug!("Sending {mime_type} to the paste target");
+                if let Err(e) = File::from(fd).write_all(&offer.data) {
+                    tracing::error!("Failed to send {mime_type} to the paste target: {e}");
+                }
+            }
+            zwlr_data_control_source_v1::Event::Cancelled => {
+                source.destroy();
+                state.cancelled = true;
+            }
+            _ => (),
+        }
+    }
+}
+
+impl Dispatch<ZwlrDataControlDeviceV1, ()> for SelectionState {
+    fn event(
+        _: &mut Self,
+        _: &ZwlrDataControlDeviceV1,
+        _: zwlr_data_control_device_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+
+    event_created_child!(SelectionState, ZwlrDataControlDeviceV1, [
+        zwlr_data_control_device_v1::EVT_DATA_OFFER_OPCODE => (ZwlrDataControlOfferV1, ()),
+    ]);
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for SelectionState {
+    fn event(
+        _: &mut Self,
+        _: &WlRegistry,
+        _: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+delegate_noop!(SelectionState: ignore WlSeat);
+delegate_noop!(SelectionState: ignore ZwlrDataControlManagerV1);
+delegate_noop!(SelectionState: ignore ZwlrDataControlOfferV1);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mime_types(offers: &[Offer]) -> Vec<&str> {
+        offers
+            .iter()
+            .map(|offer| offer.mime_type.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn uri_list_escapes_reserved_bytes() {
+        let offer = Offer::uri_list(Path::new("/tmp/my shot #1 (100%).png"));
+        assert_eq!(offer.mime_type, "text/uri-list");
+        assert_eq!(
+            offer.data,
+            b"file:///tmp/my%20shot%20%231%20%28100%25%29.png\r\n"
+        );
+    }
+
+    #[test]
+    fn uri_list_escapes_non_ascii_bytes() {
+        let path = Path::new(std::ffi::OsStr::from_bytes(b"/tmp/\xc3\xa9t\xe9.png"));
+        assert_eq!(
+            Offer::uri_list(path).data,
+            b"file:///tmp/%C3%A9t%E9.png\r\n"
+        );
+    }
+
+    #[test]
+    fn duplicate_mime_types_keep_the_first_offer() {
+        let offers = dedup_offers(vec![
+            Offer::new("image/png", vec![1]),
+            Offer::new("image/jpeg", vec![2]),
+            Offer::new("image/png", vec![3]),
+        ]);
+        assert_eq!(
+            offers,
+            [
+                Offer::new("image/png", vec![1]),
+                Offer::new("image/jpeg", vec![2]),
+            ]
+        );
+    }
+
+    #[test]
+    fn chosen_encoding_is_offered_first() {
+        let image = DynamicImage::new_rgb8(2, 2);
+        let offers = image_offers(&image, EncodingFormat::Jpg, None, None).expect("offers");
+        assert_eq!(mime_types(&offers), ["image/jpeg", "image/png"]);
+        assert!(offers[0].data.starts_with(&[0xff, 0xd8]));
+        assert!(offers[1].data.starts_with(b"\x89PNG"));
+    }
+
+    #[test]
+    fn png_is_offered_once() {
+        let image = DynamicImage::new_rgb8(2, 2);
+        let offers = image_offers(&image, EncodingFormat::Png, None, None).expect("offers");
+        assert_eq!(mime_types(&offers), ["image/png"]);
+    }
+
+    #[test]
+    fn saved_file_is_offered_last() {
+        let image = DynamicImage::new_rgb8(2, 2);
+        let saved_to = Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+        let offers =
+            image_offers(&image, EncodingFormat::Qoi, Some(&saved_to), None).expect("offers");
+        assert_eq!(
+            mime_types(&offers),
+            ["image/qoi", "image/png", "text/uri-list"]
+        );
+        assert!(offers[2].data.ends_with(b"/wayshot/Cargo.toml\r\n"));
+    }
+}
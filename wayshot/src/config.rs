@@ -0,0 +1,202 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    process::exit,
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use libwayshot::output::OutputSelector;
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify};
+use serde::Deserialize;
+
+use crate::logging;
+
+/// Contents of the wayshot configuration file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub base: Base,
+    pub plugins: Plugins,
+    pub log: Log,
+    pub selection: Selection,
+    pub feedback: Feedback,
+}
+
+/// The `[base]` table of the configuration file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Base {
+    /// Output to capture when none was chosen on the command line, parsed as a
+    /// [`libwayshot::output::OutputSelector`].
+    pub default_output: Option<String>,
+    /// Rhai script deciding file names, post-processing and sinks of every capture.
+    pub script: Option<PathBuf>,
+}
+
+/// The `[plugins]` table of the configuration file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Plugins {
+    /// Directory to load processor plugins from, `plugins/` next to the config file by default.
+    pub directory: Option<PathBuf>,
+}
+
+/// The `[selection]` table of the configuration file.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Selection {
+    /// Pull the edges of interactive selections to output and window edges.
+    pub snap: bool,
+    /// How close, in logical pixels, an edge has to be to snap.
+    pub snap_distance: u32,
+    /// Snap edges that are not close to an output or window to multiples of this many pixels.
+    pub grid: Option<u32>,
+}
+
+impl Default for Selection {
+    fn default() -> Self {
+        Self {
+            snap: true,
+            snap_distance: 12,
+            grid: None,
+        }
+    }
+}
+
+/// The `[feedback]` table of the configuration file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Feedback {
+    /// Sound file played once a capture was saved.
+    pub sound: Option<PathBuf>,
+    /// Sound theme name the notification server is asked to play with the notifications of
+    /// `--gui`, eg: `screen-capture`.
+    pub notification_sound: Option<String>,
+}
+
+/// The `[log]` table of the configuration file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Log {
+    /// Level of everything not listed below, `info` by default.
+    pub level: Option<String>,
+    /// Level per subsystem, eg: `capture = "debug"`, or per tracing target.
+    #[serde(flatten)]
+    pub subsystems: BTreeMap<String, String>,
+}
+
+impl Config {
+    /// Load the configuration file at `path`, falling back to the defaults if it does not exist.
+    pub fn load(path: &Path) -> Self {
+        match Self::parse(path) {
+            Ok(config) => config,
+            Err(e) => {
+                tracing::error!("{e}");
+                exit(1);
+            }
+        }
+    }
+
+    /// Like [`Config::load`], but report a file that does not parse or hold valid settings
+    /// instead of exiting.
+    pub fn parse(path: &Path) -> Result<Self, String> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(_) => {
+                tracing::debug!("No config file found at {}", path.display());
+                return Ok(Self::default());
+            }
+        };
+
+        let config: Self = toml::from_str(&contents)
+            .map_err(|e| format!("Failed to parse config file {}: {e}", path.display()))?;
+        config.default_output()?;
+        Ok(config)
+    }
+
+    /// `base.default_output` parsed as an [`OutputSelector`].
+    pub fn default_output(&self) -> Result<Option<OutputSelector>, String> {
+        self.base
+            .default_output
+            .as_deref()
+            .map(|selector| selector.parse().map_err(|e| format!("{e}")))
+            .transpose()
+    }
+
+    /// `$XDG_CONFIG_HOME/wayshot/config.toml`, or `~/.config/wayshot/config.toml`.
+    pub fn default_path() -> PathBuf {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .unwrap_or_default()
+            .join("wayshot")
+            .join("config.toml")
+    }
+}
+
+/// Watch the configuration file at `path` and send it on the returned channel every time it was
+/// written, renamed into place or removed, for the modes that keep running: the daemon, recording
+/// and timelapses. The log levels are applied right away, the rest is up to the receiver.
+///
+/// A file that does not parse is reported and skipped, the previous settings stay in effect.
+pub fn watch(path: PathBuf) -> Result<Receiver<Config>, Box<dyn Error>> {
+    let inotify = Inotify::init(InitFlags::IN_CLOEXEC)?;
+    // Editors usually save by renaming a new file over the old one, so watch the directory.
+    let directory = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let file_name = path
+        .file_name()
+        .ok_or("config path is not a file")?
+        .to_owned();
+    inotify.add_watch(
+        directory,
+        AddWatchFlags::IN_CLOSE_WRITE | AddWatchFlags::IN_MOVED_TO | AddWatchFlags::IN_DELETE,
+    )?;
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || loop {
+        let events = match inotify.read_events() {
+            Ok(events) => events,
+            Err(e) => {
+                tracing::warn!("Stopped watching {}: {e}", path.display());
+                return;
+            }
+        };
+        // A single save can be several events, reload once per batch.
+        if !events
+            .iter()
+            .any(|event| event.name.as_ref() == Some(&file_name))
+        {
+            continue;
+        }
+        let config = match Config::parse(&path) {
+            Ok(config) => config,
+            Err(e) => {
+                tracing::warn!("Keeping the previous settings: {e}");
+                continue;
+            }
+        };
+        if let Err(e) = logging::reload(&config.log) {
+            tracing::warn!("Keeping the previous log levels: {e}");
+        }
+        tracing::info!("Reloaded {}", path.display());
+        // Nobody listening only means the mode has nothing else to update.
+        let _ = sender.send(config);
+    });
+    Ok(receiver)
+}
+
+/// Expand a leading `~/` to the home directory, so paths in the config file can be written the
+/// way they are typed in a shell.
+pub fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => path.to_path_buf(),
+    }
+}
@@ -0,0 +1,227 @@
+use std::{
+    error::Error,
+    fs::File,
+    os::fd::AsFd,
+    thread,
+    time::{Duration, Instant},
+};
+
+use libwayshot::overlay::{
+    text::{self, LabelStyle},
+    Canvas,
+};
+use wayland_client::{
+    delegate_noop,
+    globals::{registry_queue_init, GlobalListContents},
+    protocol::{
+        wl_buffer::WlBuffer,
+        wl_compositor::WlCompositor,
+        wl_region::WlRegion,
+        wl_registry::{self, WlRegistry},
+        wl_shm::{self, WlShm},
+        wl_shm_pool::WlShmPool,
+        wl_surface::WlSurface,
+    },
+    Connection, Dispatch, QueueHandle,
+};
+use wayland_protocols_wlr::layer_shell::v1::client::{
+    zwlr_layer_shell_v1::{Layer, ZwlrLayerShellV1},
+    zwlr_layer_surface_v1::{self, KeyboardInteractivity, ZwlrLayerSurfaceV1},
+};
+
+use crate::{signals, utils};
+
+// Premultiplied Argb8888, stored as little endian B, G, R, A.
+const BACKGROUND: [u8; 4] = [22, 22, 22, 0xc0];
+const STYLE: LabelStyle = LabelStyle {
+    size: 96.0,
+    foreground: [0xe6, 0xe6, 0xe6, 0xff],
+    background: None,
+    padding: 24,
+};
+
+struct CountdownState {
+    closed: bool,
+}
+
+/// Wait for `delay`, counting the remaining seconds down on the compositor behind `conn` when
+/// `overlay` is set, so the user can open menus or tooltips before the capture.
+///
+/// The countdown is drawn in the middle of the focused output and is gone when this returns. If
+/// it cannot be shown, eg: without wlr-layer-shell, the wait goes on without it. A signal ends the
+/// wait early.
+pub fn wait(conn: &Connection, delay: Duration, overlay: bool) {
+    let deadline = Instant::now() + delay;
+    if overlay {
+        if let Err(e) = count_down(conn, deadline) {
+            tracing::warn!("Failed to show the countdown: {e}");
+        }
+    }
+    let slice = Duration::from_millis(signals::POLL_INTERVAL_MS as u64);
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() || signals::received().is_some() {
+            break;
+        }
+        thread::sleep(remaining.min(slice));
+    }
+}
+
+fn count_down(conn: &Connection, deadline: Instant) -> Result<(), Box<dyn Error>> {
+    let (globals, mut event_queue) = registry_queue_init::<CountdownState>(conn)?;
+    let qh = event_queue.handle();
+
+    let compositor = globals.bind::<WlCompositor, _, _>(&qh, 4..=4, ())?;
+    let shm = globals.bind::<WlShm, _, _>(&qh, 1..=1, ())?;
+    let layer_shell = globals.bind::<ZwlrLayerShellV1, _, _>(&qh, 1..=4, ())?;
+
+    // The first number has the most digits, the surface keeps its size.
+    let (width, height) = text::label_size(&seconds_left(deadline).to_string(), &STYLE)?;
+
+    let surface = compositor.create_surface(&qh, ());
+    // Let clicks go through the countdown.
+    let empty_region = compositor.create_region(&qh, ());
+    surface.set_input_region(Some(&empty_region));
+    empty_region.destroy();
+    // Without an anchor the surface is centered on the output the compositor picks.
+    let layer_surface = layer_shell.get_layer_surface(
+        &surface,
+        None,
+        Layer::Overlay,
+        "wayshot-countdown".to_string(),
+        &qh,
+        (),
+    );
+    layer_surface.set_size(width, height);
+    layer_surface.set_keyboard_interactivity(KeyboardInteractivity::None);
+    surface.commit();
+
+    let mut state = CountdownState { closed: false };
+    // Buffers may only be attached once the surface is configured.
+    event_queue.roundtrip(&mut state)?;
+
+    let mut shown = None;
+    let mut attached: Option<(WlBuffer, WlShmPool)> = None;
+    let result = loop {
+        let seconds = seconds_left(deadline);
+        if seconds == 0 || state.closed || signals::received().is_some() {
+            break Ok(());
+        }
+        if shown != Some(seconds) {
+            let (buffer, pool) = match draw(&shm, &qh, width, height, seconds) {
+                Ok(drawn) => drawn,
+                Err(e) => break Err(e),
+            };
+            surface.attach(Some(&buffer), 0, 0);
+            surface.damage_buffer(0, 0, width as i32, height as i32);
+            surface.commit();
+            // The storage is never reused, so the previous buffer can go before its release.
+            if let Some((buffer, pool)) = attached.replace((buffer, pool)) {
+                buffer.destroy();
+                pool.destroy();
+            }
+            shown = Some(seconds);
+        }
+        conn.flush()?;
+
+        let until_next_second = deadline
+            .saturating_duration_since(Instant::now())
+            .saturating_sub(Duration::from_secs(seconds - 1));
+        thread::sleep(
+            until_next_second.min(Duration::from_millis(signals::POLL_INTERVAL_MS as u64)),
+        );
+        event_queue.dispatch_pending(&mut state)?;
+    };
+
+    layer_surface.destroy();
+    surface.destroy();
+    if let Some((buffer, pool)) = attached {
+        buffer.destroy();
+        pool.destroy();
+    }
+    // Make sure the countdown is gone before the capture.
+    event_queue.roundtrip(&mut state)?;
+    result
+}
+
+/// Whole seconds left until `deadline`, rounded up.
+fn seconds_left(deadline: Instant) -> u64 {
+    deadline
+        .saturating_duration_since(Instant::now())
+        .as_millis()
+        .div_ceil(1000) as u64
+}
+
+/// Draw `seconds` centered on a `width` x `height` buffer.
+fn draw(
+    shm: &WlShm,
+    qh: &QueueHandle<CountdownState>,
+    width: u32,
+    height: u32,
+    seconds: u64,
+) -> Result<(WlBuffer, WlShmPool), Box<dyn Error>> {
+    let stride = width * 4;
+    let mem_file = File::from(libwayshot::create_shm_fd()?);
+    mem_file.set_len(u64::from(stride * height))?;
+    let mut mmap = utils::map_shm(&mem_file)?;
+    let mut canvas = Canvas {
+        pixels: &mut mmap,
+        width,
+        height,
+    };
+    canvas.fill(BACKGROUND);
+    text::draw_label(&mut canvas, &seconds.to_string(), &STYLE)?;
+
+    let pool = shm.create_pool(mem_file.as_fd(), (stride * height) as i32, qh, ());
+    let buffer = pool.create_buffer(
+        0,
+        width as i32,
+        height as i32,
+        stride as i32,
+        wl_shm::Format::Argb8888,
+        qh,
+        (),
+    );
+    Ok((buffer, pool))
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for CountdownState {
+    fn event(
+        _: &mut Self,
+        _: &WlRegistry,
+        _: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwlrLayerSurfaceV1, ()> for CountdownState {
+    fn event(
+        state: &mut Self,
+        layer_surface: &ZwlrLayerSurfaceV1,
+        event: zwlr_layer_surface_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_layer_surface_v1::Event::Configure { serial, .. } => {
+                layer_surface.ack_configure(serial);
+            }
+            zwlr_layer_surface_v1::Event::Closed => {
+                state.closed = true;
+            }
+            _ => (),
+        }
+    }
+}
+
+delegate_noop!(CountdownState: ignore WlCompositor);
+delegate_noop!(CountdownState: ignore WlSurface);
+delegate_noop!(CountdownState: ignore WlRegion);
+delegate_noop!(CountdownState: ignore WlShm);
+delegate_noop!(CountdownState: ignore WlShmPool);
+delegate_noop!(CountdownState: ignore WlBuffer);
+delegate_noop!(CountdownState: ignore ZwlrLayerShellV1);
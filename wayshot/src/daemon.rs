@@ -0,0 +1,355 @@
+use std::{
+    env,
+    error::Error,
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    sync::{mpsc::Receiver, Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use image::ImageFormat;
+use libwayshot::{
+    output::OutputSelector,
+    write::{self, EncodeOptions},
+    OutputEvent, WayshotConnection,
+};
+use nix::{
+    errno::Errno,
+    poll::{poll, PollFd, PollFlags},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config, dbus, latest::LatestCapture, region_from_geometry, signals, workspace,
+};
+
+/// How long a client may take to send its request before the daemon moves on.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A capture asked for by `wayshot client`, sent to the daemon as one line of JSON.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CaptureRequest {
+    /// `--slurp` geometry, relative to `output` when both are given.
+    pub geometry: Option<String>,
+    /// `--output`, by name or stable identifier.
+    pub output: Option<String>,
+    pub cursor: bool,
+    /// Extension of the format to encode the image in, eg: `png`.
+    pub extension: String,
+    /// `--display`, the Wayland socket of the compositor to capture when the daemon is connected
+    /// to several. The daemon's own display when not set.
+    #[serde(default)]
+    pub display: Option<String>,
+}
+
+/// Answer of the daemon as one line of JSON, followed by `length` bytes of encoded image when the
+/// capture succeeded.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum CaptureResponse {
+    Image { length: usize },
+    Error { message: String },
+}
+
+/// `$XDG_RUNTIME_DIR/wayshot-$WAYLAND_DISPLAY.sock`, so daemons of different sessions do not
+/// collide.
+pub fn default_socket_path() -> PathBuf {
+    session_socket_path("wayshot")
+}
+
+/// `$XDG_RUNTIME_DIR/<prefix>-$WAYLAND_DISPLAY.sock`.
+pub fn session_socket_path(prefix: &str) -> PathBuf {
+    let runtime_dir = env::var_os("XDG_RUNTIME_DIR").map_or_else(env::temp_dir, PathBuf::from);
+    let display = env::var("WAYLAND_DISPLAY").unwrap_or_else(|_| "wayland-0".to_string());
+    runtime_dir.join(format!("{prefix}-{}.sock", socket_name(&display)))
+}
+
+/// Name of the Wayland socket `display` refers to, eg: `wayland-1` for both `wayland-1` and
+/// `/run/user/1000/wayland-1`, so connections are found whichever way they were named.
+pub fn socket_name(display: &str) -> String {
+    Path::new(display).file_name().map_or_else(
+        || display.to_string(),
+        |name| name.to_string_lossy().into_owned(),
+    )
+}
+
+/// Connections of the daemon to every compositor it captures from, keyed by socket name.
+struct Connections {
+    /// Socket name of the connection requests without a display go to.
+    primary: String,
+    connections: Vec<(String, Arc<Mutex<WayshotConnection>>)>,
+}
+
+impl Connections {
+    /// The connection to `display`, the primary one without it.
+    fn get(&self, display: Option<&str>) -> Result<&Mutex<WayshotConnection>, Box<dyn Error>> {
+        let name = display.map_or_else(|| self.primary.clone(), socket_name);
+        self.connections
+            .iter()
+            .find(|(socket, _)| *socket == name)
+            .map(|(_, wayshot_conn)| &**wayshot_conn)
+            .ok_or_else(|| format!("the daemon is not connected to {name}").into())
+    }
+}
+
+/// Serve capture requests on `socket_path` until interrupted, and on the session bus as well with
+/// `dbus`. `connections` pairs displays with a connection to them: requests naming a display are
+/// taken with its connection, matched by Wayland socket, the others with the first one. The
+/// outputs are kept up to date as monitors are plugged and unplugged, and the default output
+/// follows the configs received on `reloads`. Every capture is also published to `latest`, if
+/// any.
+pub fn run(
+    connections: Vec<(String, WayshotConnection)>,
+    socket_path: &Path,
+    default_output: Option<OutputSelector>,
+    reloads: Option<Receiver<Config>>,
+    dbus: bool,
+    latest: Option<LatestCapture>,
+) -> Result<(), Box<dyn Error>> {
+    let mut connections = connections.into_iter();
+    let Some((display, wayshot_conn)) = connections.next() else {
+        return Err("the daemon needs a connection to capture with".into());
+    };
+    let wayshot_conn = Arc::new(Mutex::new(wayshot_conn));
+    let mut all_connections = Connections {
+        primary: socket_name(&display),
+        connections: vec![(socket_name(&display), wayshot_conn.clone())],
+    };
+    for (display, other_conn) in connections {
+        let name = socket_name(&display);
+        if all_connections.get(Some(&name)).is_ok() {
+            return Err(format!("connected to {name} twice").into());
+        }
+        tracing::info!("Also capturing from {name}");
+        all_connections
+            .connections
+            .push((name, Arc::new(Mutex::new(other_conn))));
+    }
+    if UnixStream::connect(socket_path).is_ok() {
+        return Err(format!(
+            "a wayshot daemon is already listening on {}",
+            socket_path.display()
+        )
+        .into());
+    }
+    // Left behind by a daemon that did not exit cleanly.
+    let _ = fs::remove_file(socket_path);
+    let listener = UnixListener::bind(socket_path)?;
+    tracing::info!("Listening on {}", socket_path.display());
+
+    let _bus = if dbus {
+        let bus = dbus::serve(wayshot_conn, latest.clone())?;
+        tracing::info!("Serving {} on the session bus", dbus::BUS_NAME);
+        Some(bus)
+    } else {
+        None
+    };
+
+    let result = serve(
+        &all_connections,
+        &listener,
+        default_output,
+        reloads.as_ref(),
+        latest.as_ref(),
+    );
+    let _ = fs::remove_file(socket_path);
+    result
+}
+
+fn serve(
+    connections: &Connections,
+    listener: &UnixListener,
+    mut default_output: Option<OutputSelector>,
+    reloads: Option<&Receiver<Config>>,
+    latest: Option<&LatestCapture>,
+) -> Result<(), Box<dyn Error>> {
+    let mut watchers = Vec::with_capacity(connections.connections.len());
+    for (name, wayshot_conn) in &connections.connections {
+        watchers.push((name, wayshot_conn, lock(wayshot_conn)?.watch_outputs()?));
+    }
+    while signals::received().is_none() {
+        // Configs are validated before they are sent, only the latest one matters.
+        if let Some(config) = reloads.and_then(|reloads| reloads.try_iter().last()) {
+            default_output = config.default_output()?;
+        }
+
+        for (name, wayshot_conn, watcher) in &mut watchers {
+            for event in watcher.dispatch(&mut *lock(wayshot_conn)?)? {
+                match event {
+                    OutputEvent::Added(output) => {
+                        tracing::info!("Output {} added to {name}", output.name)
+                    }
+                    OutputEvent::Removed(output) => {
+                        tracing::info!("Output {} removed from {name}", output.name)
+                    }
+                    OutputEvent::Changed(output) => {
+                        tracing::debug!("Output {} of {name} changed", output.name)
+                    }
+                }
+            }
+        }
+
+        let mut fds = [PollFd::new(listener, PollFlags::POLLIN)];
+        match poll(&mut fds, signals::POLL_INTERVAL_MS) {
+            Ok(0) | Err(Errno::EINTR) => continue,
+            Ok(_) => {}
+            Err(e) => return Err(e.into()),
+        }
+        let (stream, _) = listener.accept()?;
+        // One failed client should not take the daemon down.
+        if let Err(e) = answer(connections, stream, default_output.as_ref(), latest) {
+            tracing::warn!("Failed to answer a capture request: {e}");
+        }
+    }
+    tracing::info!("Interrupted, exiting");
+    Ok(())
+}
+
+fn lock(
+    wayshot_conn: &Mutex<WayshotConnection>,
+) -> Result<MutexGuard<'_, WayshotConnection>, String> {
+    wayshot_conn.lock().map_err(|e| e.to_string())
+}
+
+fn answer(
+    connections: &Connections,
+    mut stream: UnixStream,
+    default_output: Option<&OutputSelector>,
+    latest: Option<&LatestCapture>,
+) -> Result<(), Box<dyn Error>> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    #[cfg(feature = "metrics")]
+    let started = std::time::Instant::now();
+    let captured = serde_json::from_str(&line)
+        .map_err(Box::<dyn Error>::from)
+        .and_then(|request: CaptureRequest| {
+            let wayshot_conn = lock(connections.get(request.display.as_deref())?)?;
+            capture(&wayshot_conn, &request, default_output, latest)
+        });
+    #[cfg(feature = "metrics")]
+    crate::prometheus::record_capture(
+        "socket",
+        captured.as_ref().map(Vec::len).map_err(AsRef::as_ref),
+        started.elapsed(),
+    );
+
+    let (response, image) = match captured {
+        Ok(image) => (
+            CaptureResponse::Image {
+                length: image.len(),
+            },
+            image,
+        ),
+        Err(e) => {
+            tracing::warn!("Capture request failed: {e}");
+            (
+                CaptureResponse::Error {
+                    message: e.to_string(),
+                },
+                Vec::new(),
+            )
+        }
+    };
+    serde_json::to_writer(&mut stream, &response)?;
+    stream.write_all(b"\n")?;
+    stream.write_all(&image)?;
+    Ok(())
+}
+
+/// Take the capture `request` describes, the same way the command line options would, publish it
+/// to `latest` and encode it.
+fn capture(
+    wayshot_conn: &WayshotConnection,
+    request: &CaptureRequest,
+    default_output: Option<&OutputSelector>,
+    latest: Option<&LatestCapture>,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let format = ImageFormat::from_extension(&request.extension)
+        .ok_or_else(|| format!("unknown image extension {:?}", request.extension))?;
+    let output = match (&request.geometry, &request.output, default_output) {
+        (Some(_), _, _) => None,
+        (None, Some(output_name), _) => Some(
+            wayshot_conn
+                .find_output(output_name)
+                .ok_or("No output found")?,
+        ),
+        (None, None, Some(selector)) => Some(
+            wayshot_conn
+                .select_output(&workspace::resolve_selector(selector)?)
+                .ok_or("No output found")?,
+        ),
+        (None, None, None) => None,
+    };
+    let (image, region) = match (&request.geometry, output) {
+        (Some(geometry), _) => {
+            let region =
+                region_from_geometry(wayshot_conn, geometry, request.output.as_ref(), None)?;
+            (wayshot_conn.screenshot(region, request.cursor)?, region)
+        }
+        (None, Some(output)) => (
+            wayshot_conn.screenshot_single_output(output, request.cursor)?,
+            output.region(),
+        ),
+        (None, None) => (
+            wayshot_conn.screenshot_all(request.cursor)?,
+            wayshot_conn.desktop_region()?,
+        ),
+    };
+
+    let capture = write::Capture::new(&image).with_color(wayshot_conn.color_description(region));
+    if let Some(latest) = latest {
+        latest.publish_or_warn(&capture, None);
+    }
+    Ok(write::encode(&capture, EncodeOptions::with_format(format))?)
+}
+
+/// Send `request` to the daemon listening on `socket_path` and return the encoded image.
+pub fn request_capture(
+    socket_path: &Path,
+    request: &CaptureRequest,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut stream = UnixStream::connect(socket_path).map_err(|e| {
+        format!(
+            "cannot reach a wayshot daemon on {}: {e}",
+            socket_path.display()
+        )
+    })?;
+    serde_json::to_writer(&mut stream, request)?;
+    stream.write_all(b"\n")?;
+
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    match serde_json::from_str(&line)? {
+        CaptureResponse::Image { length } => {
+            let mut image = vec![0; length];
+            reader.read_exact(&mut image)?;
+            Ok(image)
+        }
+        CaptureResponse::Error { message } => Err(message.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn displays_are_keyed_by_socket_name() {
+        assert_eq!(socket_name("wayland-1"), "wayland-1");
+        assert_eq!(socket_name("/run/user/1000/wayland-1"), "wayland-1");
+    }
+
+    #[test]
+    fn requests_without_display_go_to_the_primary_connection() {
+        let request: CaptureRequest = serde_json::from_str(
+            r#"{"geometry":null,"output":null,"cursor":false,"extension":"png"}"#,
+        )
+        .expect("request");
+        assert_eq!(request.display, None);
+    }
+}
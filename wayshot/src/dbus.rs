@@ -0,0 +1,167 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use image::DynamicImage;
+use libwayshot::{
+    write::{self, EncodeOptions, WriteTarget},
+    CaptureRegion, WayshotConnection,
+};
+use zbus::{
+    blocking, fdo, interface,
+    zvariant::{self, OwnedValue},
+};
+
+use crate::{daemon, latest::LatestCapture, workspace};
+
+pub const BUS_NAME: &str = "org.waycrate.Wayshot";
+const OBJECT_PATH: &str = "/org/waycrate/Wayshot";
+
+type Options = HashMap<String, OwnedValue>;
+
+/// The `org.waycrate.Wayshot` interface served by `wayshot daemon --dbus`. Every method saves the
+/// capture and returns the path of the file.
+struct Wayshot {
+    wayshot_conn: Arc<Mutex<WayshotConnection>>,
+    latest: Option<LatestCapture>,
+}
+
+impl Wayshot {
+    /// Take a capture with the shared connection and save it where `options` say.
+    ///
+    /// Recognized options are `cursor` (b), `extension` (s, png by default) and `path` (s), which
+    /// defaults to a new file next to the daemon's socket.
+    fn save(
+        &self,
+        options: &Options,
+        capture: impl FnOnce(
+            &WayshotConnection,
+            bool,
+        ) -> Result<(DynamicImage, CaptureRegion), Box<dyn Error>>,
+    ) -> fdo::Result<String> {
+        let cursor = option::<bool>(options, "cursor")?.unwrap_or(false);
+        let path = match option::<&str>(options, "path")? {
+            Some(path) => PathBuf::from(path),
+            None => {
+                let extension = option::<&str>(options, "extension")?.unwrap_or("png");
+                let time = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |time| time.as_millis());
+                daemon::default_socket_path().with_file_name(format!("wayshot-{time}.{extension}"))
+            }
+        };
+
+        let wayshot_conn = self
+            .wayshot_conn
+            .lock()
+            .map_err(|e| fdo::Error::Failed(e.to_string()))?;
+        #[cfg(feature = "metrics")]
+        let started = std::time::Instant::now();
+        let saved = capture(&wayshot_conn, cursor).and_then(|(image, region)| {
+            let capture =
+                write::Capture::new(&image).with_color(wayshot_conn.color_description(region));
+            write::save(&capture, WriteTarget::File(&path), EncodeOptions::default())?;
+            if let Some(latest) = &self.latest {
+                latest.publish_or_warn(&capture, Some(&path));
+            }
+            Ok(())
+        });
+        #[cfg(feature = "metrics")]
+        crate::prometheus::record_capture(
+            "dbus",
+            saved
+                .as_ref()
+                .map(|()| std::fs::metadata(&path).map_or(0, |metadata| metadata.len() as usize))
+                .map_err(AsRef::as_ref),
+            started.elapsed(),
+        );
+        match saved {
+            Ok(()) => {
+                tracing::debug!("Saved capture to {}", path.display());
+                Ok(path.to_string_lossy().into_owned())
+            }
+            Err(e) => {
+                tracing::warn!("D-Bus capture failed: {e}");
+                Err(fdo::Error::Failed(e.to_string()))
+            }
+        }
+    }
+}
+
+/// The value of option `name`, `None` when not given.
+fn option<'a, T>(options: &'a Options, name: &str) -> fdo::Result<Option<T>>
+where
+    T: TryFrom<&'a OwnedValue, Error = zvariant::Error>,
+{
+    options
+        .get(name)
+        .map(T::try_from)
+        .transpose()
+        .map_err(|e| fdo::Error::InvalidArgs(format!("option {name}: {e}")))
+}
+
+#[interface(name = "org.waycrate.Wayshot")]
+impl Wayshot {
+    /// Capture the output with the given connector name or stable identifier.
+    fn capture_output(&self, name: &str, options: Options) -> fdo::Result<String> {
+        self.save(&options, |wayshot_conn, cursor| {
+            let output = wayshot_conn.find_output(name).ok_or("No output found")?;
+            Ok((
+                wayshot_conn.screenshot_single_output(output, cursor)?,
+                output.region(),
+            ))
+        })
+    }
+
+    /// Capture a region of the global compositor space, in logical pixels.
+    fn capture_region(
+        &self,
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+        options: Options,
+    ) -> fdo::Result<String> {
+        let region = CaptureRegion {
+            x_coordinate: x,
+            y_coordinate: y,
+            width,
+            height,
+        };
+        self.save(&options, |wayshot_conn, cursor| {
+            Ok((wayshot_conn.screenshot(region, cursor)?, region))
+        })
+    }
+
+    /// Capture the focused window, through the sway or Hyprland IPC.
+    fn capture_active_window(&self, options: Options) -> fdo::Result<String> {
+        self.save(&options, |wayshot_conn, cursor| {
+            let driver = workspace::detect_driver()
+                .ok_or("No supported compositor IPC found, only sway and Hyprland are supported")?;
+            let region = driver.active_window()?.ok_or("No window has focus")?;
+            Ok((wayshot_conn.screenshot(region, cursor)?, region))
+        })
+    }
+}
+
+/// Claim [`BUS_NAME`] on the session bus and serve the interface until the returned connection is
+/// dropped. Requests are handled on the connection's executor thread.
+pub fn serve(
+    wayshot_conn: Arc<Mutex<WayshotConnection>>,
+    latest: Option<LatestCapture>,
+) -> Result<blocking::Connection, Box<dyn Error>> {
+    Ok(blocking::connection::Builder::session()?
+        .name(BUS_NAME)?
+        .serve_at(
+            OBJECT_PATH,
+            Wayshot {
+                wayshot_conn,
+                latest,
+            },
+        )?
+        .build()?)
+}
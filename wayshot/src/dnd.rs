@@ -0,0 +1,291 @@
+use std::{error::Error, fs::File, io::Write, os::fd::AsFd};
+
+use image::DynamicImage;
+use libwayshot::create_shm_fd;
+use wayland_client::{
+    delegate_noop, event_created_child,
+    globals::{registry_queue_init, GlobalListContents},
+    protocol::{
+        wl_buffer::WlBuffer,
+        wl_compositor::WlCompositor,
+        wl_data_device::{self, WlDataDevice},
+        wl_data_device_manager::{DndAction, WlDataDeviceManager},
+        wl_data_offer::WlDataOffer,
+        wl_data_source::{self, WlDataSource},
+        wl_pointer::{self, ButtonState, WlPointer},
+        wl_registry::{self, WlRegistry},
+        wl_seat::{self, Capability, WlSeat},
+        wl_shm::{self, WlShm},
+        wl_shm_pool::WlShmPool,
+        wl_surface::WlSurface,
+    },
+    Connection, Dispatch, QueueHandle, WEnum,
+};
+use wayland_protocols_wlr::layer_shell::v1::client::{
+    zwlr_layer_shell_v1::{Layer, ZwlrLayerShellV1},
+    zwlr_layer_surface_v1::{self, KeyboardInteractivity, ZwlrLayerSurfaceV1},
+};
+
+use crate::{clipboard::Offer, signals, utils};
+
+/// Longest side of the thumbnail the user drags from.
+const THUMBNAIL_SIZE: u32 = 256;
+/// Left mouse button, from linux/input-event-codes.h.
+const BTN_LEFT: u32 = 0x110;
+/// Right mouse button, from linux/input-event-codes.h.
+const BTN_RIGHT: u32 = 0x111;
+
+struct DndState {
+    offers: Vec<Offer>,
+    surface: WlSurface,
+    buffer: WlBuffer,
+    pointer_focus: bool,
+    /// Serial of the button press that should start the drag.
+    pending_drag: Option<u32>,
+    drag_started: bool,
+    done: bool,
+}
+
+/// Show a thumbnail of `image` on top of every window of the compositor behind `conn` and let the
+/// user drag it into another application, which receives the contents of `offers`.
+///
+/// Blocks until the drop finished, was cancelled, or the user right clicked the thumbnail.
+pub fn start_drag(
+    conn: &Connection,
+    image: &DynamicImage,
+    offers: Vec<Offer>,
+) -> Result<(), Box<dyn Error>> {
+    let (globals, mut event_queue) = registry_queue_init::<DndState>(conn)?;
+    let qh = event_queue.handle();
+
+    let compositor = globals.bind::<WlCompositor, _, _>(&qh, 1..=4, ())?;
+    let shm = globals.bind::<WlShm, _, _>(&qh, 1..=1, ())?;
+    let layer_shell = globals.bind::<ZwlrLayerShellV1, _, _>(&qh, 1..=4, ())?;
+    let data_device_manager = globals.bind::<WlDataDeviceManager, _, _>(&qh, 3..=3, ())?;
+    let seat = globals.bind::<WlSeat, _, _>(&qh, 1..=7, ())?;
+    let data_device = data_device_manager.get_data_device(&seat, &qh, ());
+
+    let thumbnail = image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE).to_rgba8();
+    let (width, height) = thumbnail.dimensions();
+    let stride = width * 4;
+
+    let mem_file = File::from(create_shm_fd()?);
+    mem_file.set_len(u64::from(stride * height))?;
+    let mut mmap = utils::map_shm(&mem_file)?;
+    for (dst, src) in mmap.chunks_exact_mut(4).zip(thumbnail.pixels()) {
+        // Argb8888 is stored as little endian, so B, G, R, A in memory.
+        dst.copy_from_slice(&[src[2], src[1], src[0], src[3]]);
+    }
+    let pool = shm.create_pool(mem_file.as_fd(), (stride * height) as i32, &qh, ());
+    let buffer = pool.create_buffer(
+        0,
+        width as i32,
+        height as i32,
+        stride as i32,
+        wl_shm::Format::Argb8888,
+        &qh,
+        (),
+    );
+
+    let surface = compositor.create_surface(&qh, ());
+    let layer_surface = layer_shell.get_layer_surface(
+        &surface,
+        None,
+        Layer::Overlay,
+        "wayshot-dnd".to_string(),
+        &qh,
+        (),
+    );
+    layer_surface.set_size(width, height);
+    layer_surface.set_keyboard_interactivity(KeyboardInteractivity::None);
+    surface.commit();
+
+    let mut state = DndState {
+        offers,
+        surface,
+        buffer,
+        pointer_focus: false,
+        pending_drag: None,
+        drag_started: false,
+        done: false,
+    };
+
+    tracing::info!("Drag the thumbnail into another window, right click it to cancel");
+    while !state.done {
+        utils::dispatch_interruptible(&mut event_queue, &mut state)?;
+        if signals::received().is_some() {
+            break;
+        }
+
+        if let Some(serial) = state.pending_drag.take() {
+            let source = data_device_manager.create_data_source(&qh, ());
+            for offer in &state.offers {
+                source.offer(offer.mime_type.clone());
+            }
+            source.set_actions(DndAction::Copy);
+            data_device.start_drag(Some(&source), &state.surface, None, serial);
+            state.drag_started = true;
+        }
+    }
+
+    layer_surface.destroy();
+    state.surface.destroy();
+    state.buffer.destroy();
+    pool.destroy();
+    data_device.release();
+    // Make sure the surface is gone even if we are about to exit because of a signal.
+    conn.flush()?;
+
+    Ok(())
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for DndState {
+    fn event(
+        _: &mut Self,
+        _: &WlRegistry,
+        _: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<WlSeat, ()> for DndState {
+    fn event(
+        _: &mut Self,
+        seat: &WlSeat,
+        event: wl_seat::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let wl_seat::Event::Capabilities {
+            capabilities: WEnum::Value(capabilities),
+        } = event
+        {
+            if capabilities.contains(Capability::Pointer) {
+                seat.get_pointer(qh, ());
+            }
+        }
+    }
+}
+
+impl Dispatch<WlPointer, ()> for DndState {
+    fn event(
+        state: &mut Self,
+        _: &WlPointer,
+        event: wl_pointer::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_pointer::Event::Enter { surface, .. } => {
+                state.pointer_focus = surface == state.surface;
+            }
+            wl_pointer::Event::Leave { .. } => {
+                state.pointer_focus = false;
+            }
+            wl_pointer::Event::Button {
+                serial,
+                button,
+                state: WEnum::Value(ButtonState::Pressed),
+                ..
+            } if state.pointer_focus && !state.drag_started => match button {
+                BTN_LEFT => state.pending_drag = Some(serial),
+                BTN_RIGHT => {
+                    tracing::info!("Drag and drop cancelled");
+                    state.done = true;
+                }
+                _ => (),
+            },
+            _ => (),
+        }
+    }
+}
+
+impl Dispatch<ZwlrLayerSurfaceV1, ()> for DndState {
+    fn event(
+        state: &mut Self,
+        layer_surface: &ZwlrLayerSurfaceV1,
+        event: zwlr_layer_surface_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_layer_surface_v1::Event::Configure { serial, .. } => {
+                layer_surface.ack_configure(serial);
+                state.surface.attach(Some(&state.buffer), 0, 0);
+                state.surface.commit();
+            }
+            zwlr_layer_surface_v1::Event::Closed => {
+                state.done = true;
+            }
+            _ => (),
+        }
+    }
+}
+
+impl Dispatch<WlDataSource, ()> for DndState {
+    fn event(
+        state: &mut Self,
+        source: &WlDataSource,
+        event: wl_data_source::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_data_source::Event::Send { mime_type, fd } => {
+                let Some(offer) = state
+                    .offers
+                    .iter()
+                    .find(|offer| offer.mime_type == mime_type)
+                else {
+                    return;
+                };
+                tracing::debug!("Sending {mime_type} to the drop target");
+                if let Err(e) = File::from(fd).write_all(&offer.data) {
+                    tracing::error!("Failed to send {mime_type} to the drop target: {e}");
+                }
+            }
+            wl_data_source::Event::Cancelled => {
+                tracing::info!("Drag and drop cancelled");
+                source.destroy();
+                state.done = true;
+            }
+            wl_data_source::Event::DndFinished => {
+                source.destroy();
+                state.done = true;
+            }
+            _ => (),
+        }
+    }
+}
+
+impl Dispatch<WlDataDevice, ()> for DndState {
+    fn event(
+        _: &mut Self,
+        _: &WlDataDevice,
+        _: wl_data_device::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+
+    event_created_child!(DndState, WlDataDevice, [
+        wl_data_device::EVT_DATA_OFFER_OPCODE => (WlDataOffer, ()),
+    ]);
+}
+
+delegate_noop!(DndState: ignore WlDataOffer);
+delegate_noop!(DndState: ignore WlCompositor);
+delegate_noop!(DndState: ignore WlSurface);
+delegate_noop!(DndState: ignore WlShm);
+delegate_noop!(DndState: ignore WlShmPool);
+delegate_noop!(DndState: ignore WlBuffer);
+delegate_noop!(DndState: ignore WlDataDeviceManager);
+delegate_noop!(DndState: ignore ZwlrLayerShellV1);
@@ -0,0 +1,35 @@
+use std::{
+    io::ErrorKind,
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+};
+
+/// Players tried in order, PipeWire's first and PulseAudio's, also served by pipewire-pulse, after.
+const PLAYERS: [&str; 2] = ["pw-play", "paplay"];
+
+/// Play `sound` in the background, so the capture is not held up until it finished.
+pub fn play_sound(sound: &Path) {
+    for player in PLAYERS {
+        let result = Command::new(player)
+            .arg(sound)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        match result {
+            Ok(mut child) => {
+                tracing::debug!("Playing {} with {player}", sound.display());
+                // Reaped in case wayshot keeps running to serve the clipboard.
+                thread::spawn(move || child.wait());
+                return;
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => {
+                tracing::warn!("Failed to run {player}: {e}");
+                return;
+            }
+        }
+    }
+    tracing::warn!("Neither pw-play nor paplay is installed to play the capture sound");
+}
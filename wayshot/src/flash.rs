@@ -0,0 +1,227 @@
+use std::{error::Error, fs::File, os::fd::AsFd, thread, time::Duration};
+
+use libwayshot::{CaptureRegion, WayshotConnection};
+use wayland_client::{
+    delegate_noop,
+    globals::{registry_queue_init, GlobalListContents},
+    protocol::{
+        wl_buffer::WlBuffer,
+        wl_compositor::WlCompositor,
+        wl_output::{self, WlOutput},
+        wl_region::WlRegion,
+        wl_registry::{self, WlRegistry},
+        wl_shm::{self, WlShm},
+        wl_shm_pool::WlShmPool,
+        wl_surface::WlSurface,
+    },
+    Connection, Dispatch, QueueHandle,
+};
+use wayland_protocols_wlr::layer_shell::v1::client::{
+    zwlr_layer_shell_v1::{Layer, ZwlrLayerShellV1},
+    zwlr_layer_surface_v1::{self, Anchor, KeyboardInteractivity, ZwlrLayerSurfaceV1},
+};
+
+use crate::utils;
+
+/// How long the captured region stays highlighted.
+const FLASH_DURATION: Duration = Duration::from_millis(200);
+const BORDER_WIDTH: i32 = 3;
+// Premultiplied Argb8888, stored as little endian B, G, R, A.
+const DIM: [u8; 4] = [0, 0, 0, 0x60];
+const BORDER: [u8; 4] = [0xff, 0xff, 0xff, 0xff];
+const CLEAR: [u8; 4] = [0, 0, 0, 0];
+
+/// One full screen surface per output the region touches.
+struct FlashSurface {
+    surface: WlSurface,
+    layer_surface: ZwlrLayerSurfaceV1,
+    buffer: WlBuffer,
+    pool: WlShmPool,
+}
+
+#[derive(Default)]
+struct FlashState {
+    outputs: Vec<(WlOutput, Option<String>)>,
+    surfaces: Vec<FlashSurface>,
+}
+
+/// Briefly dim everything but `region` and draw a border around it, to confirm what was captured
+/// when the region was not selected interactively.
+pub fn flash_region(
+    wayshot_conn: &WayshotConnection,
+    region: CaptureRegion,
+) -> Result<(), Box<dyn Error>> {
+    let conn = &wayshot_conn.conn;
+    let (globals, mut event_queue) = registry_queue_init::<FlashState>(conn)?;
+    let qh = event_queue.handle();
+
+    let compositor = globals.bind::<WlCompositor, _, _>(&qh, 1..=4, ())?;
+    let shm = globals.bind::<WlShm, _, _>(&qh, 1..=1, ())?;
+    let layer_shell = globals.bind::<ZwlrLayerShellV1, _, _>(&qh, 1..=4, ())?;
+
+    // Output names are only sent from wl_output version 4 on.
+    let mut state = FlashState::default();
+    for global in globals.contents().clone_list() {
+        if global.interface == "wl_output" && global.version >= 4 {
+            let output = globals
+                .registry()
+                .bind::<WlOutput, _, _>(global.name, 4, &qh, ());
+            state.outputs.push((output, None));
+        }
+    }
+    event_queue.roundtrip(&mut state)?;
+
+    // Let clicks go through the highlight.
+    let empty_region = compositor.create_region(&qh, ());
+    for output_info in wayshot_conn.get_all_outputs() {
+        let area = output_info.region();
+        if !intersects(&region, &area) {
+            continue;
+        }
+        let Some((output, _)) = state
+            .outputs
+            .iter()
+            .find(|(_, name)| name.as_deref() == Some(output_info.name.as_str()))
+        else {
+            tracing::debug!("Cannot flash {}, no wl_output v4 name", output_info.name);
+            continue;
+        };
+
+        let (width, height) = (area.width, area.height);
+        let stride = width * 4;
+        let mem_file = File::from(libwayshot::create_shm_fd()?);
+        mem_file.set_len((stride * height) as u64)?;
+        let mut mmap = utils::map_shm(&mem_file)?;
+        for (index, pixel) in mmap.chunks_exact_mut(4).enumerate() {
+            let x = area.x_coordinate + index as i32 % width;
+            let y = area.y_coordinate + index as i32 / width;
+            pixel.copy_from_slice(&highlight_pixel(&region, x, y));
+        }
+        let pool = shm.create_pool(mem_file.as_fd(), stride * height, &qh, ());
+        let buffer =
+            pool.create_buffer(0, width, height, stride, wl_shm::Format::Argb8888, &qh, ());
+
+        let surface = compositor.create_surface(&qh, ());
+        surface.set_input_region(Some(&empty_region));
+        let layer_surface = layer_shell.get_layer_surface(
+            &surface,
+            Some(output),
+            Layer::Overlay,
+            "wayshot-flash".to_string(),
+            &qh,
+            state.surfaces.len(),
+        );
+        layer_surface.set_anchor(Anchor::Top | Anchor::Bottom | Anchor::Left | Anchor::Right);
+        layer_surface.set_exclusive_zone(-1);
+        layer_surface.set_size(width as u32, height as u32);
+        layer_surface.set_keyboard_interactivity(KeyboardInteractivity::None);
+        surface.commit();
+        state.surfaces.push(FlashSurface {
+            surface,
+            layer_surface,
+            buffer,
+            pool,
+        });
+    }
+    empty_region.destroy();
+
+    // The buffers are attached once the surfaces are configured.
+    event_queue.roundtrip(&mut state)?;
+    conn.flush()?;
+    thread::sleep(FLASH_DURATION);
+
+    for flash_surface in state.surfaces {
+        flash_surface.layer_surface.destroy();
+        flash_surface.surface.destroy();
+        flash_surface.buffer.destroy();
+        flash_surface.pool.destroy();
+    }
+    conn.flush()?;
+    Ok(())
+}
+
+fn intersects(a: &CaptureRegion, b: &CaptureRegion) -> bool {
+    a.x_coordinate < b.x_coordinate + b.width
+        && b.x_coordinate < a.x_coordinate + a.width
+        && a.y_coordinate < b.y_coordinate + b.height
+        && b.y_coordinate < a.y_coordinate + a.height
+}
+
+/// Color of the pixel at `x`, `y` in the global compositor space.
+fn highlight_pixel(region: &CaptureRegion, x: i32, y: i32) -> [u8; 4] {
+    let inside = |inset: i32| {
+        x >= region.x_coordinate + inset
+            && x < region.x_coordinate + region.width - inset
+            && y >= region.y_coordinate + inset
+            && y < region.y_coordinate + region.height - inset
+    };
+    if inside(BORDER_WIDTH) {
+        CLEAR
+    } else if inside(0) {
+        BORDER
+    } else {
+        DIM
+    }
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for FlashState {
+    fn event(
+        _: &mut Self,
+        _: &WlRegistry,
+        _: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<WlOutput, ()> for FlashState {
+    fn event(
+        state: &mut Self,
+        wl_output: &WlOutput,
+        event: wl_output::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wl_output::Event::Name { name } = event {
+            if let Some((_, output_name)) = state
+                .outputs
+                .iter_mut()
+                .find(|(output, _)| output == wl_output)
+            {
+                *output_name = Some(name);
+            }
+        }
+    }
+}
+
+impl Dispatch<ZwlrLayerSurfaceV1, usize> for FlashState {
+    fn event(
+        state: &mut Self,
+        layer_surface: &ZwlrLayerSurfaceV1,
+        event: zwlr_layer_surface_v1::Event,
+        index: &usize,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let zwlr_layer_surface_v1::Event::Configure { serial, .. } = event {
+            layer_surface.ack_configure(serial);
+            if let Some(flash_surface) = state.surfaces.get(*index) {
+                flash_surface
+                    .surface
+                    .attach(Some(&flash_surface.buffer), 0, 0);
+                flash_surface.surface.commit();
+            }
+        }
+    }
+}
+
+delegate_noop!(FlashState: ignore WlCompositor);
+delegate_noop!(FlashState: ignore WlSurface);
+delegate_noop!(FlashState: ignore WlRegion);
+delegate_noop!(FlashState: ignore WlShm);
+delegate_noop!(FlashState: ignore WlShmPool);
+delegate_noop!(FlashState: ignore WlBuffer);
+delegate_noop!(FlashState: ignore ZwlrLayerShellV1);
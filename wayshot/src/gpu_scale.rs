@@ -0,0 +1,251 @@
+//! Downscaling recorded frames with wgpu, for `wayshot record --scale` when built with the
+//! `gpu-scale` feature.
+//!
+//! Frames are uploaded into a texture and drawn into the smaller target with linear filtering,
+//! which costs the GPU far less than resampling costs the CPU. wgpu is built without backends,
+//! packagers enable the ones they ship alongside the feature, eg: `--features
+//! gpu-scale,wgpu/vulkan`. Without an adapter, frames are scaled on the CPU.
+
+use std::{
+    error::Error,
+    future::Future,
+    pin::pin,
+    sync::mpsc,
+    task::{Context, Poll, Waker},
+    thread,
+};
+
+use image::RgbaImage;
+
+/// Draws a single triangle covering the target, sampling the frame at each pixel's center.
+const SHADER: &str = r#"
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+@group(0) @binding(0) var frame: texture_2d<f32>;
+@group(0) @binding(1) var linear: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(frame, linear, in.uv);
+}
+"#;
+
+/// A GPU scaling frames of any size to `width`x`height`.
+pub struct GpuScaler {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::RenderPipeline,
+    sampler: wgpu::Sampler,
+    target: wgpu::Texture,
+    /// The scaled frame copied out of `target`, with rows padded to
+    /// [`wgpu::COPY_BYTES_PER_ROW_ALIGNMENT`].
+    readback: wgpu::Buffer,
+    padded_row: u32,
+    /// The uploaded frame, recreated when the capture size changes.
+    source: Option<wgpu::Texture>,
+}
+
+impl GpuScaler {
+    /// Set up the first adapter wgpu finds, fails when it finds none.
+    pub fn new(width: u32, height: u32) -> Result<Self, Box<dyn Error>> {
+        let instance =
+            wgpu::Instance::new(wgpu::InstanceDescriptor::new_without_display_handle_from_env());
+        let adapter = block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))?;
+        tracing::debug!("Scaling frames with {:?}", adapter.get_info());
+        let (device, queue) = block_on(adapter.request_device(&wgpu::DeviceDescriptor::default()))?;
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("wayshot scale"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("wayshot scale"),
+            layout: None,
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::TextureFormat::Rgba8Unorm.into())],
+            }),
+            multiview_mask: None,
+            cache: None,
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let target = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("wayshot scaled frame"),
+            size: extent(width, height),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let padded_row = (width * 4).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("wayshot scaled frame"),
+            size: u64::from(padded_row) * u64::from(height),
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Ok(Self {
+            device,
+            queue,
+            pipeline,
+            sampler,
+            target,
+            readback,
+            padded_row,
+            source: None,
+        })
+    }
+
+    /// The size frames are scaled to.
+    pub fn size(&self) -> (u32, u32) {
+        (self.target.width(), self.target.height())
+    }
+
+    pub fn scale(&mut self, frame: &RgbaImage) -> Result<RgbaImage, Box<dyn Error>> {
+        let size = extent(frame.width(), frame.height());
+        let source = match &self.source {
+            Some(source) if source.size() == size => source,
+            _ => self
+                .source
+                .insert(self.device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some("wayshot frame"),
+                    size,
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: wgpu::TextureFormat::Rgba8Unorm,
+                    usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                    view_formats: &[],
+                })),
+        };
+        self.queue.write_texture(
+            source.as_image_copy(),
+            frame.as_raw(),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(frame.width() * 4),
+                rows_per_image: Some(frame.height()),
+            },
+            size,
+        );
+
+        let source_view = source.create_view(&Default::default());
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("wayshot scale"),
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&source_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+        let target_view = self.target.create_view(&Default::default());
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("wayshot scale"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &target_view,
+                    depth_slice: None,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+        let (width, height) = (self.target.width(), self.target.height());
+        encoder.copy_texture_to_buffer(
+            self.target.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &self.readback,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(self.padded_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            extent(width, height),
+        );
+        self.queue.submit([encoder.finish()]);
+
+        let (sender, receiver) = mpsc::channel();
+        self.readback
+            .map_async(wgpu::MapMode::Read, .., move |mapped| {
+                let _ = sender.send(mapped);
+            });
+        self.device.poll(wgpu::PollType::wait_indefinitely())?;
+        receiver.recv()??;
+        let mut scaled = Vec::with_capacity(width as usize * height as usize * 4);
+        {
+            let mapped = self.readback.get_mapped_range(..)?;
+            for row in mapped
+                .chunks_exact(self.padded_row as usize)
+                .take(height as usize)
+            {
+                scaled.extend_from_slice(&row[..width as usize * 4]);
+            }
+        }
+        self.readback.unmap();
+        Ok(RgbaImage::from_raw(width, height, scaled).ok_or("the scaled frame is too short")?)
+    }
+}
+
+fn extent(width: u32, height: u32) -> wgpu::Extent3d {
+    wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    }
+}
+
+/// Native wgpu futures are ready once polled, so there is no need for an executor.
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let mut context = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+        thread::yield_now();
+    }
+}
@@ -0,0 +1,132 @@
+use std::{
+    error::Error,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use libwayshot::CaptureRegion;
+
+use crate::{
+    snap::SnapTargets,
+    utils::{self, AspectRatio},
+};
+
+/// A region the user dragged out, along with the constraints applied to it.
+#[derive(Debug, Copy, Clone)]
+pub struct Selection {
+    pub region: CaptureRegion,
+    /// Aspect ratio the selection was constrained to.
+    pub aspect: Option<AspectRatio>,
+    /// Whether edges were pulled to output or window edges, or the grid.
+    pub edges_snapped: bool,
+    /// Whether the size was snapped to a common size such as 1920x1080.
+    pub snapped: bool,
+}
+
+/// Let the user drag out a region with slurp, optionally locked to `aspect`. The edges snap to
+/// `snap_targets`, whose outputs and windows slurp also shows as guides, and the size snaps to a
+/// common size when it is close to one. `None` if the user cancelled.
+pub fn select_region(
+    aspect: Option<AspectRatio>,
+    snap_targets: Option<&SnapTargets>,
+) -> Result<Option<Selection>, Box<dyn Error>> {
+    let aspect_arg = aspect.map(|aspect| aspect.to_string());
+    let args: Vec<&str> = match &aspect_arg {
+        Some(aspect) => vec!["-a", aspect],
+        None => Vec::new(),
+    };
+    // slurp highlights rectangles given on stdin, and selects one on click.
+    let guides: String = snap_targets
+        .map(|snap_targets| {
+            snap_targets
+                .rectangles
+                .iter()
+                .map(|rectangle| {
+                    format!(
+                        "{},{} {}x{}\n",
+                        rectangle.x_coordinate,
+                        rectangle.y_coordinate,
+                        rectangle.width,
+                        rectangle.height
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let Some(geometry) = run_slurp(&args, &guides)? else {
+        return Ok(None);
+    };
+    let mut region =
+        utils::parse_geometry(&geometry).ok_or_else(|| format!("slurp returned {geometry:?}"))?;
+
+    let edges_snapped = match snap_targets.and_then(|snap_targets| snap_targets.snap(region)) {
+        Some(snapped) => {
+            region = snapped;
+            true
+        }
+        None => false,
+    };
+    // slurp's own constraint works on screen pixels, make the ratio exact in logical pixels.
+    if let Some(aspect) = aspect {
+        region = aspect.constrain(region);
+    }
+    let snapped = utils::snap_to_common_size(region, aspect);
+    Ok(Some(Selection {
+        region: snapped.unwrap_or(region),
+        aspect,
+        edges_snapped,
+        snapped: snapped.is_some(),
+    }))
+}
+
+/// Let the user drag out regions one after another until they press Escape, see
+/// [`select_region`]. Fails if not a single region was selected.
+pub fn select_regions(
+    aspect: Option<AspectRatio>,
+    snap_targets: Option<&SnapTargets>,
+) -> Result<Vec<Selection>, Box<dyn Error>> {
+    let mut selections = Vec::new();
+    while let Some(selection) = select_region(aspect, snap_targets)? {
+        selections.push(selection);
+    }
+    if selections.is_empty() {
+        return Err("Selection cancelled".into());
+    }
+    Ok(selections)
+}
+
+/// Run slurp with `args`, `None` if the user cancelled the selection.
+fn run_slurp(args: &[&str], input: &str) -> Result<Option<String>, Box<dyn Error>> {
+    let mut slurp = Command::new("slurp")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run slurp, is it installed? {e}"))?;
+    if let Some(mut stdin) = slurp.stdin.take() {
+        stdin.write_all(input.as_bytes())?;
+    }
+    let output = slurp.wait_with_output()?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(Some(String::from_utf8(output.stdout)?.trim().to_string()))
+}
+
+/// Show a desktop notification, there is no terminal to log to when launched from a keybinding.
+pub fn notify(summary: &str, body: &str) {
+    notify_with_sound(summary, body, None);
+}
+
+/// Like [`notify`], asking the notification server to play the sound theme sound `sound_name`.
+pub fn notify_with_sound(summary: &str, body: &str, sound_name: Option<&str>) {
+    let mut command = Command::new("notify-send");
+    command.args(["--app-name", "wayshot"]);
+    if let Some(sound_name) = sound_name {
+        command.arg(format!("--hint=string:sound-name:{sound_name}"));
+    }
+    let result = command.args([summary, body]).status();
+    if let Err(e) = result {
+        tracing::debug!("Failed to run notify-send: {e}");
+    }
+}
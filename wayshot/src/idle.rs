@@ -0,0 +1,126 @@
+use std::{error::Error, time::Duration};
+
+use nix::{
+    errno::Errno,
+    poll::{poll, PollFd, PollFlags},
+};
+use wayland_client::{
+    delegate_noop,
+    globals::{registry_queue_init, GlobalListContents},
+    protocol::{
+        wl_registry::{self, WlRegistry},
+        wl_seat::WlSeat,
+    },
+    Connection, Dispatch, EventQueue, QueueHandle,
+};
+use wayland_protocols::ext::idle_notify::v1::client::{
+    ext_idle_notification_v1::{self, ExtIdleNotificationV1},
+    ext_idle_notifier_v1::ExtIdleNotifierV1,
+};
+
+use crate::signals;
+
+#[derive(Default)]
+struct IdleState {
+    idle: bool,
+}
+
+/// Whether the session is idle, through ext-idle-notify.
+///
+/// Idle inhibitors are respected, so a playing video keeps the session active.
+pub struct IdleMonitor {
+    event_queue: EventQueue<IdleState>,
+    state: IdleState,
+    _notification: ExtIdleNotificationV1,
+}
+
+impl IdleMonitor {
+    /// Consider the session of the compositor behind `conn` idle once there was no input for
+    /// `timeout`.
+    pub fn new(conn: &Connection, timeout: Duration) -> Result<Self, Box<dyn Error>> {
+        let (globals, mut event_queue) = registry_queue_init::<IdleState>(conn)?;
+        let qh = event_queue.handle();
+
+        let notifier = globals
+            .bind::<ExtIdleNotifierV1, _, _>(&qh, 1..=1, ())
+            .map_err(|_| "The compositor does not support ext-idle-notify")?;
+        let seat = globals.bind::<WlSeat, _, _>(&qh, 1..=1, ())?;
+        let timeout_ms = u32::try_from(timeout.as_millis()).unwrap_or(u32::MAX);
+        let notification = notifier.get_idle_notification(timeout_ms, &seat, &qh, ());
+
+        let mut state = IdleState::default();
+        event_queue.roundtrip(&mut state)?;
+        Ok(Self {
+            event_queue,
+            state,
+            _notification: notification,
+        })
+    }
+
+    /// Whether the session is idle right now.
+    pub fn is_idle(&mut self) -> Result<bool, Box<dyn Error>> {
+        self.dispatch(0)?;
+        Ok(self.state.idle)
+    }
+
+    /// Block until the session is active again. Returns `false` if a signal came first.
+    pub fn wait_until_active(&mut self) -> Result<bool, Box<dyn Error>> {
+        while self.state.idle {
+            if signals::received().is_some() {
+                return Ok(false);
+            }
+            self.dispatch(signals::POLL_INTERVAL_MS)?;
+        }
+        Ok(true)
+    }
+
+    /// Read and handle the events that arrive within `timeout_ms`.
+    fn dispatch(&mut self, timeout_ms: i32) -> Result<(), Box<dyn Error>> {
+        self.event_queue.flush()?;
+        if let Some(guard) = self.event_queue.prepare_read() {
+            let connection_fd = guard.connection_fd();
+            let mut fds = [PollFd::new(&connection_fd, PollFlags::POLLIN)];
+            match poll(&mut fds, timeout_ms) {
+                Ok(0) | Err(Errno::EINTR) => {}
+                Ok(_) => {
+                    guard.read()?;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        self.event_queue.dispatch_pending(&mut self.state)?;
+        Ok(())
+    }
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for IdleState {
+    fn event(
+        _: &mut Self,
+        _: &WlRegistry,
+        _: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ExtIdleNotificationV1, ()> for IdleState {
+    fn event(
+        state: &mut Self,
+        _: &ExtIdleNotificationV1,
+        event: ext_idle_notification_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            ext_idle_notification_v1::Event::Idled => state.idle = true,
+            ext_idle_notification_v1::Event::Resumed => state.idle = false,
+            _ => {}
+        }
+    }
+}
+
+delegate_noop!(IdleState: ignore WlSeat);
+delegate_noop!(IdleState: ExtIdleNotifierV1);
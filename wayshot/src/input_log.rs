@@ -0,0 +1,421 @@
+//! `wayshot record --input-log`: a JSON lines sidecar with the timings of pointer and keyboard
+//! events, to annotate or replay demo recordings.
+//!
+//! Wayland only sends input to the focused client, so the events are read from the evdev devices
+//! in /dev/input, which needs read access to them (usually membership of the `input` group).
+//! Keys are logged without their code unless `--input-log-keys` is given, so typed text does not
+//! end up in the log.
+
+use std::{
+    error::Error,
+    ffi::c_long,
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, ErrorKind, Read, Write},
+    mem::size_of,
+    os::unix::fs::OpenOptionsExt,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use nix::{
+    errno::Errno,
+    libc,
+    poll::{poll, PollFd, PollFlags},
+};
+use serde::Serialize;
+
+use crate::signals;
+
+/// Size of a `struct input_event`: a `struct timeval`, then type, code and value.
+const EVENT_SIZE: usize = 2 * size_of::<c_long>() + 8;
+
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_REL: u16 = 0x02;
+const SYN_REPORT: u16 = 0;
+const REL_X: u16 = 0x00;
+const REL_Y: u16 = 0x01;
+const REL_HWHEEL: u16 = 0x06;
+const REL_WHEEL: u16 = 0x08;
+/// `BTN_LEFT` up to `BTN_TASK`, the mouse buttons.
+const MOUSE_BUTTONS: std::ops::Range<u16> = 0x110..0x118;
+/// The other `BTN_*` codes: joysticks, tablets and touchpad contacts, not logged.
+const OTHER_BUTTONS: std::ops::Range<u16> = 0x100..0x160;
+/// Key auto-repeat, only presses and releases are logged.
+const KEY_REPEAT: i32 = 2;
+
+/// What `--input-log` writes and where.
+pub struct InputLogOptions {
+    pub file: PathBuf,
+    /// Log which key was pressed, not only when.
+    pub keys: bool,
+}
+
+/// First line of the log.
+#[derive(Serialize)]
+struct Header {
+    version: u32,
+    /// Whether key events carry their code, `false` when they were redacted.
+    keys: bool,
+}
+
+/// One line of the log, `t` is in seconds since the recording started.
+#[derive(Serialize, Debug, PartialEq)]
+struct Line {
+    t: f64,
+    #[serde(flatten)]
+    entry: Entry,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(tag = "event", rename_all = "lowercase")]
+enum Entry {
+    /// Relative pointer motion, summed over one hardware report.
+    Motion { dx: i32, dy: i32 },
+    /// Wheel clicks, positive `dy` scrolls up.
+    Scroll { dx: i32, dy: i32 },
+    /// Linux button code, eg: 272 for the left button.
+    Button { button: u16, pressed: bool },
+    /// Linux key code, `None` when redacted.
+    Key { code: Option<u16>, pressed: bool },
+}
+
+/// A `struct input_event` as read from a device.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct RawEvent {
+    time: Duration,
+    kind: u16,
+    code: u16,
+    value: i32,
+}
+
+impl RawEvent {
+    fn parse(bytes: &[u8; EVENT_SIZE]) -> Self {
+        const LONG: usize = size_of::<c_long>();
+        let long = |at: usize| {
+            let mut raw = [0; LONG];
+            raw.copy_from_slice(&bytes[at..at + LONG]);
+            c_long::from_ne_bytes(raw)
+        };
+        let u16_at = |at: usize| u16::from_ne_bytes([bytes[at], bytes[at + 1]]);
+        let (seconds, micros) = (long(0), long(LONG));
+        let time = Duration::from_secs(seconds.max(0) as u64)
+            + Duration::from_micros(micros.clamp(0, 999_999) as u64);
+        let value = &bytes[2 * LONG + 4..];
+        Self {
+            time,
+            kind: u16_at(2 * LONG),
+            code: u16_at(2 * LONG + 2),
+            value: i32::from_ne_bytes([value[0], value[1], value[2], value[3]]),
+        }
+    }
+}
+
+/// Turns the events of one device into log entries, summing relative motion until the device
+/// reports a complete state.
+#[derive(Default)]
+struct Decoder {
+    keys: bool,
+    motion: (i32, i32),
+    scroll: (i32, i32),
+}
+
+impl Decoder {
+    fn new(keys: bool) -> Self {
+        Self {
+            keys,
+            ..Self::default()
+        }
+    }
+
+    fn decode(&mut self, event: RawEvent) -> Vec<Entry> {
+        match (event.kind, event.code) {
+            (EV_KEY, _) if event.value == KEY_REPEAT => Vec::new(),
+            (EV_KEY, code) if MOUSE_BUTTONS.contains(&code) => vec![Entry::Button {
+                button: code,
+                pressed: event.value != 0,
+            }],
+            (EV_KEY, code) if OTHER_BUTTONS.contains(&code) => Vec::new(),
+            (EV_KEY, code) => vec![Entry::Key {
+                code: self.keys.then_some(code),
+                pressed: event.value != 0,
+            }],
+            (EV_REL, REL_X) => {
+                self.motion.0 = self.motion.0.saturating_add(event.value);
+                Vec::new()
+            }
+            (EV_REL, REL_Y) => {
+                self.motion.1 = self.motion.1.saturating_add(event.value);
+                Vec::new()
+            }
+            (EV_REL, REL_HWHEEL) => {
+                self.scroll.0 = self.scroll.0.saturating_add(event.value);
+                Vec::new()
+            }
+            (EV_REL, REL_WHEEL) => {
+                self.scroll.1 = self.scroll.1.saturating_add(event.value);
+                Vec::new()
+            }
+            (EV_SYN, SYN_REPORT) => {
+                let mut entries = Vec::new();
+                let (dx, dy) = std::mem::take(&mut self.motion);
+                if (dx, dy) != (0, 0) {
+                    entries.push(Entry::Motion { dx, dy });
+                }
+                let (dx, dy) = std::mem::take(&mut self.scroll);
+                if (dx, dy) != (0, 0) {
+                    entries.push(Entry::Scroll { dx, dy });
+                }
+                entries
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Input events being written to a sidecar file by a thread, until [`InputLog::finish`].
+pub struct InputLog {
+    stop: Arc<AtomicBool>,
+    reader: JoinHandle<io::Result<usize>>,
+    file: PathBuf,
+}
+
+impl InputLog {
+    /// Open the input devices and start logging, with times relative to now.
+    pub fn start(options: &InputLogOptions) -> Result<Self, Box<dyn Error>> {
+        let devices = open_devices()?;
+        if devices.is_empty() {
+            return Err(
+                "No readable input device in /dev/input, is your user in the input group?".into(),
+            );
+        }
+        let mut log = BufWriter::new(File::create(&options.file)?);
+        serde_json::to_writer(
+            &mut log,
+            &Header {
+                version: 1,
+                keys: options.keys,
+            },
+        )?;
+        log.write_all(b"\n")?;
+        tracing::warn!(
+            "Logging pointer and keyboard event timings of {} input devices to {}{}",
+            devices.len(),
+            options.file.display(),
+            if options.keys {
+                ", including which keys are pressed"
+            } else {
+                ""
+            }
+        );
+
+        // Devices stamp their events with the wall clock.
+        let started = SystemTime::now().duration_since(UNIX_EPOCH)?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let reader = thread::spawn({
+            let stop = stop.clone();
+            let keys = options.keys;
+            move || read_events(devices, keys, started, &stop, log)
+        });
+        Ok(Self {
+            stop,
+            reader,
+            file: options.file.clone(),
+        })
+    }
+
+    /// Stop logging and flush the file.
+    pub fn finish(self) -> Result<(), Box<dyn Error>> {
+        self.stop.store(true, Ordering::SeqCst);
+        let logged = self
+            .reader
+            .join()
+            .map_err(|_| "the input log thread panicked")??;
+        tracing::info!("Logged {logged} input events to {}", self.file.display());
+        Ok(())
+    }
+}
+
+/// The readable event devices, unreadable ones are skipped.
+fn open_devices() -> io::Result<Vec<File>> {
+    let mut devices = Vec::new();
+    for entry in fs::read_dir("/dev/input")? {
+        let path = entry?.path();
+        if !path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with("event"))
+        {
+            continue;
+        }
+        match OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&path)
+        {
+            Ok(device) => devices.push(device),
+            Err(e) => tracing::debug!("Skipping {}: {e}", path.display()),
+        }
+    }
+    Ok(devices)
+}
+
+/// Write the events of `devices` to `log` until `stop` is set, returns how many entries were
+/// written.
+fn read_events(
+    mut devices: Vec<File>,
+    keys: bool,
+    started: Duration,
+    stop: &AtomicBool,
+    mut log: impl Write,
+) -> io::Result<usize> {
+    let mut decoders: Vec<_> = devices.iter().map(|_| Decoder::new(keys)).collect();
+    let mut logged = 0;
+    let mut event = [0; EVENT_SIZE];
+    while !stop.load(Ordering::SeqCst) {
+        let mut fds: Vec<_> = devices
+            .iter()
+            .map(|device| PollFd::new(device, PollFlags::POLLIN))
+            .collect();
+        match poll(&mut fds, signals::POLL_INTERVAL_MS) {
+            Ok(0) | Err(Errno::EINTR) => continue,
+            Ok(_) => {}
+            Err(e) => return Err(e.into()),
+        }
+        let ready: Vec<_> = fds
+            .iter()
+            .map(|fd| fd.revents().is_some_and(|events| !events.is_empty()))
+            .collect();
+        drop(fds);
+        // Walk backwards so unplugged devices can be removed in place.
+        for index in (0..devices.len()).rev() {
+            if !ready[index] {
+                continue;
+            }
+            loop {
+                match devices[index].read_exact(&mut event) {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(e) => {
+                        tracing::info!("Input device went away, no longer logging it: {e}");
+                        devices.remove(index);
+                        decoders.remove(index);
+                        break;
+                    }
+                }
+                let event = RawEvent::parse(&event);
+                let t = event.time.saturating_sub(started).as_secs_f64();
+                for entry in decoders[index].decode(event) {
+                    serde_json::to_writer(&mut log, &Line { t, entry })?;
+                    log.write_all(b"\n")?;
+                    logged += 1;
+                }
+            }
+        }
+    }
+    log.flush()?;
+    Ok(logged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(kind: u16, code: u16, value: i32) -> RawEvent {
+        RawEvent {
+            time: Duration::ZERO,
+            kind,
+            code,
+            value,
+        }
+    }
+
+    #[test]
+    fn parses_input_events() {
+        const LONG: usize = size_of::<c_long>();
+        let mut bytes = [0; EVENT_SIZE];
+        bytes[..LONG].copy_from_slice(&(12 as c_long).to_ne_bytes());
+        bytes[LONG..2 * LONG].copy_from_slice(&(500_000 as c_long).to_ne_bytes());
+        bytes[2 * LONG..2 * LONG + 2].copy_from_slice(&EV_REL.to_ne_bytes());
+        bytes[2 * LONG + 2..2 * LONG + 4].copy_from_slice(&REL_Y.to_ne_bytes());
+        bytes[2 * LONG + 4..].copy_from_slice(&(-3i32).to_ne_bytes());
+        assert_eq!(
+            RawEvent::parse(&bytes),
+            RawEvent {
+                time: Duration::from_millis(12_500),
+                kind: EV_REL,
+                code: REL_Y,
+                value: -3,
+            }
+        );
+    }
+
+    #[test]
+    fn redacts_keys_by_default() {
+        let mut decoder = Decoder::new(false);
+        // KEY_A
+        assert_eq!(
+            decoder.decode(event(EV_KEY, 30, 1)),
+            [Entry::Key {
+                code: None,
+                pressed: true
+            }]
+        );
+        assert!(decoder.decode(event(EV_KEY, 30, KEY_REPEAT)).is_empty());
+
+        let mut decoder = Decoder::new(true);
+        assert_eq!(
+            decoder.decode(event(EV_KEY, 30, 0)),
+            [Entry::Key {
+                code: Some(30),
+                pressed: false
+            }]
+        );
+    }
+
+    #[test]
+    fn decodes_buttons_and_sums_motion() {
+        let mut decoder = Decoder::new(false);
+        assert_eq!(
+            decoder.decode(event(EV_KEY, 0x110, 1)),
+            [Entry::Button {
+                button: 0x110,
+                pressed: true
+            }]
+        );
+        // BTN_TOUCH
+        assert!(decoder.decode(event(EV_KEY, 0x14a, 1)).is_empty());
+
+        for (code, value) in [(REL_X, 2), (REL_Y, -1), (REL_X, 3), (REL_WHEEL, 1)] {
+            assert!(decoder.decode(event(EV_REL, code, value)).is_empty());
+        }
+        assert_eq!(
+            decoder.decode(event(EV_SYN, SYN_REPORT, 0)),
+            [
+                Entry::Motion { dx: 5, dy: -1 },
+                Entry::Scroll { dx: 0, dy: 1 }
+            ]
+        );
+        assert!(decoder.decode(event(EV_SYN, SYN_REPORT, 0)).is_empty());
+    }
+
+    #[test]
+    fn serializes_flat_lines() {
+        let line = Line {
+            t: 1.5,
+            entry: Entry::Key {
+                code: None,
+                pressed: true,
+            },
+        };
+        assert_eq!(
+            serde_json::to_string(&line).expect("serializing a line"),
+            r#"{"t":1.5,"event":"key","code":null,"pressed":true}"#
+        );
+    }
+}
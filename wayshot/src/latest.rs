@@ -0,0 +1,80 @@
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use image::ImageFormat;
+use libwayshot::write::{self, Capture, EncodeOptions, WriteTarget};
+use serde::Serialize;
+
+/// Written as JSON next to the latest capture.
+#[derive(Debug, Serialize)]
+struct LatestMetadata<'a> {
+    image: &'a Path,
+    /// Where the capture was saved as well, eg: the numbered shot of a timelapse.
+    saved_to: Option<&'a Path>,
+    width: u32,
+    height: u32,
+    /// Seconds since the Unix epoch.
+    captured_at: u64,
+}
+
+/// `--latest`: a stable path always holding the newest capture, for wallpapers or status bars to
+/// reference, and its metadata in a JSON file next to it, eg: `latest.json` for `latest.png`.
+///
+/// Both files are written to a temporary file and renamed into place, so readers never see a
+/// partial file. The image is replaced before its metadata.
+#[derive(Debug, Clone)]
+pub struct LatestCapture {
+    image_path: PathBuf,
+    metadata_path: PathBuf,
+}
+
+impl LatestCapture {
+    /// Publish captures at `image_path`, in the format of its extension.
+    pub fn new(image_path: PathBuf) -> Result<Self, Box<dyn Error>> {
+        ImageFormat::from_path(&image_path)
+            .map_err(|e| format!("--latest {}: {e}", image_path.display()))?;
+        let metadata_path = image_path.with_extension("json");
+        Ok(Self {
+            image_path,
+            metadata_path,
+        })
+    }
+
+    /// Replace the latest capture with `capture`, which was also saved to `saved_to` if any.
+    pub fn publish(
+        &self,
+        capture: &Capture<'_>,
+        saved_to: Option<&Path>,
+    ) -> Result<(), Box<dyn Error>> {
+        write::save(
+            capture,
+            WriteTarget::File(&self.image_path),
+            EncodeOptions::default(),
+        )?;
+        let metadata = LatestMetadata {
+            image: &self.image_path,
+            saved_to,
+            width: capture.image.width(),
+            height: capture.image.height(),
+            captured_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_secs()),
+        };
+        write::replace_file(&self.metadata_path, &serde_json::to_vec_pretty(&metadata)?)?;
+        tracing::debug!(
+            "Published the latest capture at {}",
+            self.image_path.display()
+        );
+        Ok(())
+    }
+
+    /// [`Self::publish`], logging failures: the capture itself was taken and saved.
+    pub fn publish_or_warn(&self, capture: &Capture<'_>, saved_to: Option<&Path>) {
+        if let Err(e) = self.publish(capture, saved_to) {
+            tracing::warn!("Failed to update {}: {e}", self.image_path.display());
+        }
+    }
+}
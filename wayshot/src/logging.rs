@@ -0,0 +1,96 @@
+use std::{error::Error, fs, path::Path, sync::OnceLock};
+
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::{fmt, prelude::*, reload, EnvFilter, Registry};
+
+use crate::config::Log;
+
+/// Subsystem names accepted in the `[log]` table and the tracing targets they stand for. Any
+/// other key is used as a target as is, eg: `"wayshot::utils"`.
+const SUBSYSTEMS: &[(&str, &str)] = &[
+    ("capture", "libwayshot"),
+    ("clipboard", "wayshot::clipboard"),
+    ("daemon", "wayshot::daemon"),
+    ("dnd", "wayshot::dnd"),
+    ("gui", "wayshot::gui"),
+    ("nested", "wayshot::nested"),
+    ("plugins", "wayshot::plugins"),
+    ("record", "wayshot::record"),
+    ("script", "wayshot::script"),
+    ("workspace", "wayshot::workspace"),
+];
+
+/// Rotated log files kept next to `--log-file`, one per day.
+const MAX_LOG_FILES: usize = 7;
+
+/// Handle to swap the filter of the global subscriber, and whether `--debug` was given.
+static FILTER: OnceLock<(reload::Handle<EnvFilter, Registry>, bool)> = OnceLock::new();
+
+/// Build the filter directives for `config`, eg: `info,libwayshot=debug`.
+///
+/// `--debug` traces everything and ignores the per subsystem levels.
+fn directives(config: &Log, debug: bool) -> String {
+    if debug {
+        return "trace".to_string();
+    }
+    let mut directives = vec![config.level.clone().unwrap_or_else(|| "info".to_string())];
+    for (subsystem, level) in &config.subsystems {
+        let target = SUBSYSTEMS
+            .iter()
+            .find(|(name, _)| name == subsystem)
+            .map_or(subsystem.as_str(), |(_, target)| target);
+        directives.push(format!("{target}={level}"));
+    }
+    directives.join(",")
+}
+
+/// Install the global subscriber, logging to stderr and, if given, to daily rotated files at
+/// `log_file`.
+pub fn init(config: &Log, debug: bool, log_file: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let filter = filter(config, debug)?;
+    let (filter, handle) = reload::Layer::new(filter);
+    let _ = FILTER.set((handle, debug));
+
+    let file_layer = match log_file {
+        Some(log_file) => {
+            let file_name = log_file
+                .file_name()
+                .ok_or_else(|| format!("--log-file {} is not a file", log_file.display()))?;
+            let directory = log_file
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+                .unwrap_or(Path::new("."));
+            fs::create_dir_all(directory)?;
+            let appender = RollingFileAppender::builder()
+                .rotation(Rotation::DAILY)
+                .filename_prefix(file_name.to_string_lossy())
+                .max_log_files(MAX_LOG_FILES)
+                .build(directory)?;
+            Some(fmt::layer().with_ansi(false).with_writer(appender))
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer().with_writer(std::io::stderr))
+        .with(file_layer)
+        .init();
+    Ok(())
+}
+
+/// Apply the levels of a reloaded `[log]` table to the global subscriber.
+pub fn reload(config: &Log) -> Result<(), Box<dyn Error>> {
+    let Some((handle, debug)) = FILTER.get() else {
+        return Ok(());
+    };
+    handle.reload(filter(config, *debug)?)?;
+    Ok(())
+}
+
+fn filter(config: &Log, debug: bool) -> Result<EnvFilter, Box<dyn Error>> {
+    let directives = directives(config, debug);
+    Ok(EnvFilter::builder()
+        .parse(&directives)
+        .map_err(|e| format!("Invalid [log] levels {directives:?}: {e}"))?)
+}
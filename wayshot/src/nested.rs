@@ -0,0 +1,116 @@
+use std::{
+    collections::HashSet,
+    env,
+    error::Error,
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How long the headless compositor gets to create its socket.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long the command gets to draw something before it is captured.
+const SETTLE_DELAY: Duration = Duration::from_secs(1);
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A headless sway session running a single command, torn down on drop.
+pub struct NestedSession {
+    compositor: Child,
+    command: Option<Child>,
+    socket: String,
+}
+
+impl NestedSession {
+    /// Start a headless sway, run `command` through `sh -c` inside it and wait for it to settle.
+    pub fn start(command: &str) -> Result<Self, Box<dyn Error>> {
+        let runtime_dir = env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .ok_or("XDG_RUNTIME_DIR is not set")?;
+        let existing_sockets = wayland_sockets(&runtime_dir);
+
+        tracing::debug!("Starting headless sway");
+        let compositor = Command::new("sway")
+            .args(["--config", "/dev/null"])
+            .env("WLR_BACKENDS", "headless")
+            .env("WLR_LIBINPUT_NO_DEVICES", "1")
+            .env_remove("WAYLAND_DISPLAY")
+            .env_remove("WAYLAND_SOCKET")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("Failed to start sway: {e}"))?;
+
+        let mut session = Self {
+            compositor,
+            command: None,
+            socket: String::new(),
+        };
+
+        // sway picks the first free wayland-N socket, so watch for the one that appears.
+        let started = Instant::now();
+        session.socket = loop {
+            if let Some(socket) = wayland_sockets(&runtime_dir)
+                .difference(&existing_sockets)
+                .next()
+            {
+                break socket.to_string_lossy().into_owned();
+            }
+            if let Some(status) = session.compositor.try_wait()? {
+                return Err(format!("sway exited early with {status}").into());
+            }
+            if started.elapsed() > STARTUP_TIMEOUT {
+                return Err("Timed out waiting for the nested compositor to start".into());
+            }
+            thread::sleep(POLL_INTERVAL);
+        };
+        // The socket exists slightly before the compositor accepts clients.
+        thread::sleep(POLL_INTERVAL);
+        tracing::debug!("Nested compositor listening on {}", session.socket);
+
+        session.command = Some(
+            Command::new("sh")
+                .args(["-c", command])
+                .env("WAYLAND_DISPLAY", &session.socket)
+                .stdin(Stdio::null())
+                .spawn()
+                .map_err(|e| format!("Failed to run {command:?}: {e}"))?,
+        );
+        thread::sleep(SETTLE_DELAY);
+
+        Ok(session)
+    }
+
+    /// Name of the Wayland socket of the nested compositor.
+    pub fn socket(&self) -> &str {
+        &self.socket
+    }
+}
+
+impl Drop for NestedSession {
+    fn drop(&mut self) {
+        if let Some(command) = &mut self.command {
+            let _ = command.kill();
+            let _ = command.wait();
+        }
+        let _ = self.compositor.kill();
+        let _ = self.compositor.wait();
+    }
+}
+
+fn wayland_sockets(runtime_dir: &Path) -> HashSet<OsString> {
+    let Ok(entries) = fs::read_dir(runtime_dir) else {
+        return HashSet::new();
+    };
+    entries
+        .flatten()
+        .map(|entry| entry.file_name())
+        .filter(|name| {
+            let name = name.to_string_lossy();
+            name.starts_with("wayland-") && !name.ends_with(".lock")
+        })
+        .collect()
+}
@@ -0,0 +1,129 @@
+use std::{
+    error::Error,
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use image::DynamicImage;
+use libloading::{Library, Symbol};
+
+/// Symbol every dynamic library plugin has to export:
+///
+/// ```c
+/// int32_t wayshot_process_rgba8(uint8_t *pixels, uint32_t width, uint32_t height);
+/// ```
+///
+/// `pixels` holds `width * height` tightly packed RGBA pixels which may be modified in place. The
+/// return value is one of [`PLUGIN_KEEP`], [`PLUGIN_VETO`], anything else is treated as an error.
+const PROCESS_SYMBOL: &[u8] = b"wayshot_process_rgba8";
+const PLUGIN_KEEP: i32 = 0;
+const PLUGIN_VETO: i32 = 1;
+
+type ProcessFn = unsafe extern "C" fn(*mut u8, u32, u32) -> i32;
+
+/// What a plugin decided to do with a capture.
+pub enum Verdict {
+    /// Hand the (possibly transformed) image on to the next plugin and then the sinks.
+    Keep(DynamicImage),
+    /// Drop the capture, nothing gets saved or copied.
+    Veto,
+}
+
+/// Transforms or vetoes captures before they are saved, printed or copied.
+pub trait ProcessorPlugin {
+    fn name(&self) -> &str;
+    fn process(&mut self, image: DynamicImage) -> Result<Verdict, Box<dyn Error>>;
+}
+
+/// A plugin loaded from a shared library exporting [`PROCESS_SYMBOL`].
+struct DylibPlugin {
+    name: String,
+    // Keeps `process` valid.
+    _library: Library,
+    process: ProcessFn,
+}
+
+impl DylibPlugin {
+    fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let name = path
+            .file_stem()
+            .unwrap_or(path.as_os_str())
+            .to_string_lossy()
+            .into_owned();
+        // SAFETY: plugins are trusted code the user installed into their own config directory,
+        // loading them runs their initializers just like any other shared library.
+        let library = unsafe { Library::new(path)? };
+        // SAFETY: the symbol is declared with the signature plugins must export, see
+        // `PROCESS_SYMBOL`. The pointer stays valid as long as `library`, kept alongside it.
+        let process = unsafe {
+            let symbol: Symbol<ProcessFn> = library.get(PROCESS_SYMBOL)?;
+            *symbol
+        };
+        Ok(Self {
+            name,
+            _library: library,
+            process,
+        })
+    }
+}
+
+impl ProcessorPlugin for DylibPlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn process(&mut self, image: DynamicImage) -> Result<Verdict, Box<dyn Error>> {
+        let mut buffer = image.into_rgba8();
+        let (width, height) = buffer.dimensions();
+        // SAFETY: the buffer holds exactly width * height RGBA pixels, as the ABI promises.
+        let verdict = unsafe { (self.process)(buffer.as_mut_ptr(), width, height) };
+        match verdict {
+            PLUGIN_KEEP => Ok(Verdict::Keep(buffer.into())),
+            PLUGIN_VETO => Ok(Verdict::Veto),
+            code => Err(format!("plugin {} failed with code {code}", self.name).into()),
+        }
+    }
+}
+
+/// Load every `*.so` in `directory`, in file name order so plugins can be ordered with a numeric
+/// prefix such as `10-redact.so`.
+pub fn load_plugins(directory: &Path) -> Result<Vec<Box<dyn ProcessorPlugin>>, Box<dyn Error>> {
+    let Ok(entries) = fs::read_dir(directory) else {
+        tracing::debug!("No plugin directory at {}", directory.display());
+        return Ok(Vec::new());
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension() == Some(OsStr::new("so")))
+        .collect();
+    paths.sort();
+
+    paths
+        .iter()
+        .map(|path| {
+            tracing::debug!("Loading plugin {}", path.display());
+            let plugin = DylibPlugin::load(path)
+                .map_err(|e| format!("Failed to load plugin {}: {e}", path.display()))?;
+            Ok(Box::new(plugin) as Box<dyn ProcessorPlugin>)
+        })
+        .collect()
+}
+
+/// Run `image` through every plugin, returning `None` as soon as one vetoes it.
+pub fn run_plugins(
+    plugins: &mut [Box<dyn ProcessorPlugin>],
+    mut image: DynamicImage,
+) -> Result<Option<DynamicImage>, Box<dyn Error>> {
+    for plugin in plugins {
+        match plugin.process(image)? {
+            Verdict::Keep(processed) => image = processed,
+            Verdict::Veto => {
+                tracing::info!("Capture vetoed by plugin {}", plugin.name());
+                return Ok(None);
+            }
+        }
+    }
+    Ok(Some(image))
+}
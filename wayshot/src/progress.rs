@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+/// Reports how far a long operation got, such as encoding a recording.
+///
+/// Operations go through one or more stages, each counting steps, eg: frames.
+pub trait Progress: Send + Sync {
+    /// A stage begins, `total` is `None` when the number of steps is not known ahead.
+    fn start(&self, stage: &str, total: Option<u64>);
+    /// `done` steps of the current stage are complete.
+    fn update(&self, done: u64);
+    /// The operation is over, successfully or not.
+    fn finish(&self);
+}
+
+/// A progress bar on stderr, with the estimated time left for stages of a known length. Nothing
+/// is drawn when stderr is not a terminal.
+pub struct TerminalProgress {
+    bar: ProgressBar,
+}
+
+impl Default for TerminalProgress {
+    fn default() -> Self {
+        Self {
+            bar: ProgressBar::with_draw_target(None, ProgressDrawTarget::stderr()),
+        }
+    }
+}
+
+impl Progress for TerminalProgress {
+    fn start(&self, stage: &str, total: Option<u64>) {
+        let template = match total {
+            Some(_) => "{msg} [{bar:30}] {pos}/{len} ({eta} left)",
+            None => "{spinner} {msg} {pos} ({elapsed})",
+        };
+        if let Ok(style) = ProgressStyle::with_template(template) {
+            self.bar.set_style(style.progress_chars("=> "));
+        }
+        self.bar.reset();
+        match total {
+            Some(total) => self.bar.set_length(total),
+            None => self.bar.unset_length(),
+        }
+        self.bar.set_message(stage.to_string());
+        self.bar.enable_steady_tick(Duration::from_millis(200));
+    }
+
+    fn update(&self, done: u64) {
+        self.bar.set_position(done);
+    }
+
+    fn finish(&self) {
+        self.bar.finish_and_clear();
+    }
+}
@@ -0,0 +1,66 @@
+//! `wayshot daemon --metrics`: capture counts, failures, latency and encoded bytes in the
+//! Prometheus text format, for fleets of kiosks and the like to watch over.
+
+use std::{error::Error, net::SocketAddr, time::Duration};
+
+use metrics::{counter, describe_counter, describe_histogram, histogram, Unit};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder};
+
+const CAPTURES: &str = "wayshot_captures_total";
+const FAILURES: &str = "wayshot_capture_failures_total";
+const DURATION: &str = "wayshot_capture_duration_seconds";
+const ENCODED_BYTES: &str = "wayshot_encoded_bytes_total";
+
+/// Captures rarely take more than a frame or two, slow ones point at a struggling compositor.
+const DURATION_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
+
+/// Serve the metrics at `http://<address>/metrics` from a background thread.
+pub fn serve(address: SocketAddr) -> Result<(), Box<dyn Error>> {
+    PrometheusBuilder::new()
+        .with_http_listener(address)
+        .set_buckets_for_metric(Matcher::Full(DURATION.to_string()), DURATION_BUCKETS)?
+        .install()?;
+    describe_counter!(CAPTURES, "Captures requested from the daemon");
+    describe_counter!(FAILURES, "Captures that failed, by reason");
+    describe_histogram!(DURATION, Unit::Seconds, "Time taken to capture and encode");
+    describe_counter!(
+        ENCODED_BYTES,
+        Unit::Bytes,
+        "Bytes of encoded images sent to clients"
+    );
+    tracing::info!("Serving metrics on http://{address}/metrics");
+    Ok(())
+}
+
+/// Account for a capture that took `elapsed` and produced `encoded_bytes`, or failed with
+/// `error`.
+pub fn record_capture(
+    interface: &'static str,
+    outcome: Result<usize, &(dyn Error + 'static)>,
+    elapsed: Duration,
+) {
+    counter!(CAPTURES, "interface" => interface).increment(1);
+    histogram!(DURATION, "interface" => interface).record(elapsed.as_secs_f64());
+    match outcome {
+        Ok(encoded_bytes) => {
+            counter!(ENCODED_BYTES, "interface" => interface).increment(encoded_bytes as u64)
+        }
+        Err(error) => {
+            counter!(FAILURES, "interface" => interface, "reason" => failure_reason(error))
+                .increment(1)
+        }
+    }
+}
+
+/// Label grouping `error` by the stage that failed, see [`libwayshot::Error`].
+fn failure_reason(error: &(dyn Error + 'static)) -> &'static str {
+    match error.downcast_ref::<libwayshot::Error>() {
+        Some(error) if error.is_connection_error() => "connection",
+        Some(error) if error.is_unsupported() => "unsupported",
+        Some(libwayshot::Error::Timeout) => "timeout",
+        Some(error) if error.is_retryable() => "compositor",
+        Some(_) => "capture",
+        // Invalid geometries, unknown outputs and the like.
+        None => "request",
+    }
+}
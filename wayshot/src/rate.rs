@@ -0,0 +1,151 @@
+use std::time::Duration;
+
+/// Frames queued for the encoder beyond which it is considered to fall behind, in seconds of
+/// recording.
+const BEHIND_SECONDS: f64 = 0.5;
+/// Samples in a row the encoder has to keep up before the rate is raised again.
+const CALM_SAMPLES: usize = 60;
+
+/// How far behind the encoder is when a frame is pushed.
+#[derive(Debug, Clone, Copy)]
+pub struct Load {
+    /// Frames waiting to be encoded.
+    pub queued: usize,
+    /// How long after its due time the frame was pushed.
+    pub latency: Duration,
+}
+
+/// A change of the capture rate, reported to the [`RateController::on_change`] callbacks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateChange {
+    /// The encoder fell behind, frames are captured at `fps` now.
+    Lowered { fps: u32 },
+    /// The encoder caught up, frames are captured at `fps` now.
+    Raised { fps: u32 },
+}
+
+/// Lowers the rate frames are captured at while the encoder falls behind, and raises it back once
+/// it kept up for a while, for `wayshot record --min-fps`.
+///
+/// The video keeps its frame rate, frames are repeated in between. The encoder's quantization
+/// stays as it is: ffmpeg cannot change it in the middle of a raw video stream.
+pub struct RateController<'a> {
+    max_fps: u32,
+    min_fps: u32,
+    fps: u32,
+    calm: usize,
+    callbacks: Vec<Box<dyn FnMut(RateChange) + 'a>>,
+}
+
+impl<'a> RateController<'a> {
+    /// Captures start at `max_fps`, and never drop below `min_fps`.
+    pub fn new(max_fps: u32, min_fps: u32) -> Self {
+        let min_fps = min_fps.clamp(1, max_fps);
+        Self {
+            max_fps,
+            min_fps,
+            fps: max_fps,
+            calm: 0,
+            callbacks: Vec::new(),
+        }
+    }
+
+    /// Call `callback` on every change of the rate, eg: to show it.
+    pub fn on_change(&mut self, callback: impl FnMut(RateChange) + 'a) {
+        self.callbacks.push(Box::new(callback));
+    }
+
+    /// Video frame intervals between two captured frames.
+    pub fn step(&self) -> usize {
+        self.max_fps.div_ceil(self.fps) as usize
+    }
+
+    /// Adjust the rate to the `load` after pushing a frame.
+    pub fn update(&mut self, load: Load) -> Option<RateChange> {
+        let interval = Duration::from_secs(1) / self.fps;
+        let behind = load.queued as f64 > BEHIND_SECONDS * f64::from(self.max_fps)
+            || load.latency > interval * 2;
+        let change = if behind {
+            self.calm = 0;
+            let fps = (self.fps / 2).max(self.min_fps);
+            (fps != self.fps).then_some(RateChange::Lowered { fps })
+        } else if load.queued == 0 && load.latency < interval / 2 {
+            self.calm += 1;
+            let fps = (self.fps * 2).min(self.max_fps);
+            (self.calm >= CALM_SAMPLES && fps != self.fps).then_some(RateChange::Raised { fps })
+        } else {
+            self.calm = 0;
+            None
+        };
+        if let Some(change) = change {
+            let (RateChange::Lowered { fps } | RateChange::Raised { fps }) = change;
+            self.fps = fps;
+            self.calm = 0;
+            for callback in &mut self.callbacks {
+                callback(change);
+            }
+        }
+        change
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+
+    const CALM: Load = Load {
+        queued: 0,
+        latency: Duration::ZERO,
+    };
+
+    #[test]
+    fn lowers_while_behind_and_raises_once_caught_up() {
+        let mut controller = RateController::new(60, 10);
+        let changes = Rc::new(RefCell::new(Vec::new()));
+        controller.on_change({
+            let changes = changes.clone();
+            move |change| changes.borrow_mut().push(change)
+        });
+
+        let behind = Load {
+            queued: 100,
+            latency: Duration::ZERO,
+        };
+        assert_eq!(
+            controller.update(behind),
+            Some(RateChange::Lowered { fps: 30 })
+        );
+        assert_eq!(controller.step(), 2);
+        controller.update(behind);
+        assert_eq!(controller.step(), 4);
+        assert_eq!(
+            controller.update(behind),
+            Some(RateChange::Lowered { fps: 10 })
+        );
+        assert_eq!(controller.update(behind), None);
+
+        for _ in 1..CALM_SAMPLES {
+            assert_eq!(controller.update(CALM), None);
+        }
+        assert_eq!(
+            controller.update(CALM),
+            Some(RateChange::Raised { fps: 20 })
+        );
+        assert_eq!(changes.borrow().len(), 4);
+    }
+
+    #[test]
+    fn lowers_on_latency() {
+        let mut controller = RateController::new(30, 1);
+        let late = Load {
+            queued: 0,
+            latency: Duration::from_millis(100),
+        };
+        assert_eq!(
+            controller.update(late),
+            Some(RateChange::Lowered { fps: 15 })
+        );
+    }
+}
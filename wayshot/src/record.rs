@@ -0,0 +1,430 @@
+use std::{
+    error::Error,
+    io::Write,
+    os::unix::process::CommandExt,
+    path::PathBuf,
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::{atomic::Ordering, Arc},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime},
+};
+
+use image::RgbaImage;
+use libwayshot::{CaptureSource, FramePacing, WayshotConnection};
+
+use crate::{
+    animation::{AnimationEncoder, AnimationFormat},
+    bugreport,
+    progress::Progress,
+    rate::{Load, RateChange, RateController},
+    replay::ReplayBuffer,
+    scale::{FrameSize, Scaler},
+    signals,
+    spool::{FrameSpool, Pushed, Queued},
+    subtitles::{self, Subtitles},
+};
+
+/// Distance of the burnt in timestamp from the bottom left corner.
+const TIMESTAMP_MARGIN: u32 = 8;
+
+/// Bytes spooled to disk at most while ffmpeg falls behind, beyond that frames are dropped.
+const SPOOL_LIMIT: u64 = 4 << 30;
+
+/// What `wayshot record` should produce.
+pub struct RecordOptions {
+    /// The container and codecs are picked by ffmpeg from the extension, eg: `.webm` or `.mp4`.
+    pub file: PathBuf,
+    /// Write an animated image instead of a video.
+    pub animation: Option<AnimationFormat>,
+    pub fps: u32,
+    /// Stop after this long, or when interrupted if `None`.
+    pub duration: Option<Duration>,
+    /// PulseAudio / PipeWire source to record alongside the video.
+    pub audio: Option<String>,
+    /// Bytes of frames kept in memory while ffmpeg falls behind, the following ones are spooled
+    /// to the temporary directory.
+    pub memory_limit: usize,
+    /// Keep only this many seconds in a ring of segments, saved to numbered files named after
+    /// `file` on SIGUSR1.
+    pub replay: Option<u64>,
+    /// Scale the frames to this size before encoding them.
+    pub scale: Option<FrameSize>,
+    /// Capture fewer frames, down to this rate, while the encoder falls behind.
+    pub min_fps: Option<u32>,
+    /// Burn the wall clock time into the frames.
+    pub timestamp: bool,
+    /// Write the wall clock time of every second of video to this SRT file.
+    pub subtitles: Option<PathBuf>,
+    /// Log input events next to the recording.
+    #[cfg(feature = "input-log")]
+    pub input_log: Option<crate::input_log::InputLogOptions>,
+}
+
+/// An ffmpeg process encoding raw RGBA frames, fed by a thread so a slow encoder does not hold up
+/// the capture.
+struct Encoder {
+    ffmpeg: Child,
+    spool: Arc<FrameSpool>,
+    writer: JoinHandle<Result<(), String>>,
+    /// Whether frames are being spooled or dropped, to log when that changes.
+    state: Pushed,
+    /// Removes the segments once ffmpeg is done with them.
+    replay: Option<ReplayBuffer>,
+}
+
+impl Encoder {
+    fn spawn(width: u32, height: u32, options: &RecordOptions) -> Result<Self, Box<dyn Error>> {
+        let mut command = Command::new("ffmpeg");
+        command
+            .args(["-hide_banner", "-loglevel", "error", "-y"])
+            // Frames arrive at a constant rate, gaps in the capture are filled by repeating the
+            // previous frame, so buffered frames keep their timing.
+            .args(["-f", "rawvideo", "-pix_fmt", "rgba"])
+            .args(["-framerate", &options.fps.to_string()])
+            .args(["-s", &format!("{width}x{height}"), "-i", "-"]);
+        if let Some(source) = &options.audio {
+            command.args(["-f", "pulse", "-i", source, "-shortest"]);
+        }
+        command
+            // yuv420p is what players expect, and it needs even dimensions.
+            .args([
+                "-vf",
+                "pad=ceil(iw/2)*2:ceil(ih/2)*2",
+                "-pix_fmt",
+                "yuv420p",
+            ])
+            .args(["-r", &options.fps.to_string()]);
+        let replay = options
+            .replay
+            .map(|seconds| ReplayBuffer::new(&options.file, seconds))
+            .transpose()?;
+        match &replay {
+            Some(replay) => replay.output_args(&mut command),
+            None => {
+                command.arg(&options.file);
+            }
+        }
+        command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            // Keep ffmpeg out of our process group: Ctrl-C stops wayshot, which then closes the
+            // stream so ffmpeg can finish the file.
+            .process_group(0);
+
+        tracing::debug!("Starting {command:?}");
+        let mut ffmpeg = command
+            .spawn()
+            .map_err(|e| format!("Failed to run ffmpeg, is it installed? {e}"))?;
+        let mut stdin = ffmpeg.stdin.take().ok_or("Failed to open ffmpeg's stdin")?;
+
+        let frame_len = width as usize * height as usize * 4;
+        let spool = Arc::new(FrameSpool::new(
+            frame_len,
+            options.memory_limit,
+            SPOOL_LIMIT,
+        )?);
+        let writer = thread::spawn({
+            let spool = spool.clone();
+            move || {
+                let written = feed(&spool, &mut stdin).map_err(|e| e.to_string());
+                // Makes the capture stop if ffmpeg went away.
+                spool.close();
+                written
+            }
+        });
+        Ok(Self {
+            ffmpeg,
+            spool,
+            writer,
+            state: Pushed::Memory,
+            replay,
+        })
+    }
+
+    /// Queue `frame`, returns whether it was kept rather than dropped.
+    fn push(&mut self, frame: RgbaImage, repeat: usize) -> Result<bool, Box<dyn Error>> {
+        let pushed = self.spool.push(frame.into_raw(), repeat)?;
+        if pushed != self.state {
+            match pushed {
+                Pushed::Memory => tracing::info!("ffmpeg caught up"),
+                Pushed::Spooled => {
+                    tracing::info!("ffmpeg is falling behind, spooling frames to disk")
+                }
+                Pushed::Dropped => {
+                    tracing::warn!("ffmpeg is falling too far behind, dropping frames")
+                }
+            }
+            self.state = pushed;
+        }
+        Ok(pushed != Pushed::Dropped)
+    }
+
+    /// Let the queued frames through, close the stream and wait for ffmpeg to write out the file.
+    fn finish(self, progress: &dyn Progress) -> Result<(), Box<dyn Error>> {
+        let Self {
+            mut ffmpeg,
+            spool,
+            writer,
+            replay,
+            ..
+        } = self;
+        spool.close();
+        let queued = spool.queued();
+        if queued > 0 {
+            progress.start("Encoding queued frames", Some(queued as u64));
+            while !writer.is_finished() {
+                progress.update(queued.saturating_sub(spool.queued()) as u64);
+                thread::sleep(Duration::from_millis(signals::POLL_INTERVAL_MS as u64));
+            }
+        }
+        let written = writer
+            .join()
+            .map_err(|_| "the ffmpeg writer thread panicked")?;
+        let status = ffmpeg.wait()?;
+        if !status.success() {
+            return Err(format!("ffmpeg exited with {status}").into());
+        }
+        written?;
+        drop(replay);
+        Ok(())
+    }
+}
+
+/// Write the frames of `spool` to ffmpeg's `stdin` until it is closed and drained.
+fn feed(spool: &FrameSpool, stdin: &mut ChildStdin) -> Result<(), Box<dyn Error>> {
+    while let Some(Queued { frame, repeat }) = spool.pop()? {
+        for _ in 0..repeat {
+            stdin.write_all(&frame)?;
+        }
+    }
+    Ok(())
+}
+
+/// Where recorded frames go.
+enum Sink {
+    Video(Encoder),
+    Animation(AnimationEncoder),
+}
+
+impl Sink {
+    /// Add `frame`, shown for `repeat` frame intervals in videos. Animations time their frames
+    /// themselves. Returns whether the frame was kept, the next one is shown longer otherwise.
+    fn push(&mut self, frame: RgbaImage, repeat: usize) -> Result<bool, Box<dyn Error>> {
+        match self {
+            Sink::Video(encoder) => encoder.push(frame, repeat),
+            Sink::Animation(encoder) => {
+                encoder.push(frame);
+                Ok(true)
+            }
+        }
+    }
+
+    /// Frames waiting to be encoded.
+    fn queued(&self) -> usize {
+        match self {
+            Sink::Video(encoder) => encoder.spool.queued(),
+            Sink::Animation(_) => 0,
+        }
+    }
+
+    /// Save the replay buffer, if recording into one.
+    fn save_replay(&mut self) {
+        let Sink::Video(Encoder {
+            replay: Some(replay),
+            ..
+        }) = self
+        else {
+            return;
+        };
+        match replay.save() {
+            Ok(file) => tracing::info!("Saved the replay to {}", file.display()),
+            Err(e) => tracing::warn!("Failed to save the replay: {e}"),
+        }
+    }
+
+    fn finish(self, progress: &dyn Progress) -> Result<(), Box<dyn Error>> {
+        match self {
+            Sink::Video(encoder) => encoder.finish(progress),
+            Sink::Animation(encoder) => encoder.finish(progress),
+        }
+    }
+}
+
+/// Capture `source` at `options.fps` and encode it into `options.file` until the duration elapsed
+/// or wayshot is interrupted, reporting the recorded and encoded frames to `progress`.
+pub fn record(
+    wayshot_conn: &WayshotConnection,
+    source: CaptureSource,
+    cursor_overlay: bool,
+    options: &RecordOptions,
+    progress: &dyn Progress,
+) -> Result<(), Box<dyn Error>> {
+    let pacing = FramePacing {
+        interval: Duration::from_secs(1) / options.fps,
+        limit: options
+            .duration
+            .map(|duration| (duration.as_secs_f64() * f64::from(options.fps)).ceil() as usize),
+    };
+    let mut frames = wayshot_conn.frames(source, cursor_overlay, pacing);
+
+    // The first frame fixes the video size.
+    let Some(first_frame) = frames.next() else {
+        return Ok(());
+    };
+    let first_frame = first_frame?.to_rgba8();
+    let captured = SystemTime::now();
+    let capture_size = first_frame.dimensions();
+    let mut scaler = options.scale.map(Scaler::new);
+    let mut first_frame = match &mut scaler {
+        Some(scaler) => scaler.scale(first_frame),
+        None => first_frame,
+    };
+    if options.timestamp {
+        burn_in_timestamp(&mut first_frame, captured);
+    }
+    let mut subtitles = options
+        .subtitles
+        .as_deref()
+        .map(Subtitles::create)
+        .transpose()?;
+    if let Some(subtitles) = &mut subtitles {
+        subtitles.push(Duration::ZERO, captured)?;
+    }
+    // Frames are placed on the timeline by when they were captured, counted in frame intervals.
+    let started = Instant::now();
+    let interval = Duration::from_secs(1) / options.fps;
+    let (width, height) = first_frame.dimensions();
+    let mut sink = match options.animation {
+        Some(format) => {
+            if options.audio.is_some() {
+                tracing::warn!("Animated images have no audio, ignoring --audio");
+            }
+            if options.replay.is_some() {
+                return Err("--replay needs a video, not an animated image".into());
+            }
+            Sink::Animation(AnimationEncoder::new(
+                format,
+                options.file.clone(),
+                width,
+                height,
+            ))
+        }
+        None => Sink::Video(Encoder::spawn(width, height, options)?),
+    };
+    let save_requested = match options.replay {
+        Some(seconds) => {
+            tracing::info!(
+                "Recording {width}x{height} at {} fps, keeping the last {seconds} seconds. \
+                 Send SIGUSR1 to save them, eg: pkill -USR1 wayshot",
+                options.fps
+            );
+            Some(signals::flag_on_usr1()?)
+        }
+        None => {
+            tracing::info!(
+                "Recording {width}x{height} at {} fps to {}",
+                options.fps,
+                options.file.display()
+            );
+            None
+        }
+    };
+
+    #[cfg(feature = "input-log")]
+    let input_log = options
+        .input_log
+        .as_ref()
+        .map(crate::input_log::InputLog::start)
+        .transpose()?;
+    let total = pacing.limit.map(|limit| limit as u64);
+    progress.start("Recording", total);
+    let mut rate = options.min_fps.map(|min_fps| {
+        let mut rate = RateController::new(options.fps, min_fps);
+        rate.on_change(|change| {
+            let fps = match change {
+                RateChange::Lowered { fps } => {
+                    tracing::warn!("The encoder is falling behind, capturing at {fps} fps");
+                    fps
+                }
+                RateChange::Raised { fps } => {
+                    tracing::info!("The encoder caught up, capturing at {fps} fps");
+                    fps
+                }
+            };
+            progress.start(&format!("Recording at {fps} fps"), total);
+        });
+        rate
+    });
+    let mut written = 1;
+    sink.push(first_frame, 1)?;
+    for frame in frames {
+        if signals::received().is_some() {
+            break;
+        }
+        if save_requested
+            .as_ref()
+            .is_some_and(|requested| requested.swap(false, Ordering::SeqCst))
+        {
+            sink.save_replay();
+        }
+        let frame = frame?;
+        let captured = SystemTime::now();
+        if (frame.width(), frame.height()) != capture_size {
+            tracing::error!("Capture size changed while recording, stopping");
+            break;
+        }
+        let due = (started.elapsed().as_secs_f64() / interval.as_secs_f64()).round() as usize + 1;
+        // Captured before the previous one was shown for a whole interval, or for as many as the
+        // rate controller asks for.
+        let step = rate.as_ref().map_or(1, RateController::step);
+        if due < written + step {
+            continue;
+        }
+        let frame = frame.to_rgba8();
+        let mut frame = match &mut scaler {
+            Some(scaler) => scaler.scale(frame),
+            None => frame,
+        };
+        if options.timestamp {
+            burn_in_timestamp(&mut frame, captured);
+        }
+        if sink.push(frame, due - written)? {
+            if let Some(subtitles) = &mut subtitles {
+                subtitles.push(interval.saturating_mul((due - 1) as u32), captured)?;
+            }
+            written = due;
+            progress.update(written as u64);
+        }
+        if let Some(rate) = &mut rate {
+            rate.update(Load {
+                queued: sink.queued(),
+                latency: started
+                    .elapsed()
+                    .saturating_sub(interval.saturating_mul((due - 1) as u32)),
+            });
+        }
+    }
+
+    #[cfg(feature = "input-log")]
+    if let Some(input_log) = input_log {
+        input_log.finish()?;
+    }
+    if let Some(subtitles) = subtitles {
+        subtitles.finish()?;
+    }
+    let finished = sink.finish(progress);
+    progress.finish();
+    finished?;
+    if options.replay.is_none() {
+        tracing::info!("Recorded {written} frames to {}", options.file.display());
+    }
+    Ok(())
+}
+
+/// Draw the wall clock time `captured` in the bottom left corner of `frame`.
+fn burn_in_timestamp(frame: &mut RgbaImage, captured: SystemTime) {
+    let y = frame
+        .height()
+        .saturating_sub(bugreport::GLYPH_SIZE + TIMESTAMP_MARGIN);
+    bugreport::draw_text(frame, &subtitles::format_utc(captured), TIMESTAMP_MARGIN, y);
+}
@@ -0,0 +1,138 @@
+use std::{
+    env,
+    error::Error,
+    fs, io,
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+};
+
+use crate::utils;
+
+/// Length of the segments ffmpeg cuts the replay buffer into, saved replays end with the last
+/// complete one.
+const SEGMENT_SECONDS: u64 = 2;
+/// Segments kept beyond the listed ones, so a save copying the oldest listed segment does not
+/// race with ffmpeg overwriting it.
+const SPARE_SEGMENTS: u64 = 2;
+
+/// `wayshot record --replay`: ffmpeg writes the recording into a ring of short segments in a
+/// temporary directory, and [`ReplayBuffer::save`] joins the latest of them into a file.
+pub struct ReplayBuffer {
+    directory: PathBuf,
+    /// Saved replays are numbered after this file, eg: `replay-1.mkv`.
+    file: PathBuf,
+    segments: u64,
+    saved: usize,
+}
+
+impl ReplayBuffer {
+    /// A buffer holding at least the last `seconds` of the recording, saved next to `file`.
+    pub fn new(file: &Path, seconds: u64) -> io::Result<Self> {
+        let directory = env::temp_dir().join(format!("wayshot-replay-{}", process::id()));
+        fs::create_dir_all(&directory)?;
+        Ok(Self {
+            directory,
+            file: file.to_owned(),
+            segments: seconds.div_ceil(SEGMENT_SECONDS).max(1),
+            saved: 0,
+        })
+    }
+
+    fn segment_list(&self) -> PathBuf {
+        self.directory.join("replay.ffconcat")
+    }
+
+    /// ffmpeg output options writing the ring of segments, in place of the output file.
+    pub fn output_args(&self, command: &mut Command) {
+        let extension = self
+            .file
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or("mkv");
+        command
+            .args(["-f", "segment", "-reset_timestamps", "1"])
+            .args(["-segment_time", &SEGMENT_SECONDS.to_string()])
+            // Every segment has to start on a key frame to be playable on its own.
+            .args([
+                "-force_key_frames",
+                &format!("expr:gte(t,n_forced*{SEGMENT_SECONDS})"),
+            ])
+            // The list only names complete segments, the one being written is left out.
+            .args(["-segment_list_type", "ffconcat", "-segment_list"])
+            .arg(self.segment_list())
+            .args(["-segment_list_size", &self.segments.to_string()])
+            .args([
+                "-segment_wrap",
+                &(self.segments + SPARE_SEGMENTS).to_string(),
+            ])
+            .arg(self.directory.join(format!("segment-%03d.{extension}")));
+    }
+
+    /// Join the buffered segments into the next numbered file, returns its path.
+    pub fn save(&mut self) -> Result<PathBuf, Box<dyn Error>> {
+        let list = self.segment_list();
+        if !list.exists() {
+            return Err(format!(
+                "Nothing to save yet, the first {SEGMENT_SECONDS} seconds are still being encoded"
+            )
+            .into());
+        }
+        let file: PathBuf =
+            utils::get_numbered_file_name(&self.file.to_string_lossy(), self.saved + 1).into();
+        let status = Command::new("ffmpeg")
+            .args(["-hide_banner", "-loglevel", "error", "-y"])
+            .args(["-f", "concat", "-safe", "0", "-i"])
+            .arg(&list)
+            .args(["-c", "copy"])
+            .arg(&file)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .status()
+            .map_err(|e| format!("Failed to run ffmpeg: {e}"))?;
+        if !status.success() {
+            return Err(format!("ffmpeg exited with {status}").into());
+        }
+        self.saved += 1;
+        Ok(file)
+    }
+}
+
+impl Drop for ReplayBuffer {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_dir_all(&self.directory) {
+            tracing::warn!(
+                "Failed to remove the replay segments in {}: {e}",
+                self.directory.display()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_enough_segments() {
+        let replay = ReplayBuffer::new(Path::new("clip.mp4"), 5).expect("creating a replay buffer");
+        assert_eq!(replay.segments, 3);
+        let mut command = Command::new("ffmpeg");
+        replay.output_args(&mut command);
+        let args: Vec<_> = command
+            .get_args()
+            .map(|arg| arg.to_string_lossy())
+            .collect();
+        let after = |option: &str| {
+            let index = args
+                .iter()
+                .position(|arg| arg == option)
+                .expect("option is set");
+            args[index + 1].clone()
+        };
+        assert_eq!(after("-segment_list_size"), "3");
+        assert_eq!(after("-segment_wrap"), "5");
+        assert!(args
+            .last()
+            .is_some_and(|output| output.ends_with("segment-%03d.mp4")));
+    }
+}
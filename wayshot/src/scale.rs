@@ -0,0 +1,98 @@
+use std::str::FromStr;
+
+use image::{
+    imageops::{self, FilterType},
+    RgbaImage,
+};
+
+/// The size recorded frames are scaled to, parsed from eg: `1920x1080`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FromStr for FrameSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid size {s:?}, expected eg: 1920x1080");
+        let (width, height) = s.split_once('x').ok_or_else(invalid)?;
+        let width = width.trim().parse::<u32>().map_err(|_| invalid())?;
+        let height = height.trim().parse::<u32>().map_err(|_| invalid())?;
+        if width == 0 || height == 0 {
+            return Err(invalid());
+        }
+        Ok(Self { width, height })
+    }
+}
+
+/// Scales recorded frames to a [`FrameSize`], on the GPU when built with the `gpu-scale` feature
+/// and an adapter is available, on the CPU otherwise.
+pub enum Scaler {
+    #[cfg(feature = "gpu-scale")]
+    Gpu(Box<crate::gpu_scale::GpuScaler>),
+    Cpu(FrameSize),
+}
+
+impl Scaler {
+    pub fn new(size: FrameSize) -> Self {
+        #[cfg(feature = "gpu-scale")]
+        match crate::gpu_scale::GpuScaler::new(size.width, size.height) {
+            Ok(scaler) => return Self::Gpu(Box::new(scaler)),
+            Err(e) => tracing::info!("Scaling frames on the CPU, no GPU is available: {e}"),
+        }
+        Self::Cpu(size)
+    }
+
+    pub fn scale(&mut self, frame: RgbaImage) -> RgbaImage {
+        match self {
+            #[cfg(feature = "gpu-scale")]
+            Self::Gpu(scaler) => match scaler.scale(&frame) {
+                Ok(scaled) => scaled,
+                Err(e) => {
+                    tracing::warn!("Failed to scale on the GPU, scaling on the CPU instead: {e}");
+                    let (width, height) = scaler.size();
+                    *self = Self::Cpu(FrameSize { width, height });
+                    self.scale(frame)
+                }
+            },
+            Self::Cpu(size) => {
+                if frame.dimensions() == (size.width, size.height) {
+                    return frame;
+                }
+                imageops::resize(&frame, size.width, size.height, FilterType::Triangle)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_frame_sizes() {
+        assert_eq!(
+            "1920x1080".parse(),
+            Ok(FrameSize {
+                width: 1920,
+                height: 1080
+            })
+        );
+        assert!("1920".parse::<FrameSize>().is_err());
+        assert!("0x1080".parse::<FrameSize>().is_err());
+    }
+
+    #[test]
+    fn scales_on_the_cpu() {
+        let mut scaler = Scaler::Cpu(FrameSize {
+            width: 2,
+            height: 1,
+        });
+        let frame = RgbaImage::from_pixel(4, 2, image::Rgba([10, 20, 30, 255]));
+        let scaled = scaler.scale(frame);
+        assert_eq!(scaled.dimensions(), (2, 1));
+        assert!(scaled.pixels().all(|pixel| pixel.0 == [10, 20, 30, 255]));
+    }
+}
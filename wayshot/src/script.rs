@@ -0,0 +1,109 @@
+use std::{
+    error::Error,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use image::DynamicImage;
+use rhai::{Dynamic, Engine, Scope, AST};
+
+use crate::bugreport;
+
+/// A capture on its way to the sinks, together with the sinks it goes to.
+#[derive(Clone)]
+pub struct Capture {
+    pub image: DynamicImage,
+    pub file: Option<String>,
+    pub stdout: bool,
+    pub clipboard: bool,
+    pub dnd: bool,
+}
+
+/// A user script deciding what happens to every capture.
+///
+/// The script has to define `fn process(capture)`, returning the (possibly modified) capture, or
+/// `()` to drop it.
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+}
+
+impl Script {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut engine = Engine::new();
+        engine
+            .register_type_with_name::<Capture>("Capture")
+            .register_get("width", |capture: &mut Capture| {
+                i64::from(capture.image.width())
+            })
+            .register_get("height", |capture: &mut Capture| {
+                i64::from(capture.image.height())
+            })
+            .register_get_set(
+                "file",
+                |capture: &mut Capture| capture.file.clone().unwrap_or_default(),
+                |capture: &mut Capture, file: String| {
+                    capture.file = (!file.is_empty()).then_some(file);
+                },
+            )
+            .register_get_set(
+                "stdout",
+                |capture: &mut Capture| capture.stdout,
+                |capture: &mut Capture, stdout: bool| capture.stdout = stdout,
+            )
+            .register_get_set(
+                "clipboard",
+                |capture: &mut Capture| capture.clipboard,
+                |capture: &mut Capture, clipboard: bool| capture.clipboard = clipboard,
+            )
+            .register_get_set(
+                "dnd",
+                |capture: &mut Capture| capture.dnd,
+                |capture: &mut Capture, dnd: bool| capture.dnd = dnd,
+            )
+            .register_fn(
+                "crop",
+                |capture: &mut Capture, x: i64, y: i64, width: i64, height: i64| {
+                    let clamp = |value: i64| value.clamp(0, i64::from(u32::MAX)) as u32;
+                    capture.image =
+                        capture
+                            .image
+                            .crop_imm(clamp(x), clamp(y), clamp(width), clamp(height));
+                },
+            )
+            .register_fn(
+                "annotate",
+                |capture: &mut Capture, x: i64, y: i64, text: &str| {
+                    let clamp = |value: i64| value.clamp(0, i64::from(u32::MAX)) as u32;
+                    let mut image = capture.image.to_rgba8();
+                    bugreport::draw_text(&mut image, text, clamp(x), clamp(y));
+                    capture.image = image.into();
+                },
+            )
+            .register_fn("unix_time", || {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |time| time.as_secs() as i64)
+            });
+
+        let ast = engine
+            .compile_file(path.to_path_buf())
+            .map_err(|e| format!("Failed to load script {}: {e}", path.display()))?;
+        Ok(Self { engine, ast })
+    }
+
+    /// Run the script's `process` function, `None` means the script dropped the capture.
+    pub fn process(&self, capture: Capture) -> Result<Option<Capture>, Box<dyn Error>> {
+        let result: Dynamic =
+            self.engine
+                .call_fn(&mut Scope::new(), &self.ast, "process", (capture,))?;
+        if result.is_unit() {
+            tracing::info!("Capture dropped by script");
+            return Ok(None);
+        }
+        result
+            .try_cast::<Capture>()
+            .map(Some)
+            .ok_or_else(|| "script process() must return the capture or ()".into())
+    }
+}
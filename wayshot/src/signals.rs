@@ -0,0 +1,65 @@
+use std::{
+    process::exit,
+    sync::{
+        atomic::{AtomicBool, AtomicI32, Ordering},
+        Arc, LazyLock,
+    },
+    thread,
+};
+
+use signal_hook::{
+    consts::{SIGINT, SIGTERM, SIGUSR1},
+    flag,
+    iterator::Signals,
+};
+
+/// How often long running waits check whether they were interrupted.
+pub const POLL_INTERVAL_MS: i32 = 100;
+
+/// Last SIGINT / SIGTERM received, 0 if none.
+static RECEIVED: AtomicI32 = AtomicI32::new(0);
+/// Set along with [`RECEIVED`], for waits inside libwayshot, see [`interrupt_flag`].
+static INTERRUPTED: LazyLock<Arc<AtomicBool>> = LazyLock::new(Arc::default);
+
+/// Catch SIGINT and SIGTERM instead of dying on the spot, so drag and drop surfaces, the nested
+/// compositor and half written files get cleaned up. Long running steps poll [`received`].
+pub fn install() -> std::io::Result<()> {
+    let mut signals = Signals::new([SIGINT, SIGTERM])?;
+    thread::spawn(move || {
+        for signal in signals.forever() {
+            tracing::debug!("Received signal {signal}");
+            RECEIVED.store(signal, Ordering::SeqCst);
+            INTERRUPTED.store(true, Ordering::SeqCst);
+        }
+    });
+    Ok(())
+}
+
+/// A flag set on every SIGUSR1, which stops killing wayshot. Used to save `wayshot record --replay`.
+pub fn flag_on_usr1() -> std::io::Result<Arc<AtomicBool>> {
+    let received = Arc::new(AtomicBool::new(false));
+    flag::register(SIGUSR1, received.clone())?;
+    Ok(received)
+}
+
+/// A flag set once wayshot is interrupted, for
+/// [`libwayshot::WayshotConnection::set_interrupt`] so the drag and drop overlays go away.
+pub fn interrupt_flag() -> Arc<AtomicBool> {
+    INTERRUPTED.clone()
+}
+
+/// The signal that interrupted wayshot, if any.
+pub fn received() -> Option<i32> {
+    match RECEIVED.load(Ordering::SeqCst) {
+        0 => None,
+        signal => Some(signal),
+    }
+}
+
+/// Exit with the conventional `128 + signal` status if wayshot was interrupted.
+pub fn exit_if_received() {
+    if let Some(signal) = received() {
+        tracing::info!("Interrupted, exiting");
+        exit(128 + signal);
+    }
+}
@@ -0,0 +1,75 @@
+use libwayshot::CaptureRegion;
+
+/// Edges and centers of outputs and windows that selection edges are pulled towards, and an
+/// optional grid for everything else.
+#[derive(Debug, Clone)]
+pub struct SnapTargets {
+    /// Outputs and windows, also shown as guides while selecting.
+    pub rectangles: Vec<CaptureRegion>,
+    vertical: Vec<i32>,
+    horizontal: Vec<i32>,
+    grid: Option<u32>,
+    distance: u32,
+}
+
+impl SnapTargets {
+    /// Snap to the edges and centers of `rectangles` within `distance` logical pixels, and
+    /// otherwise to multiples of `grid`.
+    pub fn new(rectangles: Vec<CaptureRegion>, grid: Option<u32>, distance: u32) -> Self {
+        let mut vertical = Vec::new();
+        let mut horizontal = Vec::new();
+        for rectangle in &rectangles {
+            vertical.extend([
+                rectangle.x_coordinate,
+                rectangle.x_coordinate + rectangle.width / 2,
+                rectangle.x_coordinate + rectangle.width,
+            ]);
+            horizontal.extend([
+                rectangle.y_coordinate,
+                rectangle.y_coordinate + rectangle.height / 2,
+                rectangle.y_coordinate + rectangle.height,
+            ]);
+        }
+        Self {
+            rectangles,
+            vertical,
+            horizontal,
+            grid: grid.filter(|grid| *grid > 1),
+            distance,
+        }
+    }
+
+    /// Move every edge of `region` to the closest target within reach. `None` if nothing moved.
+    pub fn snap(&self, region: CaptureRegion) -> Option<CaptureRegion> {
+        let left = self.snap_coordinate(&self.vertical, region.x_coordinate);
+        let right = self.snap_coordinate(&self.vertical, region.x_coordinate + region.width);
+        let top = self.snap_coordinate(&self.horizontal, region.y_coordinate);
+        let bottom = self.snap_coordinate(&self.horizontal, region.y_coordinate + region.height);
+        let snapped = CaptureRegion {
+            x_coordinate: left,
+            y_coordinate: top,
+            width: right - left,
+            height: bottom - top,
+        };
+        // Tiny selections may collapse when both edges snap to the same line.
+        (snapped != region && snapped.width > 0 && snapped.height > 0).then_some(snapped)
+    }
+
+    fn snap_coordinate(&self, lines: &[i32], value: i32) -> i32 {
+        let closest_line = lines
+            .iter()
+            .map(|line| (line.abs_diff(value), *line))
+            .filter(|(distance, _)| *distance <= self.distance)
+            .min();
+        if let Some((_, line)) = closest_line {
+            return line;
+        }
+        match self.grid {
+            Some(grid) => {
+                let grid = f64::from(grid);
+                ((f64::from(value) / grid).round() * grid) as i32
+            }
+            None => value,
+        }
+    }
+}
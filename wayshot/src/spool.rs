@@ -0,0 +1,165 @@
+use std::{
+    collections::VecDeque,
+    env,
+    error::Error,
+    fs::{self, File, OpenOptions},
+    io,
+    os::unix::fs::{FileExt, OpenOptionsExt},
+    process,
+    sync::{Condvar, Mutex},
+};
+
+/// A queued frame, written `repeat` times to fill the time until the next one was captured.
+pub struct Queued {
+    pub frame: Vec<u8>,
+    pub repeat: usize,
+}
+
+/// What [`FrameSpool::push`] did with a frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Pushed {
+    Memory,
+    Spooled,
+    /// Both watermarks were reached, the frame was dropped.
+    Dropped,
+}
+
+#[derive(Default)]
+struct State {
+    memory: VecDeque<Queued>,
+    memory_bytes: usize,
+    /// Repeat counts of the frames in the spool file, oldest first.
+    spooled: VecDeque<usize>,
+    read_offset: u64,
+    write_offset: u64,
+    closed: bool,
+}
+
+/// Frames waiting for a slower consumer, such as an encoder, in the order they were pushed.
+///
+/// Frames are kept in memory up to `memory_limit` bytes, then appended to an unnamed file in the
+/// temporary directory up to `spool_limit` bytes, and dropped beyond that. Once frames were spooled,
+/// the following ones are spooled as well until the consumer caught up, so the order is kept.
+pub struct FrameSpool {
+    frame_len: usize,
+    memory_limit: usize,
+    spool_limit: u64,
+    file: File,
+    state: Mutex<State>,
+    ready: Condvar,
+}
+
+impl FrameSpool {
+    /// A spool for frames of exactly `frame_len` bytes.
+    pub fn new(
+        frame_len: usize,
+        memory_limit: usize,
+        spool_limit: u64,
+    ) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            frame_len,
+            memory_limit,
+            spool_limit,
+            file: unnamed_temp_file()?,
+            state: Mutex::new(State::default()),
+            ready: Condvar::new(),
+        })
+    }
+
+    /// Queue `frame`, to be taken `repeat` times by [`FrameSpool::pop`].
+    pub fn push(&self, frame: Vec<u8>, repeat: usize) -> Result<Pushed, Box<dyn Error>> {
+        if frame.len() != self.frame_len {
+            return Err("frame size changed while spooling".into());
+        }
+        let mut state = self.state.lock().map_err(|e| e.to_string())?;
+        if state.closed {
+            return Err("the frame consumer stopped".into());
+        }
+        let spooling = state.write_offset > state.read_offset;
+        let pushed = if !spooling && state.memory_bytes + frame.len() <= self.memory_limit {
+            state.memory_bytes += frame.len();
+            state.memory.push_back(Queued { frame, repeat });
+            Pushed::Memory
+        } else if state.write_offset + frame.len() as u64 <= self.spool_limit {
+            // The file only shrinks once drained, so its whole size counts against the limit.
+            self.file.write_all_at(&frame, state.write_offset)?;
+            state.write_offset += frame.len() as u64;
+            state.spooled.push_back(repeat);
+            Pushed::Spooled
+        } else {
+            Pushed::Dropped
+        };
+        self.ready.notify_one();
+        Ok(pushed)
+    }
+
+    /// Wait for the oldest frame and its repeat count, `None` once closed and drained.
+    pub fn pop(&self) -> Result<Option<Queued>, Box<dyn Error>> {
+        let mut state = self.state.lock().map_err(|e| e.to_string())?;
+        loop {
+            if let Some(queued) = state.memory.pop_front() {
+                state.memory_bytes -= queued.frame.len();
+                return Ok(Some(queued));
+            }
+            if let Some(repeat) = state.spooled.pop_front() {
+                let offset = state.read_offset;
+                state.read_offset += self.frame_len as u64;
+                // Pushes only append past the write offset, the frame can be read unlocked.
+                drop(state);
+                let mut frame = vec![0; self.frame_len];
+                self.file.read_exact_at(&mut frame, offset)?;
+                return Ok(Some(Queued { frame, repeat }));
+            }
+            if state.write_offset > 0 {
+                // Caught up, give the disk space back.
+                self.file.set_len(0)?;
+                state.read_offset = 0;
+                state.write_offset = 0;
+            }
+            if state.closed {
+                return Ok(None);
+            }
+            state = self.ready.wait(state).map_err(|e| e.to_string())?;
+        }
+    }
+
+    /// Number of frames waiting to be taken.
+    pub fn queued(&self) -> usize {
+        self.state
+            .lock()
+            .map_or(0, |state| state.memory.len() + state.spooled.len())
+    }
+
+    /// No more frames will be pushed, [`FrameSpool::pop`] returns `None` once the queued ones
+    /// were taken. Pushing afterwards fails, so the producer notices a consumer that gave up.
+    pub fn close(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.closed = true;
+        }
+        self.ready.notify_all();
+    }
+}
+
+/// A file in the temporary directory without a name, so it is gone once closed even if wayshot
+/// is killed. Falls back to a named file removed right away where O_TMPFILE is not supported.
+fn unnamed_temp_file() -> io::Result<File> {
+    let directory = env::temp_dir();
+    let unnamed = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .mode(0o600)
+        .custom_flags(nix::libc::O_TMPFILE)
+        .open(&directory);
+    if let Ok(file) = unnamed {
+        return Ok(file);
+    }
+    let path = directory.join(format!(".wayshot-spool-{}", process::id()));
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)?;
+    fs::remove_file(&path)?;
+    Ok(file)
+}
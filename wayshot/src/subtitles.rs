@@ -0,0 +1,132 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// How long each subtitle cue of `wayshot record --subtitles` lasts.
+const CUE_LENGTH: Duration = Duration::from_secs(1);
+
+/// An SRT sidecar showing the wall clock time while a recording plays, one cue per second of
+/// video.
+pub struct Subtitles {
+    file: BufWriter<File>,
+    /// Cues written so far, SRT numbers them from 1.
+    cues: u64,
+    /// The first second of video without a cue yet.
+    next_second: u64,
+}
+
+impl Subtitles {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            file: BufWriter::new(File::create(path)?),
+            cues: 0,
+            next_second: 0,
+        })
+    }
+
+    /// A frame shown at `position` in the video was captured at `time`. Writes a cue when the
+    /// frame starts a new second, seconds without frames have no cue.
+    pub fn push(&mut self, position: Duration, time: SystemTime) -> io::Result<()> {
+        let second = position.as_secs() / CUE_LENGTH.as_secs();
+        if second < self.next_second {
+            return Ok(());
+        }
+        self.cues += 1;
+        self.next_second = second + 1;
+        let start = CUE_LENGTH * second as u32;
+        writeln!(
+            self.file,
+            "{}\n{} --> {}\n{}\n",
+            self.cues,
+            srt_time(start),
+            srt_time(start + CUE_LENGTH),
+            format_utc(time)
+        )
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// `HH:MM:SS,mmm`, as SRT wants its cue times.
+fn srt_time(position: Duration) -> String {
+    let seconds = position.as_secs();
+    format!(
+        "{:02}:{:02}:{:02},{:03}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        position.subsec_millis()
+    )
+}
+
+/// `time` as eg: `2025-10-09 08:53:20 UTC`.
+pub fn format_utc(time: SystemTime) -> String {
+    let seconds = match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_secs() as i64,
+        Err(before) => -(before.duration().as_secs() as i64),
+    };
+    let (days, second_of_day) = (seconds.div_euclid(86_400), seconds.rem_euclid(86_400));
+    // Days to a proleptic Gregorian date, from Howard Hinnant's `civil_from_days`.
+    let shifted = days + 719_468;
+    let era = shifted.div_euclid(146_097);
+    let day_of_era = shifted - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} UTC",
+        second_of_day / 3600,
+        second_of_day / 60 % 60,
+        second_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn formats_utc_dates() {
+        let at = |seconds| UNIX_EPOCH + Duration::from_secs(seconds);
+        assert_eq!(format_utc(at(0)), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_utc(at(951_782_400)), "2000-02-29 00:00:00 UTC");
+        assert_eq!(format_utc(at(1_760_000_000)), "2025-10-09 08:53:20 UTC");
+    }
+
+    #[test]
+    fn writes_a_cue_per_second() {
+        let path = std::env::temp_dir().join(format!("wayshot-{}.srt", std::process::id()));
+        let mut subtitles = Subtitles::create(&path).expect("creating the subtitles");
+        let started = UNIX_EPOCH + Duration::from_secs(1_760_000_000);
+        for millis in [0, 500, 1000, 3200] {
+            let position = Duration::from_millis(millis);
+            subtitles
+                .push(position, started + position)
+                .expect("writing a cue");
+        }
+        subtitles.finish().expect("flushing the subtitles");
+        let written = fs::read_to_string(&path).expect("reading the subtitles");
+        fs::remove_file(&path).expect("removing the subtitles");
+        assert_eq!(
+            written,
+            "1\n00:00:00,000 --> 00:00:01,000\n2025-10-09 08:53:20 UTC\n\n\
+             2\n00:00:01,000 --> 00:00:02,000\n2025-10-09 08:53:21 UTC\n\n\
+             3\n00:00:03,000 --> 00:00:04,000\n2025-10-09 08:53:23 UTC\n\n"
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use std::{
+    error::Error,
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+use libwayshot::{
+    output::{ColorDescription, OutputInfo},
+    write::{self, EncodeOptions, WriteTarget},
+    Error as WayshotError, WayshotConnection,
+};
+
+use crate::{idle::IdleMonitor, latest::LatestCapture, signals, utils};
+
+/// How long without input before `--when active` considers the session idle.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Settings of `--interval`.
+pub struct TimelapseOptions {
+    pub interval: Duration,
+    /// Number of shots to save, `None` to go on until interrupted.
+    pub count: Option<usize>,
+    /// Only save a shot once the output changed since the previous one.
+    pub skip_unchanged: bool,
+    /// Pause while the session is idle, and take a shot as soon as it is active again.
+    pub when_active: bool,
+    /// Shots are saved next to it, numbered from 1.
+    pub file_path: String,
+    /// Also keep the newest shot there.
+    pub latest: Option<LatestCapture>,
+}
+
+/// Save a shot of `output` every `options.interval`, reusing one capture buffer throughout.
+///
+/// With `skip_unchanged`, a shot that is due waits until the compositor reports damage, so a
+/// static screen produces no files. With `when_active`, no shots are taken while the session is
+/// idle. Returns the number of saved shots.
+pub fn run(
+    wayshot_conn: &mut WayshotConnection,
+    output: &OutputInfo,
+    cursor_overlay: bool,
+    color: Option<&ColorDescription>,
+    options: &TimelapseOptions,
+) -> Result<usize, Box<dyn Error>> {
+    if options.skip_unchanged {
+        // Wake up regularly while waiting for damage, so a signal can stop the timelapse.
+        wayshot_conn.set_capture_timeout(Some(Duration::from_millis(
+            signals::POLL_INTERVAL_MS as u64,
+        )));
+    }
+    let mut context = wayshot_conn.start_streaming_capture(output, cursor_overlay)?;
+    let mut idle_monitor = if options.when_active {
+        Some(IdleMonitor::new(&wayshot_conn.conn, IDLE_TIMEOUT)?)
+    } else {
+        None
+    };
+
+    let mut saved = 0;
+    let mut next_shot = Instant::now();
+    loop {
+        if let Some(idle_monitor) = idle_monitor.as_mut() {
+            if idle_monitor.is_idle()? {
+                tracing::info!("Session idle, pausing the timelapse");
+                if !idle_monitor.wait_until_active()? {
+                    return Ok(saved);
+                }
+                tracing::info!("Session active, resuming the timelapse");
+                next_shot = Instant::now();
+            }
+        }
+        if options.skip_unchanged {
+            loop {
+                match context.capture_frame_with_damage() {
+                    Ok(_) => break,
+                    Err(WayshotError::Timeout) if signals::received().is_none() => {}
+                    Err(WayshotError::Timeout) => return Ok(saved),
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        } else {
+            context.screenshot_fast()?;
+        }
+        if let Some(frame_format) = context.frame_format().filter(|_| context.resized()) {
+            tracing::info!(
+                "Output size changed, saving {}x{} shots from now on",
+                frame_format.width,
+                frame_format.height
+            );
+        }
+
+        saved += 1;
+        let file_path = utils::get_numbered_file_name(&options.file_path, saved);
+        let image = context.image()?;
+        let capture = write::Capture::new(&image).with_color(color);
+        write::save(
+            &capture,
+            WriteTarget::File(Path::new(&file_path)),
+            EncodeOptions::default(),
+        )?;
+        tracing::info!("Saved {file_path}");
+        if let Some(latest) = &options.latest {
+            latest.publish_or_warn(&capture, Some(Path::new(&file_path)));
+        }
+        if options.count.is_some_and(|count| saved >= count) {
+            return Ok(saved);
+        }
+
+        // Shots that were delayed waiting for damage are not caught up on.
+        next_shot = (next_shot + options.interval).max(Instant::now());
+        let slice = Duration::from_millis(signals::POLL_INTERVAL_MS as u64);
+        loop {
+            if signals::received().is_some() {
+                return Ok(saved);
+            }
+            let remaining = next_shot.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            thread::sleep(remaining.min(slice));
+        }
+    }
+}
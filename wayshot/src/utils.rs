@@ -1,9 +1,57 @@
 use std::{
+    borrow::Cow,
+    error::Error,
+    fmt,
+    fs::File,
+    io,
+    path::Path,
     process::exit,
-    time::{SystemTime, UNIX_EPOCH},
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use libwayshot::CaptureRegion;
+use image::{DynamicImage, GenericImageView, ImageFormat};
+use libwayshot::{write::FormatLimits, CaptureRegion, RelativeRegion};
+use memmap2::MmapMut;
+use nix::{
+    errno::Errno,
+    poll::{poll, PollFd, PollFlags},
+};
+use wayland_client::EventQueue;
+
+use crate::signals;
+
+/// Like `blocking_dispatch`, but wakes up every [`signals::POLL_INTERVAL_MS`] so a signal can
+/// interrupt the caller.
+pub fn dispatch_interruptible<S>(
+    event_queue: &mut EventQueue<S>,
+    state: &mut S,
+) -> Result<(), Box<dyn Error>> {
+    event_queue.flush()?;
+    if let Some(guard) = event_queue.prepare_read() {
+        let connection_fd = guard.connection_fd();
+        let mut fds = [PollFd::new(&connection_fd, PollFlags::POLLIN)];
+        match poll(&mut fds, signals::POLL_INTERVAL_MS) {
+            Ok(0) | Err(Errno::EINTR) => {}
+            Ok(_) => {
+                guard.read()?;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    event_queue.dispatch_pending(state)?;
+    Ok(())
+}
+
+/// Map a shm file created with [`libwayshot::create_shm_fd`] to draw the pixels of an overlay
+/// surface into.
+pub fn map_shm(mem_file: &File) -> io::Result<MmapMut> {
+    // SAFETY: the mapping is only undefined behaviour if another process truncates the file or
+    // writes to it while it is mapped. The file is an anonymous memfd or an unlinked shm object,
+    // so only the compositor holds it besides us, and the compositor only ever reads the
+    // wl_shm buffers of surfaces.
+    unsafe { MmapMut::map_mut(mem_file) }
+}
 
 pub fn parse_geometry(g: &str) -> Option<CaptureRegion> {
     let tail = g.trim();
@@ -32,12 +80,122 @@ pub fn parse_geometry(g: &str) -> Option<CaptureRegion> {
         height = tail.parse::<i32>().ok()?;
     }
 
-    Some(CaptureRegion {
+    // Selection tools may hand out inverted rectangles when dragging up or to the left.
+    CaptureRegion {
         x_coordinate,
         y_coordinate,
         width,
         height,
-    })
+    }
+    .normalized()
+    .ok()
+}
+
+/// Parse a geometry given in percentages, eg: `"10%,10% 80%x50%"` or `"10% 10% 80% 50%"`.
+pub fn parse_relative_geometry(g: &str) -> Option<RelativeRegion> {
+    let mut fractions = g
+        .trim()
+        .split(|c: char| c == ',' || c == 'x' || c.is_whitespace())
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let percentage = part.strip_suffix('%')?.parse::<f64>().ok()?;
+            (0.0..=100.0)
+                .contains(&percentage)
+                .then_some(percentage / 100.0)
+        });
+    let region = RelativeRegion {
+        x: fractions.next()??,
+        y: fractions.next()??,
+        width: fractions.next()??,
+        height: fractions.next()??,
+    };
+    fractions.next().is_none().then_some(region)
+}
+
+/// Sizes interactive selections snap to, in logical pixels.
+const COMMON_SIZES: &[(i32, i32)] = &[
+    (1280, 720),
+    (1920, 1080),
+    (2560, 1440),
+    (3840, 2160),
+    (1080, 1080),
+    (1080, 1920),
+];
+/// How far, in logical pixels, a selection may be off a common size and still snap to it.
+const SNAP_DISTANCE: i32 = 24;
+
+/// A fixed width to height ratio for regions, eg: `16:9`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AspectRatio {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl AspectRatio {
+    /// Shrink `region` to this ratio, keeping its top left corner where it is.
+    pub fn constrain(&self, region: CaptureRegion) -> CaptureRegion {
+        let (ratio_width, ratio_height) = (i64::from(self.width), i64::from(self.height));
+        let (width, height) = (i64::from(region.width), i64::from(region.height));
+        let (width, height) = if width * ratio_height > height * ratio_width {
+            (height * ratio_width / ratio_height, height)
+        } else {
+            (width, width * ratio_height / ratio_width)
+        };
+        CaptureRegion {
+            width: width as i32,
+            height: height as i32,
+            ..region
+        }
+    }
+
+    fn matches(&self, width: i32, height: i32) -> bool {
+        i64::from(width) * i64::from(self.height) == i64::from(height) * i64::from(self.width)
+    }
+}
+
+impl FromStr for AspectRatio {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid aspect ratio {s:?}, expected eg: 16:9");
+        let (width, height) = s.split_once(':').ok_or_else(invalid)?;
+        let width = width.trim().parse::<u32>().map_err(|_| invalid())?;
+        let height = height.trim().parse::<u32>().map_err(|_| invalid())?;
+        if width == 0 || height == 0 {
+            return Err(invalid());
+        }
+        Ok(Self { width, height })
+    }
+}
+
+impl fmt::Display for AspectRatio {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.width, self.height)
+    }
+}
+
+/// Snap the size of `region` to the closest of [`COMMON_SIZES`] within [`SNAP_DISTANCE`], only
+/// considering sizes of `aspect` when given. `None` if no size is close enough.
+pub fn snap_to_common_size(
+    region: CaptureRegion,
+    aspect: Option<AspectRatio>,
+) -> Option<CaptureRegion> {
+    COMMON_SIZES
+        .iter()
+        .filter(|(width, height)| aspect.is_none_or(|aspect| aspect.matches(*width, *height)))
+        .map(|&(width, height)| {
+            let distance = (region.width - width)
+                .abs()
+                .max((region.height - height).abs());
+            (distance, width, height)
+        })
+        .filter(|(distance, _, _)| *distance <= SNAP_DISTANCE)
+        .min_by_key(|(distance, _, _)| *distance)
+        .map(|(_, width, height)| CaptureRegion {
+            width,
+            height,
+            ..region
+        })
 }
 
 /// Supported image encoding formats.
@@ -53,13 +211,31 @@ pub enum EncodingFormat {
     Qoi,
 }
 
-impl From<EncodingFormat> for image::ImageOutputFormat {
+impl From<EncodingFormat> for ImageFormat {
     fn from(format: EncodingFormat) -> Self {
         match format {
-            EncodingFormat::Jpg => image::ImageFormat::Jpeg.into(),
-            EncodingFormat::Png => image::ImageFormat::Png.into(),
-            EncodingFormat::Ppm => image::ImageFormat::Pnm.into(),
-            EncodingFormat::Qoi => image::ImageFormat::Qoi.into(),
+            EncodingFormat::Jpg => ImageFormat::Jpeg,
+            EncodingFormat::Png => ImageFormat::Png,
+            EncodingFormat::Ppm => ImageFormat::Pnm,
+            EncodingFormat::Qoi => ImageFormat::Qoi,
+        }
+    }
+}
+
+impl From<EncodingFormat> for image::ImageOutputFormat {
+    fn from(format: EncodingFormat) -> Self {
+        ImageFormat::from(format).into()
+    }
+}
+
+impl EncodingFormat {
+    /// MIME type of images encoded with this format.
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            EncodingFormat::Jpg => "image/jpeg",
+            EncodingFormat::Png => "image/png",
+            EncodingFormat::Ppm => "image/x-portable-pixmap",
+            EncodingFormat::Qoi => "image/qoi",
         }
     }
 }
@@ -75,6 +251,52 @@ impl From<EncodingFormat> for &str {
     }
 }
 
+/// How interactive steps, such as picking an output, ask the user.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Interaction {
+    /// Fuzzy selector prompt on the terminal.
+    Tty,
+    /// Graphical picker on the screen.
+    Gui,
+    /// Never prompt, use the configured policy instead.
+    None,
+}
+
+impl Interaction {
+    /// Resolve the `--interaction` value, `auto` picking the graphical picker in launcher mode, the
+    /// terminal when there is one, and no prompts otherwise so nothing hangs on a hidden prompt.
+    pub fn resolve(value: &str, gui_mode: bool, is_terminal: bool) -> Self {
+        match value {
+            "tty" => Interaction::Tty,
+            "gui" => Interaction::Gui,
+            "none" => Interaction::None,
+            _ if gui_mode => Interaction::Gui,
+            _ if is_terminal => Interaction::Tty,
+            _ => Interaction::None,
+        }
+    }
+}
+
+/// Parse durations such as `5s`, `500ms`, `2m` or `1h`, plain numbers are seconds.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration {value:?}, eg: 5s, 500ms or 2m"))?;
+    let seconds = match unit.trim() {
+        "" | "s" => number,
+        "ms" => number / 1000.0,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        unit => return Err(format!("unknown duration unit {unit:?}, use ms, s, m or h")),
+    };
+    Duration::try_from_secs_f64(seconds).map_err(|e| format!("invalid duration {value:?}: {e}"))
+}
+
 pub fn get_default_file_name(extension: EncodingFormat) -> String {
     let time = match SystemTime::now().duration_since(UNIX_EPOCH) {
         Ok(n) => n.as_secs().to_string(),
@@ -84,5 +306,97 @@ pub fn get_default_file_name(extension: EncodingFormat) -> String {
         }
     };
 
-    time + "-wayshot." + extension.into()
+    let extension: &str = extension.into();
+    time + "-wayshot." + extension
+}
+
+/// Default file name for `wayshot record`.
+pub fn get_default_recording_name(extension: &str) -> String {
+    let time = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(n) => n.as_secs().to_string(),
+        Err(_) => {
+            tracing::error!("SystemTime before UNIX EPOCH!");
+            exit(1);
+        }
+    };
+
+    time + "-wayshot." + extension
+}
+
+/// Insert `-{index}` before the extension of `file_path`, eg: `shot.png` -> `shot-1.png`.
+pub fn get_numbered_file_name(file_path: &str, index: usize) -> String {
+    let path = Path::new(file_path);
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+    let file_name = match path.extension() {
+        Some(extension) => format!("{stem}-{index}.{}", extension.to_string_lossy()),
+        None => format!("{stem}-{index}"),
+    };
+    path.with_file_name(file_name)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// The images to save for `image` at `file_path`: the image itself when the format of the file
+/// can store it, else with `auto_tile` numbered tiles that fit, eg: `shot-1.jpg`, `shot-2.jpg`.
+pub fn split_for_format<'a>(
+    image: &'a DynamicImage,
+    file_path: &str,
+    auto_tile: bool,
+) -> Result<Vec<(String, Cow<'a, DynamicImage>)>, String> {
+    let (width, height) = image.dimensions();
+    let limits = ImageFormat::from_path(file_path)
+        .ok()
+        .and_then(FormatLimits::of)
+        .filter(|limits| !limits.fits(width, height));
+    let Some(limits) = limits else {
+        return Ok(vec![(file_path.to_string(), Cow::Borrowed(image))]);
+    };
+    if !auto_tile {
+        return Err(format!(
+            "{file_path}: the {width}x{height} capture is too large for its format, at most {}x{} \
+             and {} pixels. Save it as PNG, capture less with --output or --slurp, or split it \
+             with --auto-tile",
+            limits.max_width, limits.max_height, limits.max_pixels
+        ));
+    }
+    Ok(limits
+        .tiles(width, height)
+        .into_iter()
+        .enumerate()
+        .map(|(index, (x, y, tile_width, tile_height))| {
+            (
+                get_numbered_file_name(file_path, index + 1),
+                Cow::Owned(image.crop_imm(x, y, tile_width, tile_height)),
+            )
+        })
+        .collect())
+}
+
+/// Decode the file written to `path` and check that it holds `image`: the same size, and the same
+/// pixels for the lossless formats, at the channel depth the format keeps.
+pub fn verify_written(image: &DynamicImage, path: &Path) -> Result<(), String> {
+    let format = ImageFormat::from_path(path).map_err(|e| e.to_string())?;
+    let decoded = image::open(path).map_err(|e| format!("cannot decode it back: {e}"))?;
+    if decoded.dimensions() != image.dimensions() {
+        return Err(format!(
+            "it is {:?} instead of {:?}",
+            decoded.dimensions(),
+            image.dimensions()
+        ));
+    }
+    let same_pixels = match format {
+        // Lossy, only the size can be checked.
+        ImageFormat::Jpeg => true,
+        ImageFormat::Png => decoded.to_rgba16() == image.to_rgba16(),
+        // PPM has no alpha channel.
+        ImageFormat::Pnm => decoded.to_rgb8() == image.to_rgb8(),
+        _ => decoded.to_rgba8() == image.to_rgba8(),
+    };
+    if !same_pixels {
+        return Err("its pixels differ".to_string());
+    }
+    Ok(())
 }
@@ -1,18 +1,88 @@
+#![deny(clippy::undocumented_unsafe_blocks)]
+
 use std::{
+    any::Any,
+    env,
     error::Error,
-    io::{stdout, BufWriter, Cursor, Write},
+    fs,
+    io::{stderr, stdin, stdout, BufWriter, IsTerminal, Write},
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
     process::exit,
+    sync::mpsc::Receiver,
+    time::Duration,
 };
 
-use libwayshot::WayshotConnection;
+use libwayshot::{
+    color,
+    output::OutputSelector,
+    write::{self, EncodeOptions, WriteTarget},
+    CaptureRegion, CaptureSource, FrameTiming, WayshotConnection,
+};
 
+mod animation;
+mod bugreport;
 mod clap;
+mod clipboard;
+mod config;
+mod countdown;
+mod daemon;
+mod dbus;
+mod dnd;
+mod feedback;
+mod flash;
+#[cfg(feature = "gpu-scale")]
+mod gpu_scale;
+mod gui;
+mod idle;
+#[cfg(feature = "input-log")]
+mod input_log;
+mod latest;
+mod logging;
+mod nested;
+mod plugins;
+mod progress;
+#[cfg(feature = "metrics")]
+mod prometheus;
+mod rate;
+mod record;
+mod replay;
+mod scale;
+mod script;
+mod signals;
+mod snap;
+mod spool;
+mod subtitles;
+mod timelapse;
 mod utils;
+mod workspace;
 
 use dialoguer::{theme::ColorfulTheme, FuzzySelect};
 use tracing::Level;
 
-use crate::utils::EncodingFormat;
+use crate::{
+    animation::AnimationFormat,
+    config::Config,
+    latest::LatestCapture,
+    script::Capture,
+    utils::{AspectRatio, EncodingFormat, Interaction},
+};
+
+/// Exit code used when the capture path panics instead of returning an error.
+const PANIC_EXIT_CODE: i32 = 70;
+
+/// Exit code used when `--verify-write` finds a saved file that does not hold the capture.
+const VERIFY_EXIT_CODE: i32 = 74;
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic payload"
+    }
+}
 
 fn select_ouput<T>(ouputs: &[T]) -> Option<usize>
 where
@@ -29,17 +99,107 @@ where
     Some(selection)
 }
 
+/// The region given with `--slurp`, relative to `--output` when given. Percentages are resolved
+/// against that output, or the whole desktop.
+///
+/// Invalid geometries are returned as errors rather than exiting, `wayshot daemon` keeps running
+/// after a bad request.
+fn region_from_geometry(
+    wayshot_conn: &WayshotConnection,
+    geometry: &str,
+    output_name: Option<&String>,
+    aspect: Option<AspectRatio>,
+) -> Result<CaptureRegion, Box<dyn Error>> {
+    let area = match output_name {
+        Some(output_name) => match wayshot_conn.find_output(output_name) {
+            Some(output) => Some(output.region()),
+            None => return Err("No output found".into()),
+        },
+        None => None,
+    };
+
+    if geometry.contains('%') {
+        let Some(region) = utils::parse_relative_geometry(geometry) else {
+            return Err(
+                "Invalid geometry specification, percentages must be within 0% to 100%".into(),
+            );
+        };
+        let area = match area {
+            Some(area) => area,
+            None => wayshot_conn.desktop_region()?,
+        };
+        let region = region.resolve(area)?;
+        return Ok(aspect.map_or(region, |aspect| aspect.constrain(region)));
+    }
+
+    let Some(mut region) = utils::parse_geometry(geometry) else {
+        return Err("Invalid geometry specification".into());
+    };
+    if let Some(area) = area {
+        let (Some(x_coordinate), Some(y_coordinate)) = (
+            region.x_coordinate.checked_add(area.x_coordinate),
+            region.y_coordinate.checked_add(area.y_coordinate),
+        ) else {
+            return Err(libwayshot::Error::InvalidRegion(region).into());
+        };
+        region.x_coordinate = x_coordinate;
+        region.y_coordinate = y_coordinate;
+    }
+    Ok(aspect.map_or(region, |aspect| aspect.constrain(region)))
+}
+
+/// Follow changes to the config file in the modes that keep running, without failing them if it
+/// cannot be watched.
+fn watch_config(config_path: &Path) -> Option<Receiver<Config>> {
+    match config::watch(config_path.to_path_buf()) {
+        Ok(reloads) => Some(reloads),
+        Err(e) => {
+            tracing::warn!("Not watching {} for changes: {e}", config_path.display());
+            None
+        }
+    }
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
     let args = clap::set_flags().get_matches();
-    let level = if args.get_flag("debug") {
-        Level::TRACE
-    } else {
-        Level::INFO
+
+    let config_path = args
+        .get_one::<PathBuf>("config")
+        .cloned()
+        .unwrap_or_else(Config::default_path);
+    // The config decides the log levels, report problems loading it with the defaults.
+    let config = tracing::subscriber::with_default(
+        tracing_subscriber::fmt()
+            .with_max_level(if args.get_flag("debug") {
+                Level::TRACE
+            } else {
+                Level::INFO
+            })
+            .with_writer(std::io::stderr)
+            .finish(),
+        || Config::load(&config_path),
+    );
+    logging::init(
+        &config.log,
+        args.get_flag("debug"),
+        args.get_one::<PathBuf>("log-file").map(PathBuf::as_path),
+    )?;
+
+    // Panics are reported through tracing by the catch_unwind boundary below, keep the default
+    // hook (with its location info) only when debugging.
+    if !args.get_flag("debug") {
+        panic::set_hook(Box::new(|_| {}));
+    }
+
+    signals::install()?;
+
+    let default_output = match config.default_output() {
+        Ok(selector) => selector,
+        Err(e) => {
+            tracing::error!("{e}");
+            exit(1);
+        }
     };
-    tracing_subscriber::fmt()
-        .with_max_level(level)
-        .with_writer(std::io::stderr)
-        .init();
 
     let extension = if let Some(extension) = args.get_one::<String>("extension") {
         let ext = extension.trim().to_lowercase();
@@ -66,67 +226,579 @@ fn main() -> Result<(), Box<dyn Error>> {
         file_is_stdout = true;
     } else if let Some(filepath) = args.get_one::<String>("file") {
         file_path = Some(filepath.trim().to_string());
-    } else {
+    } else if !args.get_flag("clipboard") && !args.get_flag("dnd") {
         file_path = Some(utils::get_default_file_name(extension));
     }
 
-    let wayshot_conn = WayshotConnection::new()?;
-
-    if args.get_flag("listoutputs") {
-        let valid_outputs = wayshot_conn.get_all_outputs();
-        for output in valid_outputs {
-            tracing::info!("{:#?}", output.name);
+    // Handled by the daemon, without connecting to the compositor here.
+    if let Some(client_args) = args.subcommand_matches("client") {
+        let socket_path = client_args
+            .get_one::<PathBuf>("socket")
+            .cloned()
+            .unwrap_or_else(daemon::default_socket_path);
+        let image = daemon::request_capture(
+            &socket_path,
+            &daemon::CaptureRequest {
+                geometry: args.get_one::<String>("slurp").cloned(),
+                output: args.get_one::<String>("output").cloned(),
+                cursor: args.get_flag("cursor"),
+                extension: <&str>::from(extension).to_string(),
+                display: args.get_one::<String>("display").cloned(),
+            },
+        )?;
+        if file_is_stdout {
+            stdout().lock().write_all(&image)?;
+        } else if let Some(file_path) = &file_path {
+            fs::write(file_path, image)?;
         }
-        exit(1);
+        return Ok(());
     }
 
-    let mut cursor_overlay = false;
-    if args.get_flag("cursor") {
-        cursor_overlay = true;
+    // Only serves the clipboard, without taking any capture.
+    if let Some(helper_args) = args.subcommand_matches("clipboard-helper") {
+        let wayshot_conn = match args.get_one::<String>("display") {
+            Some(display) => WayshotConnection::from_display(display)?,
+            None => WayshotConnection::new()?,
+        };
+        let socket_path = helper_args
+            .get_one::<PathBuf>("socket")
+            .cloned()
+            .unwrap_or_else(clipboard::default_helper_socket_path);
+        return clipboard::run_helper(&wayshot_conn.conn, &socket_path);
     }
 
-    let image_buffer = if let Some(slurp_region) = args.get_one::<String>("slurp") {
-        if let Some(region) = utils::parse_geometry(slurp_region) {
-            wayshot_conn.screenshot(region, cursor_overlay)?
+    // Launched from a desktop entry or keybinding daemon, nobody is watching stderr.
+    let gui_mode = args.get_flag("gui");
+    let interaction = Interaction::resolve(
+        if args.get_flag("pickoutput") {
+            "gui"
         } else {
-            tracing::error!("Invalid geometry specification");
+            args.get_one::<String>("interaction")
+                .map_or("auto", String::as_str)
+        },
+        gui_mode,
+        stdin().is_terminal(),
+    );
+
+    let mut connection = None;
+    let capture_result = panic::catch_unwind(AssertUnwindSafe(|| -> Result<_, Box<dyn Error>> {
+        // Kept alive until the capture is done, tearing the nested session down on drop.
+        let nested_session = match args.get_one::<String>("nested") {
+            Some(command) => Some(nested::NestedSession::start(command)?),
+            None => None,
+        };
+        let mut wayshot_conn = match (&nested_session, args.get_one::<String>("display")) {
+            (Some(session), _) => WayshotConnection::from_display(session.socket())?,
+            (None, Some(display)) => WayshotConnection::from_display(display)?,
+            (None, None) => WayshotConnection::new()?,
+        };
+        wayshot_conn.set_interrupt(Some(signals::interrupt_flag()));
+        // Drag and drop and the clipboard are served on the same compositor once capturing is
+        // done.
+        connection = Some(wayshot_conn.conn.clone());
+
+        if args.get_flag("listoutputs") {
+            let valid_outputs = wayshot_conn.get_all_outputs();
+            for output in valid_outputs {
+                tracing::info!("{:#?} ({})", output.name, output.id());
+            }
             exit(1);
         }
-    } else if let Some(output_name) = args.get_one::<String>("output") {
-        let outputs = wayshot_conn.get_all_outputs();
-        if let Some(output) = outputs.iter().find(|output| &output.name == output_name) {
-            wayshot_conn.screenshot_single_output(output, cursor_overlay)?
-        } else {
-            tracing::error!("No output found!\n");
-            exit(1);
+
+        if let Some(daemon_args) = args.subcommand_matches("daemon") {
+            #[cfg(feature = "metrics")]
+            if let Some(address) = daemon_args.get_one::<std::net::SocketAddr>("metrics") {
+                prometheus::serve(*address)?;
+            }
+            let socket_path = daemon_args
+                .get_one::<PathBuf>("socket")
+                .cloned()
+                .unwrap_or_else(daemon::default_socket_path);
+            let display = match (&nested_session, args.get_one::<String>("display")) {
+                (Some(session), _) => session.socket().to_string(),
+                (None, Some(display)) => display.clone(),
+                (None, None) => env::var("WAYLAND_DISPLAY").unwrap_or_else(|_| "wayland-0".into()),
+            };
+            let mut connections = vec![(display, wayshot_conn)];
+            for display in daemon_args
+                .get_many::<String>("also-display")
+                .into_iter()
+                .flatten()
+            {
+                connections.push((display.clone(), WayshotConnection::from_display(display)?));
+            }
+            daemon::run(
+                connections,
+                &socket_path,
+                default_output.clone(),
+                watch_config(&config_path),
+                daemon_args.get_flag("dbus"),
+                daemon_args
+                    .get_one::<PathBuf>("latest")
+                    .map(|path| LatestCapture::new(path.clone()))
+                    .transpose()?,
+            )?;
+            return Ok((Vec::new(), None));
+        }
+
+        let aspect = args.get_one::<AspectRatio>("aspect").copied();
+
+        let mut cursor_overlay = false;
+        if args.get_flag("cursor") {
+            cursor_overlay = true;
         }
-    } else if args.get_flag("chooseoutput") {
-        let outputs = wayshot_conn.get_all_outputs();
-        let output_names: Vec<String> = outputs
-            .iter()
-            .map(|display| display.name.to_string())
-            .collect();
-        if let Some(index) = select_ouput(&output_names) {
-            wayshot_conn.screenshot_single_output(&outputs[index], cursor_overlay)?
+
+        if let Some(record_args) = args.subcommand_matches("record") {
+            let source = if let Some(geometry) = args.get_one::<String>("slurp") {
+                CaptureSource::Region(region_from_geometry(
+                    &wayshot_conn,
+                    geometry,
+                    args.get_one::<String>("output"),
+                    aspect,
+                )?)
+            } else if let Some(output_name) = args.get_one::<String>("output") {
+                CaptureSource::Output(output_name.clone())
+            } else {
+                CaptureSource::All
+            };
+            let file = record_args.get_one::<PathBuf>("FILE_PATH");
+            let animation = match record_args.get_one::<String>("format").map(String::as_str) {
+                Some("gif") => Some(AnimationFormat::Gif),
+                Some("apng") => Some(AnimationFormat::Apng),
+                Some(_) => None,
+                None => file.and_then(|file| AnimationFormat::from_path(file)),
+            };
+            let options = record::RecordOptions {
+                file: file.cloned().unwrap_or_else(|| {
+                    utils::get_default_recording_name(match animation {
+                        Some(AnimationFormat::Gif) => "gif",
+                        Some(AnimationFormat::Apng) => "png",
+                        None => "webm",
+                    })
+                    .into()
+                }),
+                animation,
+                fps: *record_args.get_one::<u32>("fps").unwrap_or(&30),
+                duration: record_args
+                    .get_one::<u64>("duration")
+                    .map(|seconds| Duration::from_secs(*seconds)),
+                audio: record_args.get_one::<String>("audio").cloned(),
+                memory_limit: record_args
+                    .get_one::<usize>("maxmemory")
+                    .map_or(256, |mib| mib << 20),
+                replay: record_args.get_one::<u64>("replay").copied(),
+                scale: record_args.get_one::<scale::FrameSize>("scale").copied(),
+                min_fps: record_args.get_one::<u32>("min-fps").copied(),
+                timestamp: record_args.get_flag("timestamp"),
+                subtitles: record_args.get_one::<PathBuf>("subtitles").cloned(),
+                #[cfg(feature = "input-log")]
+                input_log: record_args.get_one::<PathBuf>("input-log").map(|file| {
+                    input_log::InputLogOptions {
+                        file: file.clone(),
+                        keys: record_args.get_flag("input-log-keys"),
+                    }
+                }),
+            };
+            // Only the log levels apply while recording.
+            let _reloads = watch_config(&config_path);
+            record::record(
+                &wayshot_conn,
+                source,
+                cursor_overlay,
+                &options,
+                &progress::TerminalProgress::default(),
+            )?;
+            return Ok((Vec::new(), None));
+        }
+
+        if let Some(seconds) = args.get_one::<u64>("delay") {
+            countdown::wait(
+                &wayshot_conn.conn,
+                Duration::from_secs(*seconds),
+                args.get_flag("countdown"),
+            );
+            signals::exit_if_received();
+        }
+        if args.get_flag("nextframe") {
+            wayshot_conn.set_frame_timing(FrameTiming::NextRedraw);
+        }
+
+        if let Some(interval) = args.get_one::<Duration>("interval") {
+            let output = match args.get_one::<String>("output") {
+                Some(output_name) => wayshot_conn.find_output(output_name),
+                None => wayshot_conn.select_output(&workspace::resolve_selector(
+                    default_output.as_ref().unwrap_or(&OutputSelector::Primary),
+                )?),
+            }
+            .ok_or("No output found")?
+            .clone();
+            let color = wayshot_conn.color_description(output.region()).cloned();
+            let options = timelapse::TimelapseOptions {
+                interval: *interval,
+                count: args.get_one::<u64>("count").map(|count| *count as usize),
+                skip_unchanged: args.get_flag("skipunchanged"),
+                when_active: args
+                    .get_one::<String>("when")
+                    .is_some_and(|when| when == "active"),
+                file_path: file_path
+                    .clone()
+                    .unwrap_or_else(|| utils::get_default_file_name(extension)),
+                latest: args
+                    .get_one::<PathBuf>("latest")
+                    .map(|path| LatestCapture::new(path.clone()))
+                    .transpose()?,
+            };
+            // Only the log levels apply to a timelapse.
+            let _reloads = watch_config(&config_path);
+            let saved = timelapse::run(
+                &mut wayshot_conn,
+                &output,
+                cursor_overlay,
+                color.as_ref(),
+                &options,
+            )?;
+            tracing::info!("Timelapse done, saved {saved} shots");
+            return Ok((Vec::new(), None));
+        }
+
+        // What the images were captured from, to tag them with the color description of its
+        // outputs. The whole desktop unless a branch narrows it down.
+        let mut captured_region = None;
+        let image_buffers = if args.subcommand_matches("bugreport").is_some() {
+            let lines = bugreport::collect_system_info(&wayshot_conn);
+            vec![bugreport::append_footer(
+                wayshot_conn.screenshot_all(cursor_overlay)?,
+                &lines,
+            )]
+        } else if args.get_flag("allworkspaces") {
+            let output_name = args
+                .get_one::<String>("output")
+                .ok_or("--all-workspaces requires --output")?;
+            if let Some(output) = wayshot_conn.find_output(output_name) {
+                captured_region = Some(output.region());
+                workspace::capture_all_workspaces(&wayshot_conn, output, cursor_overlay)?
+            } else {
+                tracing::error!("No output found!\n");
+                exit(1);
+            }
+        } else if let Some(geometry) = args.get_one::<String>("slurp") {
+            let region = region_from_geometry(
+                &wayshot_conn,
+                geometry,
+                args.get_one::<String>("output"),
+                aspect,
+            )?;
+            captured_region = Some(region);
+            let image_buffer = wayshot_conn.screenshot(region, cursor_overlay)?;
+            if args.get_flag("flash") {
+                // Only a confirmation, the capture itself already succeeded.
+                if let Err(e) = flash::flash_region(&wayshot_conn, region) {
+                    tracing::warn!("Failed to highlight the captured region: {e}");
+                }
+            }
+            vec![image_buffer]
+        } else if let Some(output_name) = args.get_one::<String>("output") {
+            if let Some(output) = wayshot_conn.find_output(output_name) {
+                captured_region = Some(output.region());
+                vec![wayshot_conn.screenshot_single_output(output, cursor_overlay)?]
+            } else {
+                tracing::error!("No output found!\n");
+                exit(1);
+            }
+        } else if args.get_flag("chooseoutput") || args.get_flag("pickoutput") {
+            let outputs = wayshot_conn.get_all_outputs();
+            let output = match interaction {
+                Interaction::Tty => {
+                    if !stdin().is_terminal() {
+                        tracing::error!("--interaction tty needs a terminal to prompt on");
+                        exit(1);
+                    }
+                    let output_names: Vec<String> = outputs
+                        .iter()
+                        .map(|display| display.name.to_string())
+                        .collect();
+                    select_ouput(&output_names).map(|index| &outputs[index])
+                }
+                Interaction::Gui => wayshot_conn
+                    .pick_output()?
+                    .and_then(|picked| wayshot_conn.find_output(&picked.name)),
+                // No one can answer a prompt, fall back to the configured policy.
+                Interaction::None => wayshot_conn.select_output(&workspace::resolve_selector(
+                    default_output.as_ref().unwrap_or(&OutputSelector::Primary),
+                )?),
+            };
+            if let Some(output) = output {
+                captured_region = Some(output.region());
+                vec![wayshot_conn.screenshot_single_output(output, cursor_overlay)?]
+            } else {
+                tracing::error!("No output found!\n");
+                exit(1);
+            }
+        } else if gui_mode && interaction == Interaction::Gui {
+            let snap_targets = config.selection.snap.then(|| {
+                let mut rectangles: Vec<CaptureRegion> = wayshot_conn
+                    .get_all_outputs()
+                    .iter()
+                    .map(|output| output.region())
+                    .collect();
+                match workspace::detect_driver().map(|driver| driver.windows()) {
+                    Some(Ok(windows)) => rectangles.extend(windows),
+                    Some(Err(e)) => tracing::debug!("Failed to list windows: {e}"),
+                    None => {}
+                }
+                snap::SnapTargets::new(
+                    rectangles,
+                    config.selection.grid,
+                    config.selection.snap_distance,
+                )
+            });
+            let selections = if args.get_flag("multiple") {
+                gui::select_regions(aspect, snap_targets.as_ref())?
+            } else {
+                vec![gui::select_region(aspect, snap_targets.as_ref())?
+                    .ok_or("Selection cancelled")?]
+            };
+            for selection in &selections {
+                if selection.edges_snapped {
+                    tracing::info!("Selection edges snapped to outputs, windows or the grid");
+                }
+                if let Some(aspect) = selection.aspect {
+                    tracing::info!("Selection constrained to {aspect}");
+                }
+                if selection.snapped {
+                    tracing::info!(
+                        "Selection snapped to {}x{}",
+                        selection.region.width,
+                        selection.region.height
+                    );
+                }
+            }
+            let regions: Vec<CaptureRegion> = selections
+                .iter()
+                .map(|selection| selection.region)
+                .collect();
+            // Several selections share one description, taken from the whole desktop.
+            if let [region] = regions[..] {
+                captured_region = Some(region);
+            }
+            wayshot_conn.screenshot_regions(&regions, cursor_overlay)?
+        } else if let Some(selector) = default_output {
+            if let Some(output) =
+                wayshot_conn.select_output(&workspace::resolve_selector(&selector)?)
+            {
+                captured_region = Some(output.region());
+                vec![wayshot_conn.screenshot_single_output(output, cursor_overlay)?]
+            } else {
+                tracing::error!("No output found!\n");
+                exit(1);
+            }
         } else {
-            tracing::error!("No output found!\n");
-            exit(1);
+            vec![wayshot_conn.screenshot_all(cursor_overlay)?]
+        };
+
+        let color = match captured_region {
+            Some(region) => wayshot_conn.color_description(region),
+            None => wayshot_conn
+                .desktop_region()
+                .ok()
+                .and_then(|region| wayshot_conn.color_description(region)),
+        };
+        Ok((image_buffers, color.cloned()))
+    }));
+    let (mut image_buffers, color) = match capture_result {
+        Ok(Ok(captured)) => captured,
+        Ok(Err(e)) => {
+            // Interrupting a selection is not a failure worth reporting.
+            signals::exit_if_received();
+            if gui_mode {
+                gui::notify("Screenshot failed", &e.to_string());
+            }
+            return Err(e);
+        }
+        Err(payload) => {
+            let message = panic_message(payload.as_ref());
+            tracing::error!("wayshot crashed while capturing: {message}");
+            tracing::error!("Please report this at https://github.com/waycrate/wayshot/issues");
+            // Nobody would see the log otherwise.
+            if gui_mode || !stderr().is_terminal() {
+                gui::notify(
+                    "wayshot crashed",
+                    &format!("{message}\nPlease report this at https://github.com/waycrate/wayshot/issues"),
+                );
+            }
+            exit(PANIC_EXIT_CODE);
         }
-    } else {
-        wayshot_conn.screenshot_all(cursor_overlay)?
     };
 
-    if file_is_stdout {
-        let stdout = stdout();
-        let mut buffer = Cursor::new(Vec::new());
+    if args.subcommand_matches("daemon").is_some() {
+        return Ok(());
+    }
+
+    if args.contains_id("interval") {
+        return Ok(());
+    }
+
+    if args.subcommand_matches("record").is_some() {
+        if gui_mode {
+            gui::notify("Recording saved", "");
+        }
+        return Ok(());
+    }
+
+    // Set as soon as the compositor is connected to, before anything is captured.
+    let Some(connection) = connection else {
+        return Ok(());
+    };
+
+    if let Some(temperature) = args.get_one::<u32>("neutralize-nightlight") {
+        for image_buffer in &mut image_buffers {
+            color::neutralize_color_temperature(image_buffer, *temperature);
+        }
+    }
 
+    if !args.get_flag("no-plugins") {
+        let plugin_dir = config
+            .plugins
+            .directory
+            .as_deref()
+            .map(config::expand_home)
+            .unwrap_or_else(|| config_path.with_file_name("plugins"));
+        let mut plugins = plugins::load_plugins(&plugin_dir)?;
+        if !plugins.is_empty() {
+            let mut processed = Vec::with_capacity(image_buffers.len());
+            for image_buffer in image_buffers {
+                if let Some(image_buffer) = plugins::run_plugins(&mut plugins, image_buffer)? {
+                    processed.push(image_buffer);
+                }
+            }
+            image_buffers = processed;
+            if image_buffers.is_empty() {
+                return Ok(());
+            }
+        }
+    }
+
+    let multiple_images = image_buffers.len() > 1;
+    let mut captures: Vec<Capture> = image_buffers
+        .into_iter()
+        .enumerate()
+        .map(|(index, image)| Capture {
+            image,
+            file: file_path.as_ref().map(|file_path| {
+                if multiple_images {
+                    utils::get_numbered_file_name(file_path, index + 1)
+                } else {
+                    file_path.clone()
+                }
+            }),
+            stdout: file_is_stdout,
+            // Only one image can be offered at a time.
+            clipboard: index == 0 && args.get_flag("clipboard"),
+            dnd: index == 0 && args.get_flag("dnd"),
+        })
+        .collect();
+
+    if let Some(script_path) = &config.base.script {
+        let script = script::Script::load(&config::expand_home(script_path))?;
+        let mut processed = Vec::with_capacity(captures.len());
+        for capture in captures {
+            if let Some(capture) = script.process(capture)? {
+                processed.push(capture);
+            }
+        }
+        captures = processed;
+    }
+
+    let stdout_captures: Vec<&Capture> = captures.iter().filter(|capture| capture.stdout).collect();
+    if !stdout_captures.is_empty() {
+        let stdout = stdout();
         let mut writer = BufWriter::new(stdout.lock());
-        image_buffer.write_to(&mut buffer, extension)?;
+        for capture in stdout_captures {
+            write::save(
+                &write::Capture::new(&capture.image).with_color(color.as_ref()),
+                WriteTarget::Writer(&mut writer),
+                EncodeOptions::with_format(extension.into()),
+            )?;
+        }
+        writer.flush()?;
+    }
 
-        writer.write_all(buffer.get_ref())?;
-    } else {
-        image_buffer.save(file_path.unwrap())?;
+    // Nothing has been written yet, so an interrupted capture leaves nothing behind.
+    signals::exit_if_received();
+
+    // Checked for every capture first, so a capture that cannot be saved leaves no files behind.
+    let mut saved = Vec::new();
+    for capture in &captures {
+        if let Some(file) = &capture.file {
+            saved.extend(utils::split_for_format(
+                &capture.image,
+                file,
+                args.get_flag("autotile"),
+            )?);
+        }
+    }
+    for (file, image) in &saved {
+        write::save(
+            &write::Capture::new(image).with_color(color.as_ref()),
+            WriteTarget::File(Path::new(file)),
+            EncodeOptions::default(),
+        )?;
+        if args.get_flag("verifywrite") {
+            if let Err(e) = utils::verify_written(image, Path::new(file)) {
+                tracing::error!("{file} does not hold the capture, {e}");
+                exit(VERIFY_EXIT_CODE);
+            }
+        }
+    }
+
+    if let Some(sound) = &config.feedback.sound {
+        feedback::play_sound(&config::expand_home(sound));
+    }
+
+    if gui_mode {
+        let files: Vec<&str> = saved.iter().map(|(file, _)| file.as_str()).collect();
+        let sound_name = config.feedback.notification_sound.as_deref();
+        if files.is_empty() {
+            gui::notify_with_sound("Screenshot taken", "", sound_name);
+        } else {
+            gui::notify_with_sound("Screenshot saved", &files.join("\n"), sound_name);
+        }
+    }
+
+    if let Some(capture) = captures.iter().find(|capture| capture.dnd) {
+        let offers = clipboard::image_offers(
+            &capture.image,
+            extension,
+            capture.file.as_deref().map(Path::new),
+            color.as_ref(),
+        )?;
+        dnd::start_drag(&connection, &capture.image, offers)?;
+    }
+
+    if let Some(capture) = captures.iter().find(|capture| capture.clipboard) {
+        let offers = clipboard::image_offers(
+            &capture.image,
+            extension,
+            capture.file.as_deref().map(Path::new),
+            color.as_ref(),
+        )?;
+        let handed_off = args.contains_id("clipboard-handoff") && {
+            let socket_path = args
+                .get_one::<PathBuf>("clipboard-handoff")
+                .cloned()
+                .unwrap_or_else(clipboard::default_helper_socket_path);
+            clipboard::hand_off(&socket_path, &offers)
+                .inspect_err(|e| tracing::warn!("{e}, serving the clipboard from here instead"))
+                .is_ok()
+        };
+        if !handed_off {
+            let timeout = args
+                .get_one::<u64>("clipboard-timeout")
+                .map(|seconds| Duration::from_secs(*seconds));
+            clipboard::copy_to_clipboard(&connection, offers, timeout)?;
+        }
     }
 
+    signals::exit_if_received();
     Ok(())
 }
@@ -0,0 +1,377 @@
+use std::{
+    env,
+    error::Error,
+    process::{Command, Output},
+    thread,
+    time::Duration,
+};
+
+use image::DynamicImage;
+use libwayshot::{
+    output::{OutputInfo, OutputSelector},
+    CaptureRegion, WayshotConnection,
+};
+use serde::Deserialize;
+
+/// Time given to the compositor to present a freshly activated workspace.
+const WORKSPACE_SETTLE_DELAY: Duration = Duration::from_millis(100);
+
+/// A workspace as reported by the compositor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Workspace {
+    /// Identifier handed back to the driver when activating the workspace.
+    pub id: String,
+    /// Name of the output the workspace lives on.
+    pub output: String,
+}
+
+/// Compositor specific way of listing and switching workspaces, and of finding the windows on them.
+pub trait WorkspaceDriver {
+    /// All workspaces living on `output`.
+    fn workspaces(&self, output: &str) -> Result<Vec<Workspace>, Box<dyn Error>>;
+
+    /// The workspace currently shown on `output`.
+    fn active(&self, output: &str) -> Result<Option<Workspace>, Box<dyn Error>>;
+
+    /// Make `workspace` the visible workspace on its output.
+    fn activate(&self, workspace: &Workspace) -> Result<(), Box<dyn Error>>;
+
+    /// Geometry of every window currently visible, in logical pixels.
+    fn windows(&self) -> Result<Vec<CaptureRegion>, Box<dyn Error>>;
+
+    /// Geometry of the focused window, `None` when no window has focus.
+    fn active_window(&self) -> Result<Option<CaptureRegion>, Box<dyn Error>>;
+
+    /// Name of the output holding keyboard focus.
+    fn focused_output(&self) -> Result<Option<String>, Box<dyn Error>>;
+}
+
+/// Pick a driver for the running compositor, based on the IPC sockets it advertises.
+pub fn detect_driver() -> Option<Box<dyn WorkspaceDriver>> {
+    if env::var_os("SWAYSOCK").is_some() {
+        Some(Box::new(SwayDriver))
+    } else if env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+        Some(Box::new(HyprlandDriver))
+    } else {
+        None
+    }
+}
+
+/// `selector`, with [`OutputSelector::Focused`] resolved to the focused output through the
+/// compositor's IPC, as Wayland does not tell clients which output has focus.
+pub fn resolve_selector(selector: &OutputSelector) -> Result<OutputSelector, Box<dyn Error>> {
+    if *selector != OutputSelector::Focused {
+        return Ok(selector.clone());
+    }
+    let driver =
+        detect_driver().ok_or("The focused output can only be found on sway and Hyprland")?;
+    let name = driver
+        .focused_output()?
+        .ok_or("The compositor reports no focused output")?;
+    tracing::debug!("Focused output is {name}");
+    Ok(OutputSelector::Name(name))
+}
+
+fn run(program: &str, args: &[&str]) -> Result<Output, Box<dyn Error>> {
+    tracing::debug!("Running {program} {args:?}");
+    let output = Command::new(program).args(args).output()?;
+    if !output.status.success() {
+        return Err(format!(
+            "{program} exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(output)
+}
+
+/// Talks to sway through `swaymsg`.
+struct SwayDriver;
+
+#[derive(Deserialize)]
+struct SwayWorkspace {
+    name: String,
+    output: String,
+    visible: bool,
+}
+
+#[derive(Deserialize)]
+struct SwayOutput {
+    name: String,
+    #[serde(default)]
+    focused: bool,
+}
+
+#[derive(Deserialize)]
+struct SwayRect {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+
+#[derive(Deserialize)]
+struct SwayNode {
+    rect: SwayRect,
+    /// Only set on views, ie: actual windows.
+    pid: Option<u32>,
+    visible: Option<bool>,
+    #[serde(default)]
+    focused: bool,
+    #[serde(default)]
+    nodes: Vec<SwayNode>,
+    #[serde(default)]
+    floating_nodes: Vec<SwayNode>,
+}
+
+impl SwayRect {
+    fn region(&self) -> CaptureRegion {
+        CaptureRegion {
+            x_coordinate: self.x,
+            y_coordinate: self.y,
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
+
+impl SwayNode {
+    fn focused_window(&self) -> Option<CaptureRegion> {
+        if self.pid.is_some() && self.focused {
+            return Some(self.rect.region());
+        }
+        self.nodes
+            .iter()
+            .chain(&self.floating_nodes)
+            .find_map(SwayNode::focused_window)
+    }
+
+    fn visible_windows(&self, windows: &mut Vec<CaptureRegion>) {
+        if self.pid.is_some() && self.visible == Some(true) {
+            windows.push(self.rect.region());
+        }
+        for node in self.nodes.iter().chain(&self.floating_nodes) {
+            node.visible_windows(windows);
+        }
+    }
+}
+
+impl SwayDriver {
+    fn list(&self) -> Result<Vec<SwayWorkspace>, Box<dyn Error>> {
+        let output = run("swaymsg", &["-t", "get_workspaces", "-r"])?;
+        Ok(serde_json::from_slice(&output.stdout)?)
+    }
+}
+
+impl WorkspaceDriver for SwayDriver {
+    fn workspaces(&self, output: &str) -> Result<Vec<Workspace>, Box<dyn Error>> {
+        Ok(self
+            .list()?
+            .into_iter()
+            .filter(|workspace| workspace.output == output)
+            .map(|workspace| Workspace {
+                id: workspace.name,
+                output: workspace.output,
+            })
+            .collect())
+    }
+
+    fn active(&self, output: &str) -> Result<Option<Workspace>, Box<dyn Error>> {
+        Ok(self
+            .list()?
+            .into_iter()
+            .find(|workspace| workspace.output == output && workspace.visible)
+            .map(|workspace| Workspace {
+                id: workspace.name,
+                output: workspace.output,
+            }))
+    }
+
+    fn activate(&self, workspace: &Workspace) -> Result<(), Box<dyn Error>> {
+        run(
+            "swaymsg",
+            &["workspace", "--no-auto-back-and-forth", &workspace.id],
+        )?;
+        Ok(())
+    }
+
+    fn windows(&self) -> Result<Vec<CaptureRegion>, Box<dyn Error>> {
+        let tree: SwayNode =
+            serde_json::from_slice(&run("swaymsg", &["-t", "get_tree", "-r"])?.stdout)?;
+        let mut windows = Vec::new();
+        tree.visible_windows(&mut windows);
+        Ok(windows)
+    }
+
+    fn active_window(&self) -> Result<Option<CaptureRegion>, Box<dyn Error>> {
+        let tree: SwayNode =
+            serde_json::from_slice(&run("swaymsg", &["-t", "get_tree", "-r"])?.stdout)?;
+        Ok(tree.focused_window())
+    }
+
+    fn focused_output(&self) -> Result<Option<String>, Box<dyn Error>> {
+        let outputs: Vec<SwayOutput> =
+            serde_json::from_slice(&run("swaymsg", &["-t", "get_outputs", "-r"])?.stdout)?;
+        Ok(outputs
+            .into_iter()
+            .find(|output| output.focused)
+            .map(|output| output.name))
+    }
+}
+
+/// Talks to Hyprland through `hyprctl`.
+struct HyprlandDriver;
+
+#[derive(Deserialize)]
+struct HyprlandWorkspace {
+    id: i64,
+    monitor: String,
+}
+
+#[derive(Deserialize)]
+struct HyprlandWorkspaceRef {
+    id: i64,
+}
+
+#[derive(Deserialize)]
+struct HyprlandClient {
+    at: [i32; 2],
+    size: [i32; 2],
+    mapped: bool,
+    hidden: bool,
+    workspace: HyprlandWorkspaceRef,
+}
+
+/// `hyprctl activewindow` prints an empty object when no window has focus.
+#[derive(Deserialize)]
+struct HyprlandActiveWindow {
+    at: Option<[i32; 2]>,
+    size: Option<[i32; 2]>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HyprlandMonitor {
+    name: String,
+    active_workspace: HyprlandWorkspaceRef,
+    #[serde(default)]
+    focused: bool,
+}
+
+impl WorkspaceDriver for HyprlandDriver {
+    fn workspaces(&self, output: &str) -> Result<Vec<Workspace>, Box<dyn Error>> {
+        let workspaces: Vec<HyprlandWorkspace> =
+            serde_json::from_slice(&run("hyprctl", &["-j", "workspaces"])?.stdout)?;
+        let mut workspaces: Vec<HyprlandWorkspace> = workspaces
+            .into_iter()
+            // Special workspaces (scratchpads) have negative ids and are never "shown" normally.
+            .filter(|workspace| workspace.monitor == output && workspace.id > 0)
+            .collect();
+        workspaces.sort_by_key(|workspace| workspace.id);
+        Ok(workspaces
+            .into_iter()
+            .map(|workspace| Workspace {
+                id: workspace.id.to_string(),
+                output: workspace.monitor,
+            })
+            .collect())
+    }
+
+    fn active(&self, output: &str) -> Result<Option<Workspace>, Box<dyn Error>> {
+        let monitors: Vec<HyprlandMonitor> =
+            serde_json::from_slice(&run("hyprctl", &["-j", "monitors"])?.stdout)?;
+        Ok(monitors
+            .into_iter()
+            .find(|monitor| monitor.name == output)
+            .map(|monitor| Workspace {
+                id: monitor.active_workspace.id.to_string(),
+                output: monitor.name,
+            }))
+    }
+
+    fn activate(&self, workspace: &Workspace) -> Result<(), Box<dyn Error>> {
+        run("hyprctl", &["dispatch", "workspace", &workspace.id])?;
+        Ok(())
+    }
+
+    fn windows(&self) -> Result<Vec<CaptureRegion>, Box<dyn Error>> {
+        let monitors: Vec<HyprlandMonitor> =
+            serde_json::from_slice(&run("hyprctl", &["-j", "monitors"])?.stdout)?;
+        let clients: Vec<HyprlandClient> =
+            serde_json::from_slice(&run("hyprctl", &["-j", "clients"])?.stdout)?;
+        Ok(clients
+            .into_iter()
+            .filter(|client| {
+                client.mapped
+                    && !client.hidden
+                    && monitors
+                        .iter()
+                        .any(|monitor| monitor.active_workspace.id == client.workspace.id)
+            })
+            .map(|client| CaptureRegion {
+                x_coordinate: client.at[0],
+                y_coordinate: client.at[1],
+                width: client.size[0],
+                height: client.size[1],
+            })
+            .collect())
+    }
+
+    fn active_window(&self) -> Result<Option<CaptureRegion>, Box<dyn Error>> {
+        let window: HyprlandActiveWindow =
+            serde_json::from_slice(&run("hyprctl", &["-j", "activewindow"])?.stdout)?;
+        Ok(match (window.at, window.size) {
+            (Some([x, y]), Some([width, height])) => Some(CaptureRegion {
+                x_coordinate: x,
+                y_coordinate: y,
+                width,
+                height,
+            }),
+            _ => None,
+        })
+    }
+
+    fn focused_output(&self) -> Result<Option<String>, Box<dyn Error>> {
+        let monitors: Vec<HyprlandMonitor> =
+            serde_json::from_slice(&run("hyprctl", &["-j", "monitors"])?.stdout)?;
+        Ok(monitors
+            .into_iter()
+            .find(|monitor| monitor.focused)
+            .map(|monitor| monitor.name))
+    }
+}
+
+/// Capture every workspace of `output` in turn, restoring the originally visible workspace
+/// afterwards.
+pub fn capture_all_workspaces(
+    wayshot_conn: &WayshotConnection,
+    output: &OutputInfo,
+    cursor_overlay: bool,
+) -> Result<Vec<DynamicImage>, Box<dyn Error>> {
+    let Some(driver) = detect_driver() else {
+        return Err(
+            "No supported compositor IPC found, only sway and Hyprland are supported".into(),
+        );
+    };
+
+    let original = driver.active(&output.name)?;
+    let workspaces = driver.workspaces(&output.name)?;
+    tracing::debug!("Workspaces on {}: {:#?}", output.name, workspaces);
+
+    let mut images = Vec::with_capacity(workspaces.len());
+    let result = workspaces.iter().try_for_each(|workspace| {
+        driver.activate(workspace)?;
+        // Give the compositor a chance to render the new workspace before copying it.
+        thread::sleep(WORKSPACE_SETTLE_DELAY);
+        images.push(wayshot_conn.screenshot_single_output(output, cursor_overlay)?);
+        Ok::<_, Box<dyn Error>>(())
+    });
+
+    if let Some(original) = original {
+        driver.activate(&original)?;
+    }
+    result?;
+
+    Ok(images)
+}
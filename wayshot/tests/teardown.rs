@@ -0,0 +1,180 @@
+//! Interrupting wayshot inside a headless sway must not leave overlays or partial files behind.
+//!
+//! Skipped when sway, or ffmpeg for recordings, is not installed.
+
+#[path = "../src/nested.rs"]
+mod nested;
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{Child, Command, ExitStatus, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use nested::NestedSession;
+
+/// How long wayshot gets to put its overlays up, or record something, before it is interrupted.
+const SETTLE_DELAY: Duration = Duration::from_secs(2);
+/// How long wayshot gets to exit once interrupted.
+const EXIT_TIMEOUT: Duration = Duration::from_secs(10);
+/// `128 + SIGINT`.
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+fn installed(program: &str) -> bool {
+    env::var_os("PATH")
+        .is_some_and(|path| env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+}
+
+/// A headless sway to run wayshot in, `None` when the test has to be skipped.
+fn session(requirements: &[&str]) -> Option<NestedSession> {
+    if let Some(missing) = requirements.iter().find(|program| !installed(program)) {
+        eprintln!("Skipping, {missing} is not installed");
+        return None;
+    }
+    if env::var_os("XDG_RUNTIME_DIR").is_none() {
+        eprintln!("Skipping, XDG_RUNTIME_DIR is not set");
+        return None;
+    }
+    Some(NestedSession::start("sleep infinity").expect("starting headless sway"))
+}
+
+/// A directory of its own for each test, as config home and for the files written.
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("wayshot-teardown-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).expect("creating a scratch directory");
+    dir
+}
+
+fn wayshot(session: &NestedSession, scratch: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_wayshot"));
+    command
+        .args(["--display", session.socket(), "--no-plugins"])
+        .env("XDG_CONFIG_HOME", scratch)
+        .env_remove("WAYLAND_DISPLAY")
+        .stdin(Stdio::null());
+    command
+}
+
+fn screenshot(session: &NestedSession, scratch: &Path) -> Vec<u8> {
+    let output = wayshot(session, scratch)
+        .args(["--stdout", "-e", "ppm"])
+        .output()
+        .expect("running wayshot");
+    assert!(output.status.success(), "taking a screenshot failed");
+    output.stdout
+}
+
+/// SIGINT `child` once it settled, and wait for it to exit.
+fn interrupt(mut child: Child) -> ExitStatus {
+    thread::sleep(SETTLE_DELAY);
+    assert!(
+        child.try_wait().expect("polling wayshot").is_none(),
+        "wayshot exited before it was interrupted"
+    );
+    let status = Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .expect("running kill");
+    assert!(status.success(), "kill failed");
+    let interrupted = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait().expect("polling wayshot") {
+            return status;
+        }
+        if interrupted.elapsed() > EXIT_TIMEOUT {
+            let _ = child.kill();
+            panic!("wayshot did not exit once interrupted");
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+fn interrupted_pick_output_leaves_no_overlay_or_file() {
+    let Some(session) = session(&["sway"]) else {
+        return;
+    };
+    let scratch = scratch_dir("pick-output");
+    let before = screenshot(&session, &scratch);
+
+    let file = scratch.join("out.png");
+    let child = wayshot(&session, &scratch)
+        .arg("--pick-output")
+        .arg("-f")
+        .arg(&file)
+        .spawn()
+        .expect("running wayshot");
+    assert_eq!(interrupt(child).code(), Some(INTERRUPTED_EXIT_CODE));
+    assert!(!file.exists(), "a screenshot was written");
+    assert!(
+        screenshot(&session, &scratch) == before,
+        "the overlay is still shown"
+    );
+    let _ = fs::remove_dir_all(&scratch);
+}
+
+#[test]
+fn interrupted_drag_and_drop_leaves_no_surface() {
+    let Some(session) = session(&["sway"]) else {
+        return;
+    };
+    let scratch = scratch_dir("dnd");
+    let before = screenshot(&session, &scratch);
+
+    let child = wayshot(&session, &scratch)
+        .arg("--dnd")
+        .spawn()
+        .expect("running wayshot");
+    assert_eq!(interrupt(child).code(), Some(INTERRUPTED_EXIT_CODE));
+    assert!(
+        screenshot(&session, &scratch) == before,
+        "the drag surface is still shown"
+    );
+    let _ = fs::remove_dir_all(&scratch);
+}
+
+#[test]
+fn interrupted_recording_is_finished() {
+    let Some(session) = session(&["sway", "ffmpeg"]) else {
+        return;
+    };
+    let scratch = scratch_dir("record");
+    let file = scratch.join("out.mkv");
+    let child = wayshot(&session, &scratch)
+        .arg("record")
+        .arg(&file)
+        .spawn()
+        .expect("running wayshot");
+    assert!(interrupt(child).success(), "the recording failed");
+    let size = fs::metadata(&file).map(|metadata| metadata.len());
+    assert!(size.is_ok_and(|size| size > 0), "the recording is empty");
+    if installed("ffprobe") {
+        let status = Command::new("ffprobe")
+            .args(["-v", "error"])
+            .arg(&file)
+            .status()
+            .expect("running ffprobe");
+        assert!(status.success(), "the recording is not playable");
+    }
+    let _ = fs::remove_dir_all(&scratch);
+}
+
+#[test]
+fn interrupted_replay_buffer_is_removed() {
+    let Some(session) = session(&["sway", "ffmpeg"]) else {
+        return;
+    };
+    let scratch = scratch_dir("replay");
+    let child = wayshot(&session, &scratch)
+        .args(["record", "--replay", "4"])
+        .arg(scratch.join("replay.mkv"))
+        .spawn()
+        .expect("running wayshot");
+    let segments = env::temp_dir().join(format!("wayshot-replay-{}", child.id()));
+    assert!(interrupt(child).success(), "the replay buffer failed");
+    assert!(!segments.exists(), "the replay segments were left behind");
+    let _ = fs::remove_dir_all(&scratch);
+}